@@ -4,6 +4,7 @@ use dg_xch_core::blockchain::sized_bytes::Bytes32;
 use dialoguer::theme::ColorfulTheme;
 use dialoguer::Input;
 use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
 use std::str::FromStr;
 
 #[derive(Parser, Debug)]
@@ -317,6 +318,36 @@ pub enum RootCommands {
         #[command(subcommand)]
         action: WalletAction,
     },
+    #[command(about = "Runs challenges against plots and reports proofs found as JSON", long_about = None)]
+    CheckPlots {
+        #[arg(required = true, value_name = "Plot files or directories")]
+        paths: Vec<PathBuf>,
+        #[arg(short = 'n', long, default_value_t = 30)]
+        challenges: u32,
+        #[arg(short = 's', long, default_value_t = 0)]
+        challenge_start: u32,
+        #[arg(short, long)]
+        recursive: bool,
+        #[arg(short, long, default_value_t = 4)]
+        parallel: usize,
+        #[arg(short, long, default_value_t = 4)]
+        decompressor_threads: u8,
+        #[arg(
+            short,
+            long,
+            value_name = "Write the JSON report to a file instead of stdout"
+        )]
+        output: Option<PathBuf>,
+    },
+    #[command(about = "Prints the header, memo and table layout of plot files", long_about = None)]
+    PlotInfo {
+        #[arg(required = true, value_name = "Plot files or directories")]
+        paths: Vec<PathBuf>,
+        #[arg(short, long)]
+        recursive: bool,
+        #[arg(short = 'o', long = "output")]
+        output: Option<PlotInfoOutput>,
+    },
     #[command(about = "Create a cold wallet or a PlotNFT wallet", long_about = None)]
     Curry {
         #[arg(short = 'p', long = "program")]
@@ -337,6 +368,13 @@ pub enum RootCommands {
     },
 }
 
+#[derive(Default, ValueEnum, Copy, Clone, Debug)]
+pub enum PlotInfoOutput {
+    #[default]
+    Text,
+    Json,
+}
+
 #[derive(Default, ValueEnum, Copy, Clone, Debug)]
 pub enum ProgramOutput {
     #[default]
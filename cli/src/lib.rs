@@ -1,4 +1,5 @@
-use crate::cli::ProgramOutput;
+use crate::cli::{PlotInfoOutput, ProgramOutput};
+use crate::plot_commands::{check_plot_files, plot_file_info};
 use crate::wallet_commands::{
     create_cold_wallet, get_plotnft_ready_state, migrate_plot_nft, migrate_plot_nft_with_owner_key,
 };
@@ -34,6 +35,7 @@ use std::sync::Arc;
 
 pub mod cli;
 pub mod commands;
+pub mod plot_commands;
 pub mod simulator;
 pub mod wallet_commands;
 pub mod wallets;
@@ -753,6 +755,64 @@ pub async fn run_cli() -> Result<(), Error> {
             WalletAction::WithNFT { .. } => {}
             WalletAction::Cold => create_cold_wallet()?,
         },
+        RootCommands::CheckPlots {
+            paths,
+            challenges,
+            challenge_start,
+            recursive,
+            parallel,
+            decompressor_threads,
+            output,
+        } => {
+            let reports = check_plot_files(
+                &paths,
+                recursive,
+                challenges,
+                challenge_start,
+                parallel,
+                decompressor_threads,
+            )
+            .await?;
+            match serde_json::to_string_pretty(&reports) {
+                Ok(json) => {
+                    if let Some(output) = output {
+                        tokio::fs::write(&output, json).await?;
+                        info!(
+                            "Wrote {} plot reports to {}",
+                            reports.len(),
+                            output.display()
+                        );
+                    } else {
+                        println!("{json}");
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to convert value to JSON: {e:?}");
+                }
+            }
+        }
+        RootCommands::PlotInfo {
+            paths,
+            recursive,
+            output,
+        } => {
+            let infos = plot_file_info(&paths, recursive).await?;
+            match output.unwrap_or_default() {
+                PlotInfoOutput::Text => {
+                    for info in infos {
+                        println!("{info}");
+                    }
+                }
+                PlotInfoOutput::Json => match serde_json::to_string_pretty(&infos) {
+                    Ok(json) => {
+                        println!("{json}");
+                    }
+                    Err(e) => {
+                        error!("Failed to convert value to JSON: {e:?}");
+                    }
+                },
+            }
+        }
         RootCommands::Curry {
             program,
             args,
@@ -0,0 +1,100 @@
+use dg_xch_pos::plots::decompressor::DecompressorPool;
+use dg_xch_pos::plots::plot_check::{check_plots, PlotCheckConfig, PlotCheckReport};
+use dg_xch_pos::plots::plot_info::{inspect_plot, PlotFileInfo};
+use dg_xch_pos::plots::plot_reader::read_plot_file_header_async;
+use log::{error, info, warn};
+use std::io::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs;
+
+/// Expands `paths` into the list of plot files they contain, directories are searched for
+/// `.plot` files, descending into sub directories when `recursive` is set.
+pub async fn find_plot_files(paths: &[PathBuf], recursive: bool) -> Result<Vec<PathBuf>, Error> {
+    let mut plots = vec![];
+    let mut dirs = vec![];
+    for path in paths {
+        if fs::metadata(path).await.is_ok_and(|m| m.is_dir()) {
+            dirs.push(path.clone());
+        } else {
+            plots.push(path.clone());
+        }
+    }
+    while let Some(dir) = dirs.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if fs::metadata(&path).await.is_ok_and(|m| m.is_dir()) {
+                if recursive {
+                    dirs.push(path);
+                }
+            } else if path.extension().is_some_and(|e| e == "plot") {
+                plots.push(path);
+            }
+        }
+    }
+    plots.sort();
+    plots.dedup();
+    Ok(plots)
+}
+
+async fn has_compressed_plot(plots: &[PathBuf]) -> bool {
+    for plot in plots {
+        match read_plot_file_header_async(plot).await {
+            Ok((_, header)) if header.compression_level() > 0 => return true,
+            Ok(_) => {}
+            Err(e) => warn!("Failed to read header of {}: {e:?}", plot.display()),
+        }
+    }
+    false
+}
+
+/// Runs `challenges` challenges against every plot found in `paths`. A decompressor pool is only
+/// created when one of the plots is compressed.
+pub async fn check_plot_files(
+    paths: &[PathBuf],
+    recursive: bool,
+    challenges: u32,
+    challenge_start: u32,
+    parallel: usize,
+    decompressor_threads: u8,
+) -> Result<Vec<PlotCheckReport>, Error> {
+    let plots = find_plot_files(paths, recursive).await?;
+    info!("Checking {} plots", plots.len());
+    let decompressor = if has_compressed_plot(&plots).await {
+        Some(Arc::new(DecompressorPool::new(
+            u8::try_from(parallel.max(1)).unwrap_or(u8::MAX),
+            decompressor_threads,
+        )))
+    } else {
+        None
+    };
+    let config = PlotCheckConfig {
+        challenges,
+        challenge_start,
+        parallel_plots: parallel,
+        decompressor,
+    };
+    let reports = check_plots(&plots, &config).await;
+    let failed = reports.iter().filter(|r| !r.is_healthy()).count();
+    if failed > 0 {
+        warn!("{failed} of {} plots reported errors", reports.len());
+    }
+    Ok(reports)
+}
+
+/// Reads the header and layout of every plot found in `paths`, plots that fail to open are logged
+/// and skipped.
+pub async fn plot_file_info(
+    paths: &[PathBuf],
+    recursive: bool,
+) -> Result<Vec<PlotFileInfo>, Error> {
+    let mut infos = vec![];
+    for plot in find_plot_files(paths, recursive).await? {
+        match inspect_plot(&plot).await {
+            Ok(info) => infos.push(info),
+            Err(e) => error!("Failed to read plot {}: {e:?}", plot.display()),
+        }
+    }
+    Ok(infos)
+}
@@ -0,0 +1,105 @@
+use crate::websocket::{oneshot, WsClient, WsClientConfig};
+use dg_xch_core::constants::{CHIA_CA_CRT, CHIA_CA_KEY};
+use dg_xch_core::protocols::decompressor::{
+    RequestDecompressProof, RequestDecompressQualities, RespondDecompression,
+};
+use dg_xch_core::protocols::{ChiaMessage, NodeType, ProtocolMessageTypes};
+use dg_xch_serialize::ChiaSerialize;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Milliseconds to wait for a decompression server to answer a job
+pub const DEFAULT_DECOMPRESSION_TIMEOUT_MS: u64 = 30000;
+
+/// Sends decompression jobs to a decompression server instead of running them locally.
+pub struct DecompressorClient {
+    pub client: WsClient,
+    pub request_timeout: u64,
+    next_id: AtomicU16,
+}
+impl DecompressorClient {
+    pub async fn new(
+        client_config: Arc<WsClientConfig>,
+        run: Arc<AtomicBool>,
+        timeout: u64,
+    ) -> Result<Self, Error> {
+        let client = WsClient::with_ca(
+            client_config,
+            NodeType::Harvester,
+            Arc::new(RwLock::new(HashMap::new())),
+            run,
+            CHIA_CA_CRT.as_bytes(),
+            CHIA_CA_KEY.as_bytes(),
+            timeout,
+        )
+        .await?;
+        Ok(DecompressorClient {
+            client,
+            request_timeout: DEFAULT_DECOMPRESSION_TIMEOUT_MS,
+            next_id: AtomicU16::new(0),
+        })
+    }
+
+    /// Returns the x pair behind a quality of a compressed plot, the same as
+    /// `Decompressor::get_fetch_qualties_x_pair`.
+    pub async fn decompress_qualities(
+        &self,
+        request: &RequestDecompressQualities,
+    ) -> Result<(u64, u64), Error> {
+        match self
+            .request(ProtocolMessageTypes::RequestDecompressQualities, request)
+            .await?
+            .as_slice()
+        {
+            [x1, x2] => Ok((*x1, *x2)),
+            other => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Expected an x pair, found {} values", other.len()),
+            )),
+        }
+    }
+
+    /// Returns the full proof of a compressed proof, the same as `Decompressor::decompress_proof`.
+    pub async fn decompress_proof(
+        &self,
+        request: &RequestDecompressProof,
+    ) -> Result<Vec<u64>, Error> {
+        self.request(ProtocolMessageTypes::RequestDecompressProof, request)
+            .await
+    }
+
+    async fn request<T: ChiaSerialize>(
+        &self,
+        msg_type: ProtocolMessageTypes,
+        request: &T,
+    ) -> Result<Vec<u64>, Error> {
+        let protocol_version = self.client.client_config.protocol_version;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let response = oneshot::<RespondDecompression>(
+            self.client.connection.clone(),
+            ChiaMessage::new(msg_type, protocol_version, request, Some(id))?,
+            Some(ProtocolMessageTypes::RespondDecompression),
+            protocol_version,
+            Some(id),
+            Some(self.request_timeout),
+        )
+        .await?;
+        match response.error {
+            Some(e) => Err(Error::other(format!("Decompression server error: {e}"))),
+            None => Ok(response.x_values),
+        }
+    }
+
+    pub async fn join(self) -> Result<(), Error> {
+        self.client.connection.write().await.shutdown().await?;
+        self.client.join().await
+    }
+
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.client.handle.is_finished()
+    }
+}
@@ -83,6 +83,22 @@ impl MessageHandler for NewSignagePointHandle {
             filter_prefix_bits: calculate_prefix_bits(self.constants.as_ref(), sp.peak_height),
             last_tx_height: sp.last_tx_height,
         };
+        // Stored before forwarding, harvesters can answer before this handler returns
+        self.signage_points
+            .write()
+            .await
+            .entry(sp.challenge_chain_sp)
+            .or_default()
+            .push(sp.clone());
+        *self.most_recent_sp.write().await = MostRecentSignagePoint {
+            hash: sp.challenge_chain_sp,
+            index: sp.signage_point_index,
+            timestamp: Instant::now(),
+        };
+        self.cache_time
+            .write()
+            .await
+            .insert(sp.challenge_chain_sp, Instant::now());
         let peers: Vec<Arc<SocketPeer>> = self
             .harvester_peers
             .read()
@@ -113,13 +129,6 @@ impl MessageHandler for NewSignagePointHandle {
                     .await;
             }
         }
-        {
-            //Lock Scope
-            let mut signage_points = self.signage_points.write().await;
-            if signage_points.get(&sp.challenge_chain_sp).is_none() {
-                signage_points.insert(sp.challenge_chain_sp, vec![]);
-            }
-        }
         #[cfg(feature = "metrics")]
         {
             let now = Instant::now();
@@ -140,23 +149,6 @@ impl MessageHandler for NewSignagePointHandle {
                 }
             }
         }
-        if let Some(sps) = self
-            .signage_points
-            .write()
-            .await
-            .get_mut(&sp.challenge_chain_sp)
-        {
-            sps.push(sp.clone());
-        }
-        *self.most_recent_sp.write().await = MostRecentSignagePoint {
-            hash: sp.challenge_chain_sp,
-            index: sp.signage_point_index,
-            timestamp: Instant::now(),
-        };
-        self.cache_time
-            .write()
-            .await
-            .insert(sp.challenge_chain_sp, Instant::now());
         Ok(())
     }
 }
@@ -1,8 +1,19 @@
-use crate::websocket::{WsClient, WsClientConfig};
+use crate::websocket::{MessageSubscription, WsClient, WsClientConfig};
+use dg_xch_core::blockchain::full_block::FullBlock;
+use dg_xch_core::blockchain::peer_info::TimestampedPeerInfo;
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::blockchain::spend_bundle::SpendBundle;
+use dg_xch_core::consensus::constants::{ConsensusConstants, CONSENSUS_CONSTANTS_MAP, MAINNET};
 use dg_xch_core::constants::{CHIA_CA_CRT, CHIA_CA_KEY};
-use dg_xch_core::protocols::{ChiaMessageHandler, NodeType};
+use dg_xch_core::protocols::full_node::{
+    NewPeak, NewTransaction, RequestBlock, RequestBlocks, RequestCompactVDF,
+    RequestMempoolTransactions, RequestPeers, RequestTransaction, RespondBlock, RespondBlocks,
+    RespondCompactVDF, RespondPeers, RespondTransaction,
+};
+use dg_xch_core::protocols::{ChiaMessage, ChiaMessageHandler, NodeType, ProtocolMessageTypes};
+use dg_xch_serialize::ChiaSerialize;
 use std::collections::HashMap;
-use std::io::Error;
+use std::io::{Error, ErrorKind};
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -10,6 +21,7 @@ use uuid::Uuid;
 
 pub struct FullnodeClient {
     pub client: WsClient,
+    constants: Arc<ConsensusConstants>,
 }
 impl FullnodeClient {
     pub async fn new(
@@ -18,6 +30,10 @@ impl FullnodeClient {
         handles: Option<HashMap<Uuid, Arc<ChiaMessageHandler>>>,
         timeout: u64,
     ) -> Result<Self, Error> {
+        let constants = CONSENSUS_CONSTANTS_MAP
+            .get(&client_config.network_id)
+            .cloned()
+            .unwrap_or(MAINNET.clone());
         let handles = Arc::new(RwLock::new(handles.unwrap_or_default()));
         let client = WsClient::with_ca(
             client_config,
@@ -29,7 +45,7 @@ impl FullnodeClient {
             timeout,
         )
         .await?;
-        Ok(FullnodeClient { client })
+        Ok(FullnodeClient { client, constants })
     }
 
     pub async fn join(self) -> Result<(), Error> {
@@ -41,4 +57,141 @@ impl FullnodeClient {
     pub fn is_closed(&self) -> bool {
         self.client.handle.is_finished()
     }
+
+    /// Fails with `NotFound` when the node does not have a block at `height`.
+    pub async fn request_block(
+        &self,
+        height: u32,
+        include_transaction_block: bool,
+    ) -> Result<FullBlock, Error> {
+        self.client
+            .request::<RequestBlock, RespondBlock>(
+                ProtocolMessageTypes::RequestBlock,
+                &RequestBlock {
+                    height,
+                    include_transaction_block,
+                },
+                None,
+            )
+            .await
+            .map(|r| r.block)
+    }
+
+    /// Blocks from `start_height` to `end_height` inclusive, requested in batches no larger
+    /// than the node accepts.
+    pub async fn request_blocks(
+        &self,
+        start_height: u32,
+        end_height: u32,
+        include_transaction_block: bool,
+    ) -> Result<Vec<FullBlock>, Error> {
+        if end_height < start_height {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("End height {end_height} is below start height {start_height}"),
+            ));
+        }
+        let batch_size = self.constants.max_block_count_per_requests.max(1);
+        let mut blocks = vec![];
+        let mut start = start_height;
+        loop {
+            let end = start.saturating_add(batch_size - 1).min(end_height);
+            let response = self
+                .client
+                .request::<RequestBlocks, RespondBlocks>(
+                    ProtocolMessageTypes::RequestBlocks,
+                    &RequestBlocks {
+                        start_height: start,
+                        end_height: end,
+                        include_transaction_block,
+                    },
+                    None,
+                )
+                .await?;
+            blocks.extend(response.blocks);
+            if end == end_height {
+                return Ok(blocks);
+            }
+            start = end + 1;
+        }
+    }
+
+    /// Requests a transaction from the mempool of the node.
+    pub async fn request_transaction(&self, transaction_id: Bytes32) -> Result<SpendBundle, Error> {
+        self.client
+            .request::<RequestTransaction, RespondTransaction>(
+                ProtocolMessageTypes::RequestTransaction,
+                &RequestTransaction { transaction_id },
+                None,
+            )
+            .await
+            .map(|r| r.transaction)
+    }
+
+    /// Sends a transaction to the node for its mempool, the node does not answer.
+    pub async fn push_transaction(&self, transaction: SpendBundle) -> Result<(), Error> {
+        self.send(
+            ProtocolMessageTypes::RespondTransaction,
+            &RespondTransaction { transaction },
+        )
+        .await
+    }
+
+    /// Asks the node to announce the mempool transactions that do not match `filter`, they
+    /// arrive as `NewTransaction` messages, see `subscribe_new_transactions`.
+    pub async fn request_mempool_transactions(&self, filter: Vec<u8>) -> Result<(), Error> {
+        self.send(
+            ProtocolMessageTypes::RequestMempoolTransactions,
+            &RequestMempoolTransactions { filter },
+        )
+        .await
+    }
+
+    pub async fn request_peers(&self) -> Result<Vec<TimestampedPeerInfo>, Error> {
+        self.client
+            .request::<RequestPeers, RespondPeers>(
+                ProtocolMessageTypes::RequestPeers,
+                &RequestPeers {},
+                None,
+            )
+            .await
+            .map(|r| r.peer_list)
+    }
+
+    pub async fn request_compact_vdf(
+        &self,
+        request: &RequestCompactVDF,
+    ) -> Result<RespondCompactVDF, Error> {
+        self.client
+            .request::<RequestCompactVDF, RespondCompactVDF>(
+                ProtocolMessageTypes::RequestCompactVdf,
+                request,
+                None,
+            )
+            .await
+    }
+
+    pub async fn subscribe_new_peaks(&self) -> MessageSubscription<NewPeak> {
+        self.client.subscribe(ProtocolMessageTypes::NewPeak).await
+    }
+
+    pub async fn subscribe_new_transactions(&self) -> MessageSubscription<NewTransaction> {
+        self.client
+            .subscribe(ProtocolMessageTypes::NewTransaction)
+            .await
+    }
+
+    async fn send<T: ChiaSerialize>(
+        &self,
+        msg_type: ProtocolMessageTypes,
+        msg: &T,
+    ) -> Result<(), Error> {
+        let protocol_version = self.client.client_config.protocol_version;
+        self.client
+            .connection
+            .write()
+            .await
+            .send(ChiaMessage::new(msg_type, protocol_version, msg, None)?.into())
+            .await
+    }
 }
@@ -1,3 +1,4 @@
+use crate::websocket::harvester::plot_sync::{PlotSyncInventory, PlotSyncSenders};
 use async_trait::async_trait;
 use dg_xch_core::blockchain::sized_bytes::Bytes32;
 use dg_xch_core::protocols::harvester::{HarvesterHandshake, HarvesterState};
@@ -12,6 +13,7 @@ use tokio::sync::RwLock;
 pub struct HarvesterHandshakeHandle<T: PlotManagerAsync> {
     pub plot_manager: Arc<RwLock<T>>,
     pub harvester_state: Arc<RwLock<HarvesterState>>,
+    pub plot_syncs: PlotSyncSenders,
 }
 #[async_trait]
 impl<T: PlotManagerAsync + Send + Sync> MessageHandler for HarvesterHandshakeHandle<T> {
@@ -53,6 +55,16 @@ impl<T: PlotManagerAsync + Send + Sync> MessageHandler for HarvesterHandshakeHan
                 debug!("Error loading plots: {e:?}");
             }
         }
+        if let Some(peer) = peer {
+            let inventory = PlotSyncInventory::new(&*self.plot_manager.read().await);
+            let plot_sync = self.plot_syncs.sender(&peer_id).await;
+            let mut plot_sync = plot_sync.lock().await;
+            // A handshake starts a new session with the farmer, which needs every plot again
+            plot_sync.reset();
+            plot_sync
+                .sync(peer.websocket.clone(), protocol_version, inventory)
+                .await?;
+        }
         Ok(())
     }
 }
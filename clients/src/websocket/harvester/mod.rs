@@ -1,5 +1,7 @@
 use crate::websocket::harvester::harvester_handshake::HarvesterHandshakeHandle;
 use crate::websocket::harvester::new_signage_point_harvester::NewSignagePointHarvesterHandle;
+use crate::websocket::harvester::plot_sync::{PlotSyncInventory, PlotSyncSenders};
+use crate::websocket::harvester::request_plots::RequestPlotsHandle;
 use crate::websocket::harvester::request_signatures::RequestSignaturesHandle;
 use crate::websocket::{WsClient, WsClientConfig};
 use dg_xch_core::consensus::constants::{ConsensusConstants, CONSENSUS_CONSTANTS_MAP, MAINNET};
@@ -8,6 +10,7 @@ use dg_xch_core::protocols::harvester::HarvesterState;
 use dg_xch_core::protocols::{
     ChiaMessageFilter, ChiaMessageHandler, NodeType, ProtocolMessageTypes,
 };
+use dg_xch_pos::plots::harvest_scheduler::{HarvestScheduler, HarvestSchedulerConfig};
 use dg_xch_pos::PlotManagerAsync;
 use std::collections::HashMap;
 use std::io::Error;
@@ -17,10 +20,13 @@ use tokio::sync::RwLock;
 use uuid::Uuid;
 pub mod harvester_handshake;
 pub mod new_signage_point_harvester;
+pub mod plot_sync;
+pub mod request_plots;
 pub mod request_signatures;
 
 pub struct HarvesterClient {
     pub client: WsClient,
+    pub plot_syncs: PlotSyncSenders,
 }
 impl HarvesterClient {
     pub async fn new<T: PlotManagerAsync + Send + Sync + 'static>(
@@ -30,15 +36,41 @@ impl HarvesterClient {
         harvester_state: Arc<RwLock<HarvesterState>>,
         run: Arc<AtomicBool>,
         timeout: u64,
+    ) -> Result<Self, Error> {
+        Self::with_scheduler(
+            client_config,
+            plot_manager,
+            plots_ready,
+            harvester_state,
+            run,
+            timeout,
+            Arc::new(HarvestScheduler::new(HarvestSchedulerConfig::default())),
+        )
+        .await
+    }
+
+    /// Creates a client whose signage point lookups run on `scheduler`. Plots should be loaded
+    /// with the scheduler's decompressor pool so compressed lookups share it.
+    pub async fn with_scheduler<T: PlotManagerAsync + Send + Sync + 'static>(
+        client_config: Arc<WsClientConfig>,
+        plot_manager: Arc<RwLock<T>>,
+        plots_ready: Arc<AtomicBool>,
+        harvester_state: Arc<RwLock<HarvesterState>>,
+        run: Arc<AtomicBool>,
+        timeout: u64,
+        scheduler: Arc<HarvestScheduler>,
     ) -> Result<Self, Error> {
         let constants = CONSENSUS_CONSTANTS_MAP
             .get(&client_config.network_id)
             .unwrap_or(&MAINNET);
-        let handles = Arc::new(RwLock::new(handles(
+        let plot_syncs = PlotSyncSenders::default();
+        let handles = Arc::new(RwLock::new(harvester_handles(
             constants,
             plot_manager.clone(),
             plots_ready,
             harvester_state,
+            scheduler,
+            plot_syncs.clone(),
         )));
         let client = WsClient::with_ca(
             client_config,
@@ -50,7 +82,27 @@ impl HarvesterClient {
             timeout,
         )
         .await?;
-        Ok(HarvesterClient { client })
+        Ok(HarvesterClient { client, plot_syncs })
+    }
+
+    /// Sends the plots loaded or removed since the last sync to the farmer, call after the
+    /// plots of `plot_manager` were reloaded.
+    pub async fn sync_plots<T: PlotManagerAsync>(
+        &self,
+        plot_manager: &RwLock<T>,
+    ) -> Result<(), Error> {
+        let inventory = PlotSyncInventory::new(&*plot_manager.read().await);
+        self.plot_syncs
+            .sender(&self.client.peer_id)
+            .await
+            .lock()
+            .await
+            .sync(
+                self.client.connection.clone(),
+                self.client.client_config.protocol_version,
+                inventory,
+            )
+            .await
     }
 
     pub async fn join(self) -> Result<(), Error> {
@@ -63,11 +115,14 @@ impl HarvesterClient {
     }
 }
 
-fn handles<T: PlotManagerAsync + Send + Sync + 'static>(
+/// Handlers answering a farmer, shared by harvesters that dial out and harvester servers.
+pub fn harvester_handles<T: PlotManagerAsync + Send + Sync + 'static>(
     constants: &'static ConsensusConstants,
     plot_manager: Arc<RwLock<T>>,
     plots_ready: Arc<AtomicBool>,
     harvester_state: Arc<RwLock<HarvesterState>>,
+    scheduler: Arc<HarvestScheduler>,
+    plot_syncs: PlotSyncSenders,
 ) -> HashMap<Uuid, Arc<ChiaMessageHandler>> {
     HashMap::from([
         (
@@ -80,7 +135,8 @@ fn handles<T: PlotManagerAsync + Send + Sync + 'static>(
                 }),
                 Arc::new(HarvesterHandshakeHandle {
                     plot_manager: plot_manager.clone(),
-                    harvester_state,
+                    harvester_state: harvester_state.clone(),
+                    plot_syncs,
                 }),
             )),
         ),
@@ -96,6 +152,8 @@ fn handles<T: PlotManagerAsync + Send + Sync + 'static>(
                     constants,
                     plot_manager: plot_manager.clone(),
                     plots_ready,
+                    scheduler,
+                    harvester_state,
                 }),
             )),
         ),
@@ -107,7 +165,20 @@ fn handles<T: PlotManagerAsync + Send + Sync + 'static>(
                     id: None,
                     custom_fn: None,
                 }),
-                Arc::new(RequestSignaturesHandle { plot_manager }),
+                Arc::new(RequestSignaturesHandle {
+                    plot_manager: plot_manager.clone(),
+                }),
+            )),
+        ),
+        (
+            Uuid::new_v4(),
+            Arc::new(ChiaMessageHandler::new(
+                Arc::new(ChiaMessageFilter {
+                    msg_type: Some(ProtocolMessageTypes::RequestPlots),
+                    id: None,
+                    custom_fn: None,
+                }),
+                Arc::new(RequestPlotsHandle { plot_manager }),
             )),
         ),
     ])
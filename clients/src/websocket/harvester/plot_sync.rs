@@ -0,0 +1,382 @@
+use crate::websocket::{ResponseHandler, Subscription};
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::protocols::harvester::{
+    Plot, PlotSyncDone, PlotSyncIdentifier, PlotSyncPathList, PlotSyncPlotList, PlotSyncResponse,
+    PlotSyncStart,
+};
+use dg_xch_core::protocols::plot_sync::{
+    HarvestingMode, PlotSyncErrorCode, DEFAULT_PLOT_SYNC_BATCH_SIZE,
+};
+use dg_xch_core::protocols::{
+    ChiaMessage, ChiaMessageFilter, ProtocolMessageTypes, WebsocketConnection,
+};
+use dg_xch_pos::PlotManagerAsync;
+use dg_xch_serialize::{ChiaProtocolVersion, ChiaSerialize};
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, RwLock};
+use tokio::time::timeout;
+
+/// Milliseconds to wait for the farmer to answer a plot sync message
+pub const DEFAULT_PLOT_SYNC_TIMEOUT_MS: u64 = 15000;
+
+/// Copy of the plots of a plot manager, taken so the plot manager is not locked while syncing.
+#[derive(Debug, Clone, Default)]
+pub struct PlotSyncInventory {
+    pub plots: Vec<Plot>,
+    pub invalid: Vec<String>,
+    pub keys_missing: Vec<String>,
+    pub duplicates: Vec<String>,
+}
+impl PlotSyncInventory {
+    pub fn new<T: PlotManagerAsync>(plot_manager: &T) -> Self {
+        let to_strings = |paths: Vec<&Path>| -> Vec<String> {
+            paths
+                .into_iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect()
+        };
+        Self {
+            plots: plot_manager
+                .plots()
+                .iter()
+                .map(|(path_info, info)| {
+                    let header = info.reader.header();
+                    Plot {
+                        filename: path_info.path.to_string_lossy().to_string(),
+                        size: header.k(),
+                        plot_id: header.id(),
+                        pool_public_key: info.pool_public_key,
+                        pool_contract_puzzle_hash: info.pool_contract_puzzle_hash,
+                        plot_public_key: info.plot_public_key,
+                        file_size: info.file_size,
+                        time_modified: info.time_modified,
+                        compression_level: Some(header.compression_level()),
+                    }
+                })
+                .collect(),
+            invalid: to_strings(plot_manager.invalid_plots()),
+            keys_missing: to_strings(plot_manager.keys_missing_plots()),
+            duplicates: to_strings(plot_manager.duplicate_plots()),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    #[must_use]
+    pub fn plot_file_count(&self) -> u32 {
+        (self.plots.len() + self.invalid.len() + self.keys_missing.len() + self.duplicates.len())
+            as u32
+    }
+}
+
+/// A `PlotSyncSender` per farmer, keyed by peer id, so a harvester connected to several
+/// farmers keeps a separate sync with each of them.
+#[derive(Clone, Default)]
+pub struct PlotSyncSenders {
+    senders: Arc<Mutex<HashMap<Bytes32, Arc<Mutex<PlotSyncSender>>>>>,
+}
+impl PlotSyncSenders {
+    /// The sender of `peer_id`, created on first use.
+    pub async fn sender(&self, peer_id: &Bytes32) -> Arc<Mutex<PlotSyncSender>> {
+        self.senders
+            .lock()
+            .await
+            .entry(*peer_id)
+            .or_insert_with(|| Arc::new(Mutex::new(PlotSyncSender::default())))
+            .clone()
+    }
+
+    /// Drops the senders of farmers that are no longer connected.
+    pub async fn retain(&self, peer_ids: &[Bytes32]) {
+        self.senders
+            .lock()
+            .await
+            .retain(|peer_id, _| peer_ids.contains(peer_id));
+    }
+}
+
+/// Sends the plots of a harvester to the farmer. The first sync sends every plot, later syncs
+/// only send the plots loaded or removed since the last sync. Every message waits for the
+/// farmer to accept it, any error makes the next sync start over with every plot.
+pub struct PlotSyncSender {
+    batch_size: usize,
+    pub harvesting_mode: HarvestingMode,
+    pub request_timeout: u64,
+    last_sync_id: u64,
+    synced: HashSet<String>,
+}
+impl Default for PlotSyncSender {
+    fn default() -> Self {
+        Self::new(DEFAULT_PLOT_SYNC_BATCH_SIZE)
+    }
+}
+impl PlotSyncSender {
+    /// Sends at most `batch_size` plots or paths per message, a batch size of 0 is raised to 1.
+    #[must_use]
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            batch_size: batch_size.max(1),
+            harvesting_mode: HarvestingMode::default(),
+            request_timeout: DEFAULT_PLOT_SYNC_TIMEOUT_MS,
+            last_sync_id: 0,
+            synced: HashSet::new(),
+        }
+    }
+
+    /// Makes the next sync an initial sync.
+    pub fn reset(&mut self) {
+        self.last_sync_id = 0;
+        self.synced.clear();
+    }
+
+    #[must_use]
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    #[must_use]
+    pub fn last_sync_id(&self) -> u64 {
+        self.last_sync_id
+    }
+
+    pub async fn sync(
+        &mut self,
+        connection: Arc<RwLock<WebsocketConnection>>,
+        protocol_version: ChiaProtocolVersion,
+        inventory: PlotSyncInventory,
+    ) -> Result<(), Error> {
+        let result = self
+            .send_sync(&connection, protocol_version, inventory)
+            .await;
+        if let Err(e) = &result {
+            warn!("Plot sync failed, the next sync will send every plot: {e}");
+            self.reset();
+        }
+        result
+    }
+
+    async fn send_sync(
+        &mut self,
+        connection: &Arc<RwLock<WebsocketConnection>>,
+        protocol_version: ChiaProtocolVersion,
+        inventory: PlotSyncInventory,
+    ) -> Result<(), Error> {
+        let started = Instant::now();
+        let initial = self.last_sync_id == 0;
+        let sync_id = unix_time().max(self.last_sync_id + 1);
+        let current: HashSet<String> = inventory.plots.iter().map(|p| p.filename.clone()).collect();
+        let loaded: Vec<Plot> = inventory
+            .plots
+            .iter()
+            .filter(|p| !self.synced.contains(&p.filename))
+            .cloned()
+            .collect();
+        let removed: Vec<String> = self.synced.difference(&current).cloned().collect();
+        let mut message_id = 0;
+        let mut identifier = || {
+            let identifier = PlotSyncIdentifier {
+                timestamp: unix_time(),
+                sync_id,
+                message_id,
+            };
+            message_id += 1;
+            identifier
+        };
+        self.send(
+            connection,
+            protocol_version,
+            ProtocolMessageTypes::PlotSyncStart,
+            PlotSyncStart {
+                identifier: identifier(),
+                initial,
+                last_sync_id: self.last_sync_id,
+                plot_file_count: inventory.plot_file_count(),
+                harvesting_mode: self.harvesting_mode as u8,
+            },
+        )
+        .await?;
+        for (data, r#final) in batches(&loaded, self.batch_size) {
+            self.send(
+                connection,
+                protocol_version,
+                ProtocolMessageTypes::PlotSyncLoaded,
+                PlotSyncPlotList {
+                    identifier: identifier(),
+                    data: data.to_vec(),
+                    r#final,
+                },
+            )
+            .await?;
+        }
+        for (msg_type, paths) in [
+            (ProtocolMessageTypes::PlotSyncRemoved, &removed),
+            (ProtocolMessageTypes::PlotSyncInvalid, &inventory.invalid),
+            (
+                ProtocolMessageTypes::PlotSyncKeysMissing,
+                &inventory.keys_missing,
+            ),
+            (
+                ProtocolMessageTypes::PlotSyncDuplicates,
+                &inventory.duplicates,
+            ),
+        ] {
+            for (data, r#final) in batches(paths, self.batch_size) {
+                self.send(
+                    connection,
+                    protocol_version,
+                    msg_type,
+                    PlotSyncPathList {
+                        identifier: identifier(),
+                        data: data.to_vec(),
+                        r#final,
+                    },
+                )
+                .await?;
+            }
+        }
+        self.send(
+            connection,
+            protocol_version,
+            ProtocolMessageTypes::PlotSyncDone,
+            PlotSyncDone {
+                identifier: identifier(),
+                duration: started.elapsed().as_secs(),
+            },
+        )
+        .await?;
+        info!(
+            "Synced {} plots with the farmer, {} loaded, {} removed",
+            current.len(),
+            loaded.len(),
+            removed.len()
+        );
+        self.last_sync_id = sync_id;
+        self.synced = current;
+        Ok(())
+    }
+
+    /// Sends `msg` and waits for the farmer to accept it. Responses are matched on the sync
+    /// identifier and message type, farmers do not echo message ids for plot sync messages.
+    async fn send<T: ChiaSerialize + HasIdentifier>(
+        &mut self,
+        connection: &Arc<RwLock<WebsocketConnection>>,
+        protocol_version: ChiaProtocolVersion,
+        msg_type: ProtocolMessageTypes,
+        msg: T,
+    ) -> Result<(), Error> {
+        let identifier = msg.identifier().clone();
+        let message_type = i16::from(msg_type as u8);
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<Arc<ChiaMessage>>(1);
+        let subscription = Subscription::new(
+            connection.clone(),
+            ChiaMessageFilter {
+                msg_type: Some(ProtocolMessageTypes::PlotSyncResponse),
+                id: None,
+                custom_fn: Some(Box::new(move |response| {
+                    PlotSyncResponse::from_bytes(&mut Cursor::new(&response.data), protocol_version)
+                        .is_ok_and(|r| r.identifier == identifier && r.message_type == message_type)
+                })),
+            },
+            Arc::new(ResponseHandler { channel: tx }),
+        )
+        .await;
+        connection
+            .write()
+            .await
+            .send(ChiaMessage::new(msg_type, protocol_version, &msg, None)?.into())
+            .await?;
+        let response = timeout(Duration::from_millis(self.request_timeout), rx.recv())
+            .await
+            .map_err(|_| {
+                Error::new(
+                    ErrorKind::TimedOut,
+                    format!("Timeout waiting for the farmer to answer {msg_type:?}"),
+                )
+            })?
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::ConnectionAborted,
+                    format!("Channel closed before the farmer answered {msg_type:?}"),
+                )
+            })?;
+        drop(subscription);
+        let response =
+            PlotSyncResponse::from_bytes(&mut Cursor::new(&response.data), protocol_version)?;
+        if let Some(e) = response.error {
+            Err(Error::other(format!(
+                "Farmer rejected {msg_type:?} with {:?}: {}",
+                PlotSyncErrorCode::from(e.code),
+                e.message
+            )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+trait HasIdentifier {
+    fn identifier(&self) -> &PlotSyncIdentifier;
+}
+impl HasIdentifier for PlotSyncStart {
+    fn identifier(&self) -> &PlotSyncIdentifier {
+        &self.identifier
+    }
+}
+impl HasIdentifier for PlotSyncPlotList {
+    fn identifier(&self) -> &PlotSyncIdentifier {
+        &self.identifier
+    }
+}
+impl HasIdentifier for PlotSyncPathList {
+    fn identifier(&self) -> &PlotSyncIdentifier {
+        &self.identifier
+    }
+}
+impl HasIdentifier for PlotSyncDone {
+    fn identifier(&self) -> &PlotSyncIdentifier {
+        &self.identifier
+    }
+}
+
+/// Splits `list` into batches flagged with whether they are the last one, an empty list is
+/// still sent as one empty batch.
+fn batches<T>(list: &[T], batch_size: usize) -> Vec<(&[T], bool)> {
+    if list.is_empty() {
+        return vec![(list, true)];
+    }
+    let batch_size = batch_size.max(1);
+    let count = list.len().div_ceil(batch_size);
+    list.chunks(batch_size)
+        .enumerate()
+        .map(|(index, batch)| (batch, index + 1 == count))
+        .collect()
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[test]
+fn test_plot_sync_batches() {
+    assert_eq!(PlotSyncSender::new(0).batch_size(), 1);
+    let list = [1, 2, 3];
+    assert_eq!(
+        batches(&list, 0),
+        vec![
+            (&list[0..1], false),
+            (&list[1..2], false),
+            (&list[2..3], true)
+        ]
+    );
+    assert_eq!(
+        batches(&list, 2),
+        vec![(&list[0..2], false), (&list[2..3], true)]
+    );
+    assert_eq!(batches::<u8>(&[], 2), vec![(&[][..], true)]);
+}
@@ -0,0 +1,57 @@
+use crate::websocket::harvester::plot_sync::PlotSyncInventory;
+use async_trait::async_trait;
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::protocols::harvester::RespondPlots;
+use dg_xch_core::protocols::{ChiaMessage, MessageHandler, PeerMap, ProtocolMessageTypes};
+use dg_xch_pos::PlotManagerAsync;
+use dg_xch_serialize::ChiaSerialize;
+use log::debug;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Answers a farmer asking which plots this harvester is farming.
+pub struct RequestPlotsHandle<T> {
+    pub plot_manager: Arc<RwLock<T>>,
+}
+#[async_trait]
+impl<T: PlotManagerAsync + Send + Sync> MessageHandler for RequestPlotsHandle<T> {
+    async fn handle(
+        &self,
+        msg: Arc<ChiaMessage>,
+        peer_id: Arc<Bytes32>,
+        peers: PeerMap,
+    ) -> Result<(), Error> {
+        let Some(peer) = peers.read().await.get(&peer_id).cloned() else {
+            return Err(Error::new(ErrorKind::NotFound, "Failed to find peer"));
+        };
+        let protocol_version = *peer.protocol_version.read().await;
+        let inventory = PlotSyncInventory::new(&*self.plot_manager.read().await);
+        debug!(
+            "Sending {} plots to {peer_id}, {} failed to open, {} without keys",
+            inventory.plots.len(),
+            inventory.invalid.len(),
+            inventory.keys_missing.len()
+        );
+        peer.websocket
+            .write()
+            .await
+            .send(Message::Binary(
+                ChiaMessage::new(
+                    ProtocolMessageTypes::RespondPlots,
+                    protocol_version,
+                    &RespondPlots {
+                        plots: inventory.plots,
+                        failed_to_open_filenames: inventory.invalid,
+                        no_key_filenames: inventory.keys_missing,
+                    },
+                    msg.id,
+                )?
+                .to_bytes(protocol_version)?
+                .into(),
+            ))
+            .await?;
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use async_trait::async_trait;
 use dg_xch_core::blockchain::peer_info::TimestampedPeerInfo;
 use dg_xch_core::blockchain::sized_bytes::Bytes32;
 use dg_xch_core::constants::{CHIA_CA_CRT, CHIA_CA_KEY};
-use dg_xch_core::protocols::introducer::RespondPeersIntroducer;
+use dg_xch_core::protocols::introducer::{RequestPeersIntroducer, RespondPeersIntroducer};
 use dg_xch_core::protocols::{
     ChiaMessage, ChiaMessageFilter, ChiaMessageHandler, MessageHandler, NodeType, PeerMap,
     ProtocolMessageTypes,
@@ -96,6 +96,18 @@ impl IntroducerClient {
     pub fn is_closed(&self) -> bool {
         self.client.handle.is_finished()
     }
+
+    /// Peers the introducer knows about, they are also added to the introducer state.
+    pub async fn request_peers(&self) -> Result<Vec<TimestampedPeerInfo>, Error> {
+        self.client
+            .request::<RequestPeersIntroducer, RespondPeersIntroducer>(
+                ProtocolMessageTypes::RequestPeersIntroducer,
+                &RequestPeersIntroducer {},
+                None,
+            )
+            .await
+            .map(|r| r.peer_list)
+    }
 }
 
 pub struct RespondPeersHandler {
@@ -1,7 +1,9 @@
+pub mod decompressor;
 pub mod farmer;
 pub mod full_node;
 pub mod harvester;
 pub mod introducer;
+pub mod peer_manager;
 pub mod wallet;
 
 use crate::ClientSSLConfig;
@@ -21,6 +23,7 @@ use dg_xch_core::ssl::{
 use dg_xch_core::traits::SizedBytes;
 use dg_xch_core::utils::hash_256;
 use dg_xch_serialize::{ChiaProtocolVersion, ChiaSerialize};
+use futures_util::Stream;
 use log::debug;
 use reqwest::header::{HeaderName, HeaderValue};
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
@@ -28,13 +31,15 @@ use rustls::ClientConfig;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::io::{Cursor, Error, ErrorKind};
+use std::pin::Pin;
 use std::str::FromStr;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 use std::sync::Arc;
+use std::task::{Context, Poll};
 use std::time::Duration;
 use std::{env, fs};
 use tokio::select;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{Sender, UnboundedReceiver, UnboundedSender};
 use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
 use tokio::time::timeout;
@@ -62,10 +67,13 @@ fn test_version() {
 
 pub struct WsClient {
     pub connection: Arc<RwLock<WebsocketConnection>>,
+    /// Id of the peer this client is connected to, the hash of its certificate
+    pub peer_id: Arc<Bytes32>,
     pub client_config: Arc<WsClientConfig>,
     pub handshake: Option<Handshake>,
     handle: JoinHandle<()>,
     run: Arc<AtomicBool>,
+    next_request_id: AtomicU16,
 }
 impl WsClient {
     pub async fn new(
@@ -145,6 +153,29 @@ impl WsClient {
         )
         .await
     }
+    /// Connects with an already signed certificate, avoids generating a key per connection.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn with_cert(
+        client_config: Arc<WsClientConfig>,
+        node_type: NodeType,
+        message_handlers: Arc<RwLock<HashMap<Uuid, Arc<ChiaMessageHandler>>>>,
+        run: Arc<AtomicBool>,
+        cert_bytes: &[u8],
+        key_bytes: &[u8],
+        timeout: u64,
+    ) -> Result<Self, Error> {
+        Self::build(
+            client_config,
+            node_type,
+            message_handlers,
+            run,
+            load_certs_from_bytes(cert_bytes)?,
+            load_private_key_from_bytes(key_bytes)?,
+            cert_bytes,
+            timeout,
+        )
+        .await
+    }
     #[allow(clippy::too_many_arguments)]
     async fn build(
         client_config: Arc<WsClientConfig>,
@@ -230,10 +261,12 @@ impl WsClient {
         let protocol_version = client_config.protocol_version;
         let mut ws_client = WsClient {
             connection,
+            peer_id,
             client_config,
             handshake: None,
             handle: tokio::spawn(async move { stream.run(handle_run).await }),
             run,
+            next_request_id: AtomicU16::new(0),
         };
         ws_client
             .perform_handshake(node_type, protocol_version)
@@ -257,6 +290,95 @@ impl WsClient {
         self.handle.is_finished()
     }
 
+    /// Sends `request` and waits for the message answering it, matched by message id and by the
+    /// replies `msg_type` allows. A reject message from the peer is a `NotFound` error and no
+    /// answer within `timeout` milliseconds, 15 seconds by default, a `TimedOut` error. Messages
+    /// that are not answered are an `InvalidInput` error. The response handler is removed once
+    /// the call returns or its future is dropped.
+    pub async fn request<Req: ChiaSerialize, Resp: ChiaSerialize>(
+        &self,
+        msg_type: ProtocolMessageTypes,
+        request: &Req,
+        timeout: Option<u64>,
+    ) -> Result<Resp, Error> {
+        let replies = msg_type.valid_replies();
+        if replies.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{msg_type} is not a request"),
+            ));
+        }
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let protocol_version = self.client_config.protocol_version;
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<Arc<ChiaMessage>>(1);
+        let subscription = Subscription::new(
+            self.connection.clone(),
+            ChiaMessageFilter {
+                msg_type: None,
+                id: Some(id),
+                custom_fn: Some(Box::new(|msg| replies.contains(&msg.msg_type))),
+            },
+            Arc::new(ResponseHandler { channel: tx }),
+        )
+        .await;
+        self.connection
+            .write()
+            .await
+            .send(ChiaMessage::new(msg_type, protocol_version, request, Some(id))?.into())
+            .await?;
+        let response =
+            tokio::time::timeout(Duration::from_millis(timeout.unwrap_or(15000)), rx.recv())
+                .await
+                .map_err(|_| {
+                    Error::new(
+                        ErrorKind::TimedOut,
+                        format!("Timeout waiting for the response to {msg_type}({id})"),
+                    )
+                })?
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::ConnectionAborted,
+                        format!("Channel closed before the response to {msg_type}({id})"),
+                    )
+                })?;
+        drop(subscription);
+        if response.msg_type.is_reject() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("Peer rejected {msg_type}({id}) with {}", response.msg_type),
+            ));
+        }
+        Resp::from_bytes(&mut Cursor::new(&response.data), protocol_version).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Failed to parse the response to {msg_type}: {e:?}"),
+            )
+        })
+    }
+
+    /// Parses every `msg_type` message sent by the peer as `T` until the returned subscription
+    /// is dropped.
+    pub async fn subscribe<T: ChiaSerialize + Send + 'static>(
+        &self,
+        msg_type: ProtocolMessageTypes,
+    ) -> MessageSubscription<T> {
+        let (tx, receiver) = tokio::sync::mpsc::unbounded_channel::<T>();
+        let subscription = Subscription::new(
+            self.connection.clone(),
+            ChiaMessageFilter {
+                msg_type: Some(msg_type),
+                id: None,
+                custom_fn: None,
+            },
+            Arc::new(SubscriptionHandler { channel: tx }),
+        )
+        .await;
+        MessageSubscription {
+            receiver,
+            _subscription: subscription,
+        }
+    }
+
     async fn perform_handshake(
         &mut self,
         node_type: NodeType,
@@ -326,6 +448,106 @@ impl MessageHandler for OneShotHandler {
     }
 }
 
+/// Message handler subscribed to a connection until it is dropped.
+struct Subscription {
+    connection: Arc<RwLock<WebsocketConnection>>,
+    id: Uuid,
+}
+impl Subscription {
+    async fn new(
+        connection: Arc<RwLock<WebsocketConnection>>,
+        filter: ChiaMessageFilter,
+        handle: Arc<dyn MessageHandler + Send + Sync>,
+    ) -> Self {
+        let id = Uuid::new_v4();
+        connection
+            .read()
+            .await
+            .subscribe(
+                id,
+                Arc::new(ChiaMessageHandler::new(Arc::new(filter), handle)),
+            )
+            .await;
+        Self { connection, id }
+    }
+}
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let (connection, id) = (self.connection.clone(), self.id);
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                connection.read().await.unsubscribe(id).await;
+            });
+        }
+    }
+}
+
+/// Messages of one type sent by a peer, created with `WsClient::subscribe`. Messages arrive in
+/// the order the peer sent them and are buffered until read. The message handler is removed from
+/// the connection when this is dropped.
+pub struct MessageSubscription<T> {
+    receiver: UnboundedReceiver<T>,
+    _subscription: Subscription,
+}
+impl<T> MessageSubscription<T> {
+    /// Waits for the next message.
+    pub async fn recv(&mut self) -> Option<T> {
+        self.receiver.recv().await
+    }
+}
+impl<T> Stream for MessageSubscription<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().receiver.poll_recv(cx)
+    }
+}
+
+struct SubscriptionHandler<T> {
+    channel: UnboundedSender<T>,
+}
+#[async_trait]
+impl<T: ChiaSerialize + Send + 'static> MessageHandler for SubscriptionHandler<T> {
+    async fn handle(
+        &self,
+        msg: Arc<ChiaMessage>,
+        peer_id: Arc<Bytes32>,
+        peers: PeerMap,
+    ) -> Result<(), Error> {
+        let peer = peers.read().await.get(&peer_id).cloned();
+        let protocol_version = if let Some(peer) = peer.as_ref() {
+            *peer.protocol_version.read().await
+        } else {
+            ChiaProtocolVersion::default()
+        };
+        let msg = T::from_bytes(&mut Cursor::new(&msg.data), protocol_version)?;
+        // Fails once the subscription was dropped, its handler is being removed
+        let _ = self.channel.send(msg);
+        Ok(())
+    }
+
+    fn in_order(&self) -> bool {
+        true
+    }
+}
+
+struct ResponseHandler {
+    channel: Sender<Arc<ChiaMessage>>,
+}
+#[async_trait]
+impl MessageHandler for ResponseHandler {
+    async fn handle(
+        &self,
+        msg: Arc<ChiaMessage>,
+        _peer_id: Arc<Bytes32>,
+        _peers: PeerMap,
+    ) -> Result<(), Error> {
+        // Only the first answer is waited for
+        let _ = self.channel.try_send(msg);
+        Ok(())
+    }
+}
+
 pub async fn oneshot<R: ChiaSerialize>(
     connection: Arc<RwLock<WebsocketConnection>>,
     msg: ChiaMessage,
@@ -0,0 +1,406 @@
+use dg_xch_core::blockchain::peer_info::TimestampedPeerInfo;
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::utils::hash_256;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind};
+use std::net::IpAddr;
+use std::path::Path;
+use uuid::Uuid;
+
+pub const NEW_BUCKET_COUNT: usize = 1024;
+pub const TRIED_BUCKET_COUNT: usize = 256;
+pub const BUCKET_SIZE: usize = 64;
+/// Addresses last seen longer ago are dropped first
+const HORIZON_SECS: u64 = 30 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PeerAddress {
+    pub host: String,
+    pub port: u16,
+}
+impl PeerAddress {
+    #[must_use]
+    pub fn new(host: &str, port: u16) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    /// Network group of the host, /16 for IPv4 and /32 for IPv6 addresses. Hosts from the same
+    /// group share buckets so one operator can not fill the address book.
+    #[must_use]
+    pub fn group(&self) -> Vec<u8> {
+        host_group(&self.host)
+    }
+}
+impl Display for PeerAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+impl From<&TimestampedPeerInfo> for PeerAddress {
+    fn from(value: &TimestampedPeerInfo) -> Self {
+        Self::new(&value.host, value.port)
+    }
+}
+
+fn host_group(host: &str) -> Vec<u8> {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => ip.octets()[..2].to_vec(),
+        Ok(IpAddr::V6(ip)) => ip.octets()[..4].to_vec(),
+        Err(_) => host.as_bytes().to_vec(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressInfo {
+    pub peer: PeerAddress,
+    /// Host that told us about the peer
+    pub source: String,
+    /// Last time the peer was seen online, as reported by the source
+    pub timestamp: u64,
+    pub last_try: u64,
+    pub last_success: u64,
+    /// Failed attempts since the last success
+    pub attempts: u32,
+    pub tried: bool,
+}
+impl AddressInfo {
+    /// Addresses that are not worth keeping when a bucket is full.
+    #[must_use]
+    pub fn is_terrible(&self, now: u64) -> bool {
+        if self.last_try > 0 && self.last_try + 60 >= now {
+            return false;
+        }
+        self.timestamp > now + 600
+            || self.timestamp + HORIZON_SECS < now
+            || (self.last_success == 0 && self.attempts >= 3)
+            || (self.last_success + 7 * 24 * 60 * 60 < now && self.attempts >= 10)
+    }
+}
+
+/// Known peer addresses in two tables: `new` for addresses learned from other peers and
+/// `tried` for addresses we connected to at least once. Both tables are split into buckets
+/// picked by a keyed hash of the address group, which limits how many slots hosts of one
+/// network can take.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressBook {
+    key: Bytes32,
+    new_buckets: Vec<Vec<AddressInfo>>,
+    tried_buckets: Vec<Vec<AddressInfo>>,
+    #[serde(skip)]
+    index: HashMap<PeerAddress, (bool, usize)>,
+}
+impl Default for AddressBook {
+    fn default() -> Self {
+        let mut key = [0u8; 32];
+        key[..16].copy_from_slice(Uuid::new_v4().as_bytes());
+        key[16..].copy_from_slice(Uuid::new_v4().as_bytes());
+        Self {
+            key: Bytes32::from(key),
+            new_buckets: vec![vec![]; NEW_BUCKET_COUNT],
+            tried_buckets: vec![vec![]; TRIED_BUCKET_COUNT],
+            index: HashMap::new(),
+        }
+    }
+}
+impl AddressBook {
+    pub async fn load(path: &Path) -> Result<Self, Error> {
+        let data = tokio::fs::read(path).await?;
+        let mut book: Self = serde_json::from_slice(&data).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Failed to parse address book {path:?}: {e:?}"),
+            )
+        })?;
+        if book.new_buckets.len() != NEW_BUCKET_COUNT
+            || book.tried_buckets.len() != TRIED_BUCKET_COUNT
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Address book {path:?} has an invalid bucket count"),
+            ));
+        }
+        book.rebuild_index();
+        Ok(book)
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), Error> {
+        let data = serde_json::to_vec(self).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Failed to serialize address book: {e:?}"),
+            )
+        })?;
+        // Written next to the target first so a crash never leaves a truncated book
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, data).await?;
+        tokio::fs::rename(&tmp, path).await
+    }
+
+    fn rebuild_index(&mut self) {
+        self.index.clear();
+        for (tried, buckets) in [(false, &self.new_buckets), (true, &self.tried_buckets)] {
+            for (bucket, entries) in buckets.iter().enumerate() {
+                for info in entries {
+                    self.index.insert(info.peer.clone(), (tried, bucket));
+                }
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    #[must_use]
+    pub fn tried_count(&self) -> usize {
+        self.index.values().filter(|(tried, _)| *tried).count()
+    }
+
+    #[must_use]
+    pub fn get(&self, peer: &PeerAddress) -> Option<&AddressInfo> {
+        let (tried, bucket) = self.index.get(peer)?;
+        self.table(*tried)[*bucket]
+            .iter()
+            .find(|info| &info.peer == peer)
+    }
+
+    fn get_mut(&mut self, peer: &PeerAddress) -> Option<&mut AddressInfo> {
+        let (tried, bucket) = *self.index.get(peer)?;
+        let table = if tried {
+            &mut self.tried_buckets
+        } else {
+            &mut self.new_buckets
+        };
+        table[bucket].iter_mut().find(|info| &info.peer == peer)
+    }
+
+    fn table(&self, tried: bool) -> &Vec<Vec<AddressInfo>> {
+        if tried {
+            &self.tried_buckets
+        } else {
+            &self.new_buckets
+        }
+    }
+
+    fn bucket(&self, parts: &[&[u8]], count: usize) -> usize {
+        let mut to_hash = AsRef::<[u8]>::as_ref(&self.key).to_vec();
+        for part in parts {
+            to_hash.extend_from_slice(part);
+        }
+        let hash = hash_256(to_hash);
+        let mut value = [0u8; 8];
+        value.copy_from_slice(&hash[..8]);
+        (u64::from_be_bytes(value) % count as u64) as usize
+    }
+
+    fn new_bucket(&self, peer: &PeerAddress, source: &str) -> usize {
+        self.bucket(&[&peer.group(), &host_group(source)], NEW_BUCKET_COUNT)
+    }
+
+    fn tried_bucket(&self, peer: &PeerAddress) -> usize {
+        self.bucket(
+            &[&peer.group(), peer.to_string().as_bytes()],
+            TRIED_BUCKET_COUNT,
+        )
+    }
+
+    /// Adds addresses learned from `source` to the new table. Known addresses only get their
+    /// timestamp updated. Returns the number of addresses added.
+    pub fn add(&mut self, peers: &[TimestampedPeerInfo], source: &str, now: u64) -> usize {
+        let mut added = 0;
+        for peer_info in peers {
+            let peer = PeerAddress::from(peer_info);
+            if let Some(info) = self.get_mut(&peer) {
+                info.timestamp = info.timestamp.max(peer_info.timestamp.min(now));
+                continue;
+            }
+            let bucket = self.new_bucket(&peer, source);
+            self.make_room(false, bucket, now);
+            self.new_buckets[bucket].push(AddressInfo {
+                peer: peer.clone(),
+                source: source.to_string(),
+                timestamp: peer_info.timestamp.min(now),
+                last_try: 0,
+                last_success: 0,
+                attempts: 0,
+                tried: false,
+            });
+            self.index.insert(peer, (false, bucket));
+            added += 1;
+        }
+        added
+    }
+
+    /// Drops the worst entry of a full bucket, terrible entries first and then the oldest.
+    fn make_room(&mut self, tried: bool, bucket: usize, now: u64) -> Option<AddressInfo> {
+        let entries = if tried {
+            &mut self.tried_buckets[bucket]
+        } else {
+            &mut self.new_buckets[bucket]
+        };
+        if entries.len() < BUCKET_SIZE {
+            return None;
+        }
+        let worst = entries
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, info)| {
+                (
+                    !info.is_terrible(now),
+                    if tried {
+                        info.last_success
+                    } else {
+                        info.timestamp
+                    },
+                )
+            })
+            .map(|(i, _)| i)?;
+        let removed = entries.swap_remove(worst);
+        self.index.remove(&removed.peer);
+        Some(removed)
+    }
+
+    /// Records a connection attempt that did not succeed.
+    pub fn attempt(&mut self, peer: &PeerAddress, now: u64) {
+        if let Some(info) = self.get_mut(peer) {
+            info.last_try = now;
+            info.attempts += 1;
+        }
+    }
+
+    /// Records a successful connection and moves the address to the tried table. An address
+    /// pushed out of a full tried bucket goes back to the new table.
+    pub fn mark_good(&mut self, peer: &PeerAddress, now: u64) {
+        let mut info = match self.remove(peer) {
+            Some(info) => info,
+            None => AddressInfo {
+                peer: peer.clone(),
+                source: peer.host.clone(),
+                timestamp: now,
+                last_try: now,
+                last_success: now,
+                attempts: 0,
+                tried: true,
+            },
+        };
+        info.timestamp = now;
+        info.last_try = now;
+        info.last_success = now;
+        info.attempts = 0;
+        info.tried = true;
+        let bucket = self.tried_bucket(peer);
+        if let Some(mut evicted) = self.make_room(true, bucket, now) {
+            evicted.tried = false;
+            let new_bucket = self.new_bucket(&evicted.peer, &evicted.source);
+            self.make_room(false, new_bucket, now);
+            self.index.insert(evicted.peer.clone(), (false, new_bucket));
+            self.new_buckets[new_bucket].push(evicted);
+        }
+        self.index.insert(peer.clone(), (true, bucket));
+        self.tried_buckets[bucket].push(info);
+    }
+
+    pub fn remove(&mut self, peer: &PeerAddress) -> Option<AddressInfo> {
+        let (tried, bucket) = self.index.remove(peer)?;
+        let entries = if tried {
+            &mut self.tried_buckets[bucket]
+        } else {
+            &mut self.new_buckets[bucket]
+        };
+        let position = entries.iter().position(|info| &info.peer == peer)?;
+        Some(entries.swap_remove(position))
+    }
+
+    /// Random address accepted by `filter`, taken from the tried or the new table with equal
+    /// chance when both have one.
+    pub fn select<F: Fn(&AddressInfo) -> bool>(&self, filter: F) -> Option<&AddressInfo> {
+        let tried_first = random_u64() & 1 == 0;
+        for tried in [tried_first, !tried_first] {
+            let candidates: Vec<&AddressInfo> = self
+                .table(tried)
+                .iter()
+                .flatten()
+                .filter(|info| filter(info))
+                .collect();
+            if !candidates.is_empty() {
+                return Some(candidates[(random_u64() % candidates.len() as u64) as usize]);
+            }
+        }
+        None
+    }
+
+    /// Addresses of both tables
+    pub fn iter(&self) -> impl Iterator<Item = &AddressInfo> {
+        self.new_buckets
+            .iter()
+            .chain(self.tried_buckets.iter())
+            .flatten()
+    }
+}
+
+fn random_u64() -> u64 {
+    Uuid::new_v4().as_u64_pair().0
+}
+
+#[test]
+fn test_address_book() {
+    let now = 1_700_000_000;
+    let peers: Vec<TimestampedPeerInfo> = (0..4u16)
+        .map(|i| TimestampedPeerInfo {
+            host: format!("10.0.0.{i}"),
+            port: 8444,
+            timestamp: now - 10,
+        })
+        .collect();
+    let mut book = AddressBook::default();
+    assert_eq!(book.add(&peers, "introducer", now), 4);
+    assert_eq!(book.add(&peers, "introducer", now), 0);
+    assert_eq!(book.len(), 4);
+    assert_eq!(book.tried_count(), 0);
+    let peer = PeerAddress::new("10.0.0.1", 8444);
+    book.attempt(&peer, now);
+    assert_eq!(book.get(&peer).map(|i| i.attempts), Some(1));
+    book.mark_good(&peer, now + 1);
+    let info = book.get(&peer).unwrap();
+    assert!(info.tried);
+    assert_eq!(info.attempts, 0);
+    assert_eq!(book.tried_count(), 1);
+    assert_eq!(book.len(), 4);
+    assert_eq!(
+        book.select(|info| info.tried).map(|i| i.peer.clone()),
+        Some(peer.clone())
+    );
+    assert!(book.select(|info| info.peer.port == 1).is_none());
+    assert!(book.remove(&peer).is_some());
+    assert!(book.get(&peer).is_none());
+    assert_eq!(book.len(), 3);
+}
+
+#[test]
+fn test_address_book_full_bucket() {
+    let now = 1_700_000_000;
+    let mut book = AddressBook::default();
+    // Same group and source, every address lands in one new bucket
+    let peers: Vec<TimestampedPeerInfo> = (0..=BUCKET_SIZE as u64)
+        .map(|i| TimestampedPeerInfo {
+            host: "10.1.0.1".to_string(),
+            port: 1000 + i as u16,
+            timestamp: now - 1000 + i,
+        })
+        .collect();
+    assert_eq!(book.add(&peers, "10.2.0.1", now), BUCKET_SIZE + 1);
+    assert_eq!(book.len(), BUCKET_SIZE);
+    // The oldest address made room
+    assert!(book.get(&PeerAddress::new("10.1.0.1", 1000)).is_none());
+}
@@ -0,0 +1,564 @@
+pub mod address_book;
+
+use crate::websocket::introducer::{IntroducerClient, IntroducerState};
+use crate::websocket::peer_manager::address_book::{AddressBook, AddressInfo, PeerAddress};
+use crate::websocket::{WsClient, WsClientConfig};
+use crate::ClientSSLConfig;
+use dg_xch_core::blockchain::peer_info::TimestampedPeerInfo;
+use dg_xch_core::config::SeederConfig;
+use dg_xch_core::constants::{CHIA_CA_CRT, CHIA_CA_KEY};
+use dg_xch_core::protocols::full_node::{RequestPeers, RespondPeers};
+use dg_xch_core::protocols::{ChiaMessageHandler, NodeType, ProtocolMessageTypes};
+use dg_xch_core::ssl::generate_ca_signed_cert_data;
+use dg_xch_serialize::ChiaProtocolVersion;
+use futures_util::future::join_all;
+use log::{debug, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, Mutex, RwLock};
+use uuid::Uuid;
+
+/// Events not read yet by a slow subscriber before it starts missing them
+const EVENT_BUFFER: usize = 256;
+/// Most addresses a full node sends in one `RespondPeers`, larger lists are misbehaviour
+pub const MAX_PEERS_RECEIVED_PER_REQUEST: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerEvent {
+    Connected {
+        peer: PeerAddress,
+        node_type: NodeType,
+    },
+    Disconnected {
+        peer: PeerAddress,
+        node_type: NodeType,
+    },
+    Banned {
+        peer: PeerAddress,
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct PeerManagerConfig {
+    pub network_id: String,
+    /// Node type sent in our handshakes
+    pub node_type: NodeType,
+    pub protocol_version: ChiaProtocolVersion,
+    pub ssl_info: Option<ClientSSLConfig>,
+    /// Outbound connections to keep by the node type of the peer
+    pub outbound_targets: HashMap<NodeType, usize>,
+    pub introducer: Option<PeerAddress>,
+    /// Host names resolving to peers listening on `default_port`
+    pub dns_seeders: Vec<String>,
+    pub default_port: u16,
+    pub address_book_path: Option<PathBuf>,
+    /// Seconds to wait for a peer to connect
+    pub connect_timeout: u64,
+    /// Wait after the first failed attempt, doubled for every further failure
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    pub ban_duration: Duration,
+    /// Least time between two rounds of asking the introducer and DNS seeders for peers
+    pub bootstrap_interval: Duration,
+    pub maintain_interval: Duration,
+}
+impl Default for PeerManagerConfig {
+    fn default() -> Self {
+        PeerManagerConfig {
+            network_id: "mainnet".to_string(),
+            node_type: NodeType::FullNode,
+            protocol_version: ChiaProtocolVersion::default(),
+            ssl_info: None,
+            outbound_targets: HashMap::from([(NodeType::FullNode, 8)]),
+            introducer: Some(PeerAddress::new("introducer.chia.net", 8444)),
+            dns_seeders: vec![
+                "dns-introducer.chia.net".to_string(),
+                "seeder.dexie.space".to_string(),
+            ],
+            default_port: 8444,
+            address_book_path: None,
+            connect_timeout: 5,
+            min_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(60 * 60),
+            ban_duration: Duration::from_secs(24 * 60 * 60),
+            bootstrap_interval: Duration::from_secs(5 * 60),
+            maintain_interval: Duration::from_secs(1),
+        }
+    }
+}
+impl PeerManagerConfig {
+    /// Seeds from the bootstrap peers of a seeder config instead of the default DNS seeders.
+    #[must_use]
+    pub fn from_seeder_config(seeder: &SeederConfig) -> Self {
+        PeerManagerConfig {
+            network_id: seeder.selected_network.clone(),
+            dns_seeders: seeder.bootstrap_peers.clone(),
+            default_port: seeder.other_peers_port,
+            connect_timeout: seeder.peer_connect_timeout as u64,
+            ..Default::default()
+        }
+    }
+
+    /// Wait before the next connection attempt after `attempts` failures in a row.
+    #[must_use]
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        if attempts == 0 {
+            return Duration::ZERO;
+        }
+        self.min_backoff
+            .saturating_mul(2u32.saturating_pow(attempts - 1))
+            .min(self.max_backoff)
+    }
+}
+
+pub struct PeerConnection {
+    pub peer: PeerAddress,
+    pub node_type: NodeType,
+    pub client: WsClient,
+}
+
+/// Keeps the configured number of outbound connections open. Addresses come from the
+/// introducer, DNS seeders and the peers themselves and are kept in an `AddressBook`.
+pub struct PeerManager {
+    pub config: Arc<PeerManagerConfig>,
+    pub address_book: Arc<RwLock<AddressBook>>,
+    pub connections: Arc<RwLock<HashMap<PeerAddress, Arc<PeerConnection>>>>,
+    /// Handlers copied to every new connection
+    pub handles: Arc<RwLock<HashMap<Uuid, Arc<ChiaMessageHandler>>>>,
+    banned: RwLock<HashMap<String, Instant>>,
+    /// Client certificate and key, signed once and shared by all connections
+    cert: (Vec<u8>, Vec<u8>),
+    events: broadcast::Sender<PeerEvent>,
+    last_bootstrap: Mutex<Option<Instant>>,
+    /// Peers reported by background tasks, banned on the next `maintain`
+    misbehaving: Arc<Mutex<Vec<(PeerAddress, String)>>>,
+}
+impl PeerManager {
+    /// Loads the address book from `config.address_book_path`, starting empty when there is
+    /// no file yet, and signs the client certificate used for every connection.
+    pub async fn new(
+        config: PeerManagerConfig,
+        handles: HashMap<Uuid, Arc<ChiaMessageHandler>>,
+    ) -> Result<Self, Error> {
+        let address_book = match &config.address_book_path {
+            Some(path) => match AddressBook::load(path).await {
+                Ok(book) => book,
+                Err(e) if e.kind() == ErrorKind::NotFound => AddressBook::default(),
+                Err(e) => return Err(e),
+            },
+            None => AddressBook::default(),
+        };
+        let cert = if let Some(ssl_info) = &config.ssl_info {
+            (
+                fs::read(&ssl_info.ssl_crt_path)?,
+                fs::read(&ssl_info.ssl_key_path)?,
+            )
+        } else if let (Some(crt), Some(key)) = (
+            env::var("PRIVATE_CA_CRT").ok(),
+            env::var("PRIVATE_CA_KEY").ok(),
+        ) {
+            generate_ca_signed_cert_data(crt.as_bytes(), key.as_bytes())
+                .map_err(|e| Error::other(format!("OpenSSL Errors: {e:?}")))?
+        } else {
+            generate_ca_signed_cert_data(CHIA_CA_CRT.as_bytes(), CHIA_CA_KEY.as_bytes())
+                .map_err(|e| Error::other(format!("OpenSSL Errors: {e:?}")))?
+        };
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        Ok(PeerManager {
+            config: Arc::new(config),
+            address_book: Arc::new(RwLock::new(address_book)),
+            connections: Arc::default(),
+            handles: Arc::new(RwLock::new(handles)),
+            banned: RwLock::default(),
+            cert,
+            events,
+            last_bootstrap: Mutex::default(),
+            misbehaving: Arc::default(),
+        })
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PeerEvent> {
+        self.events.subscribe()
+    }
+
+    fn publish(&self, event: PeerEvent) {
+        debug!("Peer event: {event:?}");
+        // Fails when nobody is subscribed
+        let _ = self.events.send(event);
+    }
+
+    pub async fn connected(&self, node_type: NodeType) -> Vec<Arc<PeerConnection>> {
+        self.connections
+            .read()
+            .await
+            .values()
+            .filter(|c| c.node_type == node_type)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn is_banned(&self, host: &str) -> bool {
+        self.banned
+            .read()
+            .await
+            .get(host)
+            .is_some_and(|until| *until > Instant::now())
+    }
+
+    /// Disconnects the peer, forgets its address and refuses connections to its host until
+    /// the ban expires.
+    pub async fn ban(&self, peer: &PeerAddress, reason: &str) {
+        warn!("Banning peer {peer}: {reason}");
+        self.banned
+            .write()
+            .await
+            .insert(peer.host.clone(), Instant::now() + self.config.ban_duration);
+        self.address_book.write().await.remove(peer);
+        self.disconnect(peer).await;
+        self.publish(PeerEvent::Banned {
+            peer: peer.clone(),
+            reason: reason.to_string(),
+        });
+    }
+
+    pub async fn disconnect(&self, peer: &PeerAddress) {
+        let connection = self.connections.write().await.remove(peer);
+        if let Some(connection) = connection {
+            if let Err(e) = connection.client.connection.write().await.shutdown().await {
+                debug!("Error closing connection to {peer}: {e:?}");
+            }
+            self.publish(PeerEvent::Disconnected {
+                peer: peer.clone(),
+                node_type: connection.node_type,
+            });
+        }
+    }
+
+    /// Asks the introducer and DNS seeders for peers, returns the number of new addresses.
+    pub async fn bootstrap(&self, run: Arc<AtomicBool>) -> usize {
+        *self.last_bootstrap.lock().await = Some(Instant::now());
+        let mut added = 0;
+        if let Some(introducer) = &self.config.introducer {
+            match self.introducer_peers(introducer, run).await {
+                Ok(peers) => {
+                    added +=
+                        self.address_book
+                            .write()
+                            .await
+                            .add(&peers, &introducer.host, unix_time());
+                }
+                Err(e) => warn!("Failed to get peers from introducer {introducer}: {e:?}"),
+            }
+        }
+        for seeder in &self.config.dns_seeders {
+            match tokio::net::lookup_host((seeder.as_str(), self.config.default_port)).await {
+                Ok(addresses) => {
+                    let now = unix_time();
+                    let peers: Vec<TimestampedPeerInfo> = addresses
+                        .map(|address| TimestampedPeerInfo {
+                            host: address.ip().to_string(),
+                            port: address.port(),
+                            timestamp: now,
+                        })
+                        .collect();
+                    added += self.address_book.write().await.add(&peers, seeder, now);
+                }
+                Err(e) => warn!("Failed to resolve DNS seeder {seeder}: {e:?}"),
+            }
+        }
+        info!("Bootstrap added {added} peer addresses");
+        added
+    }
+
+    async fn introducer_peers(
+        &self,
+        introducer: &PeerAddress,
+        run: Arc<AtomicBool>,
+    ) -> Result<Vec<TimestampedPeerInfo>, Error> {
+        let client = IntroducerClient::new(
+            self.client_config(introducer),
+            run,
+            Arc::new(RwLock::new(IntroducerState::default())),
+            self.config.connect_timeout,
+        )
+        .await?;
+        let peers = client.request_peers().await;
+        if let Err(e) = client.join().await {
+            debug!("Error closing introducer connection: {e:?}");
+        }
+        peers
+    }
+
+    fn client_config(&self, peer: &PeerAddress) -> Arc<WsClientConfig> {
+        Arc::new(WsClientConfig {
+            host: peer.host.clone(),
+            port: peer.port,
+            network_id: self.config.network_id.clone(),
+            ssl_info: self.config.ssl_info.clone(),
+            software_version: None,
+            protocol_version: self.config.protocol_version,
+            additional_headers: None,
+        })
+    }
+
+    async fn connect(
+        &self,
+        peer: &PeerAddress,
+        run: Arc<AtomicBool>,
+    ) -> Result<PeerConnection, Error> {
+        // Every connection gets its own handler map, request ids are only unique per client
+        let handles = Arc::new(RwLock::new(self.handles.read().await.clone()));
+        let client = WsClient::with_cert(
+            self.client_config(peer),
+            self.config.node_type,
+            handles,
+            run,
+            &self.cert.0,
+            &self.cert.1,
+            self.config.connect_timeout,
+        )
+        .await;
+        let client = match client {
+            Ok(client) => client,
+            // The peer answered with something that is not a handshake
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                self.ban(peer, &e.to_string()).await;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+        let node_type = match client.handshake.as_ref() {
+            None => Err("Missing handshake".to_string()),
+            Some(handshake) if handshake.network_id != self.config.network_id => {
+                Err(format!("Peer is on network {}", handshake.network_id))
+            }
+            Some(handshake) => match NodeType::from(handshake.node_type) {
+                NodeType::Unknown => Err(format!("Unknown node type {}", handshake.node_type)),
+                node_type => Ok(node_type),
+            },
+        };
+        let node_type = match node_type {
+            Ok(node_type) => node_type,
+            Err(reason) => {
+                let _ = client.connection.write().await.shutdown().await;
+                self.ban(peer, &reason).await;
+                return Err(Error::new(ErrorKind::InvalidData, reason));
+            }
+        };
+        Ok(PeerConnection {
+            peer: peer.clone(),
+            node_type,
+            client,
+        })
+    }
+
+    /// Asks a full node for the peers it knows, in the background
+    fn request_peers(&self, connection: &Arc<PeerConnection>) {
+        let connection = connection.clone();
+        let address_book = self.address_book.clone();
+        let misbehaving = self.misbehaving.clone();
+        tokio::spawn(async move {
+            match connection
+                .client
+                .request::<RequestPeers, RespondPeers>(
+                    ProtocolMessageTypes::RequestPeers,
+                    &RequestPeers {},
+                    None,
+                )
+                .await
+            {
+                Ok(response) if response.peer_list.len() > MAX_PEERS_RECEIVED_PER_REQUEST => {
+                    let reason = format!("Sent {} peers", response.peer_list.len());
+                    misbehaving
+                        .lock()
+                        .await
+                        .push((connection.peer.clone(), reason));
+                }
+                Ok(response) => {
+                    let added = address_book.write().await.add(
+                        &response.peer_list,
+                        &connection.peer.host,
+                        unix_time(),
+                    );
+                    debug!("Added {added} peer addresses from {}", connection.peer);
+                }
+                Err(e) if e.kind() == ErrorKind::InvalidData => {
+                    misbehaving
+                        .lock()
+                        .await
+                        .push((connection.peer.clone(), e.to_string()));
+                }
+                Err(e) => debug!("Failed to get peers from {}: {e:?}", connection.peer),
+            }
+        });
+    }
+
+    async fn missing_connections(&self) -> usize {
+        let connections = self.connections.read().await;
+        self.config
+            .outbound_targets
+            .iter()
+            .map(|(node_type, target)| {
+                let open = connections
+                    .values()
+                    .filter(|c| c.node_type == *node_type)
+                    .count();
+                target.saturating_sub(open)
+            })
+            .sum()
+    }
+
+    /// Drops closed connections and opens new ones until the outbound targets are met or the
+    /// address book has no peer ready for another attempt.
+    pub async fn maintain(&self, run: Arc<AtomicBool>) {
+        let closed: Vec<PeerAddress> = self
+            .connections
+            .read()
+            .await
+            .values()
+            .filter(|c| c.client.is_closed())
+            .map(|c| c.peer.clone())
+            .collect();
+        for peer in closed {
+            self.disconnect(&peer).await;
+        }
+        let misbehaving: Vec<(PeerAddress, String)> =
+            self.misbehaving.lock().await.drain(..).collect();
+        for (peer, reason) in misbehaving {
+            self.ban(&peer, &reason).await;
+        }
+        let missing = self.missing_connections().await;
+        if missing == 0 {
+            return;
+        }
+        let mut candidates = self.candidates(missing).await;
+        if candidates.is_empty() {
+            let bootstrap_due = self
+                .last_bootstrap
+                .lock()
+                .await
+                .is_none_or(|last| last.elapsed() >= self.config.bootstrap_interval);
+            if bootstrap_due {
+                self.bootstrap(run.clone()).await;
+                candidates = self.candidates(missing).await;
+            }
+        }
+        let results = join_all(
+            candidates
+                .iter()
+                .map(|peer| self.connect(peer, run.clone())),
+        )
+        .await;
+        for (peer, result) in candidates.into_iter().zip(results) {
+            match result {
+                Ok(connection) => self.add_connection(connection).await,
+                Err(e) => {
+                    debug!("Failed to connect to {peer}: {e:?}");
+                    self.address_book.write().await.attempt(&peer, unix_time());
+                }
+            }
+        }
+    }
+
+    async fn add_connection(&self, connection: PeerConnection) {
+        let peer = connection.peer.clone();
+        let node_type = connection.node_type;
+        let target = self
+            .config
+            .outbound_targets
+            .get(&node_type)
+            .copied()
+            .unwrap_or_default();
+        let open = self.connected(node_type).await.len();
+        if open >= target {
+            // Counted as a failed attempt so the peer is not dialed again on the next round
+            debug!("Closing connection to {peer}, no {node_type:?} peers needed");
+            let _ = connection.client.connection.write().await.shutdown().await;
+            self.address_book.write().await.attempt(&peer, unix_time());
+            return;
+        }
+        self.address_book
+            .write()
+            .await
+            .mark_good(&peer, unix_time());
+        let connection = Arc::new(connection);
+        if node_type == NodeType::FullNode {
+            self.request_peers(&connection);
+        }
+        self.connections
+            .write()
+            .await
+            .insert(peer.clone(), connection);
+        self.publish(PeerEvent::Connected { peer, node_type });
+    }
+
+    /// Up to `count` addresses that are not connected, banned or backing off.
+    async fn candidates(&self, count: usize) -> Vec<PeerAddress> {
+        let now = unix_time();
+        let connected: HashSet<PeerAddress> =
+            self.connections.read().await.keys().cloned().collect();
+        let banned: HashSet<String> = self
+            .banned
+            .read()
+            .await
+            .iter()
+            .filter(|(_, until)| **until > Instant::now())
+            .map(|(host, _)| host.clone())
+            .collect();
+        let book = self.address_book.read().await;
+        let mut selected: HashSet<PeerAddress> = HashSet::new();
+        let is_ready = |info: &AddressInfo| {
+            !connected.contains(&info.peer)
+                && !banned.contains(&info.peer.host)
+                && info.last_try + self.config.backoff(info.attempts).as_secs() <= now
+        };
+        while selected.len() < count {
+            match book.select(|info| is_ready(info) && !selected.contains(&info.peer)) {
+                Some(info) => selected.insert(info.peer.clone()),
+                None => break,
+            };
+        }
+        selected.into_iter().collect()
+    }
+
+    pub async fn save(&self) -> Result<(), Error> {
+        match &self.config.address_book_path {
+            Some(path) => self.address_book.read().await.save(path).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Maintains the connections until `run` is cleared, then closes them and saves the
+    /// address book.
+    pub async fn run(&self, run: Arc<AtomicBool>) -> Result<(), Error> {
+        let mut last_save = Instant::now();
+        while run.load(Ordering::Relaxed) {
+            self.maintain(run.clone()).await;
+            if last_save.elapsed() >= Duration::from_secs(60) {
+                if let Err(e) = self.save().await {
+                    warn!("Failed to save address book: {e:?}");
+                }
+                last_save = Instant::now();
+            }
+            tokio::time::sleep(self.config.maintain_interval).await;
+        }
+        let peers: Vec<PeerAddress> = self.connections.read().await.keys().cloned().collect();
+        for peer in peers {
+            self.disconnect(&peer).await;
+        }
+        self.save().await
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
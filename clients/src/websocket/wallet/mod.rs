@@ -1,15 +1,35 @@
-use crate::websocket::{WsClient, WsClientConfig};
+use crate::websocket::{MessageSubscription, WsClient, WsClientConfig};
+use async_trait::async_trait;
+use dg_xch_core::blockchain::coin_spend::CoinSpend;
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::blockchain::spend_bundle::SpendBundle;
 use dg_xch_core::constants::{CHIA_CA_CRT, CHIA_CA_KEY};
-use dg_xch_core::protocols::{ChiaMessageHandler, NodeType};
+use dg_xch_core::protocols::wallet::{
+    CoinState, CoinStateUpdate, NewPeakWallet, PuzzleSolutionResponse, RegisterForCoinUpdates,
+    RegisterForPhUpdates, RequestChildren, RequestCoinState, RequestPuzzleSolution,
+    RequestPuzzleState, RequestRemoveCoinSubscriptions, RequestRemovePuzzleSubscriptions,
+    RespondChildren, RespondCoinState, RespondPuzzleSolution, RespondPuzzleState,
+    RespondRemoveCoinSubscriptions, RespondRemovePuzzleSubscriptions, RespondToCoinUpdates,
+    RespondToPhUpdates, SendTransaction, TransactionAck,
+};
+use dg_xch_core::protocols::{
+    ChiaMessage, ChiaMessageFilter, ChiaMessageHandler, MessageHandler, NodeType, PeerMap,
+    ProtocolMessageTypes,
+};
+use dg_xch_serialize::{ChiaProtocolVersion, ChiaSerialize};
+use futures_util::Stream;
 use std::collections::HashMap;
-use std::io::Error;
+use std::io::{Cursor, Error};
+use std::pin::Pin;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
+use std::task::{Context, Poll};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
 pub struct WalletClient {
     pub client: WsClient,
+    pub peak: Arc<RwLock<Option<NewPeakWallet>>>,
 }
 impl WalletClient {
     pub async fn new(
@@ -17,7 +37,8 @@ impl WalletClient {
         run: Arc<AtomicBool>,
         timeout: u64,
     ) -> Result<Self, Error> {
-        let handles = Arc::new(RwLock::new(handles()));
+        let peak = Arc::new(RwLock::new(None));
+        let handles = Arc::new(RwLock::new(handles(peak.clone())));
         let client = WsClient::with_ca(
             client_config,
             NodeType::Wallet,
@@ -28,7 +49,7 @@ impl WalletClient {
             timeout,
         )
         .await?;
-        Ok(WalletClient { client })
+        Ok(WalletClient { client, peak })
     }
 
     pub async fn join(self) -> Result<(), Error> {
@@ -40,8 +61,337 @@ impl WalletClient {
     pub fn is_closed(&self) -> bool {
         self.client.handle.is_finished()
     }
+
+    /// Subscribes to coins with the given puzzle hashes, returns their states since `min_height`.
+    /// Later changes arrive as `CoinStateUpdate`, see `subscribe_coin_states`.
+    pub async fn register_puzzle_hashes(
+        &self,
+        puzzle_hashes: Vec<Bytes32>,
+        min_height: u32,
+    ) -> Result<Vec<CoinState>, Error> {
+        self.client
+            .request::<RegisterForPhUpdates, RespondToPhUpdates>(
+                ProtocolMessageTypes::RegisterInterestInPuzzleHash,
+                &RegisterForPhUpdates {
+                    puzzle_hashes,
+                    min_height,
+                },
+                None,
+            )
+            .await
+            .map(|r| r.coin_states)
+    }
+
+    /// Subscribes to the given coins, returns their states since `min_height`.
+    /// Later changes arrive as `CoinStateUpdate`, see `subscribe_coin_states`.
+    pub async fn register_coins(
+        &self,
+        coin_ids: Vec<Bytes32>,
+        min_height: u32,
+    ) -> Result<Vec<CoinState>, Error> {
+        self.client
+            .request::<RegisterForCoinUpdates, RespondToCoinUpdates>(
+                ProtocolMessageTypes::RegisterInterestInCoin,
+                &RegisterForCoinUpdates {
+                    coin_ids,
+                    min_height,
+                },
+                None,
+            )
+            .await
+            .map(|r| r.coin_states)
+    }
+
+    /// Removes puzzle hash subscriptions, all of them when `puzzle_hashes` is `None`.
+    /// Returns the puzzle hashes that were removed.
+    pub async fn remove_puzzle_subscriptions(
+        &self,
+        puzzle_hashes: Option<Vec<Bytes32>>,
+    ) -> Result<Vec<Bytes32>, Error> {
+        self.client
+            .request::<RequestRemovePuzzleSubscriptions, RespondRemovePuzzleSubscriptions>(
+                ProtocolMessageTypes::RequestRemovePuzzleSubscriptions,
+                &RequestRemovePuzzleSubscriptions { puzzle_hashes },
+                None,
+            )
+            .await
+            .map(|r| r.puzzle_hashes)
+    }
+
+    /// Removes coin subscriptions, all of them when `coin_ids` is `None`.
+    /// Returns the coin ids that were removed.
+    pub async fn remove_coin_subscriptions(
+        &self,
+        coin_ids: Option<Vec<Bytes32>>,
+    ) -> Result<Vec<Bytes32>, Error> {
+        self.client
+            .request::<RequestRemoveCoinSubscriptions, RespondRemoveCoinSubscriptions>(
+                ProtocolMessageTypes::RequestRemoveCoinSubscriptions,
+                &RequestRemoveCoinSubscriptions { coin_ids },
+                None,
+            )
+            .await
+            .map(|r| r.coin_ids)
+    }
+
+    /// Fails with `NotFound` when the node rejects the request, for example after a reorg
+    /// of `request.header_hash`.
+    pub async fn request_puzzle_state(
+        &self,
+        request: &RequestPuzzleState,
+    ) -> Result<RespondPuzzleState, Error> {
+        self.client
+            .request::<RequestPuzzleState, RespondPuzzleState>(
+                ProtocolMessageTypes::RequestPuzzleState,
+                request,
+                None,
+            )
+            .await
+    }
+
+    /// Fails with `NotFound` when the node rejects the request, for example after a reorg
+    /// of `request.header_hash`.
+    pub async fn request_coin_state(
+        &self,
+        request: &RequestCoinState,
+    ) -> Result<RespondCoinState, Error> {
+        self.client
+            .request::<RequestCoinState, RespondCoinState>(
+                ProtocolMessageTypes::RequestCoinState,
+                request,
+                None,
+            )
+            .await
+    }
+
+    /// States of the coins created by spending `coin_name`.
+    pub async fn request_children(&self, coin_name: Bytes32) -> Result<Vec<CoinState>, Error> {
+        self.client
+            .request::<RequestChildren, RespondChildren>(
+                ProtocolMessageTypes::RequestChildren,
+                &RequestChildren { coin_name },
+                None,
+            )
+            .await
+            .map(|r| r.coin_states)
+    }
+
+    /// Fails with `NotFound` when `coin_name` was not spent at `height`.
+    pub async fn request_puzzle_solution(
+        &self,
+        coin_name: Bytes32,
+        height: u32,
+    ) -> Result<PuzzleSolutionResponse, Error> {
+        self.client
+            .request::<RequestPuzzleSolution, RespondPuzzleSolution>(
+                ProtocolMessageTypes::RequestPuzzleSolution,
+                &RequestPuzzleSolution { coin_name, height },
+                None,
+            )
+            .await
+            .map(|r| r.response)
+    }
+
+    /// Spend of `coin` with the puzzle and solution revealed at `height`.
+    pub async fn request_coin_spend(
+        &self,
+        coin: &CoinState,
+        height: u32,
+    ) -> Result<CoinSpend, Error> {
+        let response = self
+            .request_puzzle_solution(coin.coin.name(), height)
+            .await?;
+        Ok(CoinSpend {
+            coin: coin.coin,
+            puzzle_reveal: response.puzzle,
+            solution: response.solution,
+        })
+    }
+
+    pub async fn send_transaction(
+        &self,
+        transaction: SpendBundle,
+    ) -> Result<TransactionAck, Error> {
+        self.client
+            .request::<SendTransaction, TransactionAck>(
+                ProtocolMessageTypes::SendTransaction,
+                &SendTransaction { transaction },
+                None,
+            )
+            .await
+    }
+
+    /// Changes to the subscribed puzzle hashes and coins in the order the node sent them, apply
+    /// them in that order with `apply_coin_state_update`.
+    pub async fn subscribe_coin_states(&self) -> MessageSubscription<CoinStateUpdate> {
+        self.client
+            .subscribe(ProtocolMessageTypes::CoinStateUpdate)
+            .await
+    }
+
+    /// Peaks of the node in the order it sent them, starting from the peak known to this client.
+    pub async fn subscribe_peaks(&self) -> PeakSubscription {
+        let peaks = self
+            .client
+            .subscribe(ProtocolMessageTypes::NewPeakWallet)
+            .await;
+        let last_height = self.peak.read().await.as_ref().map(|p| p.height);
+        PeakSubscription { peaks, last_height }
+    }
+}
+
+/// A new peak of the node. When `reorg` is set the chain followed so far was abandoned above
+/// `peak.fork_point_with_previous_peak`, coin states above it have to be rolled back with
+/// `rollback_coin_states` and requested again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletPeak {
+    pub peak: NewPeakWallet,
+    pub reorg: bool,
+}
+
+/// Stream of `WalletPeak`, created with `WalletClient::subscribe_peaks`.
+pub struct PeakSubscription {
+    peaks: MessageSubscription<NewPeakWallet>,
+    last_height: Option<u32>,
+}
+impl PeakSubscription {
+    /// Waits for the next peak.
+    pub async fn recv(&mut self) -> Option<WalletPeak> {
+        let peak = self.peaks.recv().await?;
+        Some(self.track(peak))
+    }
+
+    fn track(&mut self, peak: NewPeakWallet) -> WalletPeak {
+        let reorg = self
+            .last_height
+            .is_some_and(|height| peak.fork_point_with_previous_peak < height);
+        self.last_height = Some(peak.height);
+        WalletPeak { peak, reorg }
+    }
+}
+impl Stream for PeakSubscription {
+    type Item = WalletPeak;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<WalletPeak>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.peaks).poll_next(cx) {
+            Poll::Ready(Some(peak)) => Poll::Ready(Some(this.track(peak))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Undoes everything above `fork_height`, coins created above it are removed and coins spent
+/// above it are unspent again.
+pub fn rollback_coin_states(coin_states: &mut HashMap<Bytes32, CoinState>, fork_height: u32) {
+    coin_states.retain(|_, state| state.created_height.is_none_or(|h| h <= fork_height));
+    for state in coin_states.values_mut() {
+        if state.spent_height.is_some_and(|h| h > fork_height) {
+            state.spent_height = None;
+        }
+    }
+}
+
+/// Rolls `coin_states` back to the fork of the update and adds its items, keyed by coin id.
+pub fn apply_coin_state_update(
+    coin_states: &mut HashMap<Bytes32, CoinState>,
+    update: &CoinStateUpdate,
+) {
+    rollback_coin_states(coin_states, update.fork_height);
+    for state in &update.items {
+        coin_states.insert(state.coin.name(), state.clone());
+    }
+}
+
+fn handles(peak: Arc<RwLock<Option<NewPeakWallet>>>) -> HashMap<Uuid, Arc<ChiaMessageHandler>> {
+    HashMap::from([(
+        Uuid::new_v4(),
+        Arc::new(ChiaMessageHandler::new(
+            Arc::new(ChiaMessageFilter {
+                msg_type: Some(ProtocolMessageTypes::NewPeakWallet),
+                id: None,
+                custom_fn: None,
+            }),
+            Arc::new(NewPeakWalletHandle { peak }),
+        )),
+    )])
+}
+
+/// Keeps the latest peak of the node in `WalletClient::peak`
+pub struct NewPeakWalletHandle {
+    pub peak: Arc<RwLock<Option<NewPeakWallet>>>,
+}
+#[async_trait]
+impl MessageHandler for NewPeakWalletHandle {
+    async fn handle(
+        &self,
+        msg: Arc<ChiaMessage>,
+        peer_id: Arc<Bytes32>,
+        peers: PeerMap,
+    ) -> Result<(), Error> {
+        let peer = peers.read().await.get(&peer_id).cloned();
+        let protocol_version = if let Some(peer) = peer.as_ref() {
+            *peer.protocol_version.read().await
+        } else {
+            ChiaProtocolVersion::default()
+        };
+        let peak = NewPeakWallet::from_bytes(&mut Cursor::new(&msg.data), protocol_version)?;
+        *self.peak.write().await = Some(peak);
+        Ok(())
+    }
+
+    fn in_order(&self) -> bool {
+        true
+    }
 }
 
-fn handles() -> HashMap<Uuid, Arc<ChiaMessageHandler>> {
-    HashMap::from([])
+#[test]
+fn test_apply_coin_state_update() {
+    use dg_xch_core::blockchain::coin::Coin;
+    let coin = |amount: u64| Coin {
+        parent_coin_info: Bytes32::default(),
+        puzzle_hash: Bytes32::default(),
+        amount,
+    };
+    let state = |amount: u64, created_height: Option<u32>, spent_height: Option<u32>| CoinState {
+        coin: coin(amount),
+        spent_height,
+        created_height,
+    };
+    let mut coin_states = HashMap::new();
+    apply_coin_state_update(
+        &mut coin_states,
+        &CoinStateUpdate {
+            height: 12,
+            fork_height: 11,
+            peak_hash: Bytes32::default(),
+            items: vec![
+                state(1, Some(5), None),
+                state(2, Some(5), Some(12)),
+                state(3, Some(12), None),
+            ],
+        },
+    );
+    assert_eq!(coin_states.len(), 3);
+    // Reorg back to height 10, replacing block 12 with a block that only spends coin 1
+    apply_coin_state_update(
+        &mut coin_states,
+        &CoinStateUpdate {
+            height: 11,
+            fork_height: 10,
+            peak_hash: Bytes32::default(),
+            items: vec![state(1, Some(5), Some(11))],
+        },
+    );
+    assert_eq!(coin_states.len(), 2);
+    assert_eq!(
+        coin_states.get(&coin(1).name()),
+        Some(&state(1, Some(5), Some(11)))
+    );
+    assert_eq!(
+        coin_states.get(&coin(2).name()),
+        Some(&state(2, Some(5), None))
+    );
+    assert!(!coin_states.contains_key(&coin(3).name()));
 }
@@ -2,10 +2,14 @@ use crate::blockchain::end_of_subslot_bundle::EndOfSubSlotBundle;
 use crate::blockchain::foliage::Foliage;
 use crate::blockchain::foliage_transaction_block::FoliageTransactionBlock;
 use crate::blockchain::reward_chain_block::RewardChainBlock;
+use crate::blockchain::sized_bytes::Bytes32;
 use crate::blockchain::transactions_info::TransactionsInfo;
 use crate::blockchain::vdf_proof::VdfProof;
+use crate::utils::hash_256;
 use dg_xch_macros::ChiaSerial;
+use dg_xch_serialize::{ChiaProtocolVersion, ChiaSerialize};
 use serde::{Deserialize, Serialize};
+use std::io::Error;
 
 #[derive(ChiaSerial, Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
 pub struct HeaderBlock {
@@ -21,3 +25,9 @@ pub struct HeaderBlock {
     pub transactions_filter: Vec<u8>,
     pub transactions_info: Option<TransactionsInfo>,
 }
+impl HeaderBlock {
+    /// Hash of the foliage, the same as the header hash of the full block.
+    pub fn header_hash(&self) -> Result<Bytes32, Error> {
+        Ok(hash_256(self.foliage.to_bytes(ChiaProtocolVersion::default())?).into())
+    }
+}
@@ -3,6 +3,7 @@ pub mod coinbase;
 pub mod constants;
 pub mod overrides;
 pub mod pot_iterations;
+pub mod weight_proof;
 
 pub const CREATE_COIN_COST: u64 = 1_800_000;
 pub const AGG_SIG_COST: u64 = 1_200_000;
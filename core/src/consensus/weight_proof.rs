@@ -0,0 +1,642 @@
+use crate::blockchain::challenge_chain_subslot::ChallengeChainSubSlot;
+use crate::blockchain::header_block::HeaderBlock;
+use crate::blockchain::reward_chain_subslot::RewardChainSubSlot;
+use crate::blockchain::sized_bytes::Bytes32;
+use crate::blockchain::sub_epoch_summary::SubEpochSummary;
+use crate::blockchain::vdf_info::VdfInfo;
+use crate::blockchain::weight_proof::{SubEpochChallengeSegment, SubEpochData, WeightProof};
+use crate::consensus::constants::ConsensusConstants;
+use crate::consensus::pot_iterations::is_overflow_block;
+use crate::protocols::full_node::{RequestProofOfWeight, RespondProofOfWeight};
+use crate::utils::hash_256;
+use dg_xch_serialize::{ChiaProtocolVersion, ChiaSerialize};
+use log::debug;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+fn std_hash<T: ChiaSerialize>(value: &T) -> Result<Bytes32, Error> {
+    Ok(hash_256(value.to_bytes(ChiaProtocolVersion::default())?).into())
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Peak of the recent chain of a weight proof that passed `validate_weight_proof`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatedWeightProof {
+    pub summaries: Vec<SubEpochSummary>,
+    pub sub_epoch_weights: Vec<u128>,
+    pub peak_hash: Bytes32,
+    pub peak_height: u32,
+    pub peak_weight: u128,
+    /// Set once a `WeightProofVerifier` checked the VDFs and proofs of space, until then the
+    /// weight is only what the peer claims
+    pub verified: bool,
+}
+
+/// Checks the VDFs and proofs of space of the sampled segments and the recent chain, which
+/// `validate_weight_proof` leaves out.
+pub trait WeightProofVerifier {
+    fn verify(
+        &self,
+        constants: &ConsensusConstants,
+        weight_proof: &WeightProof,
+    ) -> Result<(), Error>;
+}
+
+/// Rebuilds the sub epoch summaries chained from the genesis challenge, together with the
+/// weight of the chain at the start of every sub epoch.
+pub fn map_sub_epoch_summaries(
+    constants: &ConsensusConstants,
+    sub_epochs: &[SubEpochData],
+) -> Result<(Vec<SubEpochSummary>, Vec<u128>), Error> {
+    let mut ses_hash = constants.genesis_challenge;
+    let mut curr_difficulty = u128::from(constants.difficulty_starting);
+    let mut total_weight = 0u128;
+    let mut summaries = Vec::with_capacity(sub_epochs.len());
+    let mut sub_epoch_weights = Vec::with_capacity(sub_epochs.len());
+    for (idx, data) in sub_epochs.iter().enumerate() {
+        let ses = SubEpochSummary {
+            prev_subepoch_summary_hash: ses_hash,
+            reward_chain_hash: data.reward_chain_hash,
+            num_blocks_overflow: data.num_blocks_overflow,
+            new_difficulty: data.new_difficulty,
+            new_sub_slot_iters: data.new_sub_slot_iters,
+        };
+        if let Some(next) = sub_epochs.get(idx + 1) {
+            // Overflow blocks of a sub epoch are counted in the one after it
+            let delta = if idx > 0 { data.num_blocks_overflow } else { 0 };
+            sub_epoch_weights.push(total_weight + curr_difficulty);
+            total_weight += curr_difficulty
+                * (u128::from(constants.sub_epoch_blocks) + u128::from(next.num_blocks_overflow)
+                    - u128::from(delta));
+        }
+        if let Some(new_difficulty) = data.new_difficulty {
+            curr_difficulty = u128::from(new_difficulty);
+        }
+        ses_hash = std_hash(&ses)?;
+        summaries.push(ses);
+    }
+    sub_epoch_weights.push(total_weight + curr_difficulty);
+    Ok((summaries, sub_epoch_weights))
+}
+
+/// Hash and height of the last sub epoch summary included in `recent_chain`.
+pub fn last_ses_hash(
+    constants: &ConsensusConstants,
+    recent_chain: &[HeaderBlock],
+) -> Option<(Bytes32, u32)> {
+    let start = recent_chain
+        .iter()
+        .rposition(|b| b.reward_chain_block.height % constants.sub_epoch_blocks == 0)?;
+    // The summary is in the first sub slot that ends after the sub epoch boundary
+    recent_chain[start..].iter().find_map(|block| {
+        block
+            .finished_sub_slots
+            .iter()
+            .find_map(|slot| slot.challenge_chain.subepoch_summary_hash)
+            .map(|hash| (hash, block.reward_chain_block.height))
+    })
+}
+
+/// Difficulty and sub slot iterations of the sub epoch at `idx`.
+#[must_use]
+pub fn sub_epoch_difficulty_and_ssi(
+    constants: &ConsensusConstants,
+    idx: usize,
+    summaries: &[SubEpochSummary],
+) -> (u64, u64) {
+    summaries[..idx.min(summaries.len())]
+        .iter()
+        .rev()
+        .find_map(|ses| {
+            ses.new_sub_slot_iters.map(|ssi| {
+                (
+                    ses.new_difficulty.unwrap_or(constants.difficulty_starting),
+                    ssi,
+                )
+            })
+        })
+        .unwrap_or((
+            constants.difficulty_starting,
+            constants.sub_slot_iters_starting,
+        ))
+}
+
+/// Checks that the summaries of the weight proof chain up to the last summary in the recent
+/// chain, and that they add up to the weight of the recent chain.
+pub fn validate_sub_epoch_summaries(
+    constants: &ConsensusConstants,
+    weight_proof: &WeightProof,
+) -> Result<(Vec<SubEpochSummary>, Vec<u128>), Error> {
+    let (last_ses_hash, last_ses_height) =
+        last_ses_hash(constants, &weight_proof.recent_chain_data).ok_or_else(|| {
+            invalid("Failed to find the last sub epoch summary in the recent chain".to_string())
+        })?;
+    if weight_proof.sub_epochs.len() < 2 {
+        return Err(invalid(format!(
+            "Expected at least 2 sub epochs, found {}",
+            weight_proof.sub_epochs.len()
+        )));
+    }
+    let (summaries, sub_epoch_weights) =
+        map_sub_epoch_summaries(constants, &weight_proof.sub_epochs)?;
+    let last = &summaries[summaries.len() - 1];
+    let ses_end_height = (summaries.len() as u32 - 1) * constants.sub_epoch_blocks
+        + u32::from(last.num_blocks_overflow)
+        - 1;
+    let end_weight = weight_proof
+        .recent_chain_data
+        .iter()
+        .find(|b| b.reward_chain_block.height == ses_end_height)
+        .map(|b| b.reward_chain_block.weight)
+        .ok_or_else(|| {
+            invalid(format!(
+                "Recent chain does not contain the sub epoch end at height {ses_end_height}"
+            ))
+        })?;
+    // The start weight of the last sub epoch counts its first block, which is the first one at
+    // the new difficulty
+    let difficulty = summaries
+        .iter()
+        .rev()
+        .find_map(|ses| ses.new_difficulty)
+        .unwrap_or(constants.difficulty_starting);
+    let ses_end_weight = sub_epoch_weights[sub_epoch_weights.len() - 1] - u128::from(difficulty);
+    if end_weight != ses_end_weight {
+        return Err(invalid(format!(
+            "Sub epoch weight {ses_end_weight} does not match block weight {end_weight} at \
+            height {ses_end_height}"
+        )));
+    }
+    if std_hash(last)? != last_ses_hash {
+        return Err(invalid(format!(
+            "Sub epoch summary hash does not match the summary at height {last_ses_height}"
+        )));
+    }
+    Ok((summaries, sub_epoch_weights))
+}
+
+/// Reward chain sub slot that was included in the summary of `segment.sub_epoch_n`, rebuilt
+/// from the first challenge segment of the sub epoch.
+pub fn segment_rc_sub_slot(
+    constants: &ConsensusConstants,
+    segment: &SubEpochChallengeSegment,
+    summaries: &[SubEpochSummary],
+    curr_ssi: u64,
+) -> Result<RewardChainSubSlot, Error> {
+    let n = segment.sub_epoch_n as usize;
+    let ses = n
+        .checked_sub(1)
+        .and_then(|i| summaries.get(i))
+        .ok_or_else(|| invalid(format!("No summary before sub epoch {n}")))?;
+    let slots = &segment.sub_slots;
+    let first_idx = slots
+        .iter()
+        .position(|s| s.cc_slot_end.is_none())
+        .filter(|idx| *idx > 0)
+        .ok_or_else(|| invalid(format!("No sub slot before the challenge of sub epoch {n}")))?;
+    let signage_point_index = slots[first_idx].signage_point_index.ok_or_else(|| {
+        invalid(format!(
+            "Missing signage point index in challenge of sub epoch {n}"
+        ))
+    })?;
+    let overflow = is_overflow_block(constants, signage_point_index)?;
+    let mut slots_n = 1;
+    let mut ses_hash = Some(std_hash(ses)?);
+    let mut new_sub_slot_iters = ses.new_sub_slot_iters;
+    let mut new_difficulty = ses.new_difficulty;
+    if overflow && first_idx >= 2 {
+        if slots[first_idx - 2].cc_slot_end.is_none() {
+            slots_n = 2;
+        } else if slots[first_idx - 1].cc_slot_end.is_some() {
+            ses_hash = None;
+            new_sub_slot_iters = None;
+            new_difficulty = None;
+        }
+    }
+    let mut idx = first_idx;
+    loop {
+        if slots[idx].cc_slot_end.is_some() {
+            slots_n -= 1;
+            if slots_n == 0 {
+                break;
+            }
+        }
+        idx = idx
+            .checked_sub(1)
+            .ok_or_else(|| invalid(format!("Missing end of slot before sub epoch {n}")))?;
+    }
+    let sub_slot = &slots[idx];
+    let cc_slot_end_info = sub_slot
+        .cc_slot_end_info
+        .ok_or_else(|| invalid(format!("Missing challenge slot end in sub epoch {n}")))?;
+    let rc_slot_end_info = segment
+        .rc_slot_end_info
+        .ok_or_else(|| invalid(format!("Missing reward slot end in sub epoch {n}")))?;
+    let (cc_vdf_info, icc_sub_slot_hash) = if idx == 0 {
+        (
+            cc_slot_end_info,
+            sub_slot
+                .icc_slot_end_info
+                .as_ref()
+                .map(std_hash)
+                .transpose()?,
+        )
+    } else {
+        // Only the first sub slot of the segment can carry the summary
+        ses_hash = None;
+        new_sub_slot_iters = None;
+        new_difficulty = None;
+        let icc_sub_slot_hash = sub_slot
+            .icc_slot_end_info
+            .map(|info| {
+                std_hash(&VdfInfo {
+                    challenge: info.challenge,
+                    number_of_iterations: curr_ssi,
+                    output: info.output,
+                })
+            })
+            .transpose()?;
+        (
+            VdfInfo {
+                challenge: cc_slot_end_info.challenge,
+                number_of_iterations: curr_ssi,
+                output: cc_slot_end_info.output,
+            },
+            icc_sub_slot_hash,
+        )
+    };
+    let cc_sub_slot = ChallengeChainSubSlot {
+        challenge_chain_end_of_slot_vdf: cc_vdf_info,
+        infused_challenge_chain_sub_slot_hash: icc_sub_slot_hash,
+        subepoch_summary_hash: ses_hash,
+        new_sub_slot_iters,
+        new_difficulty,
+    };
+    Ok(RewardChainSubSlot {
+        end_of_slot_vdf: rc_slot_end_info,
+        challenge_chain_sub_slot_hash: std_hash(&cc_sub_slot)?,
+        infused_challenge_chain_sub_slot_hash: icc_sub_slot_hash,
+        deficit: constants.min_blocks_per_challenge_block,
+    })
+}
+
+/// Challenge and signage point hash the proof of space of the challenge block at `idx` of
+/// `segment` was created for. The challenge is the hash of the last challenge chain sub slot
+/// before the block, an overflow block uses the challenge of the sub slot before that one.
+pub fn segment_block_challenge(
+    constants: &ConsensusConstants,
+    segment: &SubEpochChallengeSegment,
+    idx: usize,
+    summaries: &[SubEpochSummary],
+    first_in_sub_epoch: bool,
+) -> Result<(Bytes32, Bytes32), Error> {
+    let n = segment.sub_epoch_n as usize;
+    let slots = &segment.sub_slots;
+    let slot = slots
+        .get(idx)
+        .ok_or_else(|| invalid(format!("No sub slot {idx} in segment of sub epoch {n}")))?;
+    let signage_point_index = slot.signage_point_index.ok_or_else(|| {
+        invalid(format!(
+            "Missing signage point index in challenge of sub epoch {n}"
+        ))
+    })?;
+    let cc_sub_slot_hash = if first_in_sub_epoch && n == 0 && idx == 0 {
+        constants.genesis_challenge
+    } else {
+        let end_idx = slots[..idx]
+            .iter()
+            .rposition(|s| s.cc_slot_end_info.is_some())
+            .ok_or_else(|| {
+                invalid(format!(
+                    "Missing end of slot before challenge {idx} of sub epoch {n}"
+                ))
+            })?;
+        let end = &slots[end_idx];
+        // Only the first sub slot of the sub epoch carries the summary
+        let ses = if first_in_sub_epoch && end_idx == 0 {
+            n.checked_sub(1).and_then(|i| summaries.get(i))
+        } else {
+            None
+        };
+        std_hash(&ChallengeChainSubSlot {
+            challenge_chain_end_of_slot_vdf: end
+                .cc_slot_end_info
+                .ok_or_else(|| invalid(format!("Missing challenge slot end in sub epoch {n}")))?,
+            infused_challenge_chain_sub_slot_hash: end
+                .icc_slot_end_info
+                .as_ref()
+                .map(std_hash)
+                .transpose()?,
+            subepoch_summary_hash: ses.map(std_hash).transpose()?,
+            new_sub_slot_iters: ses.and_then(|ses| ses.new_sub_slot_iters),
+            new_difficulty: ses.and_then(|ses| ses.new_difficulty),
+        })?
+    };
+    let challenge = if signage_point_index > 0 && is_overflow_block(constants, signage_point_index)?
+    {
+        idx.checked_sub(1)
+            .and_then(|i| slots[i].cc_slot_end_info)
+            .map(|info| info.challenge)
+            .ok_or_else(|| {
+                invalid(format!(
+                    "Missing sub slot before overflow challenge {idx} of sub epoch {n}"
+                ))
+            })?
+    } else {
+        cc_sub_slot_hash
+    };
+    let cc_sp_hash = match &slot.cc_sp_vdf_info {
+        Some(info) => std_hash(&info.output)?,
+        None => cc_sub_slot_hash,
+    };
+    Ok((challenge, cc_sp_hash))
+}
+
+/// Checks the challenge segments against the summaries, segments have to be ordered by sub
+/// epoch and every sub epoch has to start at the reward chain hash of its summary.
+/// VDF and proof of space verification is not done here.
+pub fn validate_sub_epoch_segments(
+    constants: &ConsensusConstants,
+    segments: &[SubEpochChallengeSegment],
+    summaries: &[SubEpochSummary],
+) -> Result<(), Error> {
+    let mut prev_sub_epoch = None;
+    for segment in segments {
+        let n = segment.sub_epoch_n as usize;
+        if n >= summaries.len() {
+            return Err(invalid(format!(
+                "Segment for sub epoch {n} but there are only {} summaries",
+                summaries.len()
+            )));
+        }
+        if prev_sub_epoch.is_some_and(|prev| n < prev) {
+            return Err(invalid(format!(
+                "Segment for sub epoch {n} is out of order"
+            )));
+        }
+        let mut challenges = 0;
+        for slot in segment
+            .sub_slots
+            .iter()
+            .filter(|s| s.proof_of_space.is_some())
+        {
+            let signage_point_index = slot.signage_point_index.ok_or_else(|| {
+                invalid(format!(
+                    "Missing signage point index in segment of sub epoch {n}"
+                ))
+            })?;
+            is_overflow_block(constants, signage_point_index)?;
+            challenges += 1;
+        }
+        if challenges == 0 {
+            return Err(invalid(format!(
+                "Segment of sub epoch {n} has no challenge block"
+            )));
+        }
+        if prev_sub_epoch != Some(n) {
+            let rc_sub_slot_hash = if n == 0 {
+                constants.genesis_challenge
+            } else {
+                let (_, curr_ssi) = sub_epoch_difficulty_and_ssi(constants, n, summaries);
+                std_hash(&segment_rc_sub_slot(
+                    constants, segment, summaries, curr_ssi,
+                )?)?
+            };
+            if summaries[n].reward_chain_hash != rc_sub_slot_hash {
+                return Err(invalid(format!(
+                    "Reward chain hash of sub epoch {n} does not match its summary"
+                )));
+            }
+        }
+        prev_sub_epoch = Some(n);
+    }
+    Ok(())
+}
+
+/// Difficulty and sub slot iterations of every block of the recent chain. Both change at the
+/// block whose finished sub slots include a sub epoch summary, blocks before the first included
+/// summary belong to the sub epoch of that summary.
+pub fn recent_chain_difficulty_and_ssi(
+    constants: &ConsensusConstants,
+    recent_chain: &[HeaderBlock],
+    summaries: &[SubEpochSummary],
+) -> Result<Vec<(u64, u64)>, Error> {
+    let hashes = summaries
+        .iter()
+        .map(std_hash)
+        .collect::<Result<Vec<_>, _>>()?;
+    let summary_index = |hash: &Bytes32| {
+        hashes.iter().position(|h| h == hash).ok_or_else(|| {
+            invalid(format!(
+                "Recent chain includes unknown sub epoch summary {hash}"
+            ))
+        })
+    };
+    let included = |block: &HeaderBlock| {
+        block
+            .finished_sub_slots
+            .iter()
+            .filter_map(|slot| slot.challenge_chain.subepoch_summary_hash)
+            .collect::<Vec<_>>()
+    };
+    let first = recent_chain
+        .iter()
+        .find_map(|block| included(block).first().copied())
+        .map(|hash| summary_index(&hash))
+        .transpose()?
+        .unwrap_or(summaries.len());
+    let mut curr = sub_epoch_difficulty_and_ssi(constants, first, summaries);
+    let mut result = Vec::with_capacity(recent_chain.len());
+    for block in recent_chain {
+        for hash in included(block) {
+            curr = sub_epoch_difficulty_and_ssi(constants, summary_index(&hash)? + 1, summaries);
+        }
+        result.push(curr);
+    }
+    Ok(result)
+}
+
+/// Checks that the recent chain is a single chain of blocks with growing iterations, where every
+/// block adds the difficulty of its sub epoch to the weight.
+pub fn validate_recent_chain(
+    constants: &ConsensusConstants,
+    recent_chain: &[HeaderBlock],
+    summaries: &[SubEpochSummary],
+) -> Result<(), Error> {
+    let difficulties = recent_chain_difficulty_and_ssi(constants, recent_chain, summaries)?;
+    let mut prev: Option<(&HeaderBlock, Bytes32)> = None;
+    for (block, (difficulty, _)) in recent_chain.iter().zip(difficulties) {
+        let rcb = &block.reward_chain_block;
+        is_overflow_block(constants, rcb.signage_point_index)?;
+        if let Some((prev_block, prev_hash)) = prev {
+            let prev_rcb = &prev_block.reward_chain_block;
+            if rcb.height != prev_rcb.height + 1 {
+                return Err(invalid(format!(
+                    "Block at height {} follows height {}",
+                    rcb.height, prev_rcb.height
+                )));
+            }
+            if block.foliage.prev_block_hash != prev_hash {
+                return Err(invalid(format!(
+                    "Block at height {} does not extend the previous block",
+                    rcb.height
+                )));
+            }
+            if rcb.total_iters <= prev_rcb.total_iters {
+                return Err(invalid(format!(
+                    "Block at height {} does not add iterations",
+                    rcb.height
+                )));
+            }
+            if rcb.weight.checked_sub(prev_rcb.weight) != Some(u128::from(difficulty)) {
+                return Err(invalid(format!(
+                    "Block at height {} has weight {} instead of {} plus the difficulty {difficulty}",
+                    rcb.height, rcb.weight, prev_rcb.weight
+                )));
+            }
+        }
+        prev = Some((block, block.header_hash()?));
+    }
+    Ok(())
+}
+
+/// Validates the summaries, challenge segments and recent chain of a weight proof. The result
+/// is not `verified`, see `WeightProofVerifier`.
+pub fn validate_weight_proof(
+    constants: &ConsensusConstants,
+    weight_proof: &WeightProof,
+) -> Result<ValidatedWeightProof, Error> {
+    let (summaries, sub_epoch_weights) = validate_sub_epoch_summaries(constants, weight_proof)?;
+    validate_sub_epoch_segments(constants, &weight_proof.sub_epoch_segments, &summaries)?;
+    validate_recent_chain(constants, &weight_proof.recent_chain_data, &summaries)?;
+    let peak = weight_proof
+        .recent_chain_data
+        .last()
+        .ok_or_else(|| invalid("Weight proof has no recent chain".to_string()))?;
+    Ok(ValidatedWeightProof {
+        summaries,
+        sub_epoch_weights,
+        peak_hash: peak.header_hash()?,
+        peak_height: peak.reward_chain_block.height,
+        peak_weight: peak.reward_chain_block.weight,
+        verified: false,
+    })
+}
+
+/// Height up to which the chain of `received` is known to match `local`, both starting at
+/// genesis. The last received summary is not compared and the last three matching ones are
+/// not trusted, two chains can share a summary and still differ in blocks until the next sub
+/// epoch ends.
+#[must_use]
+pub fn fork_point(
+    constants: &ConsensusConstants,
+    local: &[SubEpochSummary],
+    received: &[SubEpochSummary],
+) -> u32 {
+    let matching = local
+        .iter()
+        .zip(received.iter().take(received.len().saturating_sub(1)))
+        .take_while(|(l, r)| l == r)
+        .count();
+    if matching > 3 {
+        // Summaries are included at or after the start of their sub epoch
+        (matching as u32 - 3) * constants.sub_epoch_blocks
+    } else {
+        0
+    }
+}
+
+/// Header chain of a light client, moved to the heaviest weight proof received from peers.
+/// Without a `verifier` only the first weight proof is taken, a heavier one could claim any
+/// weight.
+pub struct WeightProofTracker {
+    pub constants: Arc<ConsensusConstants>,
+    pub verifier: Option<Arc<dyn WeightProofVerifier + Send + Sync>>,
+    pub summaries: Vec<SubEpochSummary>,
+    pub peak: Option<ValidatedWeightProof>,
+}
+impl WeightProofTracker {
+    #[must_use]
+    pub fn new(constants: Arc<ConsensusConstants>) -> Self {
+        Self {
+            constants,
+            verifier: None,
+            summaries: vec![],
+            peak: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_verifier(
+        constants: Arc<ConsensusConstants>,
+        verifier: Arc<dyn WeightProofVerifier + Send + Sync>,
+    ) -> Self {
+        Self {
+            verifier: Some(verifier),
+            ..Self::new(constants)
+        }
+    }
+
+    /// Request for the weight proof of a peak a peer announced.
+    #[must_use]
+    pub fn request(&self, tip: Bytes32, tip_height: u32) -> RequestProofOfWeight {
+        RequestProofOfWeight {
+            total_number_of_blocks: tip_height + 1,
+            tip,
+        }
+    }
+
+    /// Validates the answer to `request` and moves to it when it is heavier than the current
+    /// peak and verified, or when there is no peak yet. Returns the fork point with the
+    /// previous chain, blocks above it must be synced again.
+    pub fn receive(
+        &mut self,
+        request: &RequestProofOfWeight,
+        response: &RespondProofOfWeight,
+    ) -> Result<u32, Error> {
+        if response.tip != request.tip {
+            return Err(invalid(format!(
+                "Received weight proof for {} instead of {}",
+                response.tip, request.tip
+            )));
+        }
+        let mut validated = validate_weight_proof(&self.constants, &response.wp)?;
+        if validated.peak_hash != request.tip {
+            return Err(invalid(format!(
+                "Weight proof ends at {} instead of {}",
+                validated.peak_hash, request.tip
+            )));
+        }
+        if let Some(peak) = &self.peak {
+            if validated.peak_weight <= peak.peak_weight {
+                return Err(invalid(format!(
+                    "Weight proof weight {} is not above the peak weight {}",
+                    validated.peak_weight, peak.peak_weight
+                )));
+            }
+        }
+        if let Some(verifier) = &self.verifier {
+            verifier.verify(&self.constants, &response.wp)?;
+            validated.verified = true;
+        } else if let Some(peak) = &self.peak {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "Keeping peak {} at height {}, no verifier to check the heavier weight proof",
+                    peak.peak_hash, peak.peak_height
+                ),
+            ));
+        }
+        let fork_point = fork_point(&self.constants, &self.summaries, &validated.summaries);
+        debug!(
+            "New weight proof peak {} at height {}, fork point {fork_point}",
+            validated.peak_hash, validated.peak_height
+        );
+        self.summaries.clone_from(&validated.summaries);
+        self.peak = Some(validated);
+        Ok(fork_point)
+    }
+}
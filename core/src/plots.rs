@@ -4,6 +4,7 @@ use crate::clvm::program::Program;
 use crate::constants::{DELAY_PUZZLEHASH_IDENTIFIER, DELAY_TIME_IDENTIFIER};
 use crate::pool::PoolState;
 use crate::traits::SizedBytes;
+use async_trait::async_trait;
 use hex::encode;
 use num_traits::ToPrimitive;
 use serde::{Deserialize, Serialize};
@@ -44,6 +45,12 @@ impl PlotTable {
     }
 }
 
+/// Positional reads for plots that are not local files, such as plots on network storage.
+#[async_trait]
+pub trait PlotRangeReader: Send + Sync {
+    async fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), Error>;
+}
+
 pub trait PlotFile<'a, F: AsyncSeek + AsyncRead> {
     fn table_address(&'a self, plot_table: PlotTable) -> u64 {
         match self.header() {
@@ -80,20 +87,49 @@ pub trait PlotFile<'a, F: AsyncSeek + AsyncRead> {
     fn compression_level(&'a self) -> u8 {
         self.header().compression_level()
     }
+    /// The whole plot file when it is memory mapped. Readers copy straight out of it instead
+    /// of locking `file` and seeking, so lookups on the same plot can run in parallel.
+    fn mapped(&'a self) -> Option<&'a [u8]> {
+        None
+    }
+    /// Reader used instead of `file` for remote plots, reads through it do not take the file lock.
+    fn range_reader(&'a self) -> Option<&'a dyn PlotRangeReader> {
+        None
+    }
     //The Interface stuff
     fn header(&'a self) -> &'a PlotHeader;
     fn plot_size(&'a self) -> &'a u64;
-    fn load_p7_park(&'a self, index: u64) -> u128;
+    fn load_p7_park(&'a self, index: u64) -> Result<u128, Error>;
     fn file(&'a self) -> Arc<Mutex<F>>;
 }
 
-#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct PlotMemo {
     pub pool_public_key: Option<Bytes48>,
     pub pool_contract_puzzle_hash: Option<Bytes32>,
     pub farmer_public_key: Bytes48,
     pub local_master_secret_key: Bytes32,
 }
+impl PlotMemo {
+    /// Memo bytes as stored in the plot header, 112 bytes for pool contract plots and 128 bytes
+    /// for pool public key plots. Exactly one of the pool fields must be set.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::with_capacity(128);
+        match (&self.pool_public_key, &self.pool_contract_puzzle_hash) {
+            (Some(pool_public_key), None) => bytes.extend_from_slice(pool_public_key.as_ref()),
+            (None, Some(puzzle_hash)) => bytes.extend_from_slice(puzzle_hash.as_ref()),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Plot memo needs either a pool public key or a pool contract puzzle hash",
+                ));
+            }
+        }
+        bytes.extend_from_slice(self.farmer_public_key.as_ref());
+        bytes.extend_from_slice(self.local_master_secret_key.as_ref());
+        Ok(bytes)
+    }
+}
 impl TryFrom<&[u8]> for PlotMemo {
     type Error = Error;
 
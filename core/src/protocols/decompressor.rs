@@ -0,0 +1,30 @@
+use crate::blockchain::sized_bytes::Bytes32;
+use dg_xch_macros::ChiaSerial;
+use serde::{Deserialize, Serialize};
+
+/// Asks a decompression server for the x pair behind a quality of a compressed plot.
+#[derive(ChiaSerial, Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct RequestDecompressQualities {
+    pub plot_id: Bytes32,
+    pub k: u8,
+    pub compression_level: u8,
+    pub challenge: Bytes32,
+    /// One line point, or two for compression levels 6 to 8
+    pub line_points: Vec<u128>,
+}
+
+/// Asks a decompression server for the full proof of a compressed proof read from a plot.
+#[derive(ChiaSerial, Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct RequestDecompressProof {
+    pub plot_id: Bytes32,
+    pub k: u8,
+    pub compression_level: u8,
+    pub compressed_proof: Vec<u64>,
+}
+
+/// Answer to both decompression requests, the x pair or the full proof unless `error` is set.
+#[derive(ChiaSerial, Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct RespondDecompression {
+    pub x_values: Vec<u64>,
+    pub error: Option<String>,
+}
@@ -9,6 +9,7 @@ use crate::blockchain::reward_chain_subslot::RewardChainSubSlot;
 use crate::blockchain::sized_bytes::{Bytes32, Bytes48, Bytes96};
 use crate::config::PoolWalletConfig;
 use crate::protocols::error::RecentErrors;
+use crate::protocols::plot_sync::HarvesterPlotSync;
 use crate::protocols::PeerMap;
 use blst::min_pk::SecretKey;
 use dg_xch_macros::ChiaSerial;
@@ -478,6 +479,36 @@ pub struct PlotCounts {
     pub invalid_plot_count: Arc<std::sync::atomic::AtomicI64>,
     pub total_plot_space: Arc<std::sync::atomic::AtomicI64>,
 }
+impl PlotCounts {
+    /// Sets the counts to the totals of the plot inventories synced by all harvesters.
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn update_from_syncs<S: std::hash::BuildHasher>(
+        &self,
+        syncs: &HashMap<Bytes32, HarvesterPlotSync, S>,
+    ) {
+        let (mut og, mut nft, mut compressed, mut invalid, mut space) = (0, 0, 0, 0, 0u64);
+        for sync in syncs.values() {
+            for plot in sync.plots.values() {
+                if plot.pool_public_key.is_some() {
+                    og += 1;
+                } else {
+                    nft += 1;
+                }
+                if plot.compression_level.unwrap_or_default() > 0 {
+                    compressed += 1;
+                }
+            }
+            invalid += sync.invalid.len() as i64;
+            space += sync.total_plot_size;
+        }
+        self.og_plot_count.store(og, Ordering::Relaxed);
+        self.nft_plot_count.store(nft, Ordering::Relaxed);
+        self.compressed_plot_count
+            .store(compressed, Ordering::Relaxed);
+        self.invalid_plot_count.store(invalid, Ordering::Relaxed);
+        self.total_plot_space.store(space as i64, Ordering::Relaxed);
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct SerialPlotCounts {
@@ -598,6 +629,8 @@ pub struct FarmerSharedState<T> {
     pub missing_plotnft_info: Arc<RwLock<HashMap<Bytes32, Bytes48>>>,
     pub upstream_handshake: Arc<RwLock<Option<Handshake>>>,
     pub plot_counts: Arc<PlotCounts>,
+    /// Plot inventories received from plot syncs, keyed by harvester peer id
+    pub harvester_plot_syncs: Arc<RwLock<HashMap<Bytes32, HarvesterPlotSync>>>,
     pub fullnode_state: Arc<RwLock<Option<BlockchainState>>>,
     pub data: Arc<T>,
     pub signal: Arc<AtomicBool>,
@@ -631,6 +664,7 @@ impl<T: Default> Default for FarmerSharedState<T> {
             missing_plotnft_info: Arc::new(Default::default()),
             upstream_handshake: Arc::new(Default::default()),
             plot_counts: Arc::new(Default::default()),
+            harvester_plot_syncs: Arc::new(Default::default()),
             fullnode_state: Arc::new(Default::default()),
             data: Arc::new(T::default()),
             signal: Arc::new(Default::default()),
@@ -8,7 +8,7 @@ use std::io::Error;
 #[cfg(feature = "metrics")]
 use std::sync::Arc;
 #[cfg(feature = "metrics")]
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 #[derive(ChiaSerial, Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
 pub struct PoolDifficulty {
@@ -471,9 +471,27 @@ pub struct PlotSyncResponse {
 
 use dg_xch_serialize::ChiaProtocolVersion;
 #[cfg(feature = "metrics")]
-use prometheus::core::{AtomicU64, GenericGauge};
+use prometheus::core::{AtomicU64, GenericCounterVec, GenericGauge};
 #[cfg(feature = "metrics")]
-use prometheus::Registry;
+use prometheus::{Histogram, HistogramOpts, HistogramVec, Opts, Registry};
+
+#[cfg(feature = "metrics")]
+const LOOKUP_LATENCY_BUCKETS: [f64; 11] = [
+    0.01,
+    0.05,
+    0.1,
+    0.25,
+    0.5,
+    1.0,
+    2.5,
+    5.0,
+    10.0,
+    30.0,
+    f64::INFINITY,
+];
+/// Chia warns about plot lookups slower than this, as they risk missing the signage point window
+#[cfg(feature = "metrics")]
+pub const DEFAULT_SLOW_LOOKUP_THRESHOLD: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Default, Clone)]
 pub struct HarvesterState {
@@ -497,6 +515,13 @@ pub struct HarvesterMetrics {
     pub og_plot_count: Option<GenericGauge<AtomicU64>>,
     pub nft_plot_count: Option<GenericGauge<AtomicU64>>,
     pub compressed_plot_count: Option<GenericGauge<AtomicU64>>,
+    /// Time to look up a single plot, labeled by compression level and plot directory
+    pub plot_lookup_latency: Option<HistogramVec>,
+    /// Time to look up every plot that passed the filter for a signage point
+    pub signage_point_lookup_latency: Option<Histogram>,
+    /// Lookups slower than `slow_lookup_threshold`, labeled by plot directory
+    pub slow_plot_lookups: Option<GenericCounterVec<AtomicU64>>,
+    pub slow_lookup_threshold: Duration,
 }
 #[cfg(feature = "metrics")]
 impl HarvesterMetrics {
@@ -534,6 +559,40 @@ impl HarvesterMetrics {
                 registry.register(Box::new(g.clone())).unwrap_or(());
                 Some(g)
             });
+        let plot_lookup_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "plot_lookup_latency",
+                "Time in seconds to look up proofs in a plot",
+            )
+            .buckets(LOOKUP_LATENCY_BUCKETS.to_vec()),
+            &["compression_level", "directory"],
+        )
+        .map_or(None, |h: HistogramVec| {
+            registry.register(Box::new(h.clone())).unwrap_or(());
+            Some(h)
+        });
+        let signage_point_lookup_latency = Histogram::with_opts(
+            HistogramOpts::new(
+                "signage_point_lookup_latency",
+                "Time in seconds to look up proofs in all plots for a signage point",
+            )
+            .buckets(LOOKUP_LATENCY_BUCKETS.to_vec()),
+        )
+        .map_or(None, |h: Histogram| {
+            registry.register(Box::new(h.clone())).unwrap_or(());
+            Some(h)
+        });
+        let slow_plot_lookups = GenericCounterVec::new(
+            Opts::new(
+                "slow_plot_lookups",
+                "Plot lookups slower than the slow lookup threshold",
+            ),
+            &["directory"],
+        )
+        .map_or(None, |c: GenericCounterVec<AtomicU64>| {
+            registry.register(Box::new(c.clone())).unwrap_or(());
+            Some(c)
+        });
         HarvesterMetrics {
             start_time: Arc::new(Instant::now()),
             uptime,
@@ -541,6 +600,16 @@ impl HarvesterMetrics {
             og_plot_count,
             nft_plot_count,
             compressed_plot_count,
+            plot_lookup_latency,
+            signage_point_lookup_latency,
+            slow_plot_lookups,
+            slow_lookup_threshold: DEFAULT_SLOW_LOOKUP_THRESHOLD,
         }
     }
+
+    #[must_use]
+    pub fn with_slow_lookup_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_lookup_threshold = threshold;
+        self
+    }
 }
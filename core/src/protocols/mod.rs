@@ -1,8 +1,10 @@
+pub mod decompressor;
 pub mod error;
 pub mod farmer;
 pub mod full_node;
 pub mod harvester;
 pub mod introducer;
+pub mod plot_sync;
 pub mod pool;
 pub mod shared;
 pub mod timelord;
@@ -171,6 +173,11 @@ pub enum ProtocolMessageTypes {
     MempoolItemsRemoved = 105,
     RequestCostInfo = 106,
     RespondCostInfo = 107,
+
+    //Decompression protocol (harvester < -> decompression server), not part of Chia's protocol
+    RequestDecompressQualities = 200,
+    RequestDecompressProof = 201,
+    RespondDecompression = 202,
 }
 impl From<u8> for ProtocolMessageTypes {
     #[allow(clippy::too_many_lines)]
@@ -483,11 +490,91 @@ impl From<u8> for ProtocolMessageTypes {
             i if i == ProtocolMessageTypes::RespondCostInfo as u8 => {
                 ProtocolMessageTypes::RespondCostInfo
             }
+            i if i == ProtocolMessageTypes::RequestDecompressQualities as u8 => {
+                ProtocolMessageTypes::RequestDecompressQualities
+            }
+            i if i == ProtocolMessageTypes::RequestDecompressProof as u8 => {
+                ProtocolMessageTypes::RequestDecompressProof
+            }
+            i if i == ProtocolMessageTypes::RespondDecompression as u8 => {
+                ProtocolMessageTypes::RespondDecompression
+            }
             _ => ProtocolMessageTypes::Unknown,
         }
     }
 }
 
+impl ProtocolMessageTypes {
+    /// Whether a peer sends this message instead of the response to a request it can not answer.
+    #[must_use]
+    pub fn is_reject(self) -> bool {
+        matches!(
+            self,
+            ProtocolMessageTypes::RejectBlock
+                | ProtocolMessageTypes::RejectBlocks
+                | ProtocolMessageTypes::RejectPuzzleSolution
+                | ProtocolMessageTypes::RejectHeaderRequest
+                | ProtocolMessageTypes::RejectRemovalsRequest
+                | ProtocolMessageTypes::RejectAdditionsRequest
+                | ProtocolMessageTypes::RejectHeaderBlocks
+                | ProtocolMessageTypes::RejectBlockHeaders
+                | ProtocolMessageTypes::RejectPuzzleState
+                | ProtocolMessageTypes::RejectCoinState
+        )
+    }
+
+    /// The messages a peer answers this request with, including its reject message. Empty for
+    /// messages that are not answered.
+    #[must_use]
+    pub fn valid_replies(self) -> &'static [ProtocolMessageTypes] {
+        use ProtocolMessageTypes as T;
+        match self {
+            T::RequestSignatures => &[T::RespondSignatures],
+            T::RequestSignedValues => &[T::SignedValues],
+            T::RequestCompactProofOfTime => &[T::RespondCompactProofOfTime],
+            T::RequestTransaction => &[T::RespondTransaction],
+            T::RequestProofOfWeight => &[T::RespondProofOfWeight],
+            T::RequestBlock => &[T::RespondBlock, T::RejectBlock],
+            T::RequestBlocks => &[T::RespondBlocks, T::RejectBlocks],
+            T::RequestUnfinishedBlock => &[T::RespondUnfinishedBlock],
+            T::RequestSignagePointOrEndOfSubSlot => {
+                &[T::RespondSignagePoint, T::RespondEndOfSubSlot]
+            }
+            T::RequestCompactVdf => &[T::RespondCompactVdf],
+            T::RequestPeers => &[T::RespondPeers],
+            T::RequestPuzzleSolution => &[T::RespondPuzzleSolution, T::RejectPuzzleSolution],
+            T::SendTransaction => &[T::TransactionAck],
+            T::RequestBlockHeader => &[T::RespondBlockHeader, T::RejectHeaderRequest],
+            T::RequestRemovals => &[T::RespondRemovals, T::RejectRemovalsRequest],
+            T::RequestAdditions => &[T::RespondAdditions, T::RejectAdditionsRequest],
+            T::RequestHeaderBlocks => &[
+                T::RespondHeaderBlocks,
+                T::RejectHeaderBlocks,
+                T::RejectBlockHeaders,
+            ],
+            T::RequestPeersIntroducer => &[T::RespondPeersIntroducer],
+            T::RequestPlots => &[T::RespondPlots],
+            T::RegisterInterestInPuzzleHash => &[T::RespondToPhUpdate],
+            T::RegisterInterestInCoin => &[T::RespondToCoinUpdate],
+            T::RequestChildren => &[T::RespondChildren],
+            T::RequestSesHashes => &[T::RespondSesHashes],
+            T::RequestBlockHeaders => &[
+                T::RespondBlockHeaders,
+                T::RejectBlockHeaders,
+                T::RejectHeaderBlocks,
+            ],
+            T::RequestFeeEstimates => &[T::RespondFeeEstimates],
+            T::RequestRemovePuzzleSubscriptions => &[T::RespondRemovePuzzleSubscriptions],
+            T::RequestRemoveCoinSubscriptions => &[T::RespondRemoveCoinSubscriptions],
+            T::RequestPuzzleState => &[T::RespondPuzzleState, T::RejectPuzzleState],
+            T::RequestCoinState => &[T::RespondCoinState, T::RejectCoinState],
+            T::RequestCostInfo => &[T::RespondCostInfo],
+            T::RequestDecompressQualities | T::RequestDecompressProof => &[T::RespondDecompression],
+            _ => &[],
+        }
+    }
+}
+
 impl fmt::Display for ProtocolMessageTypes {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{self:?}")
@@ -498,7 +585,7 @@ pub const INVALID_PROTOCOL_BAN_SECONDS: u8 = 10;
 pub const API_EXCEPTION_BAN_SECONDS: u8 = 10;
 pub const INTERNAL_PROTOCOL_ERROR_BAN_SECONDS: u8 = 10;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum NodeType {
     Unknown = 0,
     FullNode = 1,
@@ -533,6 +620,12 @@ pub trait MessageHandler {
         peer_id: Arc<Bytes32>,
         peers: PeerMap,
     ) -> Result<(), Error>;
+
+    /// Handlers returning true are run by the read loop before the next message is read, so they
+    /// see messages in the order the peer sent them. They must return without waiting on the peer.
+    fn in_order(&self) -> bool {
+        false
+    }
 }
 
 #[derive(ChiaSerial, Debug, Clone)]
@@ -739,9 +832,15 @@ impl ReadStream {
                                         Ok(chia_msg) => {
                                             let msg_arc: Arc<ChiaMessage> = Arc::new(chia_msg);
                                             let mut matched = false;
-                                            for v in self.message_handlers.read().await.values()
-                                                .cloned().collect::<Vec<Arc<ChiaMessageHandler>>>() {
-                                                if v.filter.matches(msg_arc.as_ref()) {
+                                            let handlers = self.message_handlers.read().await.values()
+                                                .cloned().collect::<Vec<Arc<ChiaMessageHandler>>>();
+                                            for v in handlers {
+                                                if v.filter.matches(msg_arc.as_ref()) && v.handle.in_order() {
+                                                    if let Err(e) = v.handle.handle(msg_arc.clone(), self.peer_id.clone(), self.peers.clone()).await {
+                                                        error!("Error Handling Message({:#?}): {e:?}", msg_arc.msg_type);
+                                                    }
+                                                    matched = true;
+                                                } else if v.filter.matches(msg_arc.as_ref()) {
                                                     let msg_arc_c = msg_arc.clone();
                                                     let peer_id = self.peer_id.clone();
                                                     let peers = self.peers.clone();
@@ -0,0 +1,534 @@
+use crate::protocols::harvester::{
+    Plot, PlotSyncDone, PlotSyncError, PlotSyncIdentifier, PlotSyncPathList, PlotSyncPlotList,
+    PlotSyncResponse, PlotSyncStart,
+};
+use crate::protocols::ProtocolMessageTypes;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Plots sent in a single plot sync message
+pub const DEFAULT_PLOT_SYNC_BATCH_SIZE: usize = 300;
+
+/// Order of the messages in a plot sync, every list can be split over several messages
+pub const PLOT_SYNC_ORDER: [ProtocolMessageTypes; 7] = [
+    ProtocolMessageTypes::PlotSyncStart,
+    ProtocolMessageTypes::PlotSyncLoaded,
+    ProtocolMessageTypes::PlotSyncRemoved,
+    ProtocolMessageTypes::PlotSyncInvalid,
+    ProtocolMessageTypes::PlotSyncKeysMissing,
+    ProtocolMessageTypes::PlotSyncDuplicates,
+    ProtocolMessageTypes::PlotSyncDone,
+];
+
+#[repr(i16)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlotSyncErrorCode {
+    Unknown = 0,
+    InvalidState = 1,
+    InvalidIdentifier = 2,
+    InvalidLastSyncId = 3,
+    InvalidConnectionType = 4,
+    PlotAlreadyAvailable = 5,
+    PlotNotAvailable = 6,
+    SyncIdsMatch = 7,
+}
+impl From<i16> for PlotSyncErrorCode {
+    fn from(value: i16) -> Self {
+        match value {
+            1 => PlotSyncErrorCode::InvalidState,
+            2 => PlotSyncErrorCode::InvalidIdentifier,
+            3 => PlotSyncErrorCode::InvalidLastSyncId,
+            4 => PlotSyncErrorCode::InvalidConnectionType,
+            5 => PlotSyncErrorCode::PlotAlreadyAvailable,
+            6 => PlotSyncErrorCode::PlotNotAvailable,
+            7 => PlotSyncErrorCode::SyncIdsMatch,
+            _ => PlotSyncErrorCode::Unknown,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum HarvestingMode {
+    #[default]
+    Cpu = 1,
+    Gpu = 2,
+}
+
+impl PlotSyncError {
+    #[must_use]
+    pub fn new(
+        code: PlotSyncErrorCode,
+        message: String,
+        expected_identifier: Option<PlotSyncIdentifier>,
+    ) -> Self {
+        Self {
+            code: code as i16,
+            message,
+            expected_identifier,
+        }
+    }
+}
+
+impl PlotSyncResponse {
+    /// Builds the answer the farmer sends for every plot sync message.
+    #[must_use]
+    pub fn new(
+        identifier: PlotSyncIdentifier,
+        message_type: ProtocolMessageTypes,
+        result: Result<(), PlotSyncError>,
+    ) -> Self {
+        Self {
+            identifier,
+            message_type: i16::from(message_type as u8),
+            error: result.err(),
+        }
+    }
+}
+
+/// Changes received during a sync, applied to the inventory once the sync is done.
+#[derive(Debug, Clone, Default)]
+struct PlotSyncDelta {
+    loaded: HashMap<String, Plot>,
+    removed: Vec<String>,
+    invalid: Vec<String>,
+    keys_missing: Vec<String>,
+    duplicates: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct CurrentPlotSync {
+    sync_id: u64,
+    next_message_id: u64,
+    expected: ProtocolMessageTypes,
+    /// An initial sync replaces the whole inventory once it is done
+    initial: bool,
+    harvesting_mode: u8,
+    plot_file_count: u32,
+    delta: PlotSyncDelta,
+}
+
+/// Plot inventory of a single harvester, built from the plot sync messages it sends. The
+/// inventory is only updated once a sync is done, a failed sync leaves it untouched.
+#[derive(Debug, Clone, Default)]
+pub struct HarvesterPlotSync {
+    pub plots: HashMap<String, Plot>,
+    pub invalid: Vec<String>,
+    pub keys_missing: Vec<String>,
+    pub duplicates: Vec<String>,
+    pub total_plot_size: u64,
+    pub harvesting_mode: u8,
+    pub plot_file_count: u32,
+    /// Sync id of the last completed sync, 0 before the initial sync is done
+    pub last_sync_id: u64,
+    /// Unix time the last sync completed
+    pub last_sync_time: u64,
+    /// Seconds the harvester reported for the last sync
+    pub last_sync_duration: u64,
+    current: Option<CurrentPlotSync>,
+}
+impl HarvesterPlotSync {
+    #[must_use]
+    pub fn initial_sync_done(&self) -> bool {
+        self.last_sync_id != 0
+    }
+
+    #[must_use]
+    pub fn sync_in_progress(&self) -> bool {
+        self.current.is_some()
+    }
+
+    pub fn process_start(&mut self, start: &PlotSyncStart) -> Result<(), PlotSyncError> {
+        let result = self.start(start);
+        self.finish(result)
+    }
+
+    pub fn process_loaded(&mut self, plots: &PlotSyncPlotList) -> Result<(), PlotSyncError> {
+        let result = self.loaded(plots);
+        self.finish(result)
+    }
+
+    /// Handles the removed, invalid, keys missing and duplicates lists.
+    pub fn process_path_list(
+        &mut self,
+        msg_type: ProtocolMessageTypes,
+        paths: &PlotSyncPathList,
+    ) -> Result<(), PlotSyncError> {
+        let result = self.path_list(msg_type, paths);
+        self.finish(result)
+    }
+
+    pub fn process_done(&mut self, done: &PlotSyncDone) -> Result<(), PlotSyncError> {
+        let result = self.done(done);
+        self.finish(result)
+    }
+
+    fn finish(&mut self, result: Result<(), PlotSyncError>) -> Result<(), PlotSyncError> {
+        if result.is_err() {
+            // The harvester has to start a new sync after any error
+            self.current = None;
+        }
+        result
+    }
+
+    fn start(&mut self, start: &PlotSyncStart) -> Result<(), PlotSyncError> {
+        let identifier = &start.identifier;
+        if identifier.message_id != 0 {
+            return Err(PlotSyncError::new(
+                PlotSyncErrorCode::InvalidIdentifier,
+                format!(
+                    "Sync {} has to start with message 0, found {}",
+                    identifier.sync_id, identifier.message_id
+                ),
+                Some(PlotSyncIdentifier {
+                    message_id: 0,
+                    ..identifier.clone()
+                }),
+            ));
+        }
+        if !start.initial && start.last_sync_id != self.last_sync_id {
+            return Err(PlotSyncError::new(
+                PlotSyncErrorCode::InvalidLastSyncId,
+                format!(
+                    "Expected last sync id {}, found {}",
+                    self.last_sync_id, start.last_sync_id
+                ),
+                None,
+            ));
+        }
+        if start.last_sync_id == identifier.sync_id {
+            return Err(PlotSyncError::new(
+                PlotSyncErrorCode::SyncIdsMatch,
+                format!("Sync id {} was already used", identifier.sync_id),
+                None,
+            ));
+        }
+        self.current = Some(CurrentPlotSync {
+            sync_id: identifier.sync_id,
+            next_message_id: 1,
+            expected: ProtocolMessageTypes::PlotSyncLoaded,
+            initial: start.initial,
+            harvesting_mode: start.harvesting_mode,
+            plot_file_count: start.plot_file_count,
+            delta: PlotSyncDelta::default(),
+        });
+        Ok(())
+    }
+
+    fn loaded(&mut self, plots: &PlotSyncPlotList) -> Result<(), PlotSyncError> {
+        let current = Self::validate(
+            &mut self.current,
+            ProtocolMessageTypes::PlotSyncLoaded,
+            &plots.identifier,
+        )?;
+        for plot in &plots.data {
+            if (!current.initial && self.plots.contains_key(&plot.filename))
+                || current.delta.loaded.contains_key(&plot.filename)
+            {
+                return Err(PlotSyncError::new(
+                    PlotSyncErrorCode::PlotAlreadyAvailable,
+                    format!("Plot {} is already available", plot.filename),
+                    None,
+                ));
+            }
+            current
+                .delta
+                .loaded
+                .insert(plot.filename.clone(), plot.clone());
+        }
+        Self::advance(current, plots.r#final);
+        Ok(())
+    }
+
+    fn path_list(
+        &mut self,
+        msg_type: ProtocolMessageTypes,
+        paths: &PlotSyncPathList,
+    ) -> Result<(), PlotSyncError> {
+        let current = Self::validate(&mut self.current, msg_type, &paths.identifier)?;
+        let list = match msg_type {
+            ProtocolMessageTypes::PlotSyncRemoved => {
+                for path in &paths.data {
+                    if current.initial
+                        || !self.plots.contains_key(path)
+                        || current.delta.removed.contains(path)
+                    {
+                        return Err(PlotSyncError::new(
+                            PlotSyncErrorCode::PlotNotAvailable,
+                            format!("Plot {path} is not available"),
+                            None,
+                        ));
+                    }
+                }
+                &mut current.delta.removed
+            }
+            ProtocolMessageTypes::PlotSyncInvalid => &mut current.delta.invalid,
+            ProtocolMessageTypes::PlotSyncKeysMissing => &mut current.delta.keys_missing,
+            ProtocolMessageTypes::PlotSyncDuplicates => &mut current.delta.duplicates,
+            _ => {
+                return Err(PlotSyncError::new(
+                    PlotSyncErrorCode::InvalidState,
+                    format!("{msg_type:?} is not a path list"),
+                    None,
+                ))
+            }
+        };
+        list.extend(paths.data.iter().cloned());
+        Self::advance(current, paths.r#final);
+        Ok(())
+    }
+
+    fn done(&mut self, done: &PlotSyncDone) -> Result<(), PlotSyncError> {
+        let current = Self::validate(
+            &mut self.current,
+            ProtocolMessageTypes::PlotSyncDone,
+            &done.identifier,
+        )?;
+        let sync_id = current.sync_id;
+        let initial = current.initial;
+        let harvesting_mode = current.harvesting_mode;
+        let plot_file_count = current.plot_file_count;
+        let delta = std::mem::take(&mut current.delta);
+        self.current = None;
+        if initial {
+            self.plots.clear();
+        }
+        self.harvesting_mode = harvesting_mode;
+        self.plot_file_count = plot_file_count;
+        for path in &delta.removed {
+            self.plots.remove(path);
+        }
+        self.plots.extend(delta.loaded);
+        self.invalid = delta.invalid;
+        self.keys_missing = delta.keys_missing;
+        self.duplicates = delta.duplicates;
+        self.total_plot_size = self.plots.values().map(|p| p.file_size).sum();
+        self.last_sync_id = sync_id;
+        self.last_sync_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.last_sync_duration = done.duration;
+        Ok(())
+    }
+
+    fn validate<'a>(
+        current: &'a mut Option<CurrentPlotSync>,
+        msg_type: ProtocolMessageTypes,
+        identifier: &PlotSyncIdentifier,
+    ) -> Result<&'a mut CurrentPlotSync, PlotSyncError> {
+        let Some(current) = current.as_mut() else {
+            return Err(PlotSyncError::new(
+                PlotSyncErrorCode::InvalidState,
+                format!("Received {msg_type:?} without a running sync"),
+                None,
+            ));
+        };
+        if current.expected != msg_type {
+            return Err(PlotSyncError::new(
+                PlotSyncErrorCode::InvalidState,
+                format!("Expected {:?}, found {msg_type:?}", current.expected),
+                None,
+            ));
+        }
+        if identifier.sync_id != current.sync_id || identifier.message_id != current.next_message_id
+        {
+            return Err(PlotSyncError::new(
+                PlotSyncErrorCode::InvalidIdentifier,
+                format!(
+                    "Expected sync {} message {}, found sync {} message {}",
+                    current.sync_id,
+                    current.next_message_id,
+                    identifier.sync_id,
+                    identifier.message_id
+                ),
+                Some(PlotSyncIdentifier {
+                    timestamp: identifier.timestamp,
+                    sync_id: current.sync_id,
+                    message_id: current.next_message_id,
+                }),
+            ));
+        }
+        Ok(current)
+    }
+
+    fn advance(current: &mut CurrentPlotSync, is_final: bool) {
+        current.next_message_id += 1;
+        if is_final {
+            if let Some(next) = PLOT_SYNC_ORDER
+                .iter()
+                .skip_while(|t| **t != current.expected)
+                .nth(1)
+            {
+                current.expected = *next;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_harvester_plot_sync() {
+    use crate::blockchain::sized_bytes::{Bytes32, Bytes48};
+    let identifier = |sync_id, message_id| PlotSyncIdentifier {
+        timestamp: 0,
+        sync_id,
+        message_id,
+    };
+    let plot = |filename: &str| Plot {
+        filename: filename.to_string(),
+        size: 32,
+        plot_id: Bytes32::default(),
+        pool_public_key: None,
+        pool_contract_puzzle_hash: Some(Bytes32::default()),
+        plot_public_key: Bytes48::default(),
+        file_size: 100,
+        time_modified: 0,
+        compression_level: Some(0),
+    };
+    let paths = |sync_id, message_id, data: &[&str]| PlotSyncPathList {
+        identifier: identifier(sync_id, message_id),
+        data: data.iter().map(ToString::to_string).collect(),
+        r#final: true,
+    };
+    let start = |sync_id, last_sync_id, initial| PlotSyncStart {
+        identifier: identifier(sync_id, 0),
+        initial,
+        last_sync_id,
+        plot_file_count: 2,
+        harvesting_mode: HarvestingMode::Cpu as u8,
+    };
+    let mut sync = HarvesterPlotSync::default();
+    sync.process_start(&start(1, 0, true)).unwrap();
+    sync.process_loaded(&PlotSyncPlotList {
+        identifier: identifier(1, 1),
+        data: vec![plot("a.plot")],
+        r#final: false,
+    })
+    .unwrap();
+    sync.process_loaded(&PlotSyncPlotList {
+        identifier: identifier(1, 2),
+        data: vec![plot("b.plot")],
+        r#final: true,
+    })
+    .unwrap();
+    sync.process_path_list(ProtocolMessageTypes::PlotSyncRemoved, &paths(1, 3, &[]))
+        .unwrap();
+    sync.process_path_list(
+        ProtocolMessageTypes::PlotSyncInvalid,
+        &paths(1, 4, &["c.plot"]),
+    )
+    .unwrap();
+    sync.process_path_list(ProtocolMessageTypes::PlotSyncKeysMissing, &paths(1, 5, &[]))
+        .unwrap();
+    assert!(
+        sync.plots.is_empty(),
+        "Inventory changed before the sync was done"
+    );
+    sync.process_path_list(ProtocolMessageTypes::PlotSyncDuplicates, &paths(1, 6, &[]))
+        .unwrap();
+    sync.process_done(&PlotSyncDone {
+        identifier: identifier(1, 7),
+        duration: 3,
+    })
+    .unwrap();
+    assert!(!sync.sync_in_progress());
+    assert_eq!(sync.plots.len(), 2);
+    assert_eq!(sync.invalid, vec!["c.plot".to_string()]);
+    assert_eq!(sync.total_plot_size, 200);
+    assert_eq!(sync.last_sync_id, 1);
+    //A delta sync has to continue from the last sync
+    let err = sync.process_start(&start(2, 5, false)).unwrap_err();
+    assert_eq!(
+        PlotSyncErrorCode::from(err.code),
+        PlotSyncErrorCode::InvalidLastSyncId
+    );
+    sync.process_start(&start(2, 1, false)).unwrap();
+    let err = sync
+        .process_path_list(
+            ProtocolMessageTypes::PlotSyncRemoved,
+            &paths(2, 1, &["a.plot"]),
+        )
+        .unwrap_err();
+    assert_eq!(
+        PlotSyncErrorCode::from(err.code),
+        PlotSyncErrorCode::InvalidState
+    );
+    assert!(!sync.sync_in_progress());
+    sync.process_start(&start(2, 1, false)).unwrap();
+    let err = sync
+        .process_loaded(&PlotSyncPlotList {
+            identifier: identifier(2, 1),
+            data: vec![plot("a.plot")],
+            r#final: true,
+        })
+        .unwrap_err();
+    assert_eq!(
+        PlotSyncErrorCode::from(err.code),
+        PlotSyncErrorCode::PlotAlreadyAvailable
+    );
+    sync.process_start(&start(2, 1, false)).unwrap();
+    let err = sync
+        .process_loaded(&PlotSyncPlotList {
+            identifier: identifier(2, 2),
+            data: vec![],
+            r#final: true,
+        })
+        .unwrap_err();
+    assert_eq!(err.expected_identifier, Some(identifier(2, 1)));
+    assert_eq!(sync.plots.len(), 2, "Failed syncs changed the inventory");
+    // A new initial sync replaces the inventory, but only once it is done
+    sync.process_start(&start(3, 0, true)).unwrap();
+    sync.process_loaded(&PlotSyncPlotList {
+        identifier: identifier(3, 1),
+        data: vec![plot("a.plot")],
+        r#final: true,
+    })
+    .unwrap();
+    let err = sync
+        .process_path_list(
+            ProtocolMessageTypes::PlotSyncRemoved,
+            &paths(3, 2, &["b.plot"]),
+        )
+        .unwrap_err();
+    assert_eq!(
+        PlotSyncErrorCode::from(err.code),
+        PlotSyncErrorCode::PlotNotAvailable
+    );
+    assert_eq!(
+        sync.plots.len(),
+        2,
+        "Failed initial sync changed the inventory"
+    );
+    assert_eq!(sync.invalid, vec!["c.plot".to_string()]);
+    assert_eq!(sync.last_sync_id, 1);
+    sync.process_start(&start(4, 0, true)).unwrap();
+    sync.process_loaded(&PlotSyncPlotList {
+        identifier: identifier(4, 1),
+        data: vec![plot("d.plot")],
+        r#final: true,
+    })
+    .unwrap();
+    for (message_id, msg_type) in [
+        ProtocolMessageTypes::PlotSyncRemoved,
+        ProtocolMessageTypes::PlotSyncInvalid,
+        ProtocolMessageTypes::PlotSyncKeysMissing,
+        ProtocolMessageTypes::PlotSyncDuplicates,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        sync.process_path_list(msg_type, &paths(4, message_id as u64 + 2, &[]))
+            .unwrap();
+    }
+    sync.process_done(&PlotSyncDone {
+        identifier: identifier(4, 6),
+        duration: 1,
+    })
+    .unwrap();
+    assert_eq!(
+        sync.plots.keys().collect::<Vec<_>>(),
+        vec![&"d.plot".to_string()]
+    );
+    assert!(sync.invalid.is_empty());
+    assert_eq!(sync.total_plot_size, 100);
+    assert_eq!(sync.last_sync_id, 4);
+}
@@ -13,6 +13,8 @@ use std::io::{Error, ErrorKind};
 use std::mem::size_of;
 use std::str::FromStr;
 
+pub mod plot_memo;
+
 fn _version() -> &'static str {
     env!("CARGO_PKG_VERSION")
 }
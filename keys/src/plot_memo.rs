@@ -0,0 +1,182 @@
+use crate::{master_sk_to_farmer_sk, master_sk_to_local_sk};
+use blst::min_pk::{PublicKey, SecretKey};
+use dg_xch_core::blockchain::proof_of_space::{
+    calculate_plot_id_public_key, calculate_plot_id_puzzle_hash, generate_plot_public_key,
+};
+use dg_xch_core::blockchain::sized_bytes::{Bytes32, Bytes48};
+use dg_xch_core::plots::PlotMemo;
+use dg_xch_core::traits::SizedBytes;
+use std::io::{Error, ErrorKind};
+
+/// Where the pool rewards of a plot go.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlotPool {
+    /// OG plots, pool rewards are paid to the pool public key
+    PublicKey(Bytes48),
+    /// Plots farmed to a PlotNFT
+    ContractPuzzleHash(Bytes32),
+}
+
+/// Memo and identity of a new plot, `memo_bytes` and `plot_id` are passed to the plotter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlotKeys {
+    pub memo: PlotMemo,
+    pub memo_bytes: Vec<u8>,
+    pub plot_public_key: Bytes48,
+    pub plot_id: Bytes32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PlotMemoBuilder {
+    farmer_public_key: Option<Bytes48>,
+    pool: Option<PlotPool>,
+    local_master_secret_key: Option<SecretKey>,
+}
+impl PlotMemoBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses the farmer key derived from the master key of a wallet.
+    pub fn master_secret_key(mut self, master_secret_key: &SecretKey) -> Result<Self, Error> {
+        self.farmer_public_key = Some(
+            master_sk_to_farmer_sk(master_secret_key)?
+                .sk_to_pk()
+                .to_bytes()
+                .into(),
+        );
+        Ok(self)
+    }
+
+    #[must_use]
+    pub fn farmer_public_key(mut self, farmer_public_key: Bytes48) -> Self {
+        self.farmer_public_key = Some(farmer_public_key);
+        self
+    }
+
+    #[must_use]
+    pub fn pool_public_key(mut self, pool_public_key: Bytes48) -> Self {
+        self.pool = Some(PlotPool::PublicKey(pool_public_key));
+        self
+    }
+
+    #[must_use]
+    pub fn pool_contract_puzzle_hash(mut self, pool_contract_puzzle_hash: Bytes32) -> Self {
+        self.pool = Some(PlotPool::ContractPuzzleHash(pool_contract_puzzle_hash));
+        self
+    }
+
+    /// Master key stored in the plot, every plot should have its own. A random key is generated
+    /// when this is not set.
+    #[must_use]
+    pub fn local_master_secret_key(mut self, local_master_secret_key: SecretKey) -> Self {
+        self.local_master_secret_key = Some(local_master_secret_key);
+        self
+    }
+
+    pub fn build(self) -> Result<PlotKeys, Error> {
+        let farmer_public_key = self.farmer_public_key.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "A master key or farmer public key is required to build a plot memo",
+            )
+        })?;
+        let pool = self.pool.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "A pool public key or pool contract puzzle hash is required to build a plot memo",
+            )
+        })?;
+        let local_master_secret_key = match self.local_master_secret_key {
+            Some(key) => key,
+            None => SecretKey::key_gen_v3(&rand::random::<[u8; 32]>(), &[])
+                .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("{e:?}")))?,
+        };
+        let local_sk = master_sk_to_local_sk(&local_master_secret_key)?;
+        let farmer_pk = PublicKey::from_bytes(farmer_public_key.as_ref())
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("{e:?}")))?;
+        let plot_public_key: Bytes48 = generate_plot_public_key(
+            &local_sk.sk_to_pk(),
+            &farmer_pk,
+            matches!(pool, PlotPool::ContractPuzzleHash(_)),
+        )?
+        .to_bytes()
+        .into();
+        let (pool_public_key, pool_contract_puzzle_hash, plot_id) = match pool {
+            PlotPool::PublicKey(pool_public_key) => (
+                Some(pool_public_key),
+                None,
+                calculate_plot_id_public_key(pool_public_key, plot_public_key),
+            ),
+            PlotPool::ContractPuzzleHash(puzzle_hash) => (
+                None,
+                Some(puzzle_hash),
+                calculate_plot_id_puzzle_hash(puzzle_hash, plot_public_key),
+            ),
+        };
+        let memo = PlotMemo {
+            pool_public_key,
+            pool_contract_puzzle_hash,
+            farmer_public_key,
+            local_master_secret_key: Bytes32::new(local_master_secret_key.to_bytes()),
+        };
+        Ok(PlotKeys {
+            memo_bytes: memo.to_bytes()?,
+            memo,
+            plot_public_key,
+            plot_id,
+        })
+    }
+}
+
+#[test]
+fn test_plot_memo_builder() {
+    let master_sk = SecretKey::key_gen_v3(&[1u8; 32], &[]).unwrap();
+    let local_master_sk = SecretKey::key_gen_v3(&[2u8; 32], &[]).unwrap();
+    let pool_public_key: Bytes48 = SecretKey::key_gen_v3(&[3u8; 32], &[])
+        .unwrap()
+        .sk_to_pk()
+        .to_bytes()
+        .into();
+    let og = PlotMemoBuilder::new()
+        .master_secret_key(&master_sk)
+        .unwrap()
+        .pool_public_key(pool_public_key)
+        .local_master_secret_key(local_master_sk.clone())
+        .build()
+        .unwrap();
+    assert_eq!(og.memo_bytes.len(), 128);
+    assert_eq!(
+        PlotMemo::try_from(og.memo_bytes.as_slice()).unwrap(),
+        og.memo
+    );
+    assert_eq!(
+        og.plot_id,
+        calculate_plot_id_public_key(pool_public_key, og.plot_public_key)
+    );
+    let farmer_public_key = og.memo.farmer_public_key;
+    let nft = PlotMemoBuilder::new()
+        .farmer_public_key(farmer_public_key)
+        .pool_contract_puzzle_hash(Bytes32::new([4u8; 32]))
+        .local_master_secret_key(local_master_sk)
+        .build()
+        .unwrap();
+    assert_eq!(nft.memo_bytes.len(), 112);
+    assert_eq!(
+        PlotMemo::try_from(nft.memo_bytes.as_slice()).unwrap(),
+        nft.memo
+    );
+    // PlotNFT plot keys include the taproot key
+    assert_ne!(nft.plot_public_key, og.plot_public_key);
+    let random = PlotMemoBuilder::new()
+        .farmer_public_key(farmer_public_key)
+        .pool_contract_puzzle_hash(Bytes32::new([4u8; 32]))
+        .build()
+        .unwrap();
+    assert_ne!(random.plot_id, nft.plot_id);
+    let missing_pool = PlotMemoBuilder::new()
+        .farmer_public_key(farmer_public_key)
+        .build();
+    assert_eq!(missing_pool.unwrap_err().kind(), ErrorKind::InvalidInput);
+}
@@ -0,0 +1,93 @@
+use criterion::{BenchmarkId, Criterion, Throughput};
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::consensus::constants::SIMULATOR;
+use dg_xch_core::traits::SizedBytes;
+use dg_xch_pos::plots::disk_plot::DiskPlot;
+use dg_xch_pos::plots::plot_reader::PlotReader;
+use dg_xch_pos::verifier::{proof_to_bytes, validate_proof, verify_proofs, ProofCheck};
+use std::path::Path;
+use std::thread::available_parallelism;
+use tokio::runtime::{Builder, Runtime};
+
+const K: u8 = 18;
+const BATCH_SIZES: [usize; 3] = [64, 1024, 4096];
+
+/// Plots a small plot and collects the proofs found for challenges built from its f7 values.
+#[allow(clippy::cast_possible_truncation)]
+fn collect_proofs(runtime: &Runtime, dir: &Path) -> Vec<ProofCheck> {
+    runtime.block_on(async {
+        let plot_id = Bytes32::new([7u8; 32]);
+        let plot = DiskPlot::create(dir, dir, dir, K, 0, &[5u8; 128], plot_id, &SIMULATOR)
+            .await
+            .unwrap();
+        let reader = PlotReader::new(plot, None, None).await.unwrap();
+        let mut proofs = vec![];
+        for f7 in reader.read_c3park(0).await.unwrap().iter().step_by(17) {
+            let mut challenge = [0x3cu8; 32];
+            challenge[0..8].copy_from_slice(&(f7 << (64 - K)).to_be_bytes());
+            for proof in reader.fetch_proofs_for_challenge(&challenge).await.unwrap() {
+                proofs.push(ProofCheck {
+                    plot_id,
+                    k: K,
+                    proof: proof_to_bytes(&proof),
+                    challenge: Bytes32::new(challenge),
+                });
+            }
+        }
+        proofs
+    })
+}
+
+fn verify_benchmark(c: &mut Criterion, proofs: &[ProofCheck]) {
+    let mut group = c.benchmark_group("Proof Verification");
+    for size in BATCH_SIZES {
+        let batch: Vec<ProofCheck> = proofs.iter().cycle().take(size).cloned().collect();
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(
+            BenchmarkId::new("validate_proof", size),
+            &batch,
+            |b, batch| {
+                b.iter(|| {
+                    for proof in batch {
+                        validate_proof(
+                            &proof.plot_id.bytes(),
+                            proof.k,
+                            &proof.proof,
+                            proof.challenge.as_ref(),
+                        )
+                        .unwrap();
+                    }
+                });
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("verify_proofs", size),
+            &batch,
+            |b, batch| {
+                b.iter(|| verify_proofs(batch));
+            },
+        );
+    }
+    group.finish();
+}
+
+fn main() {
+    let runtime = Builder::new_multi_thread()
+        .worker_threads(
+            available_parallelism()
+                .map(std::num::NonZero::get)
+                .unwrap_or(4),
+        )
+        .enable_all()
+        .thread_name("benchmark runtime")
+        .build()
+        .unwrap();
+    let dir = std::env::temp_dir().join(format!("dg_pos_verifier_bench_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let proofs = collect_proofs(&runtime, &dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(!proofs.is_empty(), "No proofs found to benchmark");
+    let mut criterion = Criterion::default().configure_from_args().sample_size(20);
+    verify_benchmark(&mut criterion, &proofs);
+    criterion.final_summary();
+}
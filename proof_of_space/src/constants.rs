@@ -144,6 +144,9 @@ pub const HEADER_MAGIC: [u8; 19] = [
 ]; //
 
 pub const HEADER_V2_MAGIC: [u8; 4] = [0x50, 0x4c, 0x4f, 0x54];
+pub const HEADER_V2_VERSION: u32 = 2;
+// A V2 header with a 128 byte memo and compression level
+pub const MAX_HEADER_SIZE: usize = 336;
 
 #[derive(Default)]
 pub struct PlotEntry {
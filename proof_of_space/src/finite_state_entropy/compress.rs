@@ -1,18 +1,270 @@
-#[derive(Default)]
+use crate::constants::FSE_MAX_SYMBOL_VALUE;
+use crate::finite_state_entropy::bitstream::highbit_32;
+use crate::finite_state_entropy::{fse_tablestep, FSE_MAX_TABLELOG};
+use std::io::{Error, ErrorKind};
+
+#[derive(Default, Clone)]
 pub struct CTableH {
     pub table_log: u16,
     pub fast_mode: u16,
 }
 
 #[derive(Default, Clone)]
-pub struct CTableEntry {
-    pub new_state: u16,
-    pub symbol: u8,
-    pub nb_bits: u8,
+pub struct SymbolTransform {
+    pub delta_find_state: i32,
+    pub delta_nb_bits: u32,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct CTable {
     pub header: CTableH,
-    pub table: Vec<CTableEntry>,
+    pub state_table: Vec<u16>,
+    pub symbol_tt: Vec<SymbolTransform>,
+}
+
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+#[allow(clippy::cast_possible_wrap)]
+pub fn build_ctable(
+    normalized_counter: &[i16],
+    max_symbol_value: u32,
+    table_log: u32,
+) -> Result<CTable, Error> {
+    /* Sanity Checks */
+    if max_symbol_value > FSE_MAX_SYMBOL_VALUE {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "max_symbol_value too large",
+        ));
+    }
+    if table_log > FSE_MAX_TABLELOG {
+        return Err(Error::new(ErrorKind::InvalidInput, "table_log too large"));
+    }
+    if normalized_counter.len() <= max_symbol_value as usize {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "normalized_counter is too small",
+        ));
+    }
+    let table_size = 1u32 << table_log;
+    let table_mask = table_size - 1;
+    let step = fse_tablestep(table_size);
+    let max_sv1 = max_symbol_value as usize + 1;
+    let mut cumul = vec![0u32; max_sv1 + 1];
+    let mut table_symbol = vec![0u8; table_size as usize];
+    let mut high_threshold = table_size - 1;
+
+    /* symbol start positions, lay down lowprob symbols */
+    for u in 1..=max_sv1 {
+        if normalized_counter[u - 1] == -1 {
+            cumul[u] = cumul[u - 1] + 1;
+            table_symbol[high_threshold as usize] = (u - 1) as u8;
+            high_threshold = high_threshold.wrapping_sub(1);
+        } else {
+            cumul[u] = cumul[u - 1] + normalized_counter[u - 1] as u32;
+        }
+    }
+    cumul[max_sv1] = table_size + 1;
+
+    /* Spread symbols */
+    let mut position: u32 = 0;
+    for (symbol, count) in normalized_counter.iter().enumerate().take(max_sv1) {
+        for _ in 0..*count {
+            table_symbol[position as usize] = symbol as u8;
+            position = (position + step) & table_mask;
+            while position > high_threshold {
+                /* lowprob area */
+                position = (position + step) & table_mask;
+            }
+        }
+    }
+    if position != 0 {
+        /* position must reach all cells once, otherwise normalizedCounter is incorrect */
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "normalized_counter is incorrect",
+        ));
+    }
+
+    /* Build table */
+    let mut state_table = vec![0u16; table_size as usize];
+    for (u, symbol) in table_symbol.iter().enumerate() {
+        let s = *symbol as usize;
+        state_table[cumul[s] as usize] = (table_size + u as u32) as u16;
+        cumul[s] += 1;
+    }
+
+    /* Build Symbol Transformation Table */
+    let mut symbol_tt = vec![SymbolTransform::default(); max_sv1];
+    let mut total: i32 = 0;
+    for (tt, count) in symbol_tt.iter_mut().zip(normalized_counter.iter()) {
+        match *count {
+            0 => {
+                tt.delta_nb_bits = ((table_log + 1) << 16).wrapping_sub(table_size);
+            }
+            -1 | 1 => {
+                tt.delta_nb_bits = (table_log << 16).wrapping_sub(table_size);
+                tt.delta_find_state = total - 1;
+                total += 1;
+            }
+            n => {
+                let max_bits_out = table_log - highbit_32((n - 1) as u32);
+                let min_state_plus = (n as u32) << max_bits_out;
+                tt.delta_nb_bits = (max_bits_out << 16).wrapping_sub(min_state_plus);
+                tt.delta_find_state = total - i32::from(n);
+                total += i32::from(n);
+            }
+        }
+    }
+    Ok(CTable {
+        header: CTableH {
+            table_log: table_log as u16,
+            fast_mode: 0,
+        },
+        state_table,
+        symbol_tt,
+    })
+}
+
+struct BitCStream {
+    bit_container: u64,
+    bit_pos: u32,
+    out: Vec<u8>,
+}
+impl BitCStream {
+    fn new(capacity: usize) -> Self {
+        Self {
+            bit_container: 0,
+            bit_pos: 0,
+            out: Vec::with_capacity(capacity),
+        }
+    }
+    fn add_bits(&mut self, value: u64, nb_bits: u32) {
+        let mask = (1u64 << nb_bits) - 1;
+        self.bit_container |= (value & mask) << self.bit_pos;
+        self.bit_pos += nb_bits;
+    }
+    fn flush_bits(&mut self) {
+        let nb_bytes = (self.bit_pos >> 3) as usize;
+        self.out
+            .extend_from_slice(&self.bit_container.to_le_bytes()[0..nb_bytes]);
+        self.bit_pos &= 7;
+        self.bit_container = self
+            .bit_container
+            .checked_shr(nb_bytes as u32 * 8)
+            .unwrap_or(0);
+    }
+    fn close(mut self) -> Vec<u8> {
+        self.add_bits(1, 1); /* endMark */
+        self.flush_bits();
+        if self.bit_pos > 0 {
+            self.out.push(self.bit_container.to_le_bytes()[0]);
+        }
+        self.out
+    }
+}
+
+struct CState<'a> {
+    value: u32,
+    ct: &'a CTable,
+}
+impl<'a> CState<'a> {
+    #[allow(clippy::cast_sign_loss)]
+    #[allow(clippy::cast_possible_wrap)]
+    fn new(ct: &'a CTable, symbol: u8) -> Self {
+        let tt = &ct.symbol_tt[symbol as usize];
+        let nb_bits_out = tt.delta_nb_bits.wrapping_add(1 << 15) >> 16;
+        let value = (nb_bits_out << 16).wrapping_sub(tt.delta_nb_bits);
+        let index = ((value >> nb_bits_out) as i32 + tt.delta_find_state) as usize;
+        Self {
+            value: u32::from(ct.state_table[index]),
+            ct,
+        }
+    }
+    #[allow(clippy::cast_sign_loss)]
+    #[allow(clippy::cast_possible_wrap)]
+    fn encode(&mut self, bit_c: &mut BitCStream, symbol: u8) {
+        let tt = &self.ct.symbol_tt[symbol as usize];
+        let nb_bits_out = self.value.wrapping_add(tt.delta_nb_bits) >> 16;
+        bit_c.add_bits(u64::from(self.value), nb_bits_out);
+        let index = ((self.value >> nb_bits_out) as i32 + tt.delta_find_state) as usize;
+        self.value = u32::from(self.ct.state_table[index]);
+    }
+    fn flush(&self, bit_c: &mut BitCStream) {
+        bit_c.add_bits(u64::from(self.value), u32::from(self.ct.header.table_log));
+        bit_c.flush_bits();
+    }
+}
+
+/// Compresses `src` with a table built by [`build_ctable`]. The output is readable by
+/// `decompress_using_dtable` with the matching `DTable`. Returns an empty Vec when the input holds
+/// fewer than two symbols.
+pub fn compress_using_ctable(src: &[u8], ct: &CTable) -> Result<Vec<u8>, Error> {
+    if src.len() < 2 {
+        return Ok(vec![]);
+    }
+    if let Some(s) = src.iter().find(|s| **s as usize >= ct.symbol_tt.len()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Symbol {s} is not in the compression table"),
+        ));
+    }
+    let mut bit_c = BitCStream::new(src.len() + 8);
+    let mut ip = src.len();
+    let (mut state1, mut state2);
+    if src.len() & 1 == 1 {
+        state1 = CState::new(ct, src[ip - 1]);
+        state2 = CState::new(ct, src[ip - 2]);
+        state1.encode(&mut bit_c, src[ip - 3]);
+        bit_c.flush_bits();
+        ip -= 3;
+    } else {
+        state2 = CState::new(ct, src[ip - 1]);
+        state1 = CState::new(ct, src[ip - 2]);
+        ip -= 2;
+    }
+    /* join to mod 4 */
+    if (src.len() - 2) & 2 != 0 {
+        state2.encode(&mut bit_c, src[ip - 1]);
+        state1.encode(&mut bit_c, src[ip - 2]);
+        bit_c.flush_bits();
+        ip -= 2;
+    }
+    /* 4 symbols per loop */
+    while ip > 0 {
+        state2.encode(&mut bit_c, src[ip - 1]);
+        state1.encode(&mut bit_c, src[ip - 2]);
+        state2.encode(&mut bit_c, src[ip - 3]);
+        state1.encode(&mut bit_c, src[ip - 4]);
+        bit_c.flush_bits();
+        ip -= 4;
+    }
+    state2.flush(&mut bit_c);
+    state1.flush(&mut bit_c);
+    Ok(bit_c.close())
+}
+
+#[test]
+fn test_compress_roundtrip() {
+    use crate::constants::{K_C3R, K_RVALUES};
+    use crate::encoding::{ans_decode_deltas, ans_encode_deltas};
+    use rand::{Rng, SeedableRng};
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    for r in [K_RVALUES[0], K_RVALUES[5], K_C3R] {
+        for len in [2usize, 3, 4, 5, 17, 2047, 9999] {
+            let deltas: Vec<u8> = (0..len)
+                .map(|_| {
+                    let v: f64 = rng.gen();
+                    // Roughly geometric, like the deltas found in parks
+                    (-(1.0 - v).ln() * r) as u8 % 32
+                })
+                .collect();
+            let encoded = ans_encode_deltas(&deltas, r).unwrap();
+            assert!(!encoded.is_empty());
+            let (count, decoded) = ans_decode_deltas(&encoded, encoded.len(), 10000, r).unwrap();
+            assert_eq!(count, len);
+            assert_eq!(&decoded[..len], &deltas[..]);
+        }
+    }
+    assert!(ans_encode_deltas(&[1], K_C3R).unwrap().is_empty());
 }
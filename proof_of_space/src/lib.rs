@@ -1,9 +1,10 @@
 use crate::plots::disk_plot::DiskPlot;
 use crate::plots::plot_reader::PlotReader;
-use crate::verifier::validate_proof;
+use crate::verifier::{validate_proof, verify_proof, ProofCheck, ProofFailure};
 use async_trait::async_trait;
 use dg_xch_core::blockchain::proof_of_space::{
-    calculate_pos_challenge, calculate_prefix_bits, passes_plot_filter, ProofOfSpace,
+    calculate_plot_id_public_key, calculate_plot_id_puzzle_hash, calculate_pos_challenge,
+    calculate_prefix_bits, passes_plot_filter, ProofOfSpace,
 };
 use dg_xch_core::blockchain::sized_bytes::{Bytes32, Bytes48};
 use dg_xch_core::consensus::constants::ConsensusConstants;
@@ -11,9 +12,10 @@ use dg_xch_core::protocols::harvester::HarvesterState;
 use dg_xch_core::traits::SizedBytes;
 use log::warn;
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use std::hash::{Hash, Hasher};
-use std::io::Error;
-use std::path::PathBuf;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use tokio::fs::File;
@@ -29,6 +31,7 @@ pub mod plots;
 pub mod util;
 pub mod utils;
 pub mod verifier;
+pub mod weight_proof;
 
 fn _version() -> &'static str {
     env!("CARGO_PKG_VERSION")
@@ -47,49 +50,149 @@ fn test_version() {
     println!("{}", version());
 }
 
-#[must_use]
-pub fn verify_and_get_quality_string(
+/// Why a `ProofOfSpace` was rejected by `verify_proof_of_space`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PosVerificationError {
+    /// Neither a pool public key nor a pool contract puzzle hash is set
+    MissingPoolKey,
+    /// Both a pool public key and a pool contract puzzle hash are set
+    ConflictingPoolKeys,
+    PlotTooSmall {
+        size: u8,
+        min: u8,
+    },
+    PlotTooLarge {
+        size: u8,
+        max: u8,
+    },
+    /// The challenge of the proof is not the one derived from the plot id and signage point
+    ChallengeMismatch {
+        expected: Bytes32,
+        actual: Bytes32,
+    },
+    /// The plot id does not pass the plot filter for the signage point
+    FailedPlotFilter,
+    InvalidProof(ProofFailure),
+}
+impl PosVerificationError {
+    /// Short name of the error, usable as a metrics label.
+    #[must_use]
+    pub fn reason(&self) -> &'static str {
+        match self {
+            PosVerificationError::MissingPoolKey => "missing_pool_key",
+            PosVerificationError::ConflictingPoolKeys => "conflicting_pool_keys",
+            PosVerificationError::PlotTooSmall { .. } => "plot_too_small",
+            PosVerificationError::PlotTooLarge { .. } => "plot_too_large",
+            PosVerificationError::ChallengeMismatch { .. } => "challenge_mismatch",
+            PosVerificationError::FailedPlotFilter => "failed_plot_filter",
+            PosVerificationError::InvalidProof(_) => "invalid_proof",
+        }
+    }
+}
+impl Display for PosVerificationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PosVerificationError::MissingPoolKey => {
+                f.write_str("null value for both pool_public_key and pool_contract_puzzle_hash")
+            }
+            PosVerificationError::ConflictingPoolKeys => f.write_str(
+                "Non Null value for both for pool_public_key and pool_contract_puzzle_hash",
+            ),
+            PosVerificationError::PlotTooSmall { size, min } => {
+                write!(f, "Plot size k{size} is below MIN_PLOT_SIZE k{min}")
+            }
+            PosVerificationError::PlotTooLarge { size, max } => {
+                write!(f, "Plot size k{size} is above MAX_PLOT_SIZE k{max}")
+            }
+            PosVerificationError::ChallengeMismatch { expected, actual } => {
+                write!(f, "Challenge {actual} is not the expected {expected}")
+            }
+            PosVerificationError::FailedPlotFilter => f.write_str("Plot Failed to Pass Filter"),
+            PosVerificationError::InvalidProof(e) => write!(f, "Invalid Proof: {e}"),
+        }
+    }
+}
+impl std::error::Error for PosVerificationError {}
+impl From<PosVerificationError> for Error {
+    fn from(e: PosVerificationError) -> Self {
+        Error::new(ErrorKind::InvalidData, e)
+    }
+}
+
+/// Verifies a proof of space for a signage point, returning its quality string or why it was
+/// rejected. A proof whose f7 does not match the challenge is rejected with
+/// `ProofFailure::WrongChallenge`.
+pub fn verify_proof_of_space(
     pos: &ProofOfSpace,
     constants: &ConsensusConstants,
     original_challenge_hash: Bytes32,
     signage_point: Bytes32,
     height: u32,
-) -> Option<Bytes32> {
-    if pos.pool_public_key.is_none() && pos.pool_contract_puzzle_hash.is_none() {
-        warn!("Failed to Verify ProofOfSpace: null value for both pool_public_key and pool_contract_puzzle_hash");
-        return None;
-    }
-    if pos.pool_public_key.is_some() && pos.pool_contract_puzzle_hash.is_some() {
-        warn!("Failed to Verify ProofOfSpace: Non Null value for both for pool_public_key and pool_contract_puzzle_hash");
-        return None;
-    }
+) -> Result<Bytes32, PosVerificationError> {
+    let plot_id = match (pos.pool_public_key, pos.pool_contract_puzzle_hash) {
+        (None, None) => return Err(PosVerificationError::MissingPoolKey),
+        (Some(_), Some(_)) => return Err(PosVerificationError::ConflictingPoolKeys),
+        (Some(pool_public_key), None) => {
+            calculate_plot_id_public_key(pool_public_key, pos.plot_public_key)
+        }
+        (None, Some(puzzle_hash)) => {
+            calculate_plot_id_puzzle_hash(puzzle_hash, pos.plot_public_key)
+        }
+    };
     if pos.size < constants.min_plot_size {
-        warn!("Failed to Verify ProofOfSpace: Plot failed MIN_PLOT_SIZE");
-        return None;
+        return Err(PosVerificationError::PlotTooSmall {
+            size: pos.size,
+            min: constants.min_plot_size,
+        });
     }
     if pos.size > constants.max_plot_size {
-        warn!("Failed to Verify ProofOfSpace: Plot failed MAX_PLOT_SIZE");
-        return None;
+        return Err(PosVerificationError::PlotTooLarge {
+            size: pos.size,
+            max: constants.max_plot_size,
+        });
     }
-    if let Some(plot_id) = pos.get_plot_id() {
-        if pos.challenge != calculate_pos_challenge(plot_id, original_challenge_hash, signage_point)
-        {
-            warn!("Failed to Verify ProofOfSpace: New challenge is not challenge");
-            return None;
-        }
-        if !passes_plot_filter(
-            calculate_prefix_bits(constants, height),
-            plot_id,
-            original_challenge_hash,
-            signage_point,
-        ) {
-            warn!("Failed to Verify ProofOfSpace: Plot Failed to Pass Filter");
-            return None;
-        }
-        get_quality_string(pos, &plot_id)
-    } else {
-        None
+    let expected = calculate_pos_challenge(plot_id, original_challenge_hash, signage_point);
+    if pos.challenge != expected {
+        return Err(PosVerificationError::ChallengeMismatch {
+            expected,
+            actual: pos.challenge,
+        });
     }
+    if !passes_plot_filter(
+        calculate_prefix_bits(constants, height),
+        plot_id,
+        original_challenge_hash,
+        signage_point,
+    ) {
+        return Err(PosVerificationError::FailedPlotFilter);
+    }
+    verify_proof(&ProofCheck {
+        plot_id,
+        k: pos.size,
+        proof: pos.proof.as_ref().to_vec(),
+        challenge: pos.challenge,
+    })
+    .map_err(PosVerificationError::InvalidProof)
+}
+
+/// Same as `verify_proof_of_space`, logging the reason a proof was rejected.
+#[must_use]
+pub fn verify_and_get_quality_string(
+    pos: &ProofOfSpace,
+    constants: &ConsensusConstants,
+    original_challenge_hash: Bytes32,
+    signage_point: Bytes32,
+    height: u32,
+) -> Option<Bytes32> {
+    verify_proof_of_space(
+        pos,
+        constants,
+        original_challenge_hash,
+        signage_point,
+        height,
+    )
+    .map_err(|e| warn!("Failed to Verify ProofOfSpace: {e}"))
+    .ok()
 }
 
 #[must_use]
@@ -144,4 +247,74 @@ pub trait PlotManagerAsync {
     ) -> Result<(), Error>;
     fn plots(&self) -> &HashMap<PathInfo, Arc<PlotInfo>>;
     fn plots_ready(&self) -> Arc<AtomicBool>;
+    /// Plots that failed to load
+    fn invalid_plots(&self) -> Vec<&Path> {
+        vec![]
+    }
+    /// Plots created for keys that are not known
+    fn keys_missing_plots(&self) -> Vec<&Path> {
+        vec![]
+    }
+    /// Plots with the same file name as an already loaded plot
+    fn duplicate_plots(&self) -> Vec<&Path> {
+        vec![]
+    }
+}
+
+#[test]
+fn test_verify_proof_of_space_errors() {
+    use dg_xch_core::consensus::constants::MAINNET;
+    let pos = ProofOfSpace {
+        challenge: Bytes32::default(),
+        pool_public_key: None,
+        pool_contract_puzzle_hash: Some(Bytes32::new([1u8; 32])),
+        plot_public_key: Bytes48::default(),
+        size: 32,
+        proof: vec![0u8; 256].into(),
+    };
+    let verify = |pos: &ProofOfSpace| {
+        verify_proof_of_space(pos, &MAINNET, Bytes32::default(), Bytes32::default(), 0)
+    };
+    let mut no_pool = pos.clone();
+    no_pool.pool_contract_puzzle_hash = None;
+    assert_eq!(verify(&no_pool), Err(PosVerificationError::MissingPoolKey));
+    let mut both = pos.clone();
+    both.pool_public_key = Some(Bytes48::default());
+    assert_eq!(
+        verify(&both),
+        Err(PosVerificationError::ConflictingPoolKeys)
+    );
+    let mut small = pos.clone();
+    small.size = 18;
+    assert_eq!(
+        verify(&small),
+        Err(PosVerificationError::PlotTooSmall { size: 18, min: 32 })
+    );
+    let err = verify(&pos).unwrap_err();
+    assert!(matches!(
+        err,
+        PosVerificationError::ChallengeMismatch { .. }
+    ));
+    assert_eq!(Error::from(err).kind(), ErrorKind::InvalidData);
+    // A signage point the plot passes the filter for, with a proof whose x values never match
+    let plot_id = calculate_plot_id_puzzle_hash(Bytes32::new([1u8; 32]), pos.plot_public_key);
+    let signage_point = (0u8..=255)
+        .map(|i| Bytes32::new([i; 32]))
+        .find(|sp| {
+            passes_plot_filter(
+                calculate_prefix_bits(&MAINNET, 0),
+                plot_id,
+                Bytes32::default(),
+                *sp,
+            )
+        })
+        .unwrap();
+    let mut unmatched = pos.clone();
+    unmatched.challenge = calculate_pos_challenge(plot_id, Bytes32::default(), signage_point);
+    assert_eq!(
+        verify_proof_of_space(&unmatched, &MAINNET, Bytes32::default(), signage_point, 0),
+        Err(PosVerificationError::InvalidProof(ProofFailure::NoMatch {
+            table: 2
+        }))
+    );
 }
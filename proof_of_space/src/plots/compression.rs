@@ -1,8 +1,11 @@
+use crate::constants::{ucdiv, K_ENTRIES_PER_PARK};
 use crate::encoding;
 use crate::encoding::create_normalized_count;
-use crate::finite_state_entropy::compress::CTable;
+use crate::entry_sizes::EntrySizes;
+use crate::finite_state_entropy::compress::{build_ctable, CTable};
 use crate::finite_state_entropy::decompress::DTable;
 use crate::finite_state_entropy::fse_ctable_size;
+use crate::plots::plotting::COMPRESSED_PLOT_K;
 use crate::plots::{MAX_BUCKETS, MAX_MATCHES_MULTIPLIER, MAX_MATCHES_MULTIPLIER_2T_DROP};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
@@ -139,6 +142,39 @@ pub fn get_compression_info_for_level(compression_level: u8) -> &'static Compres
     }
 }
 
+/// The level parameters are tuned for k32. Line point deltas of the lowest stored table average
+/// `2^(4 * entry_size_bits - k)`, so a smaller plot grows the stubs by `32 - k` bits to leave the
+/// ANS encoded part of the deltas as it is at k32, and the park by the larger stubs.
+#[allow(clippy::cast_possible_truncation)]
+#[must_use]
+pub fn get_compression_info_for_k(k: u8, compression_level: u8) -> CompressionInfo {
+    let info = *get_compression_info_for_level(compression_level);
+    let park_size = |k: u32, stub_size_bits: u32| {
+        (EntrySizes::line_point_size_bytes(k) + ucdiv(stub_size_bits * (K_ENTRIES_PER_PARK - 1), 8))
+            as usize
+    };
+    let k = u32::from(k.min(COMPRESSED_PLOT_K));
+    let stub_size_bits = info.stub_size_bits + u32::from(COMPRESSED_PLOT_K) - k;
+    CompressionInfo {
+        entry_size_bits: info.entry_size_bits,
+        stub_size_bits,
+        table_park_size: info.table_park_size
+            - park_size(u32::from(COMPRESSED_PLOT_K), info.stub_size_bits)
+            + park_size(k, stub_size_bits),
+        ans_rvalue: info.ans_rvalue,
+    }
+}
+
+/// Whether a `k` plot can be compressed at `compression_level`. The x pairs of the dropped
+/// table become the left values of the lowest stored table, so two truncated x values have to
+/// fit in k bits.
+#[must_use]
+pub fn compression_level_fits_k(k: u8, compression_level: u8) -> bool {
+    (1..=9).contains(&compression_level)
+        && k <= COMPRESSED_PLOT_K
+        && 2 * get_compression_info_for_level(compression_level).entry_size_bits <= u32::from(k)
+}
+
 pub fn create_compression_dtable(c_level: u8) -> Result<Arc<DTable>, Error> {
     match c_level {
         1..=9 => create_compression_dtable_for_clevel(c_level),
@@ -182,10 +218,8 @@ fn gen_compression_table(r_value: f64, out_size: &mut usize) -> Result<CTable, E
     let normalized_count = create_normalized_count(r_value)?;
     let max_symbol_value = normalized_count.len() - 1;
     let table_log = 14;
-    // let ct  = build_ctable( normalized_count, max_symbol_value, table_log );
     *out_size = fse_ctable_size(table_log, max_symbol_value as u32) as usize;
-    // ct
-    todo!()
+    build_ctable(&normalized_count, max_symbol_value as u32, table_log)
 }
 
 #[allow(clippy::cast_possible_truncation)]
@@ -1,8 +1,8 @@
 use crate::constants::{K_BC, K_EXTRA_BITS_POW, L_TARGETS};
 use crate::encoding::{line_point_to_square, line_point_to_square64, square_to_line_point128};
 use crate::plots::compression::{
-    get_compression_info_for_level, get_entries_per_bucket_for_compression_level,
-    get_max_table_pairs_for_compression_level,
+    compression_level_fits_k, get_compression_info_for_level,
+    get_entries_per_bucket_for_compression_level, get_max_table_pairs_for_compression_level,
 };
 use crate::plots::fx_generator::{
     generate_fx_for_pairs_table2, generate_fx_for_pairs_table3, generate_fx_for_pairs_table4,
@@ -301,7 +301,7 @@ impl Decompressor {
     #[allow(clippy::cast_precision_loss)]
     #[allow(clippy::cast_sign_loss)]
     pub fn prealloc_for_clevel(&mut self, k: u8, c_level: u8) {
-        assert_eq!(k, 32);
+        assert!(compression_level_fits_k(k, c_level));
         let entries_per_bucket = get_entries_per_bucket_for_compression_level(k, c_level);
         if self.max_entries_per_bucket < entries_per_bucket {
             let alloc_count = entries_per_bucket as usize * 2;
@@ -973,14 +973,11 @@ impl Decompressor {
             )?;
         }
         debug!("\tMatching Pairs");
-        let y_entries = Span::new(
-            table_ctx.context.y_buffer.as_mut_ptr(),
-            table_ctx.context.y_buffer.len(),
-        );
-        let x_entries = Span::new(
-            table_ctx.context.x_buffer.as_mut_ptr(),
-            table_ctx.context.x_buffer.len(),
-        );
+        // Only the entries of both buckets are sorted, the buffers may be sized for a larger
+        // bucket of another k or compression level
+        let entry_count = table_ctx.entries_per_bucket as usize * 2;
+        let y_entries = Span::new(table_ctx.context.y_buffer.as_mut_ptr(), entry_count);
+        let x_entries = Span::new(table_ctx.context.x_buffer.as_mut_ptr(), entry_count);
         let pairs = Self::match_pairs(
             table_ctx.context,
             y_entries,
@@ -1746,8 +1743,8 @@ impl Decompressor {
         f1_generator: Option<Arc<F1Generator>>,
     ) -> Result<Vec<u64>, Error> {
         let mut req = ProofRequest {
-            compressed_proof: vec![0u64; k as usize],
-            full_proof: vec![0u64; k as usize * 2],
+            compressed_proof: vec![0u64; POST_PROOF_CMP_X_COUNT],
+            full_proof: vec![0u64; POST_PROOF_X_COUNT],
             c_level,
             plot_id: *plot_id,
             f1_generator,
@@ -1,25 +1,40 @@
-use crate::constants::{K_MAX_BUCKETS, K_MEM_SORT_PROPORTION, K_MIN_BUCKETS};
+use crate::constants::{
+    HEADER_MAGIC, HEADER_V2_MAGIC, HEADER_V2_VERSION, K_FORMAT_DESCRIPTION, K_MAX_BUCKETS,
+    K_MEM_SORT_PROPORTION, K_MIN_BUCKETS, MAX_HEADER_SIZE,
+};
 use crate::entry_sizes::EntrySizes;
+use crate::plots::compression::compression_level_fits_k;
 use crate::plots::plot_reader::read_plot_header_async;
+use crate::plots::plotting::manifest::{PlotManifest, PlotStage};
+use crate::plots::plotting::phase1::phase1;
+use crate::plots::plotting::phase2::phase2;
+use crate::plots::plotting::phase3::phase3;
+use crate::plots::plotting::phase4::phase4;
+use crate::plots::plotting::{ProgressCallback, MAX_PLOTTING_COMPRESSION_LEVEL, MAX_PLOTTING_K};
 use crate::utils::open_read_only_async;
 use dg_xch_core::blockchain::sized_bytes::Bytes32;
 use dg_xch_core::consensus::constants::ConsensusConstants;
-use dg_xch_core::plots::{PlotFile, PlotHeader};
+use dg_xch_core::plots::{PlotFile, PlotHeader, PlotHeaderV1, PlotHeaderV2, PlotMemo};
+use dg_xch_core::traits::SizedBytes;
+use hex::encode;
 use log::info;
-use std::cmp::min;
+#[cfg(unix)]
+use memmap2::Advice;
+use memmap2::{Mmap, MmapOptions};
 use std::fmt::{Display, Formatter};
 use std::io::{Error, ErrorKind, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use time::OffsetDateTime;
 use tokio::fs;
-use tokio::io::{AsyncRead, AsyncSeek, AsyncSeekExt};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncSeekExt, AsyncWriteExt, BufWriter};
 use tokio::sync::Mutex;
 use tokio::time::Instant;
 
 #[derive(Debug)]
 pub struct DiskPlot<F: AsyncSeek + AsyncRead> {
     file: Arc<Mutex<F>>,
+    mmap: Option<Mmap>,
     pub filename: Arc<PathBuf>,
     header: PlotHeader,
     plot_size: u64,
@@ -32,99 +47,127 @@ impl DiskPlot<fs::File> {
         file.seek(SeekFrom::Start(0)).await?;
         Ok(Self {
             file: Arc::new(Mutex::new(file)),
+            mmap: None,
             filename: Arc::new(filename.to_path_buf()),
             header,
             plot_size,
         })
     }
+    /// Opens a plot and memory maps it, so reads no longer lock the file and can run in parallel.
+    /// The plot must not be modified or truncated while it is open.
+    pub async fn new_mmap(filename: &Path) -> Result<Self, Error> {
+        let mut plot = Self::new(filename).await?;
+        let file = plot.file.lock().await.try_clone().await?.into_std().await;
+        // Safety: plot files are never written once complete. A plot truncated while mapped
+        // faults on access, the same risk every mmap based reader accepts.
+        let mmap = unsafe { MmapOptions::new().map(&file)? };
+        if (mmap.len() as u64) < plot.plot_size {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("Plot {} was truncated while opening", filename.display()),
+            ));
+        }
+        #[cfg(unix)]
+        mmap.advise(Advice::Random)?;
+        plot.mmap = Some(mmap);
+        Ok(plot)
+    }
+    #[must_use]
+    pub fn is_memory_mapped(&self) -> bool {
+        self.mmap.is_some()
+    }
+    /// Creates a new plot in `final_dir` and opens it. Tables are generated in `tmp1_dir` and the
+    /// plot is written into `tmp2_dir` before being moved to `final_dir`. A `compression_level`
+    /// above 0 writes a compressed plot without table 1, readable with a `Decompressor`.
     #[allow(clippy::too_many_arguments)]
-    pub async fn create<P>(
+    pub async fn create(
         tmp1_dir: &Path,
         tmp2_dir: &Path,
         final_dir: &Path,
-        _num_plots: usize,
         k: u8,
-        _memo: &[u8],
+        compression_level: u8,
+        memo: &[u8],
         plot_id: Bytes32,
-        constants: ConsensusConstants,
+        constants: &ConsensusConstants,
     ) -> Result<Self, Error> {
-        let stripe_size = 65536;
-        let num_threads = 8;
-        let buf_megabytes = 4096;
-        let filename = format!("dg_pos_{plot_id}_{}", OffsetDateTime::now_utc());
-        let filename = Path::new(&filename);
-        if k < constants.min_plot_size || k > constants.max_plot_size {
+        Self::create_with_progress(
+            tmp1_dir,
+            tmp2_dir,
+            final_dir,
+            k,
+            compression_level,
+            memo,
+            plot_id,
+            constants,
+            None,
+        )
+        .await
+    }
+    /// Same as `create`, sending progress events to `progress`. A manifest is kept next to the
+    /// temp files in `tmp1_dir` while plotting, pass it to `resume` to continue an interrupted plot.
+    #[allow(clippy::too_many_lines)]
+    #[allow(clippy::too_many_arguments)]
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    #[allow(clippy::cast_precision_loss)]
+    pub async fn create_with_progress(
+        tmp1_dir: &Path,
+        tmp2_dir: &Path,
+        final_dir: &Path,
+        k: u8,
+        compression_level: u8,
+        memo: &[u8],
+        plot_id: Bytes32,
+        constants: &ConsensusConstants,
+        progress: Option<ProgressCallback>,
+    ) -> Result<Self, Error> {
+        let buf_megabytes: u64 = 4096;
+        if k < constants.min_plot_size || k > constants.max_plot_size || k > MAX_PLOTTING_K {
             return Err(Error::new(
                 ErrorKind::Unsupported,
                 format!("Plot size k= {k} is invalid"),
             ));
         }
-        if buf_megabytes < 10 {
+        if compression_level > MAX_PLOTTING_COMPRESSION_LEVEL {
             return Err(Error::new(
                 ErrorKind::Unsupported,
-                "Please provide at least 10MiB of RAM",
+                format!("Compression level {compression_level} is not supported"),
             ));
         }
-
-        let thread_memory = (num_threads as u64)
-            * (2 * (stripe_size + 5000)) as u64
-            * (EntrySizes::get_max_entry_size(k, 4, true) as u64)
-            / (1024 * 1024);
-
-        let sub_mbytes = 5 + min((buf_megabytes as f64 * 0.05) as u64, 50) + thread_memory;
-
-        if sub_mbytes > buf_megabytes as u64 {
+        if compression_level > 0 && !compression_level_fits_k(k, compression_level) {
             return Err(Error::new(
                 ErrorKind::Unsupported,
-                format!("Please provide more memory. At least {sub_mbytes} MiB"),
+                format!("Compression level {compression_level} does not fit k{k} plots"),
             ));
         }
-
-        let memory_size = ((buf_megabytes - sub_mbytes as u32) as u64) * 1024 * 1024;
+        PlotMemo::try_from(memo)?;
+        for (name, dir) in [
+            ("Temp", tmp1_dir),
+            ("Temp2", tmp2_dir),
+            ("Final", final_dir),
+        ] {
+            if !dir.exists() {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("{name} directory {} does not exist", dir.display()),
+                ));
+            }
+        }
+        let filename = plot_filename(k, compression_level, plot_id);
+        let memory_size = buf_megabytes * 1024 * 1024;
         let mut max_table_size = 0.0;
-
         for i in 1..=7 {
-            let memory_i = 1.3
-                * ((1u64 << k) as f64)
-                * EntrySizes::get_max_entry_size(k, i as u8, true) as f64;
-
+            let memory_i =
+                1.3 * ((1u64 << k) as f64) * f64::from(EntrySizes::get_max_entry_size(k, i, true));
             if memory_i > max_table_size {
                 max_table_size = memory_i;
             }
         }
-
-        let num_buckets = 2
-            * (((max_table_size) / (memory_size as f64 * K_MEM_SORT_PROPORTION)).ceil() as u32)
-                .next_power_of_two();
-
-        if num_buckets < K_MIN_BUCKETS {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                format!("Minimum buckets is {K_MIN_BUCKETS}"),
-            ));
-        } else if num_buckets > K_MAX_BUCKETS {
-            // if num_buckets_input != 0 {
-            //     return Err(Box::new(InvalidValueException(format!(
-            //         "Maximum buckets is {}",
-            //         K_MAX_BUCKETS
-            //     ))));
-            // }
-            let required_mem =
-                (max_table_size / K_MAX_BUCKETS as f64) / K_MEM_SORT_PROPORTION / (1024.0 * 1024.0)
-                    + sub_mbytes as f64;
-            return Err(Error::new(
-                ErrorKind::Unsupported,
-                format!("Do not have enough memory. Need {required_mem:.2} MiB"),
-            ));
-        }
-
-        let log_num_buckets = (num_buckets as f64).log2().ceil() as u32;
-
-        assert!((num_buckets as f64).log2() == log_num_buckets as f64);
-
-        if (max_table_size / num_buckets as f64) < stripe_size as f64 * 30.0f64 {
-            return Err(Error::new(ErrorKind::InvalidInput, "Stripe size too large"));
-        }
+        let num_buckets = (2
+            * ((max_table_size / (memory_size as f64 * K_MEM_SORT_PROPORTION)).ceil() as u32)
+                .next_power_of_two())
+        .clamp(K_MIN_BUCKETS, K_MAX_BUCKETS);
+        let in_memory = max_table_size < memory_size as f64 * K_MEM_SORT_PROPORTION;
 
         info!(
             "\nStarting plotting progress into temporary dirs: {} and {}",
@@ -136,64 +179,266 @@ impl DiskPlot<fs::File> {
         info!("Buffer size is: {buf_megabytes} MiB");
         info!("Using {num_buckets} buckets");
         info!("Final Directory is: {}", final_dir.display());
-        info!("Using {num_threads} threads of stripe size {stripe_size}");
         info!("Process ID is: {}", std::process::id());
-        let mut tmp_1_filenames: Vec<PathBuf> = Vec::new();
-        tmp_1_filenames.push(Path::new(tmp1_dir).join(format!("{}.sort.tmp", filename.display())));
-        for i in 1..=7 {
-            tmp_1_filenames.push(Path::new(tmp1_dir).join(format!(
-                "{}.table{}.tmp",
-                filename.display(),
-                i
-            )));
-        }
-        let tmp_2_filename = Path::new(tmp2_dir).join(format!("{}.2.tmp", filename.display()));
-        let _final_2_filename = Path::new(final_dir).join(format!("{}.2.tmp", filename.display()));
-        let final_filename = Path::new(final_dir).join(filename);
-        if !Path::new(tmp1_dir).exists() {
-            return Err(Error::new(
-                ErrorKind::NotFound,
-                format!("Temp directory {} does not exist", tmp1_dir.display()),
-            ));
-        }
-
-        if !Path::new(tmp2_dir).exists() {
-            return Err(Error::new(
-                ErrorKind::NotFound,
-                format!("Temp2 directory {} does not exist", tmp2_dir.display()),
-            ));
-        }
-
-        if !Path::new(final_dir).exists() {
-            return Err(Error::new(
-                ErrorKind::NotFound,
-                format!("Final directory {} does not exist", final_dir.display()),
-            ));
-        }
-
+        let manifest = PlotManifest::new(
+            plot_id,
+            k,
+            compression_level,
+            memo,
+            filename,
+            tmp1_dir,
+            tmp2_dir,
+            final_dir,
+            num_buckets,
+            in_memory,
+        );
         // Remove temporary files if they exist
-        for p in &tmp_1_filenames {
+        for p in manifest
+            .table_files()
+            .iter()
+            .chain([&manifest.tmp2_file(), &manifest.final_file()])
+        {
             if p.exists() {
                 fs::remove_file(p).await?;
             }
         }
-        if tmp_2_filename.exists() {
-            fs::remove_file(&tmp_2_filename).await?;
+        manifest.save().await?;
+        info!("Saving progress to {}", manifest.path().display());
+        Self::plot(manifest, progress).await
+    }
+    /// Continues a plot from the last checkpoint saved in its manifest, the
+    /// `{filename}.manifest.json` file left in the first temp directory.
+    pub async fn resume(
+        manifest_path: &Path,
+        progress: Option<ProgressCallback>,
+    ) -> Result<Self, Error> {
+        let manifest = PlotManifest::load(manifest_path).await?;
+        info!(
+            "Resuming plot {} after checkpoint {:?}",
+            manifest.filename, manifest.stage
+        );
+        Self::plot(manifest, progress).await
+    }
+    async fn plot(
+        mut manifest: PlotManifest,
+        progress: Option<ProgressCallback>,
+    ) -> Result<Self, Error> {
+        let ctx = manifest.context(progress);
+        let memo = manifest.memo()?;
+        let tmp_2_filename = manifest.tmp2_file();
+        let final_2_filename = manifest
+            .final_dir
+            .join(format!("{}.2.tmp", manifest.filename));
+        let final_filename = manifest.final_file();
+        let total_start = Instant::now();
+        if matches!(
+            manifest.stage,
+            PlotStage::Started | PlotStage::Phase1 { .. }
+        ) {
+            info!("Starting phase 1/4: Forward Propagation into tmp files");
+            let phase_start = Instant::now();
+            phase1(&ctx, &mut manifest).await?;
+            info!(
+                "Phase 1 Completed in: {:.8} seconds",
+                phase_start.elapsed().as_secs_f64()
+            );
+        }
+        // A completed phase 2 may still have rewritten tables to move into place
+        if matches!(
+            manifest.stage,
+            PlotStage::Phase1Complete | PlotStage::Phase2Complete
+        ) {
+            info!("Starting phase 2/4: Backpropagation into tmp files");
+            let phase_start = Instant::now();
+            phase2(&ctx, &mut manifest).await?;
+            info!(
+                "Phase 2 Completed in: {:.8} seconds",
+                phase_start.elapsed().as_secs_f64()
+            );
         }
-        if final_filename.exists() {
-            fs::remove_file(&final_filename).await?;
+        if !matches!(manifest.stage, PlotStage::Phase4Complete { .. }) {
+            info!(
+                "Starting phase 3/4: Compression into {}",
+                tmp_2_filename.display()
+            );
+            let phase_start = Instant::now();
+            let mut header = new_plot_header(ctx.plot_id, ctx.k, ctx.compression_level, &memo)?;
+            let header_bytes = write_plot_header(&header, &memo)?;
+            let (file, start_offset) = if let PlotStage::Phase3 { offset, .. } = manifest.stage {
+                // Drop anything written after the checkpoint
+                let mut file = fs::OpenOptions::new()
+                    .write(true)
+                    .open(&tmp_2_filename)
+                    .await?;
+                file.set_len(offset).await?;
+                file.seek(SeekFrom::Start(offset)).await?;
+                (file, offset)
+            } else {
+                let mut file = fs::File::create(&tmp_2_filename).await?;
+                file.write_all(&header_bytes).await?;
+                (file, header_bytes.len() as u64)
+            };
+            let mut writer = BufWriter::new(file);
+            let phase3_results = phase3(&ctx, &mut manifest, &mut writer, start_offset).await?;
+            info!(
+                "Phase 3 Completed in: {:.8} seconds",
+                phase_start.elapsed().as_secs_f64()
+            );
+            info!("Starting phase 4/4: Write Checkpoint tables");
+            let phase_start = Instant::now();
+            let (pointers, plot_size) = phase4(&ctx, &phase3_results, &mut writer).await?;
+            match &mut header {
+                PlotHeader::V1(h) => h.table_begin_pointers = pointers,
+                PlotHeader::V2(h) => {
+                    h.table_begin_pointers = pointers;
+                    for (i, size) in h.table_sizes.iter_mut().enumerate() {
+                        let end = pointers.get(i + 1).copied().unwrap_or(plot_size);
+                        *size = end - pointers[i];
+                    }
+                }
+                PlotHeader::GHv2_5(_) => {}
+            }
+            // Go back and fill in the table pointers now that they are known
+            let header_bytes = write_plot_header(&header, &memo)?;
+            writer.flush().await?;
+            let mut file = writer.into_inner();
+            file.seek(SeekFrom::Start(0)).await?;
+            file.write_all(&header_bytes).await?;
+            file.set_len(plot_size).await?;
+            file.sync_all().await?;
+            drop(file);
+            manifest
+                .checkpoint(PlotStage::Phase4Complete { plot_size })
+                .await?;
+            fs::remove_file(&phase3_results.p7_positions).await?;
+            fs::remove_file(ctx.table_file(7)).await?;
+            info!(
+                "Phase 4 Completed in: {:.8} seconds",
+                phase_start.elapsed().as_secs_f64()
+            );
         }
-        info!("Starting phase 1/4: Forward Propagation into tmp files: {final_dir:?}");
-        let phase_1_start = Instant::now();
-        // let table_size = phase1(
-        //
-        // ).await?;
+        if ctx.sort_prefix.exists() {
+            fs::remove_file(&ctx.sort_prefix).await?;
+        }
+        // The plot may already have been moved before an interruption
+        if tmp_2_filename.exists() {
+            if manifest.tmp2_dir == manifest.final_dir {
+                fs::rename(&tmp_2_filename, &final_filename).await?;
+            } else {
+                fs::copy(&tmp_2_filename, &final_2_filename).await?;
+                fs::remove_file(&tmp_2_filename).await?;
+                fs::rename(&final_2_filename, &final_filename).await?;
+            }
+        }
+        manifest.remove().await?;
         info!(
-            "Phase 1 Completed in: {:.8} seconds",
-            phase_1_start.elapsed().as_secs_f64()
+            "Created plot {} in {:.8} seconds",
+            final_filename.display(),
+            total_start.elapsed().as_secs_f64()
         );
-        todo!()
+        DiskPlot::new(&final_filename).await
+    }
+}
+
+/// File name of a plot created now, `plot-k{k}[-c{level}]-{date}-{plot_id}.plot`.
+#[must_use]
+pub fn plot_filename(k: u8, compression_level: u8, plot_id: Bytes32) -> String {
+    let now = OffsetDateTime::now_utc();
+    let c_level = if compression_level > 0 {
+        format!("-c{compression_level:02}")
+    } else {
+        String::new()
+    };
+    format!(
+        "plot-k{k}{c_level}-{:04}-{:02}-{:02}-{:02}-{:02}-{}.plot",
+        now.year(),
+        now.month() as u8,
+        now.day(),
+        now.hour(),
+        now.minute(),
+        encode(plot_id.bytes())
+    )
+}
+
+/// Header of a new plot, compressed plots use a V2 header. The table pointers are filled in
+/// once the tables are written.
+#[allow(clippy::cast_possible_truncation)]
+pub fn new_plot_header(
+    plot_id: Bytes32,
+    k: u8,
+    compression_level: u8,
+    memo: &[u8],
+) -> Result<PlotHeader, Error> {
+    Ok(if compression_level > 0 {
+        PlotHeader::V2(PlotHeaderV2 {
+            magic: HEADER_V2_MAGIC,
+            id: plot_id,
+            k,
+            memo_len: memo.len() as u16,
+            memo: PlotMemo::try_from(memo)?,
+            version: HEADER_V2_VERSION,
+            plot_flags: 1,
+            compression_level,
+            table_begin_pointers: [0u64; 10],
+            table_sizes: [0u64; 10],
+        })
+    } else {
+        PlotHeader::V1(PlotHeaderV1 {
+            magic: HEADER_MAGIC,
+            id: plot_id,
+            k,
+            format_desc_len: K_FORMAT_DESCRIPTION.len() as u16,
+            format_desc: K_FORMAT_DESCRIPTION.as_bytes().to_vec(),
+            memo_len: memo.len() as u16,
+            memo: PlotMemo::try_from(memo)?,
+            table_begin_pointers: [0u64; 10],
+        })
+    })
+}
+
+/// Serializes a plot header, the memo is written as given.
+#[allow(clippy::cast_possible_truncation)]
+pub fn write_plot_header(header: &PlotHeader, memo: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::with_capacity(MAX_HEADER_SIZE);
+    match header {
+        PlotHeader::V1(h) => {
+            bytes.extend_from_slice(&h.magic);
+            bytes.extend_from_slice(&h.id.bytes());
+            bytes.push(h.k);
+            bytes.extend_from_slice(&(h.format_desc.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(&h.format_desc);
+            bytes.extend_from_slice(&(memo.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(memo);
+            for pointer in h.table_begin_pointers {
+                bytes.extend_from_slice(&pointer.to_be_bytes());
+            }
+        }
+        PlotHeader::V2(h) => {
+            bytes.extend_from_slice(&h.magic);
+            bytes.extend_from_slice(&h.version.to_le_bytes());
+            bytes.extend_from_slice(&h.id.bytes());
+            bytes.push(h.k);
+            bytes.extend_from_slice(&(memo.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(memo);
+            bytes.extend_from_slice(&h.plot_flags.to_le_bytes());
+            if h.plot_flags & 1 == 1 {
+                bytes.push(h.compression_level);
+            }
+            for pointer in h.table_begin_pointers {
+                bytes.extend_from_slice(&pointer.to_be_bytes());
+            }
+            for size in h.table_sizes {
+                bytes.extend_from_slice(&size.to_be_bytes());
+            }
+        }
+        PlotHeader::GHv2_5(_) => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Writing Gigahorse plot headers is not supported",
+            ));
+        }
     }
+    Ok(bytes)
 }
 impl<F: AsyncSeek + AsyncRead> Display for DiskPlot<F> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -213,11 +458,395 @@ impl<'a, F: AsyncSeek + AsyncRead> PlotFile<'a, F> for DiskPlot<F> {
         &self.plot_size
     }
 
-    fn load_p7_park(&'a self, _index: u64) -> u128 {
-        todo!()
+    fn load_p7_park(&'a self, _index: u64) -> Result<u128, Error> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "Loading P7 Parks is Not Supported, use a PlotReader",
+        ))
     }
 
     fn file(&'a self) -> Arc<Mutex<F>> {
         self.file.clone()
     }
+
+    fn mapped(&'a self) -> Option<&'a [u8]> {
+        self.mmap.as_deref()
+    }
+}
+
+#[tokio::test]
+async fn test_create_plot() {
+    use crate::plots::plot_reader::PlotReader;
+    use crate::verifier::{proof_to_bytes, validate_proof};
+    use dg_xch_core::consensus::constants::SIMULATOR;
+    let k = 18;
+    let dir = std::env::temp_dir().join(format!("dg_pos_test_create_plot_{}", std::process::id()));
+    let tmp_dir = dir.join("tmp");
+    let final_dir = dir.join("final");
+    fs::create_dir_all(&tmp_dir).await.unwrap();
+    fs::create_dir_all(&final_dir).await.unwrap();
+    let plot_id = Bytes32::new([7u8; 32]);
+    let memo = [3u8; 128];
+    let plot = DiskPlot::create(
+        &tmp_dir, &tmp_dir, &final_dir, k, 0, &memo, plot_id, &SIMULATOR,
+    )
+    .await
+    .unwrap();
+    let path = plot.filename.as_ref().clone();
+    assert_eq!(plot.k(), k);
+    assert_eq!(plot.plot_id(), plot_id);
+    let reader = PlotReader::new(plot, None, None).await.unwrap();
+    let park_count = reader.get_c3_park_count();
+    assert!(park_count > 0);
+    let mut f7s = reader.read_c3park(0).await.unwrap();
+    f7s.extend(reader.read_c3park(park_count - 1).await.unwrap());
+    let mut checked = 0;
+    for f7 in f7s.iter().step_by(997).chain(f7s.last()) {
+        let mut challenge = [0xa5u8; 32];
+        challenge[0..8].copy_from_slice(&(f7 << (64 - k)).to_be_bytes());
+        let qualities = reader
+            .fetch_qualities_for_challenge(&challenge)
+            .await
+            .unwrap();
+        assert!(!qualities.is_empty());
+        for (index, quality) in qualities {
+            let proof = reader.fetch_ordered_proof(index).await.unwrap();
+            let v_quality =
+                validate_proof(&plot_id.bytes(), k, &proof_to_bytes(&proof), &challenge).unwrap();
+            assert_eq!(quality, v_quality);
+            checked += 1;
+        }
+    }
+    assert!(checked > 0);
+    drop(reader);
+    fs::remove_file(path).await.unwrap();
+    fs::remove_dir_all(dir).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_plot_header_v2() {
+    let keys = dg_xch_keys::plot_memo::PlotMemoBuilder::new()
+        .master_secret_key(&blst::min_pk::SecretKey::key_gen_v3(&[7u8; 32], &[]).unwrap())
+        .unwrap()
+        .pool_contract_puzzle_hash(Bytes32::new([3u8; 32]))
+        .build()
+        .unwrap();
+    let plot_id = keys.plot_id;
+    let memo = keys.memo_bytes.clone();
+    let header = PlotHeader::V2(PlotHeaderV2 {
+        magic: HEADER_V2_MAGIC,
+        id: plot_id,
+        k: 32,
+        memo_len: 112,
+        memo: keys.memo,
+        version: HEADER_V2_VERSION,
+        plot_flags: 1,
+        compression_level: 5,
+        table_begin_pointers: [9u64; 10],
+        table_sizes: [3u64; 10],
+    });
+    let header_path = std::env::temp_dir().join(format!(
+        "dg_pos_test_plot_header_{}.tmp",
+        std::process::id()
+    ));
+    let mut header_bytes = write_plot_header(&header, &memo).unwrap();
+    // MAX_HEADER_SIZE fits a 128 byte memo, PlotNFT memos are 112 bytes
+    assert_eq!(header_bytes.len(), MAX_HEADER_SIZE - 128 + memo.len());
+    header_bytes.resize(1024, 0);
+    fs::write(&header_path, header_bytes).await.unwrap();
+    let parsed = crate::plots::plot_reader::read_plot_header(
+        &mut std::fs::File::open(&header_path).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(parsed.compression_level(), 5);
+    assert_eq!(parsed.id(), plot_id);
+    assert_eq!(*parsed.memo(), keys.memo);
+    fs::remove_file(header_path).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_mmap_plot() {
+    use crate::plots::plot_reader::PlotReader;
+    use dg_xch_core::consensus::constants::SIMULATOR;
+    let k = 18;
+    let dir = std::env::temp_dir().join(format!("dg_pos_test_mmap_plot_{}", std::process::id()));
+    fs::create_dir_all(&dir).await.unwrap();
+    let plot = DiskPlot::create(
+        &dir,
+        &dir,
+        &dir,
+        k,
+        0,
+        &[3u8; 128],
+        Bytes32::new([2u8; 32]),
+        &SIMULATOR,
+    )
+    .await
+    .unwrap();
+    let path = plot.filename.as_ref().clone();
+    let reader = PlotReader::new(plot, None, None).await.unwrap();
+    assert!(!reader.plot_file().is_memory_mapped());
+    let f7s = reader.read_c3park(0).await.unwrap();
+    let mapped = Arc::new(
+        PlotReader::new(DiskPlot::new_mmap(&path).await.unwrap(), None, None)
+            .await
+            .unwrap(),
+    );
+    assert!(mapped.plot_file().is_memory_mapped());
+    let lookups: Vec<_> = f7s
+        .iter()
+        .step_by(499)
+        .map(|f7| {
+            let mut challenge = [0x5au8; 32];
+            challenge[0..8].copy_from_slice(&(f7 << (64 - k)).to_be_bytes());
+            let mapped = mapped.clone();
+            let lookup = tokio::spawn(async move {
+                mapped
+                    .fetch_qualities_for_challenge(&challenge)
+                    .await
+                    .unwrap()
+            });
+            (challenge, lookup)
+        })
+        .collect();
+    for (challenge, lookup) in lookups {
+        assert_eq!(
+            lookup.await.unwrap(),
+            reader
+                .fetch_qualities_for_challenge(&challenge)
+                .await
+                .unwrap()
+        );
+    }
+    for f7 in f7s.iter().step_by(997) {
+        let mut challenge = [0xa5u8; 32];
+        challenge[0..8].copy_from_slice(&(f7 << (64 - k)).to_be_bytes());
+        for (index, _) in reader
+            .fetch_qualities_for_challenge(&challenge)
+            .await
+            .unwrap()
+        {
+            assert_eq!(
+                mapped.fetch_ordered_proof(index).await.unwrap(),
+                reader.fetch_ordered_proof(index).await.unwrap()
+            );
+        }
+    }
+    drop(mapped);
+    drop(reader);
+    fs::remove_dir_all(dir).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_create_compressed_plot_level_fits_k() {
+    use dg_xch_core::consensus::constants::SIMULATOR;
+    let dir = std::env::temp_dir().join(format!(
+        "dg_pos_test_compressed_plot_level_fits_k_{}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).await.unwrap();
+    // Two 11 bit C6 entries do not fit a k20 line point, two 16 bit C1 entries not a k18 one
+    for (k, compression_level) in [(18, 1), (20, 6)] {
+        let compressed = DiskPlot::create(
+            &dir,
+            &dir,
+            &dir,
+            k,
+            compression_level,
+            &[5u8; 128],
+            Bytes32::new([4u8; 32]),
+            &SIMULATOR,
+        )
+        .await;
+        assert_eq!(compressed.unwrap_err().kind(), ErrorKind::Unsupported);
+    }
+    fs::remove_dir_all(dir).await.unwrap();
+}
+
+/// Plots a C7 k20, the smallest plot a level fits, and reads qualities and proofs back through
+/// the decompressor.
+#[tokio::test]
+async fn test_create_small_compressed_plot() {
+    use crate::plots::decompressor::DecompressorPool;
+    use crate::plots::plot_reader::PlotReader;
+    use crate::verifier::{proof_to_bytes, validate_proof};
+    use dg_xch_core::consensus::constants::SIMULATOR;
+    use dg_xch_core::plots::PlotTable;
+    let k = 20;
+    let dir = std::env::temp_dir().join(format!(
+        "dg_pos_test_small_compressed_plot_{}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).await.unwrap();
+    let plot_id = Bytes32::new([4u8; 32]);
+    let plot = DiskPlot::create(&dir, &dir, &dir, k, 7, &[5u8; 128], plot_id, &SIMULATOR)
+        .await
+        .unwrap();
+    assert_eq!(plot.compression_level(), 7);
+    let pool = Arc::new(DecompressorPool::new(1, 2));
+    let reader = PlotReader::new(plot, Some(pool.clone()), Some(pool))
+        .await
+        .unwrap();
+    assert_eq!(reader.get_lowest_stored_table(), PlotTable::Table2);
+    assert!(reader.is_compressed_table(PlotTable::Table2));
+    let f7s = reader.read_c3park(0).await.unwrap();
+    let (mut checked, mut matched) = (0, 0);
+    for f7 in f7s.iter().step_by(97) {
+        let mut challenge = [0xa5u8; 32];
+        challenge[0..8].copy_from_slice(&(f7 << (64 - k)).to_be_bytes());
+        // A compressed plot drops some proofs, skip the lookups that lost theirs
+        let Ok(qualities) = reader.fetch_qualities_for_challenge(&challenge).await else {
+            continue;
+        };
+        for (index, quality) in qualities {
+            let Ok(proof) = reader.fetch_ordered_proof(index).await else {
+                continue;
+            };
+            let v_quality =
+                validate_proof(&plot_id.bytes(), k, &proof_to_bytes(&proof), &challenge).unwrap();
+            // The quality lookup stops at the first table with a single match, which can be a
+            // false one, so only most of the qualities have to match their proofs
+            if quality == v_quality {
+                matched += 1;
+            }
+            checked += 1;
+        }
+    }
+    assert!(checked > 100);
+    assert!(matched * 10 >= checked * 9);
+    drop(reader);
+    fs::remove_dir_all(dir).await.unwrap();
+}
+
+/// Plots a C1 k32 and reads qualities and proofs back through the decompressor. Set
+/// `DG_XCH_PLOT_TEST_DIR` to a directory with room for the k32 temp files.
+#[tokio::test]
+#[ignore = "plots a compressed k32"]
+#[allow(clippy::cast_possible_truncation)]
+async fn test_create_compressed_plot() {
+    use crate::plots::decompressor::DecompressorPool;
+    use crate::plots::plot_reader::PlotReader;
+    use crate::plots::plotting::COMPRESSED_PLOT_K;
+    use crate::verifier::{proof_to_bytes, validate_proof};
+    use dg_xch_core::consensus::constants::MAINNET;
+    use dg_xch_core::plots::PlotTable;
+    use std::thread::available_parallelism;
+    let k = COMPRESSED_PLOT_K;
+    let dir = std::env::var("DG_XCH_PLOT_TEST_DIR")
+        .map_or_else(|_| std::env::temp_dir(), PathBuf::from)
+        .join(format!(
+            "dg_pos_test_compressed_plot_{}",
+            std::process::id()
+        ));
+    fs::create_dir_all(&dir).await.unwrap();
+    let plot_id = Bytes32::new([4u8; 32]);
+    let plot = DiskPlot::create(&dir, &dir, &dir, k, 1, &[5u8; 128], plot_id, &MAINNET)
+        .await
+        .unwrap();
+    assert_eq!(plot.compression_level(), 1);
+    let pool = Arc::new(DecompressorPool::new(
+        1,
+        available_parallelism()
+            .map(std::num::NonZero::get)
+            .unwrap_or(4) as u8,
+    ));
+    let reader = PlotReader::new(plot, Some(pool.clone()), Some(pool))
+        .await
+        .unwrap();
+    assert_eq!(reader.get_lowest_stored_table(), PlotTable::Table2);
+    assert!(reader.is_compressed_table(PlotTable::Table2));
+    let f7s = reader.read_c3park(0).await.unwrap();
+    let mut checked = 0;
+    for f7 in f7s.iter().step_by(997) {
+        let mut challenge = [0xa5u8; 32];
+        challenge[0..8].copy_from_slice(&(f7 << (64 - k)).to_be_bytes());
+        let qualities = reader
+            .fetch_qualities_for_challenge(&challenge)
+            .await
+            .unwrap();
+        assert!(!qualities.is_empty());
+        for (index, quality) in qualities {
+            let proof = reader.fetch_ordered_proof(index).await.unwrap();
+            let v_quality =
+                validate_proof(&plot_id.bytes(), k, &proof_to_bytes(&proof), &challenge).unwrap();
+            assert_eq!(quality, v_quality);
+            checked += 1;
+        }
+    }
+    assert!(checked > 0);
+    drop(reader);
+    fs::remove_dir_all(dir).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_resume_plot() {
+    use crate::plots::plot_reader::PlotReader;
+    use crate::plots::plotting::{progress_channel, PlotProgress};
+    use crate::verifier::{proof_to_bytes, validate_proof};
+    let k = 18;
+    let dir = std::env::temp_dir().join(format!("dg_pos_test_resume_plot_{}", std::process::id()));
+    fs::create_dir_all(&dir).await.unwrap();
+    let plot_id = Bytes32::new([9u8; 32]);
+    let memo = [5u8; 128];
+    // Sort on disk so every table is checkpointed
+    let manifest = PlotManifest::new(
+        plot_id,
+        k,
+        0,
+        &memo,
+        "plot-k18-resume.plot".to_string(),
+        &dir,
+        &dir,
+        &dir,
+        K_MIN_BUCKETS,
+        false,
+    );
+    manifest.save().await.unwrap();
+    let manifest_path = manifest.path();
+    let (forward, mut events) = progress_channel();
+    // Fails the plot on the first bucket of table 3 in phase 3, table 2 is the last one saved
+    let progress: ProgressCallback = Arc::new(move |event: &PlotProgress| {
+        forward(event);
+        assert!(event.phase != 3 || event.table != 3, "Interrupted the plot");
+    });
+    let task = tokio::spawn({
+        let manifest_path = manifest_path.clone();
+        async move { DiskPlot::resume(&manifest_path, Some(progress)).await }
+    });
+    assert!(task.await.unwrap_err().is_panic());
+    let mut last_percent = 0.0;
+    while let Ok(event) = events.try_recv() {
+        assert!(event.percent >= last_percent);
+        last_percent = event.percent;
+    }
+    assert!(last_percent > 61.0);
+    let saved = PlotManifest::load(&manifest_path).await.unwrap();
+    assert!(matches!(saved.stage, PlotStage::Phase3 { table: 2, .. }));
+    let plot = DiskPlot::resume(&manifest_path, None).await.unwrap();
+    assert!(!manifest_path.exists());
+    let path = plot.filename.as_ref().clone();
+    let reader = PlotReader::new(plot, None, None).await.unwrap();
+    let f7s = reader.read_c3park(0).await.unwrap();
+    let mut checked = 0;
+    for f7 in f7s.iter().step_by(997) {
+        let mut challenge = [0x5au8; 32];
+        challenge[0..8].copy_from_slice(&(f7 << (64 - k)).to_be_bytes());
+        let qualities = reader
+            .fetch_qualities_for_challenge(&challenge)
+            .await
+            .unwrap();
+        for (index, quality) in qualities {
+            let proof = reader.fetch_ordered_proof(index).await.unwrap();
+            let v_quality =
+                validate_proof(&plot_id.bytes(), k, &proof_to_bytes(&proof), &challenge).unwrap();
+            assert_eq!(quality, v_quality);
+            checked += 1;
+        }
+    }
+    assert!(checked > 0);
+    drop(reader);
+    fs::remove_file(path).await.unwrap();
+    let mut leftover = fs::read_dir(&dir).await.unwrap();
+    assert!(leftover.next_entry().await.unwrap().is_none());
+    fs::remove_dir_all(dir).await.unwrap();
 }
@@ -2,7 +2,7 @@ use crate::chacha8::{
     chacha8_get_keystream, chacha8_get_keystream_unsafe, chacha8_keysetup, ChachaContext,
 };
 use crate::constants::{
-    ucdiv_t, K_BC, K_EXTRA_BITS, K_F1_BLOCK_SIZE, K_F1_BLOCK_SIZE_BITS, L_TARGETS,
+    ucdiv64, ucdiv_t, K_BC, K_EXTRA_BITS, K_F1_BLOCK_SIZE, K_F1_BLOCK_SIZE_BITS, L_TARGETS,
 };
 use crate::plots::{
     get_meta_in, get_meta_out, K32Meta1, K32Meta2, K32Meta3, K32Meta4, Pair, PROOF_X_COUNT,
@@ -10,7 +10,9 @@ use crate::plots::{
 use crate::utils::bit_reader::BitReader;
 use crate::utils::radix_sort::RadixSorter;
 use crate::utils::span::Span;
-use crate::utils::{bytes_to_u64, calc_thread_vars, ThreadVars};
+use crate::utils::{
+    bytes_to_u64, calc_thread_vars, slice_u64from_bytes, slice_u64from_bytes_full, ThreadVars,
+};
 use blake3::Hasher;
 use dg_xch_core::plots::PlotTable;
 use log::debug;
@@ -76,49 +78,61 @@ impl F1Generator {
             ),
         ];
         debug!("\t\tFXData: ({thread_count})");
-        (0..thread_count)
-            .map(|i| {
-                let thread_vars = calc_thread_vars(i, thread_count, f1blocks_per_bucket);
-                F1Job {
-                    thread_vars,
-                    blocks,
-                    x_sources,
-                    x_entries,
-                    y_entries,
-                }
-            })
-            .collect::<Vec<F1Job>>()
-            .into_par_iter()
-            .for_each(|job| unsafe {
-                let entries_per_thread = job.thread_vars.count * F1ENTRIES_PER_BLOCK as usize;
-                let entries_offset = job.thread_vars.offset * F1ENTRIES_PER_BLOCK as usize;
-                let ciphertext_bytes: Span<u32> =
-                    job.blocks.range(entries_offset, entries_per_thread);
-                let mut x_slice;
-                let mut y_slice;
-                for ((x_source, x_entries), y_entries) in
-                    job.x_sources.iter().zip(job.x_entries).zip(job.y_entries)
-                {
-                    let x_start = (x_source * bucket_entry_count as u32) + entries_offset as u32;
-                    let block_index = u64::from(x_start) / u64::from(F1ENTRIES_PER_BLOCK);
-                    x_slice = x_entries.slice(entries_offset);
-                    y_slice = y_entries.slice(entries_offset);
-                    chacha8_get_keystream_unsafe(
-                        &self.context,
-                        block_index,
-                        job.thread_vars.count as u32,
-                        ciphertext_bytes,
-                    );
-                    for j in 0..entries_per_thread as isize {
-                        // Get the starting and end locations of y in bits relative to our block
-                        let x = x_start + j as u32;
-                        let mut y = u64::from(ciphertext_bytes[j].to_be());
-                        y = (y << K_EXTRA_BITS) | u64::from(x >> x_shift);
-                        x_slice[j] = x;
-                        y_slice[j] = y;
+        if self.k < 32 {
+            for (i, x_source) in x_sources.iter().enumerate() {
+                let range = i * bucket_entry_count..(i + 1) * bucket_entry_count;
+                self.generate_f1_bits(
+                    u64::from(*x_source) * bucket_entry_count as u64,
+                    &mut x_tmp_buffer[range.clone()],
+                    &mut y_tmp_buffer[range],
+                );
+            }
+        } else {
+            (0..thread_count)
+                .map(|i| {
+                    let thread_vars = calc_thread_vars(i, thread_count, f1blocks_per_bucket);
+                    F1Job {
+                        thread_vars,
+                        blocks,
+                        x_sources,
+                        x_entries,
+                        y_entries,
                     }
-                }
-            });
+                })
+                .collect::<Vec<F1Job>>()
+                .into_par_iter()
+                .for_each(|job| unsafe {
+                    let entries_per_thread = job.thread_vars.count * F1ENTRIES_PER_BLOCK as usize;
+                    let entries_offset = job.thread_vars.offset * F1ENTRIES_PER_BLOCK as usize;
+                    let ciphertext_bytes: Span<u32> =
+                        job.blocks.range(entries_offset, entries_per_thread);
+                    let mut x_slice;
+                    let mut y_slice;
+                    for ((x_source, x_entries), y_entries) in
+                        job.x_sources.iter().zip(job.x_entries).zip(job.y_entries)
+                    {
+                        let x_start =
+                            (x_source * bucket_entry_count as u32) + entries_offset as u32;
+                        let block_index = u64::from(x_start) / u64::from(F1ENTRIES_PER_BLOCK);
+                        x_slice = x_entries.slice(entries_offset);
+                        y_slice = y_entries.slice(entries_offset);
+                        chacha8_get_keystream_unsafe(
+                            &self.context,
+                            block_index,
+                            job.thread_vars.count as u32,
+                            ciphertext_bytes,
+                        );
+                        for j in 0..entries_per_thread as isize {
+                            // Get the starting and end locations of y in bits relative to our block
+                            let x = x_start + j as u32;
+                            let mut y = u64::from(ciphertext_bytes[j].to_be());
+                            y = (y << K_EXTRA_BITS) | u64::from(x >> x_shift);
+                            x_slice[j] = x;
+                            y_slice[j] = y;
+                        }
+                    }
+                });
+        }
         let merged_entry_count = bucket_entry_count * 2;
         debug!("\t\tFX Sort");
         RadixSorter::new(thread_count, merged_entry_count).sort_keyed(
@@ -130,6 +144,31 @@ impl F1Generator {
         );
         Ok(())
     }
+
+    /// F1 of the `x_out.len()` x values from `x_start` below k32, where every x takes k bits of
+    /// the keystream instead of a whole u32.
+    #[allow(clippy::cast_possible_truncation)]
+    fn generate_f1_bits(&self, x_start: u64, x_out: &mut [u32], y_out: &mut [u64]) {
+        let k = u64::from(self.k);
+        let block_bits = u64::from(K_F1_BLOCK_SIZE_BITS);
+        let start = x_start * k / block_bits;
+        let end = ucdiv64((x_start + x_out.len() as u64) * k, block_bits);
+        let mut ciphertext_bytes = Vec::new();
+        chacha8_get_keystream(
+            &self.context,
+            start,
+            (end - start) as u32,
+            &mut ciphertext_bytes,
+        );
+        let mut start_bit = (x_start * k % block_bits) as u32;
+        let x_shift = self.k - K_EXTRA_BITS;
+        for (x, (x_out, y_out)) in (x_start..).zip(x_out.iter_mut().zip(y_out)) {
+            let y = slice_u64from_bytes(&ciphertext_bytes, start_bit, u32::from(self.k));
+            *x_out = x as u32;
+            *y_out = (y << K_EXTRA_BITS) | (x >> x_shift);
+            start_bit += u32::from(self.k);
+        }
+    }
 }
 
 struct F1Job {
@@ -248,7 +287,7 @@ pub fn generate_fx_for_pairs_table2(
 ) {
     debug_assert!(y_out.len() >= pairs.len());
     debug_assert!(meta_out.len() >= pairs.len());
-    if thread_count == 1 {
+    if thread_count == 1 || (pairs.len() as usize) < thread_count {
         generate_fx_table2(k, pairs, y_in, meta_in, y_out, meta_out);
     } else {
         (0..thread_count)
@@ -285,6 +324,18 @@ fn generate_fx_table2(
             .iter_mut()
             .zip(meta_out[0..pairs.len()].iter_mut()),
     ) {
+        if k < 32 {
+            let meta = |i: u32| [u64::from(meta_in[i as usize]), 0];
+            let (y, meta) = generate_fx_bits(
+                k,
+                PlotTable::Table2,
+                y_in[pair.left as usize],
+                meta(pair.left),
+                meta(pair.right),
+            );
+            (*y_out, *meta_out) = (y, meta[0]);
+            continue;
+        }
         let l = u64::from(meta_in[pair.left as usize]);
         let r = u64::from(meta_in[pair.right as usize]);
         input[0..8].copy_from_slice(&((y_in[pair.left as usize] << 26) | (l >> 6)).to_be_bytes());
@@ -309,7 +360,7 @@ pub fn generate_fx_for_pairs_table3(
 ) {
     assert!(y_out.len() >= pairs.len());
     assert!(meta_out.len() >= pairs.len());
-    if thread_count == 1 {
+    if thread_count == 1 || (pairs.len() as usize) < thread_count {
         generate_fx_table3(k, pairs, y_in, meta_in, y_out, meta_out);
     } else {
         (0..thread_count)
@@ -346,6 +397,19 @@ fn generate_fx_table3(
             .iter_mut()
             .zip(meta_out[0..pairs.len()].iter_mut()),
     ) {
+        if k < 32 {
+            let meta = |i: u32| [meta_in[i as usize], 0];
+            let (y, meta) = generate_fx_bits(
+                k,
+                PlotTable::Table3,
+                y_in[pair.left as usize],
+                meta(pair.left),
+                meta(pair.right),
+            );
+            *y_out = y;
+            (meta_out.m0, meta_out.m1) = (meta[0], meta[1]);
+            continue;
+        }
         let l = &meta_in[pair.left as usize];
         let r = &meta_in[pair.right as usize];
         input[0..8].copy_from_slice(&((y_in[pair.left as usize] << 26) | (l >> 38)).to_be_bytes());
@@ -372,7 +436,7 @@ pub fn generate_fx_for_pairs_table4(
 ) {
     assert!(y_out.len() >= pairs.len());
     assert!(meta_out.len() >= pairs.len());
-    if thread_count == 1 {
+    if thread_count == 1 || (pairs.len() as usize) < thread_count {
         generate_fx_table4(k, pairs, y_in, meta_in, y_out, meta_out);
     } else {
         (0..thread_count)
@@ -410,6 +474,19 @@ fn generate_fx_table4(
             .iter_mut()
             .zip(meta_out[0..pairs.len()].iter_mut()),
     ) {
+        if k < 32 {
+            let meta = |i: u32| [meta_in[i as usize].m0, meta_in[i as usize].m1];
+            let (y, meta) = generate_fx_bits(
+                k,
+                PlotTable::Table4,
+                y_in[pair.left as usize],
+                meta(pair.left),
+                meta(pair.right),
+            );
+            *y_out = y;
+            (meta_out.m0, meta_out.m1) = (meta[0], meta[1]);
+            continue;
+        }
         let l = &meta_in[pair.left as usize];
         let r = &meta_in[pair.right as usize];
         input[0..8]
@@ -446,7 +523,7 @@ pub fn generate_fx_for_pairs_table5(
 ) {
     assert!(y_out.len() >= pairs.len());
     assert!(meta_out.len() >= pairs.len());
-    if thread_count == 1 {
+    if thread_count == 1 || (pairs.len() as usize) < thread_count {
         generate_fx_table5(k, pairs, y_in, meta_in, y_out, meta_out);
     } else {
         (0..thread_count)
@@ -484,6 +561,19 @@ fn generate_fx_table5(
             .iter_mut()
             .zip(meta_out[0..pairs.len()].iter_mut()),
     ) {
+        if k < 32 {
+            let meta = |i: u32| [meta_in[i as usize].m0, meta_in[i as usize].m1];
+            let (y, meta) = generate_fx_bits(
+                k,
+                PlotTable::Table5,
+                y_in[pair.left as usize],
+                meta(pair.left),
+                meta(pair.right),
+            );
+            *y_out = y;
+            (meta_out.m0, meta_out.m1) = (meta[0], meta[1]);
+            continue;
+        }
         let l = &meta_in[pair.left as usize];
         let r = &meta_in[pair.right as usize];
         input[0..8]
@@ -520,7 +610,7 @@ pub fn generate_fx_for_pairs_table6(
 ) {
     assert!(y_out.len() >= pairs.len());
     assert!(meta_out.len() >= pairs.len());
-    if thread_count == 1 {
+    if thread_count == 1 || (pairs.len() as usize) < thread_count {
         generate_fx_table6(k, pairs, y_in, meta_in, y_out, meta_out);
     } else {
         (0..thread_count)
@@ -558,6 +648,18 @@ fn generate_fx_table6(
             .iter_mut()
             .zip(meta_out[0..pairs.len()].iter_mut()),
     ) {
+        if k < 32 {
+            let meta = |i: u32| [meta_in[i as usize].m0, meta_in[i as usize].m1];
+            let (y, meta) = generate_fx_bits(
+                k,
+                PlotTable::Table6,
+                y_in[pair.left as usize],
+                meta(pair.left),
+                meta(pair.right),
+            );
+            (*y_out, *meta_out) = (y, meta[0]);
+            continue;
+        }
         let l0 = &meta_in[pair.left as usize].m0;
         let l1 = &meta_in[pair.left as usize].m1 & 0xFFFF_FFFF;
         let r0 = &meta_in[pair.right as usize].m0;
@@ -579,6 +681,50 @@ fn generate_fx_table6(
     }
 }
 
+/// Fx of a pair below k32. The metadata of an entry is packed right aligned into the words of
+/// the k32 meta types, the first 2k bits in the first word and the rest in the second.
+#[allow(clippy::cast_possible_truncation)]
+fn generate_fx_bits(k: u8, table: PlotTable, y: u64, l: [u64; 2], r: [u64; 2]) -> (u64, [u64; 2]) {
+    let k = usize::from(k);
+    let y_size = k + usize::from(K_EXTRA_BITS);
+    let meta_in_size = k * get_meta_in(table).multiplier;
+    let mut input = BitReader::new(y, y_size);
+    for meta in [l, r] {
+        let first = meta_in_size.min(2 * k);
+        input.append_value(meta[0], first);
+        if meta_in_size > first {
+            input.append_value(meta[1], meta_in_size - first);
+        }
+    }
+    let mut hasher = Hasher::new();
+    hasher.update(&input.to_bytes());
+    let hash = hasher.finalize();
+    let hash = hash.as_bytes();
+    let y = bytes_to_u64(hash) >> (64 - y_size);
+    let meta = if table < PlotTable::Table4 {
+        // The left and right metadata are concatenated
+        if meta_in_size < 2 * k {
+            [(l[0] << meta_in_size) | r[0], 0]
+        } else {
+            [l[0], r[0]]
+        }
+    } else {
+        let meta_out_size = k * get_meta_out(table).multiplier;
+        let first = meta_out_size.min(2 * k);
+        let slice =
+            |start: usize, bits: usize| slice_u64from_bytes_full(hash, start as u32, bits as u32);
+        [
+            slice(y_size, first),
+            if meta_out_size > first {
+                slice(y_size + first, meta_out_size - first)
+            } else {
+                0
+            },
+        ]
+    };
+    (y, meta)
+}
+
 #[allow(clippy::cast_possible_truncation)]
 #[must_use]
 pub fn fx_match(y_l: &u64, y_r: &u64) -> bool {
@@ -0,0 +1,522 @@
+use crate::plots::decompressor::DecompressorPool;
+use crate::verifier::proof_to_bytes;
+use crate::{PathInfo, PlotInfo};
+use dg_xch_core::blockchain::proof_of_space::{
+    calculate_pos_challenge, passes_plot_filter, ProofBytes, ProofOfSpace,
+};
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::config::HarvesterConfig;
+use dg_xch_core::consensus::constants::ConsensusConstants;
+use dg_xch_core::consensus::pot_iterations::{
+    calculate_iterations_quality, calculate_sp_interval_iters,
+};
+use dg_xch_core::protocols::harvester::NewSignagePointHarvester;
+use futures_util::stream::FuturesUnordered;
+use futures_util::StreamExt;
+use log::{debug, error, info, warn};
+use std::io::ErrorKind;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread::available_parallelism;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::sync::{Notify, Semaphore, SemaphorePermit};
+use tokio::time::{timeout, Instant};
+
+#[derive(Debug, Clone)]
+pub struct HarvestSchedulerConfig {
+    /// Decompressors in the shared pool, also the number of compressed lookups run at once
+    pub decompressor_depth: u8,
+    /// Threads used by each decompressor
+    pub decompressor_threads: u8,
+    /// Lookups against uncompressed plots run at once
+    pub max_disk_lookups: usize,
+    /// Time allowed for all lookups of a single plot, proofs found later are likely too late
+    /// for the farmer to use on the signage point
+    pub lookup_timeout: Duration,
+}
+impl Default for HarvestSchedulerConfig {
+    #[allow(clippy::cast_possible_truncation)]
+    fn default() -> Self {
+        Self {
+            decompressor_depth: 1,
+            decompressor_threads: available_parallelism()
+                .unwrap_or_else(|_| {
+                    NonZeroUsize::new(8).expect("Safe Value Expected for Non Zero Usize")
+                })
+                .get()
+                .min(u8::MAX as usize) as u8,
+            max_disk_lookups: 64,
+            lookup_timeout: Duration::from_secs(5),
+        }
+    }
+}
+impl From<&HarvesterConfig> for HarvestSchedulerConfig {
+    #[allow(clippy::cast_possible_truncation)]
+    fn from(config: &HarvesterConfig) -> Self {
+        let mut scheduler_config = Self::default();
+        if config.parallel_decompressor_count > 0 {
+            scheduler_config.decompressor_depth =
+                config.parallel_decompressor_count.min(u16::from(u8::MAX)) as u8;
+        }
+        if config.decompressor_thread_count > 0 {
+            scheduler_config.decompressor_threads =
+                config.decompressor_thread_count.min(u16::from(u8::MAX)) as u8;
+        }
+        if config.num_threads > 0 {
+            scheduler_config.max_disk_lookups = config.num_threads;
+        }
+        scheduler_config
+    }
+}
+
+/// Limits the lookups sharing the decompressor pool. Proof fetches are let through before any
+/// waiting quality lookup, so a proof that passed its iterations check is not stuck behind the
+/// quality lookups of other plots.
+#[derive(Debug)]
+struct LookupQueue {
+    permits: Semaphore,
+    waiting_proofs: AtomicUsize,
+    proofs_started: Notify,
+}
+impl LookupQueue {
+    fn new(permits: usize) -> Self {
+        Self {
+            permits: Semaphore::new(permits.max(1)),
+            waiting_proofs: AtomicUsize::new(0),
+            proofs_started: Notify::new(),
+        }
+    }
+
+    async fn acquire_quality(&self) -> SemaphorePermit<'_> {
+        loop {
+            let proofs_started = self.proofs_started.notified();
+            if self.waiting_proofs.load(Ordering::Acquire) > 0 {
+                proofs_started.await;
+                continue;
+            }
+            let permit = self
+                .permits
+                .acquire()
+                .await
+                .expect("Lookup Semaphore is never closed");
+            if self.waiting_proofs.load(Ordering::Acquire) == 0 {
+                return permit;
+            }
+        }
+    }
+
+    async fn acquire_proof(&self) -> SemaphorePermit<'_> {
+        let _waiting = WaitingProof::new(self);
+        self.permits
+            .acquire()
+            .await
+            .expect("Lookup Semaphore is never closed")
+    }
+}
+
+/// Counts a proof fetch as waiting until it is dropped, so a fetch cancelled while waiting for
+/// its permit does not hold back the quality lookups forever.
+struct WaitingProof<'a>(&'a LookupQueue);
+impl<'a> WaitingProof<'a> {
+    fn new(queue: &'a LookupQueue) -> Self {
+        queue.waiting_proofs.fetch_add(1, Ordering::AcqRel);
+        Self(queue)
+    }
+}
+impl Drop for WaitingProof<'_> {
+    fn drop(&mut self) {
+        if self.0.waiting_proofs.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.proofs_started.notify_waiters();
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HarvestProof {
+    pub path: PathInfo,
+    pub quality: Bytes32,
+    pub proof: ProofOfSpace,
+    pub is_partial: bool,
+    pub compression_level: u8,
+}
+
+/// Time spent on a plot that passed the plot filter.
+#[derive(Debug, Clone)]
+pub struct PlotLookup {
+    pub path: PathInfo,
+    pub compression_level: u8,
+    pub qualities: usize,
+    pub proofs: usize,
+    pub quality_time: Duration,
+    pub proof_time: Duration,
+    pub error: Option<String>,
+}
+impl PlotLookup {
+    #[must_use]
+    pub fn total_time(&self) -> Duration {
+        self.quality_time + self.proof_time
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HarvestCounts {
+    pub og_passed: usize,
+    pub og_total: usize,
+    pub pool_passed: usize,
+    pub pool_total: usize,
+    pub compressed_passed: usize,
+    pub compressed_total: usize,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct HarvestResult {
+    pub proofs: Vec<HarvestProof>,
+    pub lookups: Vec<PlotLookup>,
+    pub counts: HarvestCounts,
+    pub elapsed: Duration,
+}
+impl HarvestResult {
+    /// The `count` lookups that took the longest, slowest first.
+    #[must_use]
+    pub fn slowest(&self, count: usize) -> Vec<&PlotLookup> {
+        let mut lookups: Vec<&PlotLookup> = self.lookups.iter().collect();
+        lookups.sort_by_key(|l| std::cmp::Reverse(l.total_time()));
+        lookups.truncate(count);
+        lookups
+    }
+}
+
+/// Runs the lookups for a signage point across a set of plots. Compressed plots share a single
+/// `DecompressorPool`, plots should be loaded with `decompressor_pool` so their readers use it.
+#[derive(Debug)]
+pub struct HarvestScheduler {
+    config: HarvestSchedulerConfig,
+    decompressor_pool: OnceLock<Arc<DecompressorPool>>,
+    compressed_lookups: Arc<LookupQueue>,
+    disk_lookups: Arc<Semaphore>,
+}
+impl HarvestScheduler {
+    #[must_use]
+    pub fn new(config: HarvestSchedulerConfig) -> Self {
+        Self {
+            compressed_lookups: Arc::new(LookupQueue::new(usize::from(config.decompressor_depth))),
+            disk_lookups: Arc::new(Semaphore::new(config.max_disk_lookups.max(1))),
+            decompressor_pool: OnceLock::new(),
+            config,
+        }
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &HarvestSchedulerConfig {
+        &self.config
+    }
+
+    /// The shared pool, allocated on first use as every decompressor is preallocated for k32.
+    pub fn decompressor_pool(&self) -> Arc<DecompressorPool> {
+        self.decompressor_pool
+            .get_or_init(|| {
+                Arc::new(DecompressorPool::new(
+                    self.config.decompressor_depth.max(1),
+                    self.config.decompressor_threads,
+                ))
+            })
+            .clone()
+    }
+
+    /// Looks up proofs for `signage_point` in every plot that passes the plot filter.
+    pub async fn harvest(
+        &self,
+        constants: &'static ConsensusConstants,
+        signage_point: Arc<NewSignagePointHarvester>,
+        plots: Vec<(PathInfo, Arc<PlotInfo>)>,
+    ) -> HarvestResult {
+        let (found, mut receiver) = unbounded_channel();
+        let mut result = self
+            .harvest_streaming(constants, signage_point, plots, found)
+            .await;
+        while let Ok(proof) = receiver.try_recv() {
+            result.proofs.push(proof);
+        }
+        result
+    }
+
+    /// Same as `harvest`, but every proof is sent on `found` as soon as it is read instead of
+    /// being collected into `HarvestResult::proofs`, which is left empty.
+    pub async fn harvest_streaming(
+        &self,
+        constants: &'static ConsensusConstants,
+        signage_point: Arc<NewSignagePointHarvester>,
+        plots: Vec<(PathInfo, Arc<PlotInfo>)>,
+        found: UnboundedSender<HarvestProof>,
+    ) -> HarvestResult {
+        let start = Instant::now();
+        let mut result = HarvestResult::default();
+        let mut jobs = FuturesUnordered::new();
+        for (path, plot_info) in plots {
+            let header = plot_info.reader.header();
+            let compression_level = header.compression_level();
+            let passed = passes_plot_filter(
+                signage_point.filter_prefix_bits,
+                header.id(),
+                signage_point.challenge_hash,
+                signage_point.sp_hash,
+            );
+            let counts = &mut result.counts;
+            if plot_info.pool_public_key.is_some() {
+                counts.og_total += 1;
+                counts.og_passed += usize::from(passed);
+            } else if compression_level > 0 {
+                counts.compressed_total += 1;
+                counts.compressed_passed += usize::from(passed);
+            } else {
+                counts.pool_total += 1;
+                counts.pool_passed += usize::from(passed);
+            }
+            if !passed {
+                continue;
+            }
+            let lookup_timeout = self.config.lookup_timeout;
+            let qualities_read = Arc::new(OnceLock::new());
+            let task = PlotTask {
+                path: path.clone(),
+                plot_info,
+                signage_point: signage_point.clone(),
+                constants,
+                compressed_lookups: self.compressed_lookups.clone(),
+                disk_lookups: self.disk_lookups.clone(),
+                found: found.clone(),
+                qualities_read: qualities_read.clone(),
+            };
+            jobs.push(tokio::spawn(async move {
+                match timeout(lookup_timeout, task.run()).await {
+                    Ok(lookup) => lookup,
+                    Err(_) => {
+                        // Whatever the quality lookup did not use was spent fetching proofs
+                        let (qualities, quality_time) =
+                            qualities_read.get().copied().unwrap_or((0, lookup_timeout));
+                        PlotLookup {
+                            path,
+                            compression_level,
+                            qualities,
+                            proofs: 0,
+                            quality_time,
+                            proof_time: lookup_timeout.saturating_sub(quality_time),
+                            error: Some(format!("Timed out after {lookup_timeout:?}")),
+                        }
+                    }
+                }
+            }));
+        }
+        while let Some(joined) = jobs.next().await {
+            match joined {
+                Ok(lookup) => {
+                    if let Some(e) = &lookup.error {
+                        debug!("Failed to read plot {}: {e}", lookup.path.file_name);
+                    }
+                    result.lookups.push(lookup);
+                }
+                Err(e) => {
+                    error!("Failed to join reader thread: {e:?}");
+                }
+            }
+        }
+        result.elapsed = start.elapsed();
+        result
+    }
+}
+
+struct PlotTask {
+    path: PathInfo,
+    plot_info: Arc<PlotInfo>,
+    signage_point: Arc<NewSignagePointHarvester>,
+    constants: &'static ConsensusConstants,
+    compressed_lookups: Arc<LookupQueue>,
+    disk_lookups: Arc<Semaphore>,
+    found: UnboundedSender<HarvestProof>,
+    /// Set once the qualities are read, with their count and the time taken
+    qualities_read: Arc<OnceLock<(usize, Duration)>>,
+}
+impl PlotTask {
+    async fn run(self) -> PlotLookup {
+        let reader = &self.plot_info.reader;
+        let header = reader.header();
+        let plot_id = header.id();
+        let k = header.k();
+        let memo = header.memo();
+        let compression_level = header.compression_level();
+        let mut lookup = PlotLookup {
+            path: self.path.clone(),
+            compression_level,
+            qualities: 0,
+            proofs: 0,
+            quality_time: Duration::ZERO,
+            proof_time: Duration::ZERO,
+            error: None,
+        };
+        let sp_challenge_hash = calculate_pos_challenge(
+            plot_id,
+            self.signage_point.challenge_hash,
+            self.signage_point.sp_hash,
+        );
+        debug!(
+            "Starting Search for challenge {sp_challenge_hash} in plot {}",
+            self.path.file_name
+        );
+        let start = Instant::now();
+        let qualities = {
+            let _permit = if compression_level > 0 {
+                self.compressed_lookups.acquire_quality().await
+            } else {
+                self.disk_lookups
+                    .acquire()
+                    .await
+                    .expect("Lookup Semaphore is never closed")
+            };
+            reader
+                .fetch_qualities_for_challenge(sp_challenge_hash.as_ref())
+                .await
+        };
+        lookup.quality_time = start.elapsed();
+        let qualities = match qualities {
+            Ok(qualities) => qualities,
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => {
+                lookup.error = Some(format!("{e:?}"));
+                return lookup;
+            }
+        };
+        lookup.qualities = qualities.len();
+        let _ = self
+            .qualities_read
+            .set((lookup.qualities, lookup.quality_time));
+        if qualities.is_empty() {
+            return lookup;
+        }
+        debug!(
+            "Plot: {} Qualities Found: {}",
+            self.path.file_name,
+            qualities.len()
+        );
+        let mut difficulty = self.signage_point.difficulty;
+        let mut sub_slot_iters = self.signage_point.sub_slot_iters;
+        let mut is_partial = false;
+        if let Some(pool_contract_puzzle_hash) = &memo.pool_contract_puzzle_hash {
+            if let Some(pool_difficulty) = self
+                .signage_point
+                .pool_difficulties
+                .iter()
+                .find(|p| p.pool_contract_puzzle_hash == *pool_contract_puzzle_hash)
+            {
+                difficulty = pool_difficulty.difficulty;
+                sub_slot_iters = pool_difficulty.sub_slot_iters;
+                is_partial = true;
+                debug!("Setting Difficulty for pool: {difficulty}");
+            } else {
+                warn!("Failed to find Pool Contract Difficulties for PH: {pool_contract_puzzle_hash} ");
+            }
+        }
+        let sp_interval_iters = match calculate_sp_interval_iters(self.constants, sub_slot_iters) {
+            Ok(sp_interval_iters) => sp_interval_iters,
+            Err(e) => {
+                lookup.error = Some(format!("{e:?}"));
+                return lookup;
+            }
+        };
+        let start = Instant::now();
+        for (index, quality) in qualities {
+            let required_iters = calculate_iterations_quality(
+                self.constants.difficulty_constant_factor,
+                quality,
+                k,
+                difficulty,
+                self.signage_point.sp_hash,
+            );
+            if required_iters >= sp_interval_iters {
+                debug!("Not Enough Iterations: {required_iters} > {sp_interval_iters}");
+                continue;
+            }
+            info!(
+                "Plot: {}, Passed Required Iterations, Loading Index: {index}",
+                self.path.file_name
+            );
+            let proof = {
+                let _permit = if compression_level > 0 {
+                    self.compressed_lookups.acquire_proof().await
+                } else {
+                    self.disk_lookups
+                        .acquire()
+                        .await
+                        .expect("Lookup Semaphore is never closed")
+                };
+                reader.fetch_ordered_proof(index).await
+            };
+            match proof {
+                Ok(proof) => {
+                    lookup.proofs += 1;
+                    // Fails when the receiver is gone, nobody is waiting for the proof anymore
+                    let _ = self.found.send(HarvestProof {
+                        path: self.path.clone(),
+                        quality,
+                        proof: ProofOfSpace {
+                            challenge: sp_challenge_hash,
+                            pool_contract_puzzle_hash: self.plot_info.pool_contract_puzzle_hash,
+                            plot_public_key: self.plot_info.plot_public_key,
+                            pool_public_key: self.plot_info.pool_public_key,
+                            proof: ProofBytes::from(proof_to_bytes(&proof)),
+                            size: k,
+                        },
+                        is_partial,
+                        compression_level,
+                    });
+                }
+                Err(e) => {
+                    error!("Failed to read Proof: {e:?}");
+                    lookup.error = Some(format!("{e:?}"));
+                }
+            }
+        }
+        lookup.proof_time = start.elapsed();
+        lookup
+    }
+}
+
+#[tokio::test]
+async fn test_lookup_queue_prioritises_proofs() {
+    let queue = Arc::new(LookupQueue::new(1));
+    let order = Arc::new(std::sync::Mutex::new(vec![]));
+    let held = queue.acquire_quality().await;
+    let quality = {
+        let (queue, order) = (queue.clone(), order.clone());
+        tokio::spawn(async move {
+            let _permit = queue.acquire_quality().await;
+            order.lock().unwrap().push("quality");
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let proof = {
+        let (queue, order) = (queue.clone(), order.clone());
+        tokio::spawn(async move {
+            let _permit = queue.acquire_proof().await;
+            order.lock().unwrap().push("proof");
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    drop(held);
+    proof.await.unwrap();
+    quality.await.unwrap();
+    assert_eq!(*order.lock().unwrap(), vec!["proof", "quality"]);
+}
+
+#[tokio::test]
+async fn test_lookup_queue_cancelled_proof() {
+    let queue = LookupQueue::new(1);
+    let held = queue.acquire_quality().await;
+    let cancelled = timeout(Duration::from_millis(50), queue.acquire_proof()).await;
+    assert!(cancelled.is_err());
+    assert_eq!(queue.waiting_proofs.load(Ordering::Acquire), 0);
+    drop(held);
+    let quality = timeout(Duration::from_secs(1), queue.acquire_quality()).await;
+    assert!(quality.is_ok());
+}
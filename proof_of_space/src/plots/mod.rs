@@ -9,8 +9,14 @@ pub mod d_tables;
 pub mod decompressor;
 pub mod disk_plot;
 pub mod fx_generator;
+pub mod harvest_scheduler;
+pub mod plot_check;
+pub mod plot_converter;
+pub mod plot_info;
+pub mod plot_manager;
 pub mod plot_reader;
-// pub mod plotting;
+pub mod plotting;
+pub mod remote_plot;
 pub const PROOF_X_COUNT: usize = 64;
 const BB_PLOT_VERSION: u32 = 1;
 const MAX_MATCHES_MULTIPLIER: f64 = 0.005;
@@ -0,0 +1,266 @@
+use crate::plots::decompressor::DecompressorPool;
+use crate::plots::disk_plot::DiskPlot;
+use crate::plots::plot_reader::PlotReader;
+use crate::verifier::{proof_to_bytes, validate_proof};
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::traits::SizedBytes;
+use dg_xch_core::utils::hash_256;
+use futures_util::stream::{iter, StreamExt};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone)]
+pub struct PlotCheckConfig {
+    /// Number of challenges run against every plot
+    pub challenges: u32,
+    /// Index of the first challenge, challenges are the hashes of consecutive indexes
+    pub challenge_start: u32,
+    /// Plots checked at the same time by `check_plots`
+    pub parallel_plots: usize,
+    /// Required to fetch proofs from compressed plots
+    pub decompressor: Option<Arc<DecompressorPool>>,
+}
+impl Default for PlotCheckConfig {
+    fn default() -> Self {
+        Self {
+            challenges: 30,
+            challenge_start: 0,
+            parallel_plots: 4,
+            decompressor: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlotCheckError {
+    /// Challenge that failed, None when the plot could not be opened
+    pub challenge_index: Option<u32>,
+    pub message: String,
+}
+
+/// Outcome of checking a plot. A healthy plot finds about one valid proof per challenge.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlotCheckReport {
+    pub path: PathBuf,
+    pub plot_id: Option<Bytes32>,
+    pub k: u8,
+    pub compression_level: u8,
+    pub challenges: u32,
+    pub qualities_found: u64,
+    pub valid_proofs: u64,
+    pub invalid_proofs: u64,
+    /// Valid proofs per challenge
+    pub proof_ratio: f64,
+    /// Time to fetch a proof, which includes decompression for compressed plots
+    pub average_proof_ms: f64,
+    pub max_proof_ms: f64,
+    pub errors: Vec<PlotCheckError>,
+}
+impl PlotCheckReport {
+    /// Report for a plot that could not be opened.
+    #[must_use]
+    pub fn failed(path: &Path, error: &Error) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            plot_id: None,
+            k: 0,
+            compression_level: 0,
+            challenges: 0,
+            qualities_found: 0,
+            valid_proofs: 0,
+            invalid_proofs: 0,
+            proof_ratio: 0.0,
+            average_proof_ms: 0.0,
+            max_proof_ms: 0.0,
+            errors: vec![PlotCheckError {
+                challenge_index: None,
+                message: error.to_string(),
+            }],
+        }
+    }
+
+    #[must_use]
+    pub fn is_healthy(&self) -> bool {
+        self.errors.is_empty() && self.invalid_proofs == 0
+    }
+}
+
+/// Challenge number `index` of a plot check.
+#[must_use]
+pub fn check_challenge(index: u32) -> Bytes32 {
+    Bytes32::new(hash_256(index.to_be_bytes()))
+}
+
+/// Runs `config.challenges` challenges against the plot at `path`, validating every proof found.
+/// Read errors and proofs that fail validation are recorded in the report, an error is only
+/// returned when the plot can not be opened.
+#[allow(clippy::cast_precision_loss)]
+pub async fn check_plot(path: &Path, config: &PlotCheckConfig) -> Result<PlotCheckReport, Error> {
+    let reader = PlotReader::new(
+        DiskPlot::new(path).await?,
+        config.decompressor.clone(),
+        config.decompressor.clone(),
+    )
+    .await?;
+    let plot_id = reader.plot_id();
+    let k = reader.header().k();
+    let compression_level = reader.header().compression_level();
+    if compression_level > 0 && config.decompressor.is_none() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "A decompressor is required to check compressed plots",
+        ));
+    }
+    let mut report = PlotCheckReport {
+        path: path.to_path_buf(),
+        plot_id: Some(plot_id),
+        k,
+        compression_level,
+        challenges: config.challenges,
+        qualities_found: 0,
+        valid_proofs: 0,
+        invalid_proofs: 0,
+        proof_ratio: 0.0,
+        average_proof_ms: 0.0,
+        max_proof_ms: 0.0,
+        errors: vec![],
+    };
+    let mut proof_time = 0.0;
+    let mut proof_count = 0u64;
+    for challenge_index in config.challenge_start..config.challenge_start + config.challenges {
+        let challenge = check_challenge(challenge_index);
+        let mut error = |message: String| {
+            warn!("{}: challenge {challenge_index}: {message}", path.display());
+            report.errors.push(PlotCheckError {
+                challenge_index: Some(challenge_index),
+                message,
+            });
+        };
+        let qualities = match reader
+            .fetch_qualities_for_challenge(challenge.as_ref())
+            .await
+        {
+            Ok(qualities) => qualities,
+            // Most challenges have no matching f7 in the plot
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => {
+                error(format!("Failed to fetch qualities: {e}"));
+                continue;
+            }
+        };
+        let timer = Instant::now();
+        let proofs = match reader.fetch_proofs_for_challenge(challenge.as_ref()).await {
+            Ok(proofs) => proofs,
+            Err(e) => {
+                error(format!("Failed to fetch proofs: {e}"));
+                continue;
+            }
+        };
+        if !proofs.is_empty() {
+            let elapsed = timer.elapsed().as_secs_f64() * 1000.0;
+            proof_time += elapsed;
+            proof_count += proofs.len() as u64;
+            report.max_proof_ms = report.max_proof_ms.max(elapsed / proofs.len() as f64);
+        }
+        if proofs.len() < qualities.len() {
+            error(format!(
+                "Found {} qualities but only {} proofs",
+                qualities.len(),
+                proofs.len()
+            ));
+        }
+        for proof in &proofs {
+            match validate_proof(
+                &plot_id.bytes(),
+                k,
+                &proof_to_bytes(proof),
+                challenge.as_ref(),
+            ) {
+                Ok(quality) if qualities.iter().any(|(_, q)| *q == quality) => {
+                    report.valid_proofs += 1;
+                }
+                Ok(quality) => {
+                    report.invalid_proofs += 1;
+                    error(format!(
+                        "Proof quality {quality} does not match any quality"
+                    ));
+                }
+                Err(e) => {
+                    report.invalid_proofs += 1;
+                    error(format!("Invalid proof: {e}"));
+                }
+            }
+        }
+        report.qualities_found += qualities.len() as u64;
+    }
+    if config.challenges > 0 {
+        report.proof_ratio = report.valid_proofs as f64 / f64::from(config.challenges);
+    }
+    if proof_count > 0 {
+        report.average_proof_ms = proof_time / proof_count as f64;
+    }
+    info!(
+        "{}: {} valid proofs in {} challenges, ratio {:.2}",
+        path.display(),
+        report.valid_proofs,
+        config.challenges,
+        report.proof_ratio
+    );
+    Ok(report)
+}
+
+/// Checks every plot in `paths`, `config.parallel_plots` at a time. Plots that can not be opened
+/// are reported as failed. Reports are returned in the order of `paths`.
+pub async fn check_plots(paths: &[PathBuf], config: &PlotCheckConfig) -> Vec<PlotCheckReport> {
+    iter(paths)
+        .map(|path| async move {
+            check_plot(path, config)
+                .await
+                .unwrap_or_else(|e| PlotCheckReport::failed(path, &e))
+        })
+        .buffered(config.parallel_plots.max(1))
+        .collect()
+        .await
+}
+
+#[tokio::test]
+async fn test_check_invalid_plot() {
+    let path = std::env::temp_dir().join(format!("dg_pos_test_check_{}.plot", std::process::id()));
+    tokio::fs::write(&path, [0u8; 512]).await.unwrap();
+    let reports = check_plots(std::slice::from_ref(&path), &PlotCheckConfig::default()).await;
+    assert_eq!(reports.len(), 1);
+    assert!(!reports[0].is_healthy());
+    assert_eq!(reports[0].errors[0].challenge_index, None);
+    let json = serde_json::to_string(&reports).unwrap();
+    let parsed: Vec<PlotCheckReport> = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, reports);
+    tokio::fs::remove_file(path).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_check_plot() {
+    use dg_xch_core::consensus::constants::SIMULATOR;
+    let k = 18;
+    let dir = std::env::temp_dir().join(format!("dg_pos_test_check_plot_{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let plot_id = Bytes32::new([8u8; 32]);
+    let plot = DiskPlot::create(&dir, &dir, &dir, k, 0, &[3u8; 128], plot_id, &SIMULATOR)
+        .await
+        .unwrap();
+    let path = plot.filename.as_ref().clone();
+    drop(plot);
+    let report = check_plot(&path, &PlotCheckConfig::default())
+        .await
+        .unwrap();
+    assert!(report.is_healthy(), "{:?}", report.errors);
+    assert_eq!(report.plot_id, Some(plot_id));
+    assert_eq!(report.k, k);
+    assert_eq!(report.challenges, 30);
+    assert!(report.valid_proofs > 0);
+    assert_eq!(report.invalid_proofs, 0);
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
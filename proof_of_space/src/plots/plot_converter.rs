@@ -0,0 +1,1583 @@
+use crate::constants::{ucdiv64, K_ENTRIES_PER_PARK, K_MAX_BUCKETS, K_OFFSET_SIZE};
+use crate::encoding::{line_point_to_square, square_to_line_point128};
+use crate::entry_sizes::EntrySizes;
+use crate::plots::compression::{compression_level_fits_k, get_compression_info_for_level};
+use crate::plots::decompressor::DecompressorPool;
+use crate::plots::disk_plot::{new_plot_header, plot_filename, write_plot_header, DiskPlot};
+use crate::plots::fx_generator::fx_match;
+use crate::plots::plot_check::{check_plot, PlotCheckConfig};
+use crate::plots::plot_reader::PlotReader;
+use crate::plots::plotting::bucket_sorter::BucketSorter;
+use crate::plots::plotting::phase1::{
+    generate_f1, match_table, new_sorter, pos_bits, sort_entry_size, table_entry_size, y_bits,
+};
+use crate::plots::plotting::phase3::{write_left_values, write_park, ParkLayout};
+use crate::plots::plotting::{
+    entry_bytes, write_bits, EntryFileReader, EntryFileWriter, EntryPacker, EntryUnpacker,
+    PlotContext, MAX_PLOTTING_COMPRESSION_LEVEL, PLOT_TABLES,
+};
+use crate::utils::open_read_only_async;
+use dg_xch_core::plots::{PlotFile, PlotHeader, PlotTable};
+use log::{info, warn};
+use std::io::{Error, ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufWriter};
+use tokio::time::Instant;
+
+#[derive(Debug, Clone)]
+pub struct PlotConversionConfig {
+    /// Compression level of the new plot, 0 writes an uncompressed plot
+    pub compression_level: u8,
+    /// Directory for the sort buckets, the final directory is used when not set
+    pub tmp_dir: Option<PathBuf>,
+    pub num_buckets: u32,
+    /// Sorts every table in memory instead of spilling the buckets to `tmp_dir`
+    pub in_memory: bool,
+    /// Challenges checked against the new plot before it is moved into place, 0 skips the check
+    pub verify_challenges: u32,
+    /// Required to lower the compression level and to check plots converted to a compressed level
+    pub decompressor: Option<Arc<DecompressorPool>>,
+}
+impl Default for PlotConversionConfig {
+    fn default() -> Self {
+        Self {
+            compression_level: 0,
+            tmp_dir: None,
+            num_buckets: K_MAX_BUCKETS,
+            in_memory: false,
+            verify_challenges: 30,
+            decompressor: None,
+        }
+    }
+}
+
+/// Rewrites the plot at `source` into `final_dir` at `config.compression_level`, keeping its
+/// plot id, memo and proofs. The line points of every stored table are rebuilt from the values
+/// of the table below them, raising the level truncates the x values of the lowest table
+/// further and drops table 1 from uncompressed plots. Lowering the level computes tables 1 to 3
+/// of the plot again to recover the x bits the source no longer stores. The checkpoint tables
+/// are copied as is.
+pub async fn convert_plot(
+    source: &Path,
+    final_dir: &Path,
+    config: &PlotConversionConfig,
+) -> Result<DiskPlot<File>, Error> {
+    let reader = PlotReader::new(
+        DiskPlot::new(source).await?,
+        config.decompressor.clone(),
+        None,
+    )
+    .await?;
+    let header = reader.header().clone();
+    let k = header.k();
+    let level = config.compression_level;
+    let source_level = match &header {
+        PlotHeader::V1(_) => 0,
+        PlotHeader::V2(h) => h.compression_level,
+        PlotHeader::GHv2_5(_) => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Gigahorse Plots are Not Supported",
+            ))
+        }
+    };
+    if level > MAX_PLOTTING_COMPRESSION_LEVEL {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("Compression level {level} is not supported"),
+        ));
+    }
+    if level > 0 && !compression_level_fits_k(k, level) {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("Compression level {level} does not fit k{k} plots"),
+        ));
+    }
+    if level < source_level {
+        if source_level >= 9 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("Can not lower the compression level of a level {source_level} plot"),
+            ));
+        }
+        if config.decompressor.is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "A decompressor is required to lower the compression level",
+            ));
+        }
+    }
+    if !final_dir.exists() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("Final directory {} does not exist", final_dir.display()),
+        ));
+    }
+    let plot_id = header.id();
+    let memo = header.memo().to_bytes()?;
+    let filename = plot_filename(k, level, plot_id);
+    let tmp_dir = config
+        .tmp_dir
+        .clone()
+        .unwrap_or_else(|| final_dir.to_path_buf());
+    let ctx = PlotContext {
+        k,
+        plot_id,
+        num_buckets: config.num_buckets,
+        in_memory: config.in_memory,
+        compression_level: level,
+        sort_prefix: tmp_dir.join(format!("{filename}.convert")),
+        table_files: vec![PathBuf::new(), tmp_dir.join(format!("{filename}.convert"))],
+        tmp_dir,
+        progress: None,
+    };
+    let tmp_filename = final_dir.join(format!("{filename}.tmp"));
+    let final_filename = final_dir.join(&filename);
+    if final_filename.exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("Plot {} already exists", final_filename.display()),
+        ));
+    }
+    info!(
+        "Converting plot {} from compression level {source_level} to {level}",
+        source.display()
+    );
+    let timer = Instant::now();
+    let written = async {
+        write_converted_plot(source, &reader, &ctx, &memo, &tmp_filename).await?;
+        if config.verify_challenges > 0 {
+            let report = check_plot(
+                &tmp_filename,
+                &PlotCheckConfig {
+                    challenges: config.verify_challenges,
+                    decompressor: config.decompressor.clone(),
+                    ..Default::default()
+                },
+            )
+            .await?;
+            // A compressed plot can not decompress the proofs through two equal truncated x
+            // values, it only has to return no invalid proofs
+            let healthy = report.is_healthy()
+                || (level > 0
+                    && report.invalid_proofs == 0
+                    && (report.valid_proofs > 0 || report.qualities_found == 0));
+            if !healthy {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Converted plot failed {} of {} proofs: {:?}",
+                        report.invalid_proofs, report.qualities_found, report.errors
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+    .await;
+    if let Err(e) = written {
+        if tmp_filename.exists() {
+            fs::remove_file(&tmp_filename).await?;
+        }
+        return Err(e);
+    }
+    fs::rename(&tmp_filename, &final_filename).await?;
+    info!(
+        "Converted plot {} in {:.8} seconds",
+        final_filename.display(),
+        timer.elapsed().as_secs_f64()
+    );
+    DiskPlot::new(&final_filename).await
+}
+
+#[allow(clippy::too_many_lines)]
+#[allow(clippy::cast_possible_truncation)]
+async fn write_converted_plot(
+    source: &Path,
+    reader: &PlotReader<File, DiskPlot<File>>,
+    ctx: &PlotContext,
+    memo: &[u8],
+    path: &Path,
+) -> Result<(), Error> {
+    let k = ctx.k;
+    let level = ctx.compression_level;
+    let index_bits = pos_bits(k);
+    let lp_bits = EntrySizes::line_point_size_bits(u32::from(k));
+    let mut header = new_plot_header(ctx.plot_id, k, level, memo)?;
+    let header_bytes = write_plot_header(&header, memo)?;
+    let mut file = BufWriter::new(File::create(path).await?);
+    file.write_all(&header_bytes).await?;
+    let mut offset = header_bytes.len() as u64;
+    let mut pointers = [0u64; 10];
+    // New values of the previous table by their index in the source plot, the new positions
+    // of its entries or the truncated x pairs of a dropped table 1
+    let mut values: Option<(PathBuf, u32, u64)> = None;
+    for table in &PLOT_TABLES[..6] {
+        let table = *table;
+        let table_index = table as u8 + 1;
+        if (table as u8) < reader.get_lowest_stored_table() as u8 {
+            continue;
+        }
+        let table_timer = Instant::now();
+        let park_count =
+            reader.plot_file().table_size(table) / reader.get_park_size_for_table(table);
+        let next_path = ctx.work_file(&format!("convert.l{table_index}"));
+        if table == PlotTable::Table1 && level > 0 {
+            // Table 1 is dropped, its x pairs are truncated into the values table 2 points to
+            let shift = u32::from(k) - get_compression_info_for_level(level).entry_size_bits;
+            let mut writer = EntryFileWriter::create(&next_path, entry_bytes(lp_bits)).await?;
+            for park_index in 0..park_count {
+                let line_points = reader.read_line_point_park(table, park_index).await?;
+                let mut out = vec![0u8; line_points.len() * entry_bytes(lp_bits)];
+                for (line_point, dst) in line_points
+                    .iter()
+                    .zip(out.chunks_exact_mut(entry_bytes(lp_bits)))
+                {
+                    let (x1, x2) = line_point_to_square(*line_point);
+                    EntryPacker::new(dst)
+                        .put(square_to_line_point128(x1 >> shift, x2 >> shift), lp_bits);
+                }
+                writer.write(&out).await?;
+            }
+            values = Some((next_path, lp_bits, writer.finish().await?));
+            info!(
+                "\tDropped table {table_index} in {:.8} seconds",
+                table_timer.elapsed().as_secs_f64()
+            );
+            continue;
+        }
+        let lp_sorter = new_sorter(
+            ctx,
+            &format!("convert.t{table_index}.lp"),
+            entry_bytes(lp_bits + index_bits),
+        )
+        .await?;
+        if let Some((values_path, value_bits, value_count)) = &values {
+            join_line_points(
+                reader,
+                ctx,
+                table,
+                park_count,
+                values_path,
+                *value_bits,
+                *value_count,
+                &lp_sorter,
+            )
+            .await?;
+            fs::remove_file(values_path).await?;
+        } else if level < reader.compression_level() {
+            // The compressed table only keeps truncated x values, the full ones are computed
+            // again from the plot id
+            let xs = recover_x_values(reader, ctx, table, park_count).await?;
+            if level == 0 {
+                pointers[PlotTable::Table1 as usize] = offset;
+                let count = write_x_pairs(ctx, &xs, &lp_sorter, &mut file, &mut offset).await?;
+                info!(
+                    "\tWrote {count} line points for table 1 in {:.8} seconds",
+                    table_timer.elapsed().as_secs_f64()
+                );
+            } else {
+                let shift = u32::from(k) - get_compression_info_for_level(level).entry_size_bits;
+                for bucket in 0..xs.num_buckets() {
+                    let data = xs.read_bucket(bucket).await?;
+                    let mut out = vec![0u8; data.len() / xs.entry_size() * lp_sorter.entry_size()];
+                    for (src, dst) in data
+                        .chunks_exact(xs.entry_size())
+                        .zip(out.chunks_exact_mut(lp_sorter.entry_size()))
+                    {
+                        let mut unpacker = EntryUnpacker::new(src);
+                        let n = unpacker.take(index_bits);
+                        let x_values = [0; 4].map(|_| unpacker.take_u64(u32::from(k)));
+                        EntryPacker::new(dst)
+                            .put(compressed_line_point(&x_values, shift), lp_bits)
+                            .put(n, index_bits);
+                    }
+                    lp_sorter.insert(&out).await?;
+                }
+            }
+            xs.cleanup().await?;
+        } else {
+            // The lowest stored table of the source, compressed x pairs are truncated to the
+            // entry size of the new level
+            let shift = if reader.is_compressed_table(table) {
+                get_compression_info_for_level(reader.compression_level()).entry_size_bits
+                    - get_compression_info_for_level(level).entry_size_bits
+            } else {
+                0
+            };
+            let mut n = 0u64;
+            for park_index in 0..park_count {
+                let line_points = reader.read_line_point_park(table, park_index).await?;
+                let mut out = vec![0u8; line_points.len() * lp_sorter.entry_size()];
+                for (line_point, dst) in line_points
+                    .iter()
+                    .zip(out.chunks_exact_mut(lp_sorter.entry_size()))
+                {
+                    let line_point = if shift > 0 {
+                        let (a, b) = line_point_to_square(*line_point);
+                        let (a1, a2) = line_point_to_square(u128::from(a));
+                        let (b1, b2) = line_point_to_square(u128::from(b));
+                        compressed_line_point(&[a1, a2, b1, b2], shift)
+                    } else {
+                        *line_point
+                    };
+                    EntryPacker::new(dst)
+                        .put(line_point, lp_bits)
+                        .put(u128::from(n), index_bits);
+                    n += 1;
+                }
+                lp_sorter.insert(&out).await?;
+            }
+        }
+        lp_sorter.flush().await?;
+        pointers[table as usize] = offset;
+        if level > 0 && table == PlotTable::Table2 {
+            pointers[PlotTable::Table1 as usize] = offset;
+        }
+        let count = write_table(ctx, table, &lp_sorter, &mut file, &mut offset, &next_path).await?;
+        lp_sorter.cleanup().await?;
+        values = Some((next_path, index_bits, count));
+        info!(
+            "\tWrote {count} line points for table {table_index} in {:.8} seconds",
+            table_timer.elapsed().as_secs_f64()
+        );
+    }
+    let (positions, _, position_count) =
+        values.ok_or_else(|| Error::new(ErrorKind::InvalidData, "Plot has no stored tables"))?;
+
+    // Table 7 keeps its f7 order, only the positions into table 6 change
+    let timer = Instant::now();
+    pointers[PlotTable::Table7 as usize] = offset;
+    offset += write_table7(reader, ctx, &positions, position_count, &mut file).await?;
+    fs::remove_file(&positions).await?;
+    info!(
+        "\tWrote table 7 in {:.8} seconds",
+        timer.elapsed().as_secs_f64()
+    );
+
+    // The checkpoint tables only depend on the f7 values and are copied
+    let plot_file = reader.plot_file();
+    let c1_address = plot_file.table_address(PlotTable::C1);
+    let end = plot_file.table_address(PlotTable::C3) + plot_file.table_size(PlotTable::C3);
+    for table in [PlotTable::C1, PlotTable::C2, PlotTable::C3] {
+        pointers[table as usize] = offset + plot_file.table_address(table) - c1_address;
+    }
+    let mut source_file = open_read_only_async(source).await?;
+    source_file.seek(SeekFrom::Start(c1_address)).await?;
+    let copied = tokio::io::copy(&mut source_file.take(end - c1_address), &mut file).await?;
+    if copied != end - c1_address {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("Checkpoint tables of {} are truncated", source.display()),
+        ));
+    }
+    offset += copied;
+
+    match &mut header {
+        PlotHeader::V1(h) => h.table_begin_pointers = pointers,
+        PlotHeader::V2(h) => {
+            h.table_begin_pointers = pointers;
+            for (i, size) in h.table_sizes.iter_mut().enumerate() {
+                let end = pointers.get(i + 1).copied().unwrap_or(offset);
+                *size = end - pointers[i];
+            }
+        }
+        PlotHeader::GHv2_5(_) => {}
+    }
+    let header_bytes = write_plot_header(&header, memo)?;
+    file.flush().await?;
+    let mut file = file.into_inner();
+    file.seek(SeekFrom::Start(0)).await?;
+    file.write_all(&header_bytes).await?;
+    file.set_len(offset).await?;
+    file.sync_all().await?;
+    Ok(())
+}
+
+/// Rebuilds the line points of `table` from the new values of the two entries of the previous
+/// table each of them points to. The back pointers are sorted on their first and then their
+/// second half so both lookups are a single pass over `values`.
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::cast_possible_truncation)]
+async fn join_line_points(
+    reader: &PlotReader<File, DiskPlot<File>>,
+    ctx: &PlotContext,
+    table: PlotTable,
+    park_count: u64,
+    values: &Path,
+    value_bits: u32,
+    value_count: u64,
+    lp_sorter: &BucketSorter,
+) -> Result<(), Error> {
+    let k = ctx.k;
+    let index_bits = pos_bits(k);
+    let lp_bits = EntrySizes::line_point_size_bits(u32::from(k));
+    let table_index = table as u8 + 1;
+    let first_sorter = new_sorter(
+        ctx,
+        &format!("convert.t{table_index}.a"),
+        entry_bytes(index_bits * 3),
+    )
+    .await?;
+    let mut n = 0u64;
+    for park_index in 0..park_count {
+        let line_points = reader.read_line_point_park(table, park_index).await?;
+        let mut out = vec![0u8; line_points.len() * first_sorter.entry_size()];
+        for (line_point, dst) in line_points
+            .iter()
+            .zip(out.chunks_exact_mut(first_sorter.entry_size()))
+        {
+            let (a, b) = line_point_to_square(*line_point);
+            EntryPacker::new(dst)
+                .put(u128::from(a), index_bits)
+                .put(u128::from(b), index_bits)
+                .put(u128::from(n), index_bits);
+            n += 1;
+        }
+        first_sorter.insert(&out).await?;
+    }
+    first_sorter.flush().await?;
+
+    let second_sorter = new_sorter(
+        ctx,
+        &format!("convert.t{table_index}.b"),
+        entry_bytes(index_bits + value_bits + index_bits),
+    )
+    .await?;
+    let mut lookup = SortedLookup::open(values, value_bits, value_count).await?;
+    for bucket in 0..first_sorter.num_buckets() {
+        let data = first_sorter.read_bucket(bucket).await?;
+        let mut out =
+            vec![0u8; data.len() / first_sorter.entry_size() * second_sorter.entry_size()];
+        for (src, dst) in data
+            .chunks_exact(first_sorter.entry_size())
+            .zip(out.chunks_exact_mut(second_sorter.entry_size()))
+        {
+            let mut unpacker = EntryUnpacker::new(src);
+            let a = unpacker.take_u64(index_bits);
+            let b = unpacker.take(index_bits);
+            let n = unpacker.take(index_bits);
+            EntryPacker::new(dst)
+                .put(b, index_bits)
+                .put(lookup.get(a).await?, value_bits)
+                .put(n, index_bits);
+        }
+        second_sorter.insert(&out).await?;
+    }
+    second_sorter.flush().await?;
+    first_sorter.cleanup().await?;
+
+    let mut lookup = SortedLookup::open(values, value_bits, value_count).await?;
+    let max_line_point = 1u128 << lp_bits;
+    for bucket in 0..second_sorter.num_buckets() {
+        let data = second_sorter.read_bucket(bucket).await?;
+        let mut out = vec![0u8; data.len() / second_sorter.entry_size() * lp_sorter.entry_size()];
+        for (src, dst) in data
+            .chunks_exact(second_sorter.entry_size())
+            .zip(out.chunks_exact_mut(lp_sorter.entry_size()))
+        {
+            let mut unpacker = EntryUnpacker::new(src);
+            let b = unpacker.take_u64(index_bits);
+            let a_value = unpacker.take(value_bits);
+            let n = unpacker.take(index_bits);
+            let b_value = lookup.get(b).await?;
+            let line_point = square_to_line_point128(a_value as u64, b_value as u64);
+            if line_point >= max_line_point {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Line point {line_point} does not fit in {lp_bits} bits"),
+                ));
+            }
+            EntryPacker::new(dst)
+                .put(line_point, lp_bits)
+                .put(n, index_bits);
+        }
+        lp_sorter.insert(&out).await?;
+    }
+    second_sorter.cleanup().await?;
+    Ok(())
+}
+
+/// Writes the sorted (line point, source index) entries of `lp_sorter` as parks and the new
+/// position of every entry, in source order, to `positions`. Returns the number of entries.
+async fn write_table(
+    ctx: &PlotContext,
+    table: PlotTable,
+    lp_sorter: &BucketSorter,
+    file: &mut BufWriter<File>,
+    offset: &mut u64,
+    positions: &Path,
+) -> Result<u64, Error> {
+    let k = ctx.k;
+    let index_bits = pos_bits(k);
+    let lp_bits = EntrySizes::line_point_size_bits(u32::from(k));
+    let layout = ParkLayout::new(k, table, ctx.compression_level)?;
+    let index_sorter = new_sorter(
+        ctx,
+        &format!("convert.t{}.idx", table as u8 + 1),
+        entry_bytes(index_bits * 2),
+    )
+    .await?;
+    let mut park: Vec<u128> = Vec::with_capacity(K_ENTRIES_PER_PARK as usize);
+    let mut new_index = 0u64;
+    for bucket in 0..lp_sorter.num_buckets() {
+        let data = lp_sorter.read_bucket(bucket).await?;
+        let mut out = vec![0u8; data.len() / lp_sorter.entry_size() * index_sorter.entry_size()];
+        for (src, dst) in data
+            .chunks_exact(lp_sorter.entry_size())
+            .zip(out.chunks_exact_mut(index_sorter.entry_size()))
+        {
+            let mut unpacker = EntryUnpacker::new(src);
+            park.push(unpacker.take(lp_bits));
+            EntryPacker::new(dst)
+                .put(unpacker.take(index_bits), index_bits)
+                .put(u128::from(new_index), index_bits);
+            new_index += 1;
+            if park.len() == K_ENTRIES_PER_PARK as usize {
+                let buf = write_park(k, &layout, &park)?;
+                file.write_all(&buf).await?;
+                *offset += buf.len() as u64;
+                park.clear();
+            }
+        }
+        index_sorter.insert(&out).await?;
+    }
+    if !park.is_empty() {
+        let buf = write_park(k, &layout, &park)?;
+        file.write_all(&buf).await?;
+        *offset += buf.len() as u64;
+    }
+    index_sorter.flush().await?;
+    write_left_values(&index_sorter, index_bits, index_bits, positions).await?;
+    index_sorter.cleanup().await?;
+    Ok(new_index)
+}
+
+/// Maps the table 6 positions stored in table 7 to their new positions and writes the parks,
+/// returning the size of the table.
+#[allow(clippy::cast_possible_truncation)]
+async fn write_table7(
+    reader: &PlotReader<File, DiskPlot<File>>,
+    ctx: &PlotContext,
+    positions: &Path,
+    position_count: u64,
+    file: &mut BufWriter<File>,
+) -> Result<u64, Error> {
+    let k = ctx.k;
+    let p7_bits = pos_bits(k);
+    let num_entries = table7_entry_count(reader).await?;
+    let position_sorter = new_sorter(ctx, "convert.t7.pos", entry_bytes(p7_bits * 2)).await?;
+    let mut index = 0u64;
+    for park_index in 0..ucdiv64(num_entries, u64::from(K_ENTRIES_PER_PARK)) {
+        let entries = reader.load_p7park(park_index as usize).await?;
+        let entries = &entries[..(num_entries - index).min(entries.len() as u64) as usize];
+        let mut out = vec![0u8; entries.len() * position_sorter.entry_size()];
+        for (position, dst) in entries
+            .iter()
+            .zip(out.chunks_exact_mut(position_sorter.entry_size()))
+        {
+            EntryPacker::new(dst)
+                .put(u128::from(*position), p7_bits)
+                .put(u128::from(index), p7_bits);
+            index += 1;
+        }
+        position_sorter.insert(&out).await?;
+    }
+    position_sorter.flush().await?;
+
+    let index_sorter = new_sorter(ctx, "convert.t7.idx", entry_bytes(p7_bits * 2)).await?;
+    let mut lookup = SortedLookup::open(positions, p7_bits, position_count).await?;
+    for bucket in 0..position_sorter.num_buckets() {
+        let data = position_sorter.read_bucket(bucket).await?;
+        let mut out = vec![0u8; data.len()];
+        for (src, dst) in data
+            .chunks_exact(position_sorter.entry_size())
+            .zip(out.chunks_exact_mut(index_sorter.entry_size()))
+        {
+            let mut unpacker = EntryUnpacker::new(src);
+            let position = unpacker.take_u64(p7_bits);
+            let index = unpacker.take(p7_bits);
+            EntryPacker::new(dst)
+                .put(index, p7_bits)
+                .put(lookup.get(position).await?, p7_bits);
+        }
+        index_sorter.insert(&out).await?;
+    }
+    index_sorter.flush().await?;
+    position_sorter.cleanup().await?;
+
+    let park_size = EntrySizes::calculate_park7_size(u32::from(k)) as usize;
+    let mut park = vec![0u8; park_size];
+    let mut park_count = 0usize;
+    let mut size = 0u64;
+    for bucket in 0..index_sorter.num_buckets() {
+        let data = index_sorter.read_bucket(bucket).await?;
+        for entry in data.chunks_exact(index_sorter.entry_size()) {
+            let mut unpacker = EntryUnpacker::new(entry);
+            unpacker.take(p7_bits);
+            let position = unpacker.take(p7_bits);
+            write_bits(&mut park, park_count * p7_bits as usize, position, p7_bits);
+            park_count += 1;
+            if park_count == K_ENTRIES_PER_PARK as usize {
+                file.write_all(&park).await?;
+                size += park_size as u64;
+                park.fill(0);
+                park_count = 0;
+            }
+        }
+    }
+    if park_count > 0 {
+        file.write_all(&park).await?;
+        size += park_size as u64;
+    }
+    index_sorter.cleanup().await?;
+    Ok(size)
+}
+
+/// Number of entries in table 7, the last park of the table is padded.
+async fn table7_entry_count(reader: &PlotReader<File, DiskPlot<File>>) -> Result<u64, Error> {
+    let mut num_entries = 0u64;
+    for c3_park in 0..reader.get_maximum_c1_entries() {
+        num_entries += reader.read_c3park(c3_park).await?.len() as u64;
+    }
+    Ok(num_entries)
+}
+
+/// Line point of the compressed table for the four x values of a table 3 entry, the x values
+/// are truncated by `shift` bits.
+#[allow(clippy::cast_possible_truncation)]
+fn compressed_line_point(x_values: &[u64; 4], shift: u32) -> u128 {
+    square_to_line_point128(
+        square_to_line_point128(x_values[0] >> shift, x_values[1] >> shift) as u64,
+        square_to_line_point128(x_values[2] >> shift, x_values[3] >> shift) as u64,
+    )
+}
+
+/// Recovers the four x values of every entry of the compressed `table` by computing tables 1 to
+/// 3 again from the plot id, the x values of a table 3 entry truncate to the line point of its
+/// compressed entry. Both are sorted by their line point and joined in a single pass. Entries
+/// sharing their line point with another table 3 entry are told apart by the y values of the
+/// entries they match in table 4. Returns the index of every entry followed by its four x
+/// values, sorted by the index.
+#[allow(clippy::too_many_lines)]
+#[allow(clippy::cast_possible_truncation)]
+async fn recover_x_values(
+    reader: &PlotReader<File, DiskPlot<File>>,
+    ctx: &PlotContext,
+    table: PlotTable,
+    park_count: u64,
+) -> Result<BucketSorter, Error> {
+    let k = ctx.k;
+    let index_bits = pos_bits(k);
+    let lp_bits = EntrySizes::line_point_size_bits(u32::from(k));
+    let x_bits = u32::from(k) * 4;
+    let candidates = forward_table3(reader, ctx).await?;
+    let xs = new_sorter(ctx, "convert.xs", entry_bytes(index_bits + x_bits)).await?;
+    let ties = new_sorter(
+        ctx,
+        "convert.ties",
+        entry_bytes(index_bits + y_bits(k) + x_bits),
+    )
+    .await?;
+    // The y of every entry by its index, shared entries are resolved later and keep a 0
+    let ys_path = ctx.work_file("convert.ys");
+    let mut ys = EntryFileWriter::create(&ys_path, entry_bytes(y_bits(k))).await?;
+    let max_entries = park_count * u64::from(K_ENTRIES_PER_PARK);
+    let mut is_tie = vec![0u64; ucdiv64(max_entries, 64) as usize];
+    let mut tie_count = 0u64;
+    let mut cursor = SortedEntries::new(&candidates);
+    let mut run_line_point = None;
+    let mut run: Vec<(u64, [u64; 4])> = vec![];
+    let mut position = 0u64;
+    for park_index in 0..park_count {
+        let line_points = reader.read_line_point_park(table, park_index).await?;
+        let mut xs_out = vec![];
+        let mut ties_out = vec![];
+        let mut ys_out = vec![0u8; line_points.len() * entry_bytes(y_bits(k))];
+        for (line_point, y_dst) in line_points
+            .iter()
+            .zip(ys_out.chunks_exact_mut(entry_bytes(y_bits(k))))
+        {
+            if run_line_point != Some(*line_point) {
+                run_line_point = Some(*line_point);
+                run.clear();
+                // Table 3 entries dropped by back propagation have no compressed entry
+                while let Some(entry) = cursor.peek().await? {
+                    let mut unpacker = EntryUnpacker::new(entry);
+                    let candidate_line_point = unpacker.take(lp_bits);
+                    if candidate_line_point > *line_point {
+                        break;
+                    }
+                    if candidate_line_point == *line_point {
+                        let y = unpacker.take_u64(y_bits(k));
+                        run.push((y, [0; 4].map(|_| unpacker.take_u64(u32::from(k)))));
+                    }
+                    cursor.advance();
+                }
+            }
+            let tie = match run.as_slice() {
+                [] => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "No table 3 entry has the line point of compressed entry {position}"
+                        ),
+                    ))
+                }
+                [(y, x_values)] => {
+                    let mut entry = vec![0u8; xs.entry_size()];
+                    let mut packer = EntryPacker::new(&mut entry);
+                    packer.put(u128::from(position), index_bits);
+                    for x in x_values {
+                        packer.put(u128::from(*x), u32::from(k));
+                    }
+                    xs_out.extend(entry);
+                    EntryPacker::new(y_dst).put(u128::from(*y), y_bits(k));
+                    false
+                }
+                run => {
+                    for (y, x_values) in run {
+                        let mut entry = vec![0u8; ties.entry_size()];
+                        let mut packer = EntryPacker::new(&mut entry);
+                        packer
+                            .put(u128::from(position), index_bits)
+                            .put(u128::from(*y), y_bits(k));
+                        for x in x_values {
+                            packer.put(u128::from(*x), u32::from(k));
+                        }
+                        ties_out.extend(entry);
+                    }
+                    tie_count += 1;
+                    true
+                }
+            };
+            is_tie[(position / 64) as usize] |= u64::from(tie) << (position % 64);
+            position += 1;
+        }
+        xs.insert(&xs_out).await?;
+        ties.insert(&ties_out).await?;
+        ys.write(&ys_out).await?;
+    }
+    let entry_count = ys.finish().await?;
+    candidates.cleanup().await?;
+    ties.flush().await?;
+    if tie_count > 0 {
+        resolve_ties(
+            reader,
+            ctx,
+            table,
+            &ties,
+            &is_tie,
+            &ys_path,
+            entry_count,
+            &xs,
+        )
+        .await?;
+    }
+    ties.cleanup().await?;
+    fs::remove_file(&ys_path).await?;
+    xs.flush().await?;
+    Ok(xs)
+}
+
+/// Computes tables 1 to 3 of the plot and returns the table 3 entries as their line point in the
+/// compressed table, followed by their y and x values, sorted by the line point.
+#[allow(clippy::cast_possible_truncation)]
+async fn forward_table3(
+    reader: &PlotReader<File, DiskPlot<File>>,
+    ctx: &PlotContext,
+) -> Result<BucketSorter, Error> {
+    let k = ctx.k;
+    let lp_bits = EntrySizes::line_point_size_bits(u32::from(k));
+    let shift =
+        u32::from(k) - get_compression_info_for_level(reader.compression_level()).entry_size_bits;
+    let mut left = new_sorter(ctx, "convert.f1", sort_entry_size(k, 1)).await?;
+    generate_f1(ctx, &left).await?;
+    left.flush().await?;
+    for table_index in 1..3u8 {
+        let right = new_sorter(
+            ctx,
+            &format!("convert.f{}", table_index + 1),
+            sort_entry_size(k, table_index + 1),
+        )
+        .await?;
+        // Only the matches are needed, the back pointers are thrown away
+        let path = ctx.work_file(&format!("convert.f{table_index}.bp"));
+        let mut writer = EntryFileWriter::create(&path, table_entry_size(k, table_index)).await?;
+        match_table(ctx, table_index, &left, &right, &mut writer).await?;
+        writer.finish().await?;
+        fs::remove_file(&path).await?;
+        right.flush().await?;
+        left.cleanup().await?;
+        left = right;
+    }
+    let sorter = new_sorter(
+        ctx,
+        "convert.f3.lp",
+        entry_bytes(lp_bits + y_bits(k) + u32::from(k) * 4),
+    )
+    .await?;
+    for bucket in 0..left.num_buckets() {
+        let data = left.read_bucket(bucket).await?;
+        let mut out = vec![0u8; data.len() / left.entry_size() * sorter.entry_size()];
+        for (src, dst) in data
+            .chunks_exact(left.entry_size())
+            .zip(out.chunks_exact_mut(sorter.entry_size()))
+        {
+            let mut unpacker = EntryUnpacker::new(src);
+            let y = unpacker.take(y_bits(k));
+            unpacker.take(pos_bits(k) + K_OFFSET_SIZE);
+            // The metadata of a table 3 entry is its four x values
+            let x_values = [0; 4].map(|_| unpacker.take_u64(u32::from(k)));
+            let mut packer = EntryPacker::new(dst);
+            packer
+                .put(compressed_line_point(&x_values, shift), lp_bits)
+                .put(y, y_bits(k));
+            for x in x_values {
+                packer.put(u128::from(x), u32::from(k));
+            }
+        }
+        sorter.insert(&out).await?;
+    }
+    sorter.flush().await?;
+    left.cleanup().await?;
+    Ok(sorter)
+}
+
+/// Picks the x values of the compressed entries in `ties` that share their line point with
+/// other table 3 entries. Every table 4 entry pairing a shared entry with a resolved one gives
+/// the y of the resolved partner, which only matches the y of the right table 3 entry. The
+/// chosen x values are inserted into `xs`.
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::too_many_lines)]
+#[allow(clippy::cast_possible_truncation)]
+async fn resolve_ties(
+    reader: &PlotReader<File, DiskPlot<File>>,
+    ctx: &PlotContext,
+    table: PlotTable,
+    ties: &BucketSorter,
+    is_tie: &[u64],
+    ys_path: &Path,
+    entry_count: u64,
+    xs: &BucketSorter,
+) -> Result<(), Error> {
+    let k = ctx.k;
+    let index_bits = pos_bits(k);
+    let is_tie = |p: u64| is_tie[(p / 64) as usize] & (1 << (p % 64)) != 0;
+
+    // Pairs of a shared entry and a resolved partner, sorted by the partner
+    let next_table = PLOT_TABLES[table as usize + 1];
+    let park_count =
+        reader.plot_file().table_size(next_table) / reader.get_park_size_for_table(next_table);
+    let partners = new_sorter(ctx, "convert.partners", entry_bytes(index_bits * 2)).await?;
+    for park_index in 0..park_count {
+        let line_points = reader.read_line_point_park(next_table, park_index).await?;
+        let mut out = vec![];
+        for line_point in line_points {
+            let (a, b) = line_point_to_square(line_point);
+            if let Some(position) = [a, b].iter().find(|p| **p >= entry_count) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Back pointer {position} is past the end of the compressed table"),
+                ));
+            }
+            let (partner, tie) = match (is_tie(a), is_tie(b)) {
+                (true, false) => (b, a),
+                (false, true) => (a, b),
+                _ => continue,
+            };
+            let mut entry = vec![0u8; partners.entry_size()];
+            EntryPacker::new(&mut entry)
+                .put(u128::from(partner), index_bits)
+                .put(u128::from(tie), index_bits);
+            out.extend(entry);
+        }
+        partners.insert(&out).await?;
+    }
+    partners.flush().await?;
+
+    // The y of every partner, sorted by the shared entry
+    let partner_ys = new_sorter(
+        ctx,
+        "convert.partner_ys",
+        entry_bytes(index_bits + y_bits(k)),
+    )
+    .await?;
+    let mut ys = SortedLookup::open(ys_path, y_bits(k), entry_count).await?;
+    for bucket in 0..partners.num_buckets() {
+        let data = partners.read_bucket(bucket).await?;
+        let mut out = vec![0u8; data.len() / partners.entry_size() * partner_ys.entry_size()];
+        for (src, dst) in data
+            .chunks_exact(partners.entry_size())
+            .zip(out.chunks_exact_mut(partner_ys.entry_size()))
+        {
+            let mut unpacker = EntryUnpacker::new(src);
+            let partner = unpacker.take_u64(index_bits);
+            EntryPacker::new(dst)
+                .put(unpacker.take(index_bits), index_bits)
+                .put(ys.get(partner).await?, y_bits(k));
+        }
+        partner_ys.insert(&out).await?;
+    }
+    partner_ys.flush().await?;
+    partners.cleanup().await?;
+
+    let mut tie_entries = SortedEntries::new(ties);
+    let mut partner_entries = SortedEntries::new(&partner_ys);
+    let mut unresolved = 0u64;
+    let mut out = vec![];
+    while let Some(entry) = tie_entries.peek().await? {
+        let position = EntryUnpacker::new(entry).take_u64(index_bits);
+        let mut candidates = vec![];
+        while let Some(entry) = tie_entries.peek().await? {
+            let mut unpacker = EntryUnpacker::new(entry);
+            if unpacker.take_u64(index_bits) != position {
+                break;
+            }
+            let y = unpacker.take_u64(y_bits(k));
+            candidates.push((y, [0; 4].map(|_| unpacker.take_u64(u32::from(k)))));
+            tie_entries.advance();
+        }
+        let mut partner_ys = vec![];
+        while let Some(entry) = partner_entries.peek().await? {
+            let mut unpacker = EntryUnpacker::new(entry);
+            if unpacker.take_u64(index_bits) != position {
+                break;
+            }
+            partner_ys.push(unpacker.take_u64(y_bits(k)));
+            partner_entries.advance();
+        }
+        // The left entry of a match is the one with the smaller y
+        let matching: Vec<&[u64; 4]> = candidates
+            .iter()
+            .filter(|(y, _)| {
+                partner_ys
+                    .iter()
+                    .all(|partner| fx_match(y.min(partner), y.max(partner)))
+            })
+            .map(|(_, x_values)| x_values)
+            .collect();
+        if matching.len() != 1 {
+            unresolved += 1;
+        }
+        let x_values = matching.first().copied().unwrap_or(&candidates[0].1);
+        let mut entry = vec![0u8; xs.entry_size()];
+        let mut packer = EntryPacker::new(&mut entry);
+        packer.put(u128::from(position), index_bits);
+        for x in x_values {
+            packer.put(u128::from(*x), u32::from(k));
+        }
+        out.extend(entry);
+        if out.len() >= xs.entry_size() << 16 {
+            xs.insert(&out).await?;
+            out.clear();
+        }
+    }
+    xs.insert(&out).await?;
+    partner_ys.cleanup().await?;
+    if unresolved > 0 {
+        warn!(
+            "{unresolved} compressed entries share their line point with table 3 entries their \
+            table 4 matches do not tell apart, they take the x values of the first one"
+        );
+    }
+    Ok(())
+}
+
+/// Writes table 1 of an uncompressed plot from the x values recovered by `recover_x_values`
+/// and inserts the line points of table 2 into `lp_sorter`. A pair of x values shared by
+/// several table 3 entries is written once. Returns the number of table 1 line points.
+#[allow(clippy::cast_possible_truncation)]
+async fn write_x_pairs(
+    ctx: &PlotContext,
+    xs: &BucketSorter,
+    lp_sorter: &BucketSorter,
+    file: &mut BufWriter<File>,
+    offset: &mut u64,
+) -> Result<u64, Error> {
+    let k = ctx.k;
+    let index_bits = pos_bits(k);
+    let lp_bits = EntrySizes::line_point_size_bits(u32::from(k));
+    let pair_sorter =
+        new_sorter(ctx, "convert.t1.lp", entry_bytes(lp_bits + index_bits + 1)).await?;
+    let pair_size = pair_sorter.entry_size();
+    for bucket in 0..xs.num_buckets() {
+        let data = xs.read_bucket(bucket).await?;
+        let mut out = vec![0u8; data.len() / xs.entry_size() * pair_size * 2];
+        for (src, dst) in data
+            .chunks_exact(xs.entry_size())
+            .zip(out.chunks_exact_mut(pair_size * 2))
+        {
+            let mut unpacker = EntryUnpacker::new(src);
+            let n = unpacker.take(index_bits);
+            let [x1, x2, x3, x4] = [0; 4].map(|_| unpacker.take_u64(u32::from(k)));
+            let (left, right) = dst.split_at_mut(pair_size);
+            EntryPacker::new(left)
+                .put(square_to_line_point128(x1, x2), lp_bits)
+                .put(n, index_bits)
+                .put(0, 1);
+            EntryPacker::new(right)
+                .put(square_to_line_point128(x3, x4), lp_bits)
+                .put(n, index_bits)
+                .put(1, 1);
+        }
+        pair_sorter.insert(&out).await?;
+    }
+    pair_sorter.flush().await?;
+
+    // The position of a pair in table 1 is the index of its line point after removing duplicates
+    let layout = ParkLayout::new(k, PlotTable::Table1, 0)?;
+    let index_sorter = new_sorter(ctx, "convert.t1.idx", entry_bytes(index_bits * 2 + 1)).await?;
+    let index_size = index_sorter.entry_size();
+    let mut park: Vec<u128> = Vec::with_capacity(K_ENTRIES_PER_PARK as usize);
+    let mut last = None;
+    let mut count = 0u64;
+    for bucket in 0..pair_sorter.num_buckets() {
+        let data = pair_sorter.read_bucket(bucket).await?;
+        let mut out = vec![0u8; data.len() / pair_size * index_size];
+        for (src, dst) in data
+            .chunks_exact(pair_size)
+            .zip(out.chunks_exact_mut(index_size))
+        {
+            let mut unpacker = EntryUnpacker::new(src);
+            let line_point = unpacker.take(lp_bits);
+            if last != Some(line_point) {
+                last = Some(line_point);
+                count += 1;
+                park.push(line_point);
+                if park.len() == K_ENTRIES_PER_PARK as usize {
+                    let buf = write_park(k, &layout, &park)?;
+                    file.write_all(&buf).await?;
+                    *offset += buf.len() as u64;
+                    park.clear();
+                }
+            }
+            EntryPacker::new(dst)
+                .put(unpacker.take(index_bits), index_bits)
+                .put(unpacker.take(1), 1)
+                .put(u128::from(count - 1), index_bits);
+        }
+        index_sorter.insert(&out).await?;
+    }
+    if !park.is_empty() {
+        let buf = write_park(k, &layout, &park)?;
+        file.write_all(&buf).await?;
+        *offset += buf.len() as u64;
+    }
+    index_sorter.flush().await?;
+    pair_sorter.cleanup().await?;
+
+    // Both pairs of an entry sort next to each other, the left one first
+    let max_line_point = 1u128 << lp_bits;
+    for bucket in 0..index_sorter.num_buckets() {
+        let data = index_sorter.read_bucket(bucket).await?;
+        if data.len() % (index_size * 2) != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Table 2 entries of bucket {bucket} are missing a pair"),
+            ));
+        }
+        let mut out = vec![0u8; data.len() / (index_size * 2) * lp_sorter.entry_size()];
+        for (src, dst) in data
+            .chunks_exact(index_size * 2)
+            .zip(out.chunks_exact_mut(lp_sorter.entry_size()))
+        {
+            let mut left = EntryUnpacker::new(&src[..index_size]);
+            let n = left.take(index_bits);
+            left.take(1);
+            let mut right = EntryUnpacker::new(&src[index_size..]);
+            right.take(index_bits + 1);
+            let line_point =
+                square_to_line_point128(left.take_u64(index_bits), right.take_u64(index_bits));
+            if line_point >= max_line_point {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Line point {line_point} does not fit in {lp_bits} bits"),
+                ));
+            }
+            EntryPacker::new(dst)
+                .put(line_point, lp_bits)
+                .put(n, index_bits);
+        }
+        lp_sorter.insert(&out).await?;
+    }
+    index_sorter.cleanup().await?;
+    Ok(count)
+}
+
+/// Walks the entries of a flushed sorter in sorted order, one bucket at a time.
+struct SortedEntries<'a> {
+    sorter: &'a BucketSorter,
+    bucket: usize,
+    data: Vec<u8>,
+    offset: usize,
+}
+impl<'a> SortedEntries<'a> {
+    fn new(sorter: &'a BucketSorter) -> Self {
+        Self {
+            sorter,
+            bucket: 0,
+            data: vec![],
+            offset: 0,
+        }
+    }
+    /// The next entry, None once every bucket is read.
+    async fn peek(&mut self) -> Result<Option<&[u8]>, Error> {
+        while self.offset >= self.data.len() {
+            if self.bucket == self.sorter.num_buckets() {
+                return Ok(None);
+            }
+            self.data = self.sorter.read_bucket(self.bucket).await?;
+            self.bucket += 1;
+            self.offset = 0;
+        }
+        Ok(Some(
+            &self.data[self.offset..self.offset + self.sorter.entry_size()],
+        ))
+    }
+    fn advance(&mut self) {
+        self.offset += self.sorter.entry_size();
+    }
+}
+
+/// Reads the values of a file written in index order for a non decreasing run of indexes.
+struct SortedLookup {
+    reader: EntryFileReader,
+    entry_size: usize,
+    bits: u32,
+    chunk: Vec<u8>,
+    start: u64,
+}
+impl SortedLookup {
+    async fn open(path: &Path, bits: u32, count: u64) -> Result<Self, Error> {
+        let entry_size = entry_bytes(bits);
+        Ok(Self {
+            reader: EntryFileReader::open(path, entry_size, count).await?,
+            entry_size,
+            bits,
+            chunk: vec![],
+            start: 0,
+        })
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    async fn get(&mut self, index: u64) -> Result<u128, Error> {
+        if index < self.start {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Lookup of {index} is before the current chunk at {}",
+                    self.start
+                ),
+            ));
+        }
+        while index >= self.start + (self.chunk.len() / self.entry_size) as u64 {
+            self.start += (self.chunk.len() / self.entry_size) as u64;
+            self.chunk = self.reader.read_chunk().await?;
+            if self.chunk.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Back pointer {index} is past the end of the previous table"),
+                ));
+            }
+        }
+        let start = (index - self.start) as usize * self.entry_size;
+        Ok(EntryUnpacker::new(&self.chunk[start..start + self.entry_size]).take(self.bits))
+    }
+}
+
+#[tokio::test]
+async fn test_convert_plot() {
+    use crate::constants::K_MIN_BUCKETS;
+    use dg_xch_core::blockchain::sized_bytes::Bytes32;
+    use dg_xch_core::consensus::constants::SIMULATOR;
+    use dg_xch_core::plots::PlotMemo;
+    use dg_xch_core::traits::SizedBytes;
+    let k = 18;
+    let dir = std::env::temp_dir().join(format!("dg_pos_test_convert_plot_{}", std::process::id()));
+    let tmp_dir = dir.join("tmp");
+    let source_dir = dir.join("source");
+    let final_dir = dir.join("final");
+    for d in [&tmp_dir, &source_dir, &final_dir] {
+        fs::create_dir_all(d).await.unwrap();
+    }
+    let plot_id = Bytes32::new([6u8; 32]);
+    let memo = [5u8; 128];
+    let plot = DiskPlot::create(
+        &tmp_dir,
+        &tmp_dir,
+        &source_dir,
+        k,
+        0,
+        &memo,
+        plot_id,
+        &SIMULATOR,
+    )
+    .await
+    .unwrap();
+    let source = plot.filename.as_ref().clone();
+    drop(plot);
+    let compressed = convert_plot(
+        &source,
+        &final_dir,
+        &PlotConversionConfig {
+            compression_level: 1,
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(compressed.unwrap_err().kind(), ErrorKind::Unsupported);
+    // Sort on disk so the bucket files are cleaned up after every table
+    let converted = convert_plot(
+        &source,
+        &final_dir,
+        &PlotConversionConfig {
+            tmp_dir: Some(tmp_dir.clone()),
+            num_buckets: K_MIN_BUCKETS,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let path = converted.filename.as_ref().clone();
+    assert_eq!(converted.plot_id(), plot_id);
+    assert_eq!(
+        *converted.memo(),
+        PlotMemo::try_from(memo.as_slice()).unwrap()
+    );
+    drop(converted);
+    // Rebuilding at the same level reproduces every park of the source plot
+    assert_eq!(
+        fs::read(&source).await.unwrap(),
+        fs::read(&path).await.unwrap()
+    );
+    let report = check_plot(&path, &PlotCheckConfig::default())
+        .await
+        .unwrap();
+    assert!(report.is_healthy(), "{:?}", report.errors);
+    assert!(report.valid_proofs > 0);
+    let mut leftover = fs::read_dir(&tmp_dir).await.unwrap();
+    assert!(leftover.next_entry().await.unwrap().is_none());
+    fs::remove_dir_all(dir).await.unwrap();
+}
+
+#[tokio::test]
+#[allow(clippy::cast_possible_truncation)]
+async fn test_write_x_pairs() {
+    use crate::constants::K_MIN_BUCKETS;
+    use dg_xch_core::blockchain::sized_bytes::Bytes32;
+    use dg_xch_core::consensus::constants::SIMULATOR;
+    use dg_xch_core::traits::SizedBytes;
+    let k = 18;
+    let index_bits = pos_bits(k);
+    let lp_bits = EntrySizes::line_point_size_bits(u32::from(k));
+    let dir =
+        std::env::temp_dir().join(format!("dg_pos_test_write_x_pairs_{}", std::process::id()));
+    fs::create_dir_all(&dir).await.unwrap();
+    let plot_id = Bytes32::new([7u8; 32]);
+    let plot = DiskPlot::create(&dir, &dir, &dir, k, 0, &[5u8; 128], plot_id, &SIMULATOR)
+        .await
+        .unwrap();
+    let source = plot.filename.as_ref().clone();
+    let reader = PlotReader::new(plot, None, None).await.unwrap();
+    let mut tables = vec![];
+    for table in [PlotTable::Table1, PlotTable::Table2] {
+        let mut line_points = vec![];
+        let park_count =
+            reader.plot_file().table_size(table) / reader.get_park_size_for_table(table);
+        for park_index in 0..park_count {
+            line_points.extend(
+                reader
+                    .read_line_point_park(table, park_index)
+                    .await
+                    .unwrap(),
+            );
+        }
+        tables.push(line_points);
+    }
+    let ctx = PlotContext {
+        k,
+        plot_id,
+        num_buckets: K_MIN_BUCKETS,
+        in_memory: true,
+        compression_level: 0,
+        sort_prefix: dir.join("x_pairs"),
+        table_files: vec![],
+        tmp_dir: dir.clone(),
+        progress: None,
+    };
+    // The x values of every table 3 entry, as recovered from a compressed plot
+    let xs = new_sorter(&ctx, "xs", entry_bytes(index_bits + u32::from(k) * 4))
+        .await
+        .unwrap();
+    let mut out = vec![0u8; tables[1].len() * xs.entry_size()];
+    for (n, (line_point, dst)) in tables[1]
+        .iter()
+        .zip(out.chunks_exact_mut(xs.entry_size()))
+        .enumerate()
+    {
+        let (a, b) = line_point_to_square(*line_point);
+        let (x1, x2) = line_point_to_square(tables[0][a as usize]);
+        let (x3, x4) = line_point_to_square(tables[0][b as usize]);
+        let mut packer = EntryPacker::new(dst);
+        packer.put(n as u128, index_bits);
+        for x in [x1, x2, x3, x4] {
+            packer.put(u128::from(x), u32::from(k));
+        }
+    }
+    xs.insert(&out).await.unwrap();
+    xs.flush().await.unwrap();
+    let lp_sorter = new_sorter(&ctx, "lp", entry_bytes(lp_bits + index_bits))
+        .await
+        .unwrap();
+    let table1_path = dir.join("table1");
+    let mut file = BufWriter::new(File::create(&table1_path).await.unwrap());
+    let mut offset = 0;
+    let count = write_x_pairs(&ctx, &xs, &lp_sorter, &mut file, &mut offset)
+        .await
+        .unwrap();
+    file.flush().await.unwrap();
+    assert_eq!(count, tables[0].len() as u64);
+    // Shared pairs are written once, which gives back the parks of the source table 1
+    let mut source_file = open_read_only_async(&source).await.unwrap();
+    source_file
+        .seek(SeekFrom::Start(
+            reader.plot_file().table_address(PlotTable::Table1),
+        ))
+        .await
+        .unwrap();
+    let mut table1 = vec![0u8; reader.plot_file().table_size(PlotTable::Table1) as usize];
+    source_file.read_exact(&mut table1).await.unwrap();
+    assert_eq!(offset, table1.len() as u64);
+    assert_eq!(fs::read(&table1_path).await.unwrap(), table1);
+    lp_sorter.flush().await.unwrap();
+    let mut table2 = vec![];
+    for bucket in 0..lp_sorter.num_buckets() {
+        let data = lp_sorter.read_bucket(bucket).await.unwrap();
+        for entry in data.chunks_exact(lp_sorter.entry_size()) {
+            let mut unpacker = EntryUnpacker::new(entry);
+            let line_point = unpacker.take(lp_bits);
+            assert_eq!(unpacker.take(index_bits), table2.len() as u128);
+            table2.push(line_point);
+        }
+    }
+    assert_eq!(table2, tables[1]);
+    drop(reader);
+    fs::remove_dir_all(dir).await.unwrap();
+}
+
+#[tokio::test]
+#[allow(clippy::cast_possible_truncation)]
+async fn test_lower_small_compressed_plot() {
+    use crate::verifier::{proof_to_bytes, validate_proof};
+    use dg_xch_core::blockchain::sized_bytes::Bytes32;
+    use dg_xch_core::consensus::constants::SIMULATOR;
+    use dg_xch_core::traits::SizedBytes;
+    // The smallest k that fits a compressed level
+    let k = 20;
+    let dir = std::env::temp_dir().join(format!(
+        "dg_pos_test_lower_small_compressed_{}",
+        std::process::id()
+    ));
+    let source_dir = dir.join("source");
+    let final_dir = dir.join("final");
+    for d in [&source_dir, &final_dir] {
+        fs::create_dir_all(d).await.unwrap();
+    }
+    let plot_id = Bytes32::new([7u8; 32]);
+    let plot = DiskPlot::create(
+        &dir,
+        &dir,
+        &source_dir,
+        k,
+        7,
+        &[5u8; 128],
+        plot_id,
+        &SIMULATOR,
+    )
+    .await
+    .unwrap();
+    let source = plot.filename.as_ref().clone();
+    drop(plot);
+    let pool = Arc::new(DecompressorPool::new(1, 2));
+    let converted = convert_plot(
+        &source,
+        &final_dir,
+        &PlotConversionConfig {
+            tmp_dir: Some(dir.clone()),
+            decompressor: Some(pool.clone()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(converted.compression_level(), 0);
+    let source_reader = PlotReader::new(
+        DiskPlot::new(&source).await.unwrap(),
+        Some(pool.clone()),
+        Some(pool),
+    )
+    .await
+    .unwrap();
+    let reader = PlotReader::new(converted, None, None).await.unwrap();
+    let mut checked = 0;
+    for f7 in source_reader
+        .read_c3park(0)
+        .await
+        .unwrap()
+        .iter()
+        .step_by(97)
+    {
+        let mut challenge = [0xa5u8; 32];
+        challenge[0..8].copy_from_slice(&(f7 << (64 - k)).to_be_bytes());
+        let Ok(qualities) = source_reader
+            .fetch_qualities_for_challenge(&challenge)
+            .await
+        else {
+            continue;
+        };
+        let mut proofs = vec![];
+        for (index, _) in reader
+            .fetch_qualities_for_challenge(&challenge)
+            .await
+            .unwrap()
+        {
+            let proof = reader.fetch_ordered_proof(index).await.unwrap();
+            assert!(
+                validate_proof(&plot_id.bytes(), k, &proof_to_bytes(&proof), &challenge).is_ok()
+            );
+            proofs.push(proof);
+        }
+        // Every proof the source can decompress is kept with its full x values
+        for (index, _) in qualities {
+            let Ok(proof) = source_reader.fetch_ordered_proof(index).await else {
+                continue;
+            };
+            assert!(
+                validate_proof(&plot_id.bytes(), k, &proof_to_bytes(&proof), &challenge).is_ok()
+            );
+            assert!(proofs.contains(&proof));
+            checked += 1;
+        }
+    }
+    assert!(checked > 100);
+    drop(reader);
+    drop(source_reader);
+    fs::remove_dir_all(dir).await.unwrap();
+}
+
+/// Lowers the level of a C2 k32 and compares the proofs of every level. Set
+/// `DG_XCH_PLOT_TEST_DIR` to a directory with room for the k32 temp files.
+#[tokio::test]
+#[ignore = "plots a compressed k32"]
+#[allow(clippy::cast_possible_truncation)]
+async fn test_convert_compressed_plot() {
+    use crate::plots::plotting::COMPRESSED_PLOT_K;
+    use crate::verifier::{proof_to_bytes, validate_proof};
+    use dg_xch_core::blockchain::sized_bytes::Bytes32;
+    use dg_xch_core::consensus::constants::MAINNET;
+    use dg_xch_core::traits::SizedBytes;
+    use std::thread::available_parallelism;
+    let k = COMPRESSED_PLOT_K;
+    let dir = std::env::var("DG_XCH_PLOT_TEST_DIR")
+        .map_or_else(|_| std::env::temp_dir(), PathBuf::from)
+        .join(format!(
+            "dg_pos_test_convert_compressed_{}",
+            std::process::id()
+        ));
+    let source_dir = dir.join("source");
+    let final_dir = dir.join("final");
+    for d in [&source_dir, &final_dir] {
+        fs::create_dir_all(d).await.unwrap();
+    }
+    let plot_id = Bytes32::new([6u8; 32]);
+    let plot = DiskPlot::create(
+        &dir,
+        &dir,
+        &source_dir,
+        k,
+        2,
+        &[5u8; 128],
+        plot_id,
+        &MAINNET,
+    )
+    .await
+    .unwrap();
+    let source = plot.filename.as_ref().clone();
+    drop(plot);
+    let without_decompressor = convert_plot(&source, &final_dir, &PlotConversionConfig::default())
+        .await
+        .unwrap_err();
+    assert_eq!(without_decompressor.kind(), ErrorKind::InvalidInput);
+    let pool = Arc::new(DecompressorPool::new(
+        1,
+        available_parallelism()
+            .map(std::num::NonZero::get)
+            .unwrap_or(4) as u8,
+    ));
+    let source_reader = PlotReader::new(
+        DiskPlot::new(&source).await.unwrap(),
+        Some(pool.clone()),
+        Some(pool.clone()),
+    )
+    .await
+    .unwrap();
+    let f7s = source_reader.read_c3park(0).await.unwrap();
+    for level in [1, 0] {
+        let converted = convert_plot(
+            &source,
+            &final_dir,
+            &PlotConversionConfig {
+                compression_level: level,
+                tmp_dir: Some(dir.clone()),
+                decompressor: Some(pool.clone()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(converted.compression_level(), level);
+        let path = converted.filename.as_ref().clone();
+        let reader = PlotReader::new(converted, Some(pool.clone()), Some(pool.clone()))
+            .await
+            .unwrap();
+        let mut checked = 0;
+        for f7 in f7s.iter().step_by(997) {
+            let mut challenge = [0xa5u8; 32];
+            challenge[0..8].copy_from_slice(&(f7 << (64 - k)).to_be_bytes());
+            let qualities = reader
+                .fetch_qualities_for_challenge(&challenge)
+                .await
+                .unwrap();
+            let sorted = |qualities: &[(u64, Bytes32)]| {
+                let mut bytes: Vec<_> = qualities.iter().map(|(_, q)| q.bytes()).collect();
+                bytes.sort();
+                bytes
+            };
+            assert_eq!(
+                sorted(&qualities),
+                sorted(
+                    &source_reader
+                        .fetch_qualities_for_challenge(&challenge)
+                        .await
+                        .unwrap()
+                )
+            );
+            for (index, quality) in qualities {
+                let proof = reader.fetch_ordered_proof(index).await.unwrap();
+                let v_quality =
+                    validate_proof(&plot_id.bytes(), k, &proof_to_bytes(&proof), &challenge)
+                        .unwrap();
+                assert_eq!(quality, v_quality);
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+        drop(reader);
+        fs::remove_file(path).await.unwrap();
+    }
+    drop(source_reader);
+    fs::remove_dir_all(dir).await.unwrap();
+}
@@ -0,0 +1,252 @@
+use crate::plots::disk_plot::DiskPlot;
+use crate::plots::plot_reader::PlotReader;
+use blst::min_pk::{PublicKey, SecretKey};
+use dg_xch_core::blockchain::proof_of_space::generate_plot_public_key;
+use dg_xch_core::blockchain::sized_bytes::{Bytes32, Bytes48};
+use dg_xch_core::plots::{PlotFile, PlotHeader, PlotMemo, PlotTable};
+use dg_xch_keys::master_sk_to_local_sk;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Every table of a plot file, the seven tables written while plotting followed by the C tables
+const PLOT_INFO_TABLES: [PlotTable; 10] = [
+    PlotTable::Table1,
+    PlotTable::Table2,
+    PlotTable::Table3,
+    PlotTable::Table4,
+    PlotTable::Table5,
+    PlotTable::Table6,
+    PlotTable::Table7,
+    PlotTable::C1,
+    PlotTable::C2,
+    PlotTable::C3,
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlotFormat {
+    V1,
+    V2,
+    GHv2_5,
+}
+impl Display for PlotFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PlotFormat::V1 => write!(f, "v1"),
+            PlotFormat::V2 => write!(f, "v2"),
+            PlotFormat::GHv2_5 => write!(f, "GHv2.5"),
+        }
+    }
+}
+
+/// Decoded plot memo. The local master secret key is never included, only whether it is set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlotMemoInfo {
+    pub pool_public_key: Option<Bytes48>,
+    pub pool_contract_puzzle_hash: Option<Bytes32>,
+    pub farmer_public_key: Bytes48,
+    pub local_master_secret_key_present: bool,
+}
+impl From<&PlotMemo> for PlotMemoInfo {
+    fn from(memo: &PlotMemo) -> Self {
+        Self {
+            pool_public_key: memo.pool_public_key,
+            pool_contract_puzzle_hash: memo.pool_contract_puzzle_hash,
+            farmer_public_key: memo.farmer_public_key,
+            local_master_secret_key_present: memo.local_master_secret_key != Bytes32::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlotTableInfo {
+    pub table: String,
+    pub pointer: u64,
+    pub size: u64,
+}
+
+/// Everything known about a plot file without running any challenges against it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlotFileInfo {
+    pub path: PathBuf,
+    pub file_size: u64,
+    pub format: PlotFormat,
+    pub k: u8,
+    pub plot_id: Bytes32,
+    pub compression_level: u8,
+    pub plot_flags: u32,
+    pub format_description: String,
+    pub memo: PlotMemoInfo,
+    /// None when the memo does not hold valid keys
+    pub plot_public_key: Option<Bytes48>,
+    /// Empty for GHv2.5 plots, which do not store table pointers
+    pub tables: Vec<PlotTableInfo>,
+    pub c1_entries: Option<u64>,
+    pub c3_park_count: Option<u64>,
+}
+impl Display for PlotFileInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Plot:              {}", self.path.display())?;
+        writeln!(f, "File Size:         {}", self.file_size)?;
+        writeln!(f, "Format:            {}", self.format)?;
+        writeln!(f, "K:                 {}", self.k)?;
+        writeln!(f, "Plot ID:           {}", self.plot_id)?;
+        writeln!(f, "Compression Level: {}", self.compression_level)?;
+        writeln!(f, "Plot Flags:        {:#x}", self.plot_flags)?;
+        if !self.format_description.is_empty() {
+            writeln!(f, "Description:       {}", self.format_description)?;
+        }
+        if let Some(pool_public_key) = &self.memo.pool_public_key {
+            writeln!(f, "Pool Public Key:   {pool_public_key}")?;
+        }
+        if let Some(puzzle_hash) = &self.memo.pool_contract_puzzle_hash {
+            writeln!(f, "Pool Contract:     {puzzle_hash}")?;
+        }
+        writeln!(f, "Farmer Public Key: {}", self.memo.farmer_public_key)?;
+        writeln!(
+            f,
+            "Local Master SK:   {}",
+            if self.memo.local_master_secret_key_present {
+                "present"
+            } else {
+                "missing"
+            }
+        )?;
+        match &self.plot_public_key {
+            Some(plot_public_key) => writeln!(f, "Plot Public Key:   {plot_public_key}")?,
+            None => writeln!(f, "Plot Public Key:   invalid memo")?,
+        }
+        if let Some(c1_entries) = self.c1_entries {
+            writeln!(f, "C1 Entries:        {c1_entries}")?;
+        }
+        if let Some(c3_park_count) = self.c3_park_count {
+            writeln!(f, "C3 Parks:          {c3_park_count}")?;
+        }
+        if !self.tables.is_empty() {
+            writeln!(f, "{:<8} {:>16} {:>16}", "Table", "Pointer", "Size")?;
+            for table in &self.tables {
+                writeln!(
+                    f,
+                    "{:<8} {:>16} {:>16}",
+                    table.table, table.pointer, table.size
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Derives the plot public key a harvester signs with from the keys stored in the plot memo.
+pub fn plot_public_key_from_memo(memo: &PlotMemo) -> Result<Bytes48, Error> {
+    let local_master_secret = SecretKey::from_bytes(memo.local_master_secret_key.as_ref())
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("{e:?}")))?;
+    let local_sk = master_sk_to_local_sk(&local_master_secret)?;
+    let farmer_public_key = PublicKey::from_bytes(memo.farmer_public_key.as_ref())
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("{e:?}")))?;
+    Ok(generate_plot_public_key(
+        &local_sk.sk_to_pk(),
+        &farmer_public_key,
+        memo.pool_contract_puzzle_hash.is_some(),
+    )?
+    .to_bytes()
+    .into())
+}
+
+/// Reads the header and table layout of the plot at `path`.
+pub async fn inspect_plot(path: &Path) -> Result<PlotFileInfo, Error> {
+    let plot = DiskPlot::new(path).await?;
+    let header = plot.header();
+    let format = match header {
+        PlotHeader::V1(_) => PlotFormat::V1,
+        PlotHeader::V2(_) => PlotFormat::V2,
+        PlotHeader::GHv2_5(_) => PlotFormat::GHv2_5,
+    };
+    let tables = if format == PlotFormat::GHv2_5 {
+        vec![]
+    } else {
+        PLOT_INFO_TABLES
+            .iter()
+            .map(|table| PlotTableInfo {
+                table: format!("{table:?}"),
+                pointer: plot.table_address(*table),
+                size: plot.table_size(*table),
+            })
+            .collect()
+    };
+    let mut info = PlotFileInfo {
+        path: path.to_path_buf(),
+        file_size: *plot.plot_size(),
+        format,
+        k: header.k(),
+        plot_id: header.id(),
+        compression_level: header.compression_level(),
+        plot_flags: header.plot_flags(),
+        format_description: String::from_utf8_lossy(header.format_desc()).to_string(),
+        memo: PlotMemoInfo::from(header.memo()),
+        plot_public_key: plot_public_key_from_memo(header.memo()).ok(),
+        tables,
+        c1_entries: None,
+        c3_park_count: None,
+    };
+    if format != PlotFormat::GHv2_5 {
+        let reader = PlotReader::new(plot, None, None).await?;
+        info.c1_entries = Some(reader.get_actual_c1_entry_count().await?);
+        info.c3_park_count = Some(reader.get_c3_park_count());
+    }
+    Ok(info)
+}
+
+#[tokio::test]
+async fn test_inspect_plot() {
+    use dg_xch_core::blockchain::proof_of_space::calculate_plot_id_puzzle_hash;
+    use dg_xch_core::consensus::constants::SIMULATOR;
+    use dg_xch_core::traits::SizedBytes;
+    use dg_xch_keys::plot_memo::PlotMemoBuilder;
+    let k = 18;
+    let dir = std::env::temp_dir().join(format!("dg_pos_test_inspect_plot_{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let keys = PlotMemoBuilder::new()
+        .master_secret_key(&SecretKey::key_gen_v3(&[7u8; 32], &[]).unwrap())
+        .unwrap()
+        .pool_contract_puzzle_hash(Bytes32::new([3u8; 32]))
+        .build()
+        .unwrap();
+    let plot = DiskPlot::create(
+        &dir,
+        &dir,
+        &dir,
+        k,
+        0,
+        &keys.memo_bytes,
+        keys.plot_id,
+        &SIMULATOR,
+    )
+    .await
+    .unwrap();
+    let path = plot.filename.as_ref().clone();
+    let park_count = PlotReader::new(plot, None, None)
+        .await
+        .unwrap()
+        .get_c3_park_count();
+    let info = inspect_plot(&path).await.unwrap();
+    assert_eq!(info.format, PlotFormat::V1);
+    assert_eq!(info.k, k);
+    assert_eq!(info.plot_id, keys.plot_id);
+    assert_eq!(info.compression_level, 0);
+    assert_eq!(info.c3_park_count, Some(park_count));
+    assert_eq!(info.tables.len(), 10);
+    assert!(info.memo.local_master_secret_key_present);
+    assert_eq!(info.memo.farmer_public_key, keys.memo.farmer_public_key);
+    assert_eq!(info.plot_public_key, Some(keys.plot_public_key));
+    assert_eq!(
+        calculate_plot_id_puzzle_hash(
+            info.memo.pool_contract_puzzle_hash.unwrap(),
+            info.plot_public_key.unwrap(),
+        ),
+        info.plot_id
+    );
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
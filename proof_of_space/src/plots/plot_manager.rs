@@ -0,0 +1,558 @@
+use crate::plots::decompressor::DecompressorPool;
+use crate::plots::disk_plot::DiskPlot;
+use crate::plots::plot_info::plot_public_key_from_memo;
+use crate::plots::plot_reader::{read_all_plot_headers_async, PlotReader};
+use crate::{PathInfo, PlotInfo, PlotManagerAsync};
+use async_trait::async_trait;
+use dg_xch_core::blockchain::sized_bytes::Bytes48;
+use dg_xch_core::config::{HarvesterConfig, PlotRefreshParameter};
+use dg_xch_core::plots::PlotHeader;
+use dg_xch_core::protocols::harvester::HarvesterState;
+use futures_util::future::join_all;
+use log::{debug, error, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use tokio::time::{sleep, Instant};
+
+enum PlotLoad {
+    Loaded(PathInfo, Arc<PlotInfo>),
+    KeysMissing(PathBuf, Bytes48),
+}
+
+/// Finds plots in the configured directories and keeps them open for farming. Plots are only
+/// loaded when their farmer key, and pool key for OG plots, were passed to `set_public_keys`.
+pub struct DiskPlotManager {
+    plot_directories: Vec<PathBuf>,
+    recursive: bool,
+    refresh_parameter: PlotRefreshParameter,
+    max_compression_level: u8,
+    decompressor_pool: Option<Arc<DecompressorPool>>,
+    memory_map: bool,
+    farmer_public_keys: Vec<Bytes48>,
+    pool_public_keys: Vec<Bytes48>,
+    plots: HashMap<PathInfo, Arc<PlotInfo>>,
+    /// Plots that failed to load, with the unix time of the failure
+    invalid_plots: HashMap<PathBuf, u64>,
+    /// Plots with the same file name as an already loaded plot
+    duplicate_plots: HashSet<PathBuf>,
+    /// Plots created for keys that are not known, with the missing key
+    keys_missing_plots: HashMap<PathBuf, Bytes48>,
+    plots_ready: Arc<AtomicBool>,
+}
+impl DiskPlotManager {
+    #[must_use]
+    pub fn new(config: &HarvesterConfig) -> Self {
+        Self {
+            plot_directories: config.plot_directories.iter().map(PathBuf::from).collect(),
+            recursive: config.recursive_plot_scan,
+            refresh_parameter: config.plots_refresh_parameter.clone(),
+            max_compression_level: config.max_compression_level_allowed,
+            decompressor_pool: None,
+            memory_map: false,
+            farmer_public_keys: vec![],
+            pool_public_keys: vec![],
+            plots: HashMap::default(),
+            invalid_plots: HashMap::default(),
+            duplicate_plots: HashSet::default(),
+            keys_missing_plots: HashMap::default(),
+            plots_ready: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Uses `pool` to decompress proofs and qualities of compressed plots.
+    #[must_use]
+    pub fn with_decompressor_pool(mut self, pool: Arc<DecompressorPool>) -> Self {
+        self.decompressor_pool = Some(pool);
+        self
+    }
+
+    /// Memory maps plots as they are loaded so lookups on the same plot do not wait on each other.
+    #[must_use]
+    pub fn with_memory_map(mut self, memory_map: bool) -> Self {
+        self.memory_map = memory_map;
+        self
+    }
+
+    /// Reloads the plots of `manager` every `interval_seconds` until `run` is cleared. Plots are
+    /// found and opened without holding `manager`, it is only locked to merge the results.
+    pub async fn refresh_loop(
+        manager: Arc<RwLock<Self>>,
+        harvester_state: Arc<RwLock<HarvesterState>>,
+        run: Arc<AtomicBool>,
+    ) {
+        let interval = manager.read().await.refresh_parameter.interval_seconds as u64;
+        while run.load(Ordering::Relaxed) {
+            let scan = manager.read().await.plot_scan();
+            let found = scan.run().await;
+            manager
+                .write()
+                .await
+                .merge_scan(found, harvester_state.clone())
+                .await;
+            let next_refresh = Instant::now() + Duration::from_secs(interval.max(1));
+            while run.load(Ordering::Relaxed) && Instant::now() < next_refresh {
+                sleep(Duration::from_millis(250)).await;
+            }
+        }
+    }
+
+    fn tracked_plots(&self) -> impl Iterator<Item = &PathBuf> {
+        self.plots
+            .keys()
+            .map(|p| &p.path)
+            .chain(self.keys_missing_plots.keys())
+            .chain(self.duplicate_plots.iter())
+            .chain(self.invalid_plots.keys())
+    }
+
+    /// Everything needed to find and open new plots, detached from the manager.
+    fn plot_scan(&self) -> PlotScan {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let retry_invalid = self.refresh_parameter.retry_invalid_seconds as u64;
+        PlotScan {
+            start: Instant::now(),
+            now,
+            plot_directories: self.plot_directories.clone(),
+            recursive: self.recursive,
+            refresh_parameter: self.refresh_parameter.clone(),
+            max_compression_level: self.max_compression_level,
+            decompressor_pool: self.decompressor_pool.clone(),
+            memory_map: self.memory_map,
+            farmer_public_keys: self.farmer_public_keys.clone(),
+            pool_public_keys: self.pool_public_keys.clone(),
+            tracked: self.tracked_plots().cloned().collect(),
+            // Invalid plots are read again once their retry time passed
+            known: self
+                .tracked_plots()
+                .filter(|path| {
+                    self.invalid_plots
+                        .get(*path)
+                        .is_none_or(|failed| now.saturating_sub(*failed) < retry_invalid)
+                })
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Applies a finished scan, dropping plots that no longer exist and adding the new ones.
+    async fn merge_scan(
+        &mut self,
+        found: ScannedPlots,
+        harvester_state: Arc<RwLock<HarvesterState>>,
+    ) {
+        let ScannedPlots {
+            start,
+            now,
+            missing,
+            failed,
+            opened,
+        } = found;
+        let retry_invalid = self.refresh_parameter.retry_invalid_seconds as u64;
+        let loaded = self.plots.len();
+        self.plots
+            .retain(|path_info, _| !missing.contains(&path_info.path));
+        if self.plots.len() != loaded {
+            info!("Removed {} missing plots", loaded - self.plots.len());
+            // A duplicate of a removed plot can now be loaded
+            self.duplicate_plots.clear();
+        }
+        self.duplicate_plots.retain(|path| !missing.contains(path));
+        self.keys_missing_plots
+            .retain(|path, _| !missing.contains(path));
+        self.invalid_plots.retain(|path, failed| {
+            !missing.contains(path) && now.saturating_sub(*failed) < retry_invalid
+        });
+        for path in failed {
+            self.invalid_plots.insert(path, now);
+        }
+        let mut added = 0;
+        for (path, result) in opened {
+            match result {
+                Ok(PlotLoad::Loaded(path_info, plot_info)) => {
+                    if let Some((existing, _)) = self.plots.get_key_value(&path_info) {
+                        // Another load may have opened the same plot while this scan ran
+                        if existing.path != path {
+                            warn!(
+                                "Plot {} is a duplicate of {}",
+                                path.display(),
+                                existing.path.display()
+                            );
+                            self.duplicate_plots.insert(path);
+                        }
+                    } else {
+                        self.plots.insert(path_info, plot_info);
+                        added += 1;
+                    }
+                }
+                Ok(PlotLoad::KeysMissing(path, key)) => {
+                    // Keys set while the scan ran are checked on the next load
+                    if self.farmer_public_keys.contains(&key)
+                        || self.pool_public_keys.contains(&key)
+                    {
+                        continue;
+                    }
+                    debug!("Missing key {key} for plot {}", path.display());
+                    self.keys_missing_plots.insert(path, key);
+                }
+                Err(e) => {
+                    error!("Failed to load plot {}: {e:?}", path.display());
+                    self.invalid_plots.insert(path, now);
+                }
+            }
+        }
+        let mut state = harvester_state.write().await;
+        state.og_plot_count = 0;
+        state.nft_plot_count = 0;
+        state.compressed_plot_count = 0;
+        state.plot_space = 0;
+        for info in self.plots.values() {
+            if info.pool_public_key.is_some() {
+                state.og_plot_count += 1;
+            } else {
+                state.nft_plot_count += 1;
+            }
+            if info.reader.header().compression_level() > 0 {
+                state.compressed_plot_count += 1;
+            }
+            state.plot_space += info.file_size;
+        }
+        state.invalid_plot_count = self.invalid_plots.len();
+        state.missing_keys = self.keys_missing_plots.values().copied().collect();
+        drop(state);
+        if !self.keys_missing_plots.is_empty() {
+            warn!(
+                "{} plots were created for keys that are not configured",
+                self.keys_missing_plots.len()
+            );
+        }
+        info!(
+            "Loaded {added} new plots in {:.3} seconds, {} plots total, {} invalid, {} duplicates",
+            start.elapsed().as_secs_f64(),
+            self.plots.len(),
+            self.invalid_plots.len(),
+            self.duplicate_plots.len()
+        );
+        self.plots_ready.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Settings and known plots copied out of a `DiskPlotManager` for one refresh.
+struct PlotScan {
+    start: Instant,
+    now: u64,
+    plot_directories: Vec<PathBuf>,
+    recursive: bool,
+    refresh_parameter: PlotRefreshParameter,
+    max_compression_level: u8,
+    decompressor_pool: Option<Arc<DecompressorPool>>,
+    memory_map: bool,
+    farmer_public_keys: Vec<Bytes48>,
+    pool_public_keys: Vec<Bytes48>,
+    /// Every plot the manager knows about, checked for removal
+    tracked: Vec<PathBuf>,
+    /// Plots that are not read again
+    known: Vec<PathBuf>,
+}
+
+/// The outcome of a `PlotScan`, merged back into the manager.
+struct ScannedPlots {
+    start: Instant,
+    now: u64,
+    /// Tracked plots that no longer exist
+    missing: HashSet<PathBuf>,
+    /// Plots with an unreadable header
+    failed: Vec<PathBuf>,
+    opened: Vec<(PathBuf, Result<PlotLoad, Error>)>,
+}
+
+impl PlotScan {
+    async fn run(self) -> ScannedPlots {
+        let mut missing = HashSet::new();
+        for path in &self.tracked {
+            if !tokio::fs::try_exists(path).await.unwrap_or(false) {
+                missing.insert(path.clone());
+            }
+        }
+        let known: Vec<&Path> = self.known.iter().map(PathBuf::as_path).collect();
+        let mut headers = vec![];
+        let mut failed = vec![];
+        for dir in self.scan_directories().await {
+            match read_all_plot_headers_async(&dir, &known).await {
+                Ok((valid, invalid)) => {
+                    headers.extend(valid);
+                    failed.extend(invalid);
+                }
+                Err(e) => {
+                    error!("Failed to read plots in {}: {e:?}", dir.display());
+                }
+            }
+        }
+        let batch_size = self.refresh_parameter.batch_size.max(1);
+        let batch_sleep =
+            Duration::from_millis(self.refresh_parameter.batch_sleep_milliseconds as u64);
+        let mut opened = vec![];
+        for (index, batch) in headers.chunks(batch_size).enumerate() {
+            if index > 0 {
+                sleep(batch_sleep).await;
+            }
+            let results = join_all(
+                batch
+                    .iter()
+                    .map(|(path, header)| self.open_plot(path, header)),
+            )
+            .await;
+            opened.extend(batch.iter().map(|(path, _)| path.clone()).zip(results));
+        }
+        ScannedPlots {
+            start: self.start,
+            now: self.now,
+            missing,
+            failed,
+            opened,
+        }
+    }
+
+    /// Configured directories, including every sub directory when scanning recursively.
+    async fn scan_directories(&self) -> Vec<PathBuf> {
+        let mut directories = vec![];
+        let mut pending = self.plot_directories.clone();
+        while let Some(dir) = pending.pop() {
+            if !tokio::fs::metadata(&dir).await.is_ok_and(|m| m.is_dir()) {
+                warn!("Plot directory {} does not exist", dir.display());
+                continue;
+            }
+            if self.recursive {
+                match tokio::fs::read_dir(&dir).await {
+                    Ok(mut entries) => loop {
+                        match entries.next_entry().await {
+                            Ok(Some(entry)) => {
+                                // Symlinked directories are not followed to avoid loops
+                                if entry.file_type().await.is_ok_and(|t| t.is_dir()) {
+                                    pending.push(entry.path());
+                                }
+                            }
+                            Ok(None) => break,
+                            Err(e) => {
+                                error!("Failed to read directory {}: {e:?}", dir.display());
+                                break;
+                            }
+                        }
+                    },
+                    Err(e) => {
+                        error!("Failed to read directory {}: {e:?}", dir.display());
+                    }
+                }
+            }
+            directories.push(dir);
+        }
+        directories
+    }
+
+    async fn open_plot(&self, path: &Path, header: &PlotHeader) -> Result<PlotLoad, Error> {
+        let memo = header.memo();
+        if !self.farmer_public_keys.contains(&memo.farmer_public_key) {
+            return Ok(PlotLoad::KeysMissing(
+                path.to_path_buf(),
+                memo.farmer_public_key,
+            ));
+        }
+        if let Some(pool_public_key) = memo.pool_public_key {
+            if !self.pool_public_keys.contains(&pool_public_key) {
+                return Ok(PlotLoad::KeysMissing(path.to_path_buf(), pool_public_key));
+            }
+        }
+        if header.compression_level() > self.max_compression_level {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "Compression level {} is above the allowed level of {}",
+                    header.compression_level(),
+                    self.max_compression_level
+                ),
+            ));
+        }
+        let plot_public_key = plot_public_key_from_memo(memo)?;
+        let metadata = tokio::fs::metadata(path).await?;
+        let time_modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let plot = if self.memory_map {
+            DiskPlot::new_mmap(path).await?
+        } else {
+            DiskPlot::new(path).await?
+        };
+        let reader = PlotReader::new(
+            plot,
+            self.decompressor_pool.clone(),
+            self.decompressor_pool.clone(),
+        )
+        .await?;
+        let path_info = PathInfo {
+            path: path.to_path_buf(),
+            file_name: path
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        };
+        Ok(PlotLoad::Loaded(
+            path_info,
+            Arc::new(PlotInfo {
+                reader,
+                pool_public_key: memo.pool_public_key,
+                pool_contract_puzzle_hash: memo.pool_contract_puzzle_hash,
+                plot_public_key,
+                file_size: metadata.len(),
+                time_modified,
+            }),
+        ))
+    }
+}
+
+#[async_trait]
+impl PlotManagerAsync for DiskPlotManager {
+    fn set_public_keys(
+        &mut self,
+        farmer_public_keys: Vec<Bytes48>,
+        pool_public_keys: Vec<Bytes48>,
+    ) {
+        self.farmer_public_keys = farmer_public_keys;
+        self.pool_public_keys = pool_public_keys;
+        // Plots skipped for missing keys are checked again on the next load
+        self.keys_missing_plots.clear();
+    }
+
+    async fn load_plots(
+        &mut self,
+        harvester_state: Arc<RwLock<HarvesterState>>,
+    ) -> Result<(), Error> {
+        let found = self.plot_scan().run().await;
+        self.merge_scan(found, harvester_state).await;
+        Ok(())
+    }
+
+    fn plots(&self) -> &HashMap<PathInfo, Arc<PlotInfo>> {
+        &self.plots
+    }
+
+    fn plots_ready(&self) -> Arc<AtomicBool> {
+        self.plots_ready.clone()
+    }
+
+    fn invalid_plots(&self) -> Vec<&Path> {
+        self.invalid_plots.keys().map(PathBuf::as_path).collect()
+    }
+
+    fn keys_missing_plots(&self) -> Vec<&Path> {
+        self.keys_missing_plots
+            .keys()
+            .map(PathBuf::as_path)
+            .collect()
+    }
+
+    fn duplicate_plots(&self) -> Vec<&Path> {
+        self.duplicate_plots.iter().map(PathBuf::as_path).collect()
+    }
+}
+
+#[tokio::test]
+async fn test_disk_plot_manager() {
+    use crate::constants::HEADER_MAGIC;
+    use crate::plots::disk_plot::write_plot_header;
+    use blst::min_pk::SecretKey;
+    use dg_xch_core::plots::{PlotHeaderV1, PlotMemo};
+    use tokio::fs;
+    let dir = std::env::temp_dir().join(format!("dg_pos_test_plot_manager_{}", std::process::id()));
+    let nested = dir.join("a").join("b");
+    fs::create_dir_all(&nested).await.unwrap();
+    fs::create_dir_all(dir.join("c")).await.unwrap();
+    let key = |seed: u8| SecretKey::key_gen(&[seed; 32], &[]).unwrap();
+    let farmer_pk = key(1).sk_to_pk().to_bytes();
+    let pool_pk = key(2).sk_to_pk().to_bytes();
+    let unknown_pk = key(3).sk_to_pk().to_bytes();
+    let write_plot = |path: PathBuf, memo: Vec<u8>| async move {
+        let mut header = PlotHeaderV1 {
+            magic: HEADER_MAGIC,
+            id: [memo[60]; 32].into(),
+            k: 18,
+            format_desc_len: 4,
+            format_desc: b"v1.0".to_vec(),
+            memo_len: memo.len() as u16,
+            memo: PlotMemo::try_from(memo.as_slice()).unwrap(),
+            table_begin_pointers: [0u64; 10],
+        };
+        let header_size = write_plot_header(&PlotHeader::V1(header.clone()), &memo)
+            .unwrap()
+            .len() as u64;
+        // Empty tables followed by an empty C2 table
+        header.table_begin_pointers = [header_size; 10];
+        header.table_begin_pointers[9] = header_size + 64;
+        let mut bytes = write_plot_header(&PlotHeader::V1(header), &memo).unwrap();
+        bytes.resize(bytes.len() + 128, 0);
+        fs::write(path, bytes).await.unwrap();
+    };
+    let og_memo = [&pool_pk[..], &farmer_pk[..], &key(4).to_bytes()[..]].concat();
+    let nft_memo = [&[7u8; 32][..], &farmer_pk[..], &key(5).to_bytes()[..]].concat();
+    let unknown_memo = [&[7u8; 32][..], &unknown_pk[..], &key(6).to_bytes()[..]].concat();
+    write_plot(dir.join("a").join("plot-og.plot"), og_memo.clone()).await;
+    write_plot(nested.join("plot-nft.plot"), nft_memo).await;
+    write_plot(dir.join("c").join("plot-og.plot"), og_memo).await;
+    write_plot(dir.join("a").join("plot-unknown.plot"), unknown_memo).await;
+    fs::write(dir.join("c").join("plot-broken.plot"), [1u8; 64])
+        .await
+        .unwrap();
+    let config = HarvesterConfig {
+        plot_directories: vec![
+            dir.join("a").to_string_lossy().to_string(),
+            dir.join("c").to_string_lossy().to_string(),
+        ],
+        ..Default::default()
+    };
+    let state = Arc::new(RwLock::new(HarvesterState::default()));
+    let mut manager = DiskPlotManager::new(&config);
+    manager.set_public_keys(vec![farmer_pk.into()], vec![pool_pk.into()]);
+    assert!(!manager.plots_ready().load(Ordering::Relaxed));
+    manager.load_plots(state.clone()).await.unwrap();
+    assert!(manager.plots_ready().load(Ordering::Relaxed));
+    assert_eq!(manager.plots().len(), 2);
+    assert_eq!(manager.duplicate_plots().len(), 1);
+    assert_eq!(
+        manager.keys_missing_plots(),
+        vec![dir.join("a").join("plot-unknown.plot").as_path()]
+    );
+    assert_eq!(
+        manager.invalid_plots(),
+        vec![dir.join("c").join("plot-broken.plot").as_path()]
+    );
+    {
+        let state = state.read().await;
+        assert_eq!(state.og_plot_count, 1);
+        assert_eq!(state.nft_plot_count, 1);
+        assert_eq!(state.invalid_plot_count, 1);
+        assert!(state.missing_keys.contains(&unknown_pk.into()));
+    }
+    fs::remove_file(nested.join("plot-nft.plot")).await.unwrap();
+    manager.set_public_keys(
+        vec![farmer_pk.into(), unknown_pk.into()],
+        vec![pool_pk.into()],
+    );
+    manager.load_plots(state.clone()).await.unwrap();
+    assert_eq!(manager.plots().len(), 2);
+    assert!(manager.keys_missing_plots().is_empty());
+    assert_eq!(state.read().await.nft_plot_count, 1);
+    // The duplicate was released with the removed plot and is found again
+    manager.load_plots(state.clone()).await.unwrap();
+    let duplicate = manager.duplicate_plots()[0].to_path_buf();
+    fs::remove_file(&duplicate).await.unwrap();
+    manager.load_plots(state.clone()).await.unwrap();
+    assert!(manager.duplicate_plots().is_empty());
+    assert_eq!(manager.plots().len(), 2);
+    fs::remove_dir_all(dir).await.unwrap();
+}
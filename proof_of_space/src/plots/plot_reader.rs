@@ -1,16 +1,17 @@
 use crate::constants::{
     ucdiv, ucdiv64, ucdiv_t, HEADER_MAGIC, HEADER_V2_MAGIC, K_C3R, K_CHECKPOINT1INTERVAL,
-    K_CHECKPOINT2INTERVAL, K_ENTRIES_PER_PARK, K_RVALUES, K_STUB_MINUS_BITS,
+    K_CHECKPOINT2INTERVAL, K_ENTRIES_PER_PARK, K_RVALUES, K_STUB_MINUS_BITS, MAX_HEADER_SIZE,
 };
 use crate::encoding;
 use crate::encoding::{ans_decode_deltas, line_point_to_square, line_point_to_square64};
 use crate::entry_sizes::EntrySizes;
 use crate::finite_state_entropy::decompress::{decompress_using_dtable, DTable};
-use crate::plots::compression::{create_compression_dtable, get_compression_info_for_level};
+use crate::plots::compression::{create_compression_dtable, get_compression_info_for_k};
 use crate::plots::decompressor::{
     CompressedQualitiesRequest, Decompressor, DecompressorPool, LinePoint,
 };
 use crate::plots::fx_generator::F1Generator;
+use crate::plots::plotting::read_bits;
 use crate::plots::PROOF_X_COUNT;
 use crate::utils::bit_reader::BitReader;
 use crate::utils::{bytes_to_u64, open_read_only, open_read_only_async, slice_u128from_bytes};
@@ -95,6 +96,36 @@ impl<
         Ok(reader)
     }
 
+    /// Fills `buf` from `offset` in the plot. Memory mapped and remote plots are read without
+    /// taking the file lock.
+    async fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        if let Some(data) = self.file.mapped() {
+            let range = usize::try_from(offset)
+                .ok()
+                .and_then(|start| Some(start..start.checked_add(buf.len())?))
+                .filter(|range| range.end <= data.len())
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::UnexpectedEof,
+                        format!(
+                            "Read of {} bytes at {offset} is past the end of plot {}",
+                            buf.len(),
+                            self.file
+                        ),
+                    )
+                })?;
+            buf.copy_from_slice(&data[range]);
+        } else if let Some(reader) = self.file.range_reader() {
+            reader.read_exact_at(offset, buf).await?;
+        } else {
+            let file = self.file.file();
+            let mut file_lock = file.lock().await;
+            file_lock.seek(SeekFrom::Start(offset)).await?;
+            file_lock.read_exact(buf).await?;
+        }
+        Ok(())
+    }
+
     pub fn get_c3_park_count(&self) -> u64 {
         // We know how many C3 parks there are by how many
         // entries we have in the C1 table - 1 (extra 0 entry added)
@@ -145,8 +176,8 @@ impl<
 
     pub fn get_park_size_for_table(&self, table: PlotTable) -> u64 {
         if self.is_compressed_table(table) {
-            get_compression_info_for_level(self.plot_file().compression_level()).table_park_size
-                as u64
+            get_compression_info_for_k(self.plot_file().k(), self.plot_file().compression_level())
+                .table_park_size as u64
         } else if (table as u8) < self.get_lowest_stored_table() as u8 {
             0
         } else {
@@ -198,14 +229,12 @@ impl<
         // Read entries from the end of the table until the start, until we find an entry that is
         // not zero/higher than the previous one
         {
-            let file = self.file.file().clone();
-            let mut file_lock = file.lock().await;
-            file_lock.seek(SeekFrom::Start(c1read_address)).await?;
             let c1 = 0;
             let mut c1_entry_bytes = vec![0; f7size_bytes as usize];
             let mut u64_buffer = [0u8; 8];
             while c1read_address >= c1address {
-                file_lock.read_exact(&mut c1_entry_bytes).await?;
+                self.read_exact_at(c1read_address, &mut c1_entry_bytes)
+                    .await?;
                 for (i, b) in c1_entry_bytes.iter().take(size_of::<u64>()).enumerate() {
                     if (f7size_bytes as usize) < size_of::<u64>() {
                         u64_buffer[i + size_of::<u64>() - f7size_bytes as usize] = *b;
@@ -250,12 +279,8 @@ impl<
 
         // First we need to read the root F7 entry for the park,  which is in the C1 table.
         let mut c1_entry_bytes = vec![0; f7size_bytes as usize];
-        {
-            let file = self.file.file().clone();
-            let mut file_lock = file.lock().await;
-            file_lock.seek(SeekFrom::Start(c1entry_address)).await?;
-            file_lock.read_exact(&mut c1_entry_bytes).await?;
-        }
+        self.read_exact_at(c1entry_address, &mut c1_entry_bytes)
+            .await?;
         let mut f7_reader = BitReader::from_bytes_be(&c1_entry_bytes, f7size_bytes as usize * 8);
         let c1 = f7_reader.read_u64(self.plot_file().k() as usize)?;
 
@@ -267,28 +292,23 @@ impl<
         }
         // Read the size of the compressed C3 deltas
         let (count, deltas) = {
-            let mut compressed_size_bytes: [u8; 2] = [0; 2];
-            let file = self.file.file().clone();
-            let mut file_lock = file.lock().await;
-            file_lock.seek(SeekFrom::Start(park_address)).await?;
-            file_lock.read_exact(&mut compressed_size_bytes).await?;
-            let compressed_size = u16::from_be_bytes(compressed_size_bytes);
+            let mut park_buffer = vec![0; c3park_size as usize];
+            self.read_exact_at(park_address, &mut park_buffer).await?;
+            let compressed_size = u16::from_be_bytes([park_buffer[0], park_buffer[1]]);
             if compressed_size > c3park_size as u16 {
                 return Err(Error::new(
                     ErrorKind::InvalidInput,
                     format!("Invalid size for c3 deltas: {compressed_size}"),
                 ));
             }
-            let mut park_buffer = vec![0; c3park_size as usize - size_of::<u16>()];
-            file_lock.read_exact(&mut park_buffer).await?;
             ans_decode_deltas(
-                &park_buffer,
+                &park_buffer[size_of::<u16>()..],
                 compressed_size as usize,
                 K_CHECKPOINT1INTERVAL as usize,
                 K_C3R,
             )?
         };
-        let mut f7buffer = vec![0u64; count];
+        let mut f7buffer = vec![0u64; count + 1];
         let mut previous = c1;
         f7buffer[0] = c1;
         // Unpack deltas into absolute values
@@ -304,8 +324,18 @@ impl<
         self.read_p7park(park_index).await
     }
 
-    #[allow(clippy::cast_possible_truncation)]
     pub async fn read_p7park(&self, park_index: usize) -> Result<(), Error> {
+        let entries = self.load_p7park(park_index).await?;
+        let mut p7_entries = self.p7_entries.lock().await;
+        p7_entries.copy_from_slice(&entries);
+        *self.last_park.lock().await = park_index;
+        Ok(())
+    }
+
+    /// Table 6 indexes stored in a park of table 7, the park is padded with zeros past the last
+    /// entry of the table.
+    #[allow(clippy::cast_possible_truncation)]
+    pub async fn load_p7park(&self, park_index: usize) -> Result<Vec<u64>, Error> {
         let entry_size = 1 + self.plot_file().k() as usize;
         let table_address = self.file.table_address(PlotTable::Table7);
         let max_table_size = self.file.table_size(PlotTable::Table7);
@@ -321,34 +351,42 @@ impl<
         }
         let park_address = table_address + park_index as u64 * park_size;
         let mut buffer = vec![0; park_size as usize];
-        {
-            let file = self.file.file().clone();
-            let mut file_lock = file.lock().await;
-            file_lock.seek(SeekFrom::Start(park_address)).await?;
-            file_lock.read_exact(&mut buffer).await?;
-        }
+        self.read_exact_at(park_address, &mut buffer).await?;
         let mut reader = BitReader::from_bytes_be(&buffer, park_size as usize * 8);
-        for entry in self
-            .p7_entries
-            .lock()
-            .await
-            .iter_mut()
-            .take(K_ENTRIES_PER_PARK as usize)
-        {
+        let mut entries = vec![0u64; K_ENTRIES_PER_PARK as usize];
+        for entry in &mut entries {
             *entry = reader.read_u64(entry_size)?;
         }
+        Ok(entries)
+    }
+
+    /// Table 6 index stored at `p7index`. The last park read is cached, concurrent lookups that
+    /// miss the cache read their park without waiting on each other.
+    async fn p7_entry(&self, p7index: usize) -> Result<u64, Error> {
+        let park_index = p7index / K_ENTRIES_PER_PARK as usize;
+        let local_index = p7index % K_ENTRIES_PER_PARK as usize;
+        {
+            let p7_entries = self.p7_entries.lock().await;
+            if *self.last_park.lock().await == park_index {
+                return Ok(p7_entries[local_index]);
+            }
+        }
+        let entries = self.load_p7park(park_index).await?;
+        let entry = entries[local_index];
+        let mut p7_entries = self.p7_entries.lock().await;
+        p7_entries.copy_from_slice(&entries);
         *self.last_park.lock().await = park_index;
-        Ok(())
+        Ok(entry)
     }
 
     #[allow(clippy::cast_possible_truncation)]
     #[allow(clippy::too_many_lines)]
-    pub async fn fetch_proof(&self, index: u64) -> Result<Vec<u64>, Error> {
-        let mut first = vec![0u64; PROOF_X_COUNT];
-        let mut second = vec![0u64; PROOF_X_COUNT];
-        let lp_idx_src: &mut Vec<u64> = &mut first;
+    /// The x values of the proof at `index` as stored in the plot, for a compressed plot these are
+    /// the line points of the x groups the decompressor recovers the proof from.
+    pub async fn fetch_compressed_proof(&self, index: u64) -> Result<Vec<u64>, Error> {
+        let mut lp_idx_src = vec![0u64; PROOF_X_COUNT];
         lp_idx_src[0] = index;
-        let lp_idx_dst: &mut Vec<u64> = &mut second;
+        let mut lp_idx_dst = vec![0u64; PROOF_X_COUNT];
         // Fetch line points to back pointers going through all our tables
         // from 6 to 1, grabbing all of the x's that make up a proof.
         let mut lookup_count = 1;
@@ -403,12 +441,18 @@ impl<
                 dst += 2;
             }
             lookup_count <<= 1;
-            swap(lp_idx_src, lp_idx_dst);
+            swap(&mut lp_idx_src, &mut lp_idx_dst);
         }
-        if compression_level > 0 {
+        lp_idx_src.truncate(lookup_count);
+        Ok(lp_idx_src)
+    }
+
+    pub async fn fetch_proof(&self, index: u64) -> Result<Vec<u64>, Error> {
+        let proof = self.fetch_compressed_proof(index).await?;
+        let c = self.compression_level();
+        if c > 0 {
             let plot_id = self.plot_id();
             let k = self.file.k();
-            let c = self.compression_level();
             if let Some(pool) = self.proof_decompressor.as_ref() {
                 match pool.pull_wait(10000).await {
                     Ok(mut rede) => {
@@ -418,7 +462,7 @@ impl<
                             &plot_id,
                             k,
                             c,
-                            lp_idx_src,
+                            &proof,
                             Some(self.f1_generator.clone()),
                         ) {
                             Ok(p) => {
@@ -441,27 +485,31 @@ impl<
                 debug!("Search for proof at index {index} in plot {}", self.file);
                 let mut d = Decompressor::default();
                 d.prealloc_for_clevel(k, c);
-                d.decompress_proof(
-                    &plot_id,
-                    k,
-                    compression_level,
-                    lp_idx_src,
-                    Some(self.f1_generator.clone()),
-                )
+                d.decompress_proof(&plot_id, k, c, &proof, Some(self.f1_generator.clone()))
             }
         } else {
-            Ok(first)
+            Ok(proof)
         }
     }
 
-    pub fn read_line_point_park(
+    /// Decodes every line point of a park. The last park of a table may hold fewer than
+    /// `K_ENTRIES_PER_PARK` line points.
+    pub async fn read_line_point_park(
         &self,
-        _table: PlotTable,
-        _index: u64,
-        _line_points: &[u128],
-        _out_entry_count: u64,
-    ) -> bool {
-        todo!()
+        table: PlotTable,
+        park_index: u64,
+    ) -> Result<Vec<u128>, Error> {
+        let components = self.read_lp_park_components(table, park_index).await?;
+        let stub_size = self.calculate_lp_stubs_bits_size(table);
+        let mut line_points = Vec::with_capacity(components.deltas.len() + 1);
+        let mut line_point = components.base_line_point;
+        line_points.push(line_point);
+        for (i, delta) in components.deltas.iter().enumerate() {
+            let stub = read_bits(&components.stubs, i * stub_size as usize, stub_size);
+            line_point += (u128::from(*delta) << stub_size) + stub;
+            line_points.push(line_point);
+        }
+        Ok(line_points)
     }
 
     #[allow(clippy::cast_possible_truncation)]
@@ -504,13 +552,15 @@ impl<
         todo!()
     }
 
+    /// The line points of the lowest stored table on the path `challenge` takes from the proof at
+    /// `index`. From level 6 the second one is its sibling, as the decompressor needs both
+    /// leaves, otherwise it is 0.
     #[allow(clippy::cast_possible_truncation)]
-    #[allow(clippy::too_many_lines)]
-    pub async fn fetch_quality_xs_for_p7entry(
+    pub async fn fetch_quality_line_points(
         &self,
         index: u64,
         challenge: &[u8],
-    ) -> Result<(u64, u64), Error> {
+    ) -> Result<[u128; 2], Error> {
         let compression_level = self.file.compression_level();
         let last5bits = challenge[31] & 0x1f;
         let mut lp_index = index;
@@ -557,17 +607,26 @@ impl<
                 alt_index = x;
             }
         }
-        if compression_level > 0 {
-            let need_both_leaves = compression_level >= 6;
-            let x_lp0 = self.read_line_point(end_table, lp_index).await?;
-            let mut x_lp1: LinePoint = LinePoint { hi: 0, lo: 0 };
+        let need_both_leaves = compression_level >= 6;
+        Ok([
+            self.read_line_point(end_table, lp_index).await?,
             if need_both_leaves {
-                let tmp = self.read_line_point(end_table, alt_index).await?;
-                x_lp1 = LinePoint {
-                    hi: (tmp >> 64) as u64,
-                    lo: (tmp) as u64,
-                };
-            }
+                self.read_line_point(end_table, alt_index).await?
+            } else {
+                0
+            },
+        ])
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub async fn fetch_quality_xs_for_p7entry(
+        &self,
+        index: u64,
+        challenge: &[u8],
+    ) -> Result<(u64, u64), Error> {
+        let compression_level = self.file.compression_level();
+        let [x_lp0, x_lp1] = self.fetch_quality_line_points(index, challenge).await?;
+        if compression_level > 0 {
             let req = CompressedQualitiesRequest {
                 plot_id: self.plot_id(),
                 compression_level,
@@ -577,7 +636,10 @@ impl<
                         hi: (x_lp0 >> 64) as u64,
                         lo: (x_lp0) as u64,
                     },
-                    x_lp1,
+                    LinePoint {
+                        hi: (x_lp1 >> 64) as u64,
+                        lo: (x_lp1) as u64,
+                    },
                 ],
                 f1_generator: Some(self.f1_generator.clone()),
             };
@@ -614,11 +676,10 @@ impl<
                 d.get_fetch_qualties_x_pair(self.file.k(), req)
             }
         } else {
-            let lp = self.read_line_point(end_table, lp_index).await?;
             Ok(if self.file.k() <= 32 {
-                line_point_to_square64(lp as u64)
+                line_point_to_square64(x_lp0 as u64)
             } else {
-                line_point_to_square(lp)
+                line_point_to_square(x_lp0)
             })
         }
     }
@@ -656,13 +717,7 @@ impl<
         } else {
             let mut qualities = vec![];
             for i in 0..match_count {
-                let p7index = p7base_index + i;
-                let p7park = p7index / K_ENTRIES_PER_PARK as usize;
-                if p7park != *self.last_park.lock().await {
-                    self.read_p7entries(p7park).await?;
-                }
-                let local_p7index = p7index - p7park * K_ENTRIES_PER_PARK as usize;
-                let t6index = self.p7_entries.lock().await[local_p7index];
+                let t6index = self.p7_entry(p7base_index + i).await?;
                 let quality = self.fetch_quality_for_p7entry(t6index, challenge).await?;
                 qualities.push((t6index, quality));
             }
@@ -706,13 +761,7 @@ impl<
         } else {
             let mut proofs = FxHashSet::default();
             for i in 0..match_count {
-                let p7index = p7base_index + i;
-                let p7park = p7index / K_ENTRIES_PER_PARK as usize;
-                if p7park != *self.last_park.lock().await {
-                    self.read_p7entries(p7park).await?;
-                }
-                let local_p7index = p7index - p7park * K_ENTRIES_PER_PARK as usize;
-                let t6index = self.p7_entries.lock().await[local_p7index];
+                let t6index = self.p7_entry(p7base_index + i).await?;
                 match self.fetch_ordered_proof(t6index).await {
                     Ok(p) => {
                         proofs.insert(p);
@@ -765,16 +814,10 @@ impl<
         if c1entry_count == 0 {
             return Ok((0, 0));
         }
-        let mut c1_reader;
-        {
-            let file = self.file.file().clone();
-            let mut file_lock = file.lock().await;
-            file_lock.seek(SeekFrom::Start(c1entry_address)).await?;
-            // Read C1 entries until we find one equal or larger than the f7 we're looking for
-            let mut c1_buffer = vec![0; read_size];
-            file_lock.read_exact(&mut c1_buffer[0..read_size]).await?;
-            c1_reader = BitReader::from_bytes_be(&c1_buffer, read_size * 8);
-        }
+        // Read C1 entries until we find one equal or larger than the f7 we're looking for
+        let mut c1_buffer = vec![0; read_size];
+        self.read_exact_at(c1entry_address, &mut c1_buffer).await?;
+        let mut c1_reader = BitReader::from_bytes_be(&c1_buffer, read_size * 8);
         let mut c3park = c1start_index;
         let mut c1;
         let mut i = 0;
@@ -836,7 +879,7 @@ impl<
     #[allow(clippy::cast_possible_truncation)]
     pub fn calculate_max_deltas_size(&self, table: PlotTable) -> u32 {
         if self.is_compressed_table(table) {
-            let info = get_compression_info_for_level(self.file.compression_level());
+            let info = get_compression_info_for_k(self.file.k(), self.file.compression_level());
             let lp_size = ucdiv(u32::from(self.file.k() * 2), 8);
             let stub_byte_size = self.calculate_lp_stubs_size(table);
             info.table_park_size as u32 - (lp_size + stub_byte_size)
@@ -850,7 +893,7 @@ impl<
             "Getting stub bit size for invalid table."
         );
         if self.is_compressed_table(table) {
-            get_compression_info_for_level(self.file.compression_level()).stub_size_bits
+            get_compression_info_for_k(self.file.k(), self.file.compression_level()).stub_size_bits
         } else {
             u32::from(self.file.k() - K_STUB_MINUS_BITS)
         }
@@ -877,28 +920,25 @@ impl<
                 format!("Invalid Park Index: {park_index} >= {max_parks}"),
             ));
         }
-        let file = self.file.file().clone();
-        let mut file_lock = file.lock().await;
         let park_address = table_address + park_index * park_size;
-        file_lock.seek(SeekFrom::Start(park_address)).await?;
+        let line_point_size = EntrySizes::line_point_size_bytes(k) as usize;
+        let stubs_size_bytes = self.calculate_lp_stubs_size(table) as usize;
+        let max_deltas_size = self.calculate_max_deltas_size(table);
+        // The park is read at once: the checkpoint line point, the stubs, the size of the
+        // encoded deltas and the deltas themselves
+        let deltas_start = line_point_size + stubs_size_bytes + size_of::<u16>();
+        let mut park = vec![0u8; deltas_start + max_deltas_size as usize];
+        self.read_exact_at(park_address, &mut park).await?;
 
         // This is the checkpoint at the beginning of the park
-        let line_point_size = EntrySizes::line_point_size_bytes(k);
-        let mut line_point_bin = vec![0u8; line_point_size as usize];
-        file_lock.read_exact(&mut line_point_bin).await?;
-        let base_line_point = slice_u128from_bytes(line_point_bin, 0, k * 2);
+        let base_line_point = slice_u128from_bytes(&park[..line_point_size], 0, k * 2);
 
         // Reads EPP stubs
-        let stubs_size_bytes = self.calculate_lp_stubs_size(table) as usize;
-        let mut stubs = vec![0u8; stubs_size_bytes];
-        file_lock.read_exact(&mut stubs).await?;
+        let stubs = park[line_point_size..line_point_size + stubs_size_bytes].to_vec();
 
-        // Reads EPP deltas
-        let max_deltas_size = self.calculate_max_deltas_size(table);
         // Reads the size of the encoded deltas object
-        let mut encoded_deltas_buf: [u8; 2] = [0u8; 2];
-        file_lock.read_exact(&mut encoded_deltas_buf).await?;
-        let mut encoded_deltas_size = u16::from_le_bytes(encoded_deltas_buf);
+        let mut encoded_deltas_size =
+            u16::from_le_bytes([park[deltas_start - 2], park[deltas_start - 1]]);
         if !(encoded_deltas_size & 0x8000) > 0 && u32::from(encoded_deltas_size) > max_deltas_size {
             return Err(Error::new(
                 ErrorKind::InvalidInput,
@@ -909,23 +949,29 @@ impl<
         if 0x8000 & encoded_deltas_size > 0 {
             // Uncompressed
             encoded_deltas_size &= 0x7fff;
-            deltas = vec![0u8; encoded_deltas_size as usize];
-            file_lock.read_exact(&mut deltas).await?;
+            let deltas_end = deltas_start + encoded_deltas_size as usize;
+            if deltas_end <= park.len() {
+                deltas = park[deltas_start..deltas_end].to_vec();
+            } else {
+                deltas = vec![0u8; encoded_deltas_size as usize];
+                self.read_exact_at(park_address + deltas_start as u64, &mut deltas)
+                    .await?;
+            }
         } else {
             // Compressed
-            let mut deltas_bin = vec![0u8; (max_deltas_size) as usize];
-            file_lock.read_exact(&mut deltas_bin).await?;
+            let deltas_bin = &park[deltas_start..];
             //Decodes the deltas
             let num_deltas = (K_ENTRIES_PER_PARK - 1) as usize;
             let d_table = self.get_dtable_for_table(table)?;
             let mut dst = vec![0u8; num_deltas];
-            decompress_using_dtable(
+            let decoded = decompress_using_dtable(
                 &mut dst,
                 num_deltas,
-                &deltas_bin,
+                deltas_bin,
                 encoded_deltas_size as usize,
                 d_table,
             )?;
+            dst.truncate(decoded);
             deltas = dst;
         }
         Ok(LinePointParkComponents {
@@ -951,18 +997,15 @@ impl<
             let c2max_entries = c2size / f7byte_size;
             if c2max_entries > 0 {
                 let address = self.file.table_address(PlotTable::C2);
-                let file = self.file.file();
-                let mut file_lock = file.lock().await;
-                file_lock.seek(SeekFrom::Start(address)).await?;
                 let mut buffer = vec![0; c2size];
-                file_lock.read_exact(&mut buffer).await?;
+                self.read_exact_at(address, &mut buffer).await?;
                 self.c2_entries = Vec::with_capacity(c2max_entries);
                 let mut reader = BitReader::from_bytes_be(&buffer, c2size * 8);
                 let mut prev_f7 = 0;
                 for _ in 0..c2max_entries {
                     let f7 = reader.read_u64(k)?;
                     // Short circuit if we encounter an unsorted/out-of-order c2 entry
-                    if f7 < prev_f7 {
+                    if f7 < prev_f7 || (f7 == 0 && !self.c2_entries.is_empty()) {
                         break;
                     }
                     self.c2_entries.push(f7);
@@ -993,13 +1036,27 @@ pub fn reorder_proof(
 
 pub fn read_plot_header(file: &mut std::fs::File) -> Result<PlotHeader, Error> {
     use std::io::Read;
-    let mut full_buffer = [0; 320];
+    let mut full_buffer = [0; MAX_HEADER_SIZE];
     file.read_exact(&mut full_buffer)?;
+    parse_plot_header(&full_buffer)
+}
+
+/// Parses a plot header from the first bytes of a plot.
+pub fn parse_plot_header(full_buffer: &[u8]) -> Result<PlotHeader, Error> {
+    if full_buffer.len() < MAX_HEADER_SIZE {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            format!(
+                "Plot header needs {MAX_HEADER_SIZE} bytes, found {}",
+                full_buffer.len()
+            ),
+        ));
+    }
     if HEADER_V2_MAGIC == full_buffer[0..4] {
-        Ok(PlotHeader::V2(parse_v2(&full_buffer)?))
+        Ok(PlotHeader::V2(parse_v2(full_buffer)?))
     } else if HEADER_MAGIC == full_buffer[0..19] {
         //TODO Gigahorse plots also follow this format but cant be actually read, memo is encrypted.
-        Ok(PlotHeader::V1(parse_v1(&full_buffer)?))
+        Ok(PlotHeader::V1(parse_v1(full_buffer)?))
     } else {
         Err(Error::new(
             ErrorKind::InvalidInput,
@@ -1073,18 +1130,9 @@ fn parse_v2(full_buffer: &[u8]) -> Result<PlotHeaderV2, Error> {
 }
 
 pub async fn read_plot_header_async(file: &mut tokio::fs::File) -> Result<PlotHeader, Error> {
-    let mut full_buffer = [0; 320];
+    let mut full_buffer = [0; MAX_HEADER_SIZE];
     file.read_exact(&mut full_buffer).await?;
-    if HEADER_V2_MAGIC == full_buffer[0..4] {
-        Ok(PlotHeader::V2(parse_v2(&full_buffer)?))
-    } else if HEADER_MAGIC == full_buffer[0..19] {
-        Ok(PlotHeader::V1(parse_v1(&full_buffer)?))
-    } else {
-        Err(Error::new(
-            ErrorKind::InvalidInput,
-            "Invalid plot header magic",
-        ))
-    }
+    parse_plot_header(&full_buffer)
 }
 
 pub async fn read_plot_file_header_async(
@@ -0,0 +1,260 @@
+use crate::plots::plotting::read_bits;
+use rayon::prelude::*;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs;
+use tokio::fs::{remove_file, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+use tokio::sync::Mutex;
+
+const BUCKET_WRITE_BUFFER: usize = 256 * 1024;
+
+pub enum BucketStorage {
+    /// Buckets are spilled to files named `{prefix}.bucket_{index}.tmp`
+    Disk(PathBuf),
+    Memory,
+}
+pub enum Bucket {
+    File(PathBuf, Mutex<Option<BufWriter<File>>>),
+    Memory(Mutex<Vec<u8>>),
+}
+
+/// Sorts fixed size, big endian entries. Entries are split into buckets on their leading bits
+/// while being inserted, each bucket is then read back fully sorted. Reading the buckets in order
+/// yields the whole set of entries in sorted order.
+pub struct BucketSorter {
+    storage: BucketStorage,
+    buckets: Vec<Bucket>,
+    log_num_buckets: u32,
+    entry_size: usize,
+    num_entries: AtomicU64,
+}
+impl BucketSorter {
+    pub async fn new(
+        storage: BucketStorage,
+        num_buckets: u32,
+        entry_size: usize,
+    ) -> Result<Self, Error> {
+        if !num_buckets.is_power_of_two() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Bucket count must be a power of 2, got {num_buckets}"),
+            ));
+        }
+        if entry_size == 0 || num_buckets.ilog2() > entry_size as u32 * 8 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Entry size {entry_size} is too small for {num_buckets} buckets"),
+            ));
+        }
+        let mut buckets = Vec::with_capacity(num_buckets as usize);
+        for i in 0..num_buckets {
+            match &storage {
+                BucketStorage::Disk(prefix) => {
+                    let path = bucket_path(prefix, i);
+                    let file = OpenOptions::new()
+                        .create(true)
+                        .write(true)
+                        .truncate(true)
+                        .open(&path)
+                        .await?;
+                    buckets.push(Bucket::File(
+                        path,
+                        Mutex::new(Some(BufWriter::with_capacity(BUCKET_WRITE_BUFFER, file))),
+                    ));
+                }
+                BucketStorage::Memory => {
+                    buckets.push(Bucket::Memory(Mutex::default()));
+                }
+            }
+        }
+        Ok(BucketSorter {
+            storage,
+            buckets,
+            log_num_buckets: num_buckets.ilog2(),
+            entry_size,
+            num_entries: AtomicU64::default(),
+        })
+    }
+
+    /// Reopens the flushed bucket files of a sorter, used to resume an interrupted plot.
+    pub async fn open(prefix: PathBuf, num_buckets: u32, entry_size: usize) -> Result<Self, Error> {
+        if !num_buckets.is_power_of_two() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Bucket count must be a power of 2, got {num_buckets}"),
+            ));
+        }
+        let mut buckets = Vec::with_capacity(num_buckets as usize);
+        let mut num_entries = 0;
+        for i in 0..num_buckets {
+            let path = bucket_path(&prefix, i);
+            let size = fs::metadata(&path).await?.len();
+            if size % entry_size as u64 != 0 {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Bucket {} does not hold whole entries", path.display()),
+                ));
+            }
+            num_entries += size / entry_size as u64;
+            buckets.push(Bucket::File(path, Mutex::new(None)));
+        }
+        Ok(BucketSorter {
+            storage: BucketStorage::Disk(prefix),
+            buckets,
+            log_num_buckets: num_buckets.ilog2(),
+            entry_size,
+            num_entries: AtomicU64::new(num_entries),
+        })
+    }
+
+    /// Files backing the buckets, empty for in memory sorters.
+    #[must_use]
+    pub fn bucket_files(&self) -> Vec<PathBuf> {
+        self.buckets
+            .iter()
+            .filter_map(|bucket| match bucket {
+                Bucket::File(path, _) => Some(path.clone()),
+                Bucket::Memory(_) => None,
+            })
+            .collect()
+    }
+
+    #[must_use]
+    pub fn num_buckets(&self) -> usize {
+        self.buckets.len()
+    }
+
+    #[must_use]
+    pub fn num_entries(&self) -> u64 {
+        self.num_entries.load(Ordering::Relaxed)
+    }
+
+    #[must_use]
+    pub fn entry_size(&self) -> usize {
+        self.entry_size
+    }
+
+    #[must_use]
+    pub fn is_memory(&self) -> bool {
+        matches!(self.storage, BucketStorage::Memory)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn bucket_index(&self, entry: &[u8]) -> usize {
+        read_bits(entry, 0, self.log_num_buckets) as usize
+    }
+
+    /// Inserts a buffer of one or more packed entries.
+    pub async fn insert(&self, entries: &[u8]) -> Result<(), Error> {
+        if !entries.len().is_multiple_of(self.entry_size) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Buffer of {} bytes does not hold whole entries of size {}",
+                    entries.len(),
+                    self.entry_size
+                ),
+            ));
+        }
+        let mut split = vec![vec![]; self.buckets.len()];
+        for entry in entries.chunks_exact(self.entry_size) {
+            split[self.bucket_index(entry)].extend_from_slice(entry);
+        }
+        for (bucket, data) in self.buckets.iter().zip(split) {
+            if data.is_empty() {
+                continue;
+            }
+            match bucket {
+                Bucket::File(path, writer) => match writer.lock().await.as_mut() {
+                    Some(writer) => writer.write_all(&data).await?,
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            format!("Bucket {} was already flushed", path.display()),
+                        ));
+                    }
+                },
+                Bucket::Memory(buffer) => {
+                    buffer.lock().await.extend_from_slice(&data);
+                }
+            }
+        }
+        self.num_entries
+            .fetch_add((entries.len() / self.entry_size) as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Flushes and closes all bucket writers, no more entries can be inserted after this.
+    pub async fn flush(&self) -> Result<(), Error> {
+        for bucket in &self.buckets {
+            match bucket {
+                Bucket::File(_, writer) => {
+                    if let Some(mut writer) = writer.lock().await.take() {
+                        writer.flush().await?;
+                        writer.get_mut().sync_all().await?;
+                    }
+                }
+                Bucket::Memory(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads a bucket and returns its entries sorted. Memory buckets are released once read, bucket
+    /// files are kept until `cleanup` so an interrupted plot can read them again.
+    pub async fn read_bucket(&self, index: usize) -> Result<Vec<u8>, Error> {
+        let data = match &self.buckets[index] {
+            Bucket::File(path, writer) => {
+                if writer.lock().await.is_some() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "Bucket must be flushed before being read",
+                    ));
+                }
+                let mut data = vec![];
+                File::open(path).await?.read_to_end(&mut data).await?;
+                data
+            }
+            Bucket::Memory(buffer) => std::mem::take(&mut *buffer.lock().await),
+        };
+        Ok(sort_entries(&data, self.entry_size))
+    }
+
+    /// Removes the bucket files, or clears the buckets held in memory.
+    pub async fn cleanup(&self) -> Result<(), Error> {
+        for bucket in &self.buckets {
+            match bucket {
+                Bucket::File(path, writer) => {
+                    writer.lock().await.take();
+                    if path.exists() {
+                        remove_file(path).await?;
+                    }
+                }
+                Bucket::Memory(buffer) => {
+                    buffer.lock().await.clear();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn bucket_path(prefix: &Path, index: u32) -> PathBuf {
+    let mut path = prefix.to_path_buf().into_os_string();
+    path.push(format!(".bucket_{index:0>3}.tmp"));
+    PathBuf::from(path)
+}
+
+/// Sorts packed entries of `entry_size` bytes lexicographically.
+#[must_use]
+pub fn sort_entries(data: &[u8], entry_size: usize) -> Vec<u8> {
+    let mut entries: Vec<&[u8]> = data.chunks_exact(entry_size).collect();
+    entries.par_sort_unstable();
+    let mut sorted = Vec::with_capacity(data.len());
+    for entry in entries {
+        sorted.extend_from_slice(entry);
+    }
+    sorted
+}
@@ -0,0 +1,189 @@
+use crate::plots::plotting::{PlotContext, ProgressCallback};
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use hex::{decode, encode};
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+pub const PLOT_MANIFEST_VERSION: u32 = 1;
+
+/// Last checkpoint reached by a plot, plotting resumes with the step that follows it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum PlotStage {
+    /// No work has been saved yet
+    Started,
+    /// Entries of `table` are sorted into their phase 1 buckets and the tables below it are
+    /// written. Only reached when sorting on disk.
+    Phase1 {
+        table: u8,
+    },
+    Phase1Complete,
+    Phase2Complete,
+    /// Parks up to `table` are written to the plot file, which is valid up to `offset`.
+    /// `left_values` holds the new positions of the entries of the next table.
+    Phase3 {
+        table: u8,
+        offset: u64,
+        table_begin_pointers: [u64; 10],
+        left_values: PathBuf,
+    },
+    /// The plot file is complete and only needs to be moved to the final directory
+    Phase4Complete {
+        plot_size: u64,
+    },
+}
+
+/// Progress of a plot, saved next to its temp files after every completed table so an
+/// interrupted plot can be resumed with `DiskPlot::resume`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlotManifest {
+    pub version: u32,
+    pub plot_id: Bytes32,
+    pub k: u8,
+    pub compression_level: u8,
+    /// Hex encoded plot memo
+    pub memo: String,
+    pub filename: String,
+    pub tmp1_dir: PathBuf,
+    pub tmp2_dir: PathBuf,
+    pub final_dir: PathBuf,
+    pub num_buckets: u32,
+    pub in_memory: bool,
+    /// Entries in each table, indexed by table number
+    pub table_sizes: [u64; 8],
+    /// Bucket files holding the sorted entries of the table named in the stage
+    pub bucket_files: Vec<PathBuf>,
+    pub bucket_entries: u64,
+    pub stage: PlotStage,
+}
+impl PlotManifest {
+    #[allow(clippy::too_many_arguments)]
+    #[must_use]
+    pub fn new(
+        plot_id: Bytes32,
+        k: u8,
+        compression_level: u8,
+        memo: &[u8],
+        filename: String,
+        tmp1_dir: &Path,
+        tmp2_dir: &Path,
+        final_dir: &Path,
+        num_buckets: u32,
+        in_memory: bool,
+    ) -> Self {
+        Self {
+            version: PLOT_MANIFEST_VERSION,
+            plot_id,
+            k,
+            compression_level,
+            memo: encode(memo),
+            filename,
+            tmp1_dir: tmp1_dir.to_path_buf(),
+            tmp2_dir: tmp2_dir.to_path_buf(),
+            final_dir: final_dir.to_path_buf(),
+            num_buckets,
+            in_memory,
+            table_sizes: [0u64; 8],
+            bucket_files: vec![],
+            bucket_entries: 0,
+            stage: PlotStage::Started,
+        }
+    }
+
+    /// Location of the manifest of the plot named `filename`.
+    #[must_use]
+    pub fn path_for(tmp1_dir: &Path, filename: &str) -> PathBuf {
+        tmp1_dir.join(format!("{filename}.manifest.json"))
+    }
+
+    #[must_use]
+    pub fn path(&self) -> PathBuf {
+        Self::path_for(&self.tmp1_dir, &self.filename)
+    }
+
+    pub fn memo(&self) -> Result<Vec<u8>, Error> {
+        decode(&self.memo).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Temp file of each table, index 0 is the prefix used for the sort buckets.
+    #[must_use]
+    pub fn table_files(&self) -> Vec<PathBuf> {
+        let mut table_files = vec![self.tmp1_dir.join(format!("{}.sort.tmp", self.filename))];
+        for i in 1..=7 {
+            table_files.push(
+                self.tmp1_dir
+                    .join(format!("{}.table{i}.tmp", self.filename)),
+            );
+        }
+        table_files
+    }
+
+    #[must_use]
+    pub fn tmp2_file(&self) -> PathBuf {
+        self.tmp2_dir.join(format!("{}.2.tmp", self.filename))
+    }
+
+    #[must_use]
+    pub fn final_file(&self) -> PathBuf {
+        self.final_dir.join(&self.filename)
+    }
+
+    #[must_use]
+    pub fn context(&self, progress: Option<ProgressCallback>) -> PlotContext {
+        let table_files = self.table_files();
+        PlotContext {
+            k: self.k,
+            plot_id: self.plot_id,
+            num_buckets: self.num_buckets,
+            in_memory: self.in_memory,
+            compression_level: self.compression_level,
+            tmp_dir: self.tmp1_dir.clone(),
+            sort_prefix: table_files[0].clone(),
+            table_files,
+            progress,
+        }
+    }
+
+    /// Records `stage` as completed and saves the manifest.
+    pub async fn checkpoint(&mut self, stage: PlotStage) -> Result<(), Error> {
+        self.stage = stage;
+        self.save().await
+    }
+
+    /// Writes the manifest through a temp file so a crash never leaves a partial manifest.
+    pub async fn save(&self) -> Result<(), Error> {
+        let path = self.path();
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let data = serde_json::to_vec_pretty(self).map_err(Error::other)?;
+        let mut file = fs::File::create(&tmp_path).await?;
+        file.write_all(&data).await?;
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(&tmp_path, &path).await
+    }
+
+    pub async fn load(path: &Path) -> Result<Self, Error> {
+        let data = fs::read(path).await?;
+        let manifest: Self =
+            serde_json::from_slice(&data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        if manifest.version != PLOT_MANIFEST_VERSION {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("Unsupported plot manifest version {}", manifest.version),
+            ));
+        }
+        Ok(manifest)
+    }
+
+    pub async fn remove(&self) -> Result<(), Error> {
+        let path = self.path();
+        if path.exists() {
+            fs::remove_file(path).await?;
+        }
+        Ok(())
+    }
+}
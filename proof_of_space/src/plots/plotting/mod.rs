@@ -1,5 +1,250 @@
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::plots::PlotTable;
+use std::cmp::min;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+pub mod bucket_sorter;
+pub mod manifest;
 pub mod phase1;
 pub mod phase2;
 pub mod phase3;
 pub mod phase4;
-mod BucketSorter;
\ No newline at end of file
+
+/// Largest k the plotter supports, metadata of larger plots does not fit the sort entries
+pub const MAX_PLOTTING_K: u8 = 32;
+
+/// Highest compression level the plotter can write, levels above 7 change the table layout
+pub const MAX_PLOTTING_COMPRESSION_LEVEL: u8 = 7;
+/// k the compression levels are tuned for, smaller plots can use the levels that fit them
+pub const COMPRESSED_PLOT_K: u8 = 32;
+
+const IO_BUFFER_SIZE: usize = 1024 * 1024;
+const READ_CHUNK_ENTRIES: usize = 1 << 16;
+
+pub const PLOT_TABLES: [PlotTable; 7] = [
+    PlotTable::Table1,
+    PlotTable::Table2,
+    PlotTable::Table3,
+    PlotTable::Table4,
+    PlotTable::Table5,
+    PlotTable::Table6,
+    PlotTable::Table7,
+];
+
+/// Progress of a running plot, reported after each bucket of a table is processed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlotProgress {
+    pub phase: u8,
+    pub table: u8,
+    pub bucket: usize,
+    pub num_buckets: usize,
+    /// Estimated completion of the whole plot, from 0 to 100
+    pub percent: f64,
+}
+impl PlotProgress {
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn new(phase: u8, table: u8, bucket: usize, num_buckets: usize) -> Self {
+        // Share of the plotting time spent before and during each phase
+        let (start, weight, step, steps) = match phase {
+            1 => (0.0, 42.0, table.saturating_sub(1), 7),
+            2 => (42.0, 19.0, 7u8.saturating_sub(table), 7),
+            3 => (61.0, 37.0, table.saturating_sub(1), 6),
+            _ => (98.0, 2.0, 0, 1),
+        };
+        let bucket_fraction = (bucket + 1) as f64 / num_buckets.max(1) as f64;
+        let fraction = ((f64::from(step) + bucket_fraction.min(1.0)) / f64::from(steps)).min(1.0);
+        Self {
+            phase,
+            table,
+            bucket,
+            num_buckets,
+            percent: start + weight * fraction,
+        }
+    }
+}
+
+pub type ProgressCallback = Arc<dyn Fn(&PlotProgress) + Send + Sync + 'static>;
+
+/// Creates a progress callback that forwards every event to the returned channel.
+#[must_use]
+pub fn progress_channel() -> (ProgressCallback, UnboundedReceiver<PlotProgress>) {
+    let (sender, receiver) = unbounded_channel();
+    let callback: ProgressCallback = Arc::new(move |progress: &PlotProgress| {
+        let _ = sender.send(*progress);
+    });
+    (callback, receiver)
+}
+
+/// Shared settings for all plotting phases.
+#[derive(Clone)]
+pub struct PlotContext {
+    pub k: u8,
+    pub plot_id: Bytes32,
+    pub num_buckets: u32,
+    pub in_memory: bool,
+    /// Bladebit compression level of the plot, 0 for an uncompressed plot
+    pub compression_level: u8,
+    pub tmp_dir: PathBuf,
+    pub sort_prefix: PathBuf,
+    /// Temp files for the tables, index 0 is unused so tables can be indexed by number
+    pub table_files: Vec<PathBuf>,
+    pub progress: Option<ProgressCallback>,
+}
+impl PlotContext {
+    pub fn report_progress(&self, phase: u8, table: u8, bucket: usize, num_buckets: usize) {
+        if let Some(progress) = &self.progress {
+            progress(&PlotProgress::new(phase, table, bucket, num_buckets));
+        }
+    }
+    #[must_use]
+    pub fn table_file(&self, table_index: u8) -> &Path {
+        &self.table_files[table_index as usize]
+    }
+    #[must_use]
+    pub fn sort_prefix(&self, name: &str) -> PathBuf {
+        let mut prefix = self.sort_prefix.clone().into_os_string();
+        prefix.push(format!(".{name}"));
+        PathBuf::from(prefix)
+    }
+    #[must_use]
+    pub fn work_file(&self, name: &str) -> PathBuf {
+        let mut path = self.table_files[1].clone().into_os_string();
+        path.push(format!(".{name}"));
+        PathBuf::from(path)
+    }
+}
+
+/// Writes the lowest `num_bits` of `value` into `buf` as big endian bits starting at `start_bit`.
+/// The destination bits are expected to be zeroed.
+#[allow(clippy::cast_possible_truncation)]
+pub fn write_bits(buf: &mut [u8], start_bit: usize, value: u128, num_bits: u32) {
+    let mut remaining = num_bits as usize;
+    let mut bit = start_bit;
+    while remaining > 0 {
+        let offset = bit % 8;
+        let take = min(8 - offset, remaining);
+        let chunk = ((value >> (remaining - take)) & ((1u128 << take) - 1)) as u8;
+        buf[bit / 8] |= chunk << (8 - offset - take);
+        bit += take;
+        remaining -= take;
+    }
+}
+
+/// Reads `num_bits` big endian bits from `buf` starting at `start_bit`.
+#[must_use]
+pub fn read_bits(buf: &[u8], start_bit: usize, num_bits: u32) -> u128 {
+    let mut remaining = num_bits as usize;
+    let mut bit = start_bit;
+    let mut value = 0u128;
+    while remaining > 0 {
+        let offset = bit % 8;
+        let take = min(8 - offset, remaining);
+        let chunk = (buf[bit / 8] >> (8 - offset - take)) & (((1u16 << take) - 1) as u8);
+        value = (value << take) | u128::from(chunk);
+        bit += take;
+        remaining -= take;
+    }
+    value
+}
+
+/// Packs fixed width fields into a single entry.
+pub struct EntryPacker<'a> {
+    buf: &'a mut [u8],
+    bit: usize,
+}
+impl<'a> EntryPacker<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        buf.fill(0);
+        Self { buf, bit: 0 }
+    }
+    pub fn put(&mut self, value: u128, num_bits: u32) -> &mut Self {
+        write_bits(self.buf, self.bit, value, num_bits);
+        self.bit += num_bits as usize;
+        self
+    }
+}
+
+/// Reads fixed width fields out of a single entry.
+pub struct EntryUnpacker<'a> {
+    buf: &'a [u8],
+    bit: usize,
+}
+impl<'a> EntryUnpacker<'a> {
+    #[must_use]
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, bit: 0 }
+    }
+    pub fn take(&mut self, num_bits: u32) -> u128 {
+        let value = read_bits(self.buf, self.bit, num_bits);
+        self.bit += num_bits as usize;
+        value
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn take_u64(&mut self, num_bits: u32) -> u64 {
+        self.take(num_bits) as u64
+    }
+}
+
+/// Sequential writer for a temp file of fixed size entries.
+pub struct EntryFileWriter {
+    writer: BufWriter<File>,
+    entry_size: usize,
+    count: u64,
+}
+impl EntryFileWriter {
+    pub async fn create(path: &Path, entry_size: usize) -> Result<Self, Error> {
+        Ok(Self {
+            writer: BufWriter::with_capacity(IO_BUFFER_SIZE, File::create(path).await?),
+            entry_size,
+            count: 0,
+        })
+    }
+    pub async fn write(&mut self, entries: &[u8]) -> Result<(), Error> {
+        debug_assert_eq!(entries.len() % self.entry_size, 0);
+        self.writer.write_all(entries).await?;
+        self.count += (entries.len() / self.entry_size) as u64;
+        Ok(())
+    }
+    pub async fn finish(mut self) -> Result<u64, Error> {
+        self.writer.flush().await?;
+        self.writer.get_mut().sync_all().await?;
+        Ok(self.count)
+    }
+}
+
+/// Sequential reader for a temp file of fixed size entries.
+pub struct EntryFileReader {
+    reader: BufReader<File>,
+    entry_size: usize,
+    remaining: u64,
+}
+impl EntryFileReader {
+    pub async fn open(path: &Path, entry_size: usize, count: u64) -> Result<Self, Error> {
+        Ok(Self {
+            reader: BufReader::with_capacity(IO_BUFFER_SIZE, File::open(path).await?),
+            entry_size,
+            remaining: count,
+        })
+    }
+    /// Reads the next chunk of entries, an empty Vec means the end of the file was reached.
+    #[allow(clippy::cast_possible_truncation)]
+    pub async fn read_chunk(&mut self) -> Result<Vec<u8>, Error> {
+        let entries = min(self.remaining, READ_CHUNK_ENTRIES as u64) as usize;
+        let mut buf = vec![0u8; entries * self.entry_size];
+        self.reader.read_exact(&mut buf).await?;
+        self.remaining -= entries as u64;
+        Ok(buf)
+    }
+}
+
+/// Number of bytes used by an entry of `bits` bits.
+#[must_use]
+pub const fn entry_bytes(bits: u32) -> usize {
+    bits.div_ceil(8) as usize
+}
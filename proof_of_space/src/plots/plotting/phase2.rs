@@ -1,5 +1,188 @@
-use std::io::Error;
+use crate::constants::K_OFFSET_SIZE;
+use crate::plots::plotting::manifest::{PlotManifest, PlotStage};
+use crate::plots::plotting::phase1::{pos_bits, table_entry_size};
+use crate::plots::plotting::{
+    EntryFileReader, EntryFileWriter, EntryPacker, EntryUnpacker, PlotContext,
+};
+use log::info;
+use std::io::{Error, ErrorKind};
+use tokio::fs::rename;
+use tokio::time::Instant;
 
-pub async fn phase2() -> Result<Vec<()>, Error> {
-    todo!()
-}
\ No newline at end of file
+/// Tracks which entries of a table are used by the next table, with a rank index to map old
+/// positions to the positions after unused entries are dropped.
+pub struct Bitfield {
+    bits: Vec<u64>,
+    ranks: Vec<u64>,
+    size: u64,
+}
+impl Bitfield {
+    #[allow(clippy::cast_possible_truncation)]
+    #[must_use]
+    pub fn new(size: u64) -> Self {
+        Self {
+            bits: vec![0u64; size.div_ceil(64) as usize],
+            ranks: vec![],
+            size,
+        }
+    }
+    pub fn set(&mut self, index: u64) -> Result<(), Error> {
+        if index >= self.size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Back pointer {index} is outside of table of size {}",
+                    self.size
+                ),
+            ));
+        }
+        self.bits[(index / 64) as usize] |= 1 << (index % 64);
+        Ok(())
+    }
+    #[must_use]
+    pub fn get(&self, index: u64) -> bool {
+        self.bits[(index / 64) as usize] & (1 << (index % 64)) != 0
+    }
+    pub fn build_index(&mut self) {
+        self.ranks = Vec::with_capacity(self.bits.len());
+        let mut total = 0;
+        for word in &self.bits {
+            self.ranks.push(total);
+            total += u64::from(word.count_ones());
+        }
+    }
+    /// Number of set bits before `index`, requires `build_index` to have been called.
+    #[must_use]
+    pub fn rank(&self, index: u64) -> u64 {
+        let word = (index / 64) as usize;
+        let mask = (1u64 << (index % 64)) - 1;
+        self.ranks[word] + u64::from((self.bits[word] & mask).count_ones())
+    }
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.bits.iter().map(|w| u64::from(w.count_ones())).sum()
+    }
+}
+
+/// Backpropagation. Drops every entry that is not part of a full proof, walking from table 7
+/// back to table 1, and rewrites the back pointers of the remaining entries to the new
+/// positions. Returns the new table sizes. The rewritten tables only replace the phase 1 tables
+/// once all of them are written, so an interrupted run can start the phase over.
+pub async fn phase2(ctx: &PlotContext, manifest: &mut PlotManifest) -> Result<[u64; 8], Error> {
+    if manifest.stage == PlotStage::Phase2Complete {
+        replace_tables(ctx).await?;
+        return Ok(manifest.table_sizes);
+    }
+    let k = ctx.k;
+    let table_sizes = manifest.table_sizes;
+    let mut new_sizes = [0u64; 8];
+    let mut current: Option<Bitfield> = None;
+    for table_index in (2u8..=7).rev() {
+        let table_timer = Instant::now();
+        info!("\tBackpropagating on table {table_index}");
+        let entry_size = table_entry_size(k, table_index);
+        let count = table_sizes[table_index as usize];
+        let prefix_bits = if table_index == 7 { u32::from(k) } else { 0 };
+        // Mark which entries of the previous table are used
+        let mut next = Bitfield::new(table_sizes[table_index as usize - 1]);
+        let mut reader =
+            EntryFileReader::open(ctx.table_file(table_index), entry_size, count).await?;
+        let mut index = 0u64;
+        loop {
+            let chunk = reader.read_chunk().await?;
+            if chunk.is_empty() {
+                break;
+            }
+            for entry in chunk.chunks_exact(entry_size) {
+                if current.as_ref().is_none_or(|c| c.get(index)) {
+                    let mut unpacker = EntryUnpacker::new(entry);
+                    unpacker.take(prefix_bits);
+                    let pos = unpacker.take_u64(pos_bits(k));
+                    let offset = unpacker.take_u64(K_OFFSET_SIZE);
+                    next.set(pos)?;
+                    next.set(pos + offset)?;
+                }
+                index += 1;
+            }
+        }
+        next.build_index();
+        // Rewrite the table with only the used entries pointing to the new positions
+        let tmp_path = ctx.work_file(&format!("p2.t{table_index}"));
+        let mut reader =
+            EntryFileReader::open(ctx.table_file(table_index), entry_size, count).await?;
+        let mut writer = EntryFileWriter::create(&tmp_path, entry_size).await?;
+        let mut index = 0u64;
+        loop {
+            let chunk = reader.read_chunk().await?;
+            if chunk.is_empty() {
+                break;
+            }
+            let mut out = Vec::with_capacity(chunk.len());
+            let mut dst = vec![0u8; entry_size];
+            for entry in chunk.chunks_exact(entry_size) {
+                if current.as_ref().is_none_or(|c| c.get(index)) {
+                    let mut unpacker = EntryUnpacker::new(entry);
+                    let prefix = unpacker.take(prefix_bits);
+                    let pos = unpacker.take_u64(pos_bits(k));
+                    let offset = unpacker.take_u64(K_OFFSET_SIZE);
+                    let new_pos = next.rank(pos);
+                    let new_offset = next.rank(pos + offset) - new_pos;
+                    EntryPacker::new(&mut dst)
+                        .put(prefix, prefix_bits)
+                        .put(u128::from(new_pos), pos_bits(k))
+                        .put(u128::from(new_offset), K_OFFSET_SIZE);
+                    out.extend_from_slice(&dst);
+                }
+                index += 1;
+            }
+            writer.write(&out).await?;
+        }
+        new_sizes[table_index as usize] = writer.finish().await?;
+        ctx.report_progress(2, table_index, 0, 1);
+        info!(
+            "\tTable {table_index} now has {} entries, completed in {:.8} seconds",
+            new_sizes[table_index as usize],
+            table_timer.elapsed().as_secs_f64()
+        );
+        current = Some(next);
+    }
+    // Table 1 only keeps the x values that are still referenced
+    let entry_size = table_entry_size(k, 1);
+    let used = current.ok_or_else(|| Error::other("Missing bitfield for table 1"))?;
+    let tmp_path = ctx.work_file("p2.t1");
+    let mut reader = EntryFileReader::open(ctx.table_file(1), entry_size, table_sizes[1]).await?;
+    let mut writer = EntryFileWriter::create(&tmp_path, entry_size).await?;
+    let mut index = 0u64;
+    loop {
+        let chunk = reader.read_chunk().await?;
+        if chunk.is_empty() {
+            break;
+        }
+        let mut out = Vec::with_capacity(chunk.len());
+        for entry in chunk.chunks_exact(entry_size) {
+            if used.get(index) {
+                out.extend_from_slice(entry);
+            }
+            index += 1;
+        }
+        writer.write(&out).await?;
+    }
+    new_sizes[1] = writer.finish().await?;
+    ctx.report_progress(2, 1, 0, 1);
+    info!("\tTable 1 now has {} entries", new_sizes[1]);
+    manifest.table_sizes = new_sizes;
+    manifest.checkpoint(PlotStage::Phase2Complete).await?;
+    replace_tables(ctx).await?;
+    Ok(new_sizes)
+}
+
+/// Moves the rewritten tables over the phase 1 tables, skipping tables that were already moved.
+async fn replace_tables(ctx: &PlotContext) -> Result<(), Error> {
+    for table_index in 1u8..=7 {
+        let tmp_path = ctx.work_file(&format!("p2.t{table_index}"));
+        if tmp_path.exists() {
+            rename(&tmp_path, ctx.table_file(table_index)).await?;
+        }
+    }
+    Ok(())
+}
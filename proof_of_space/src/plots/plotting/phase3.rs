@@ -1,5 +1,485 @@
-use std::io::Error;
+use crate::constants::{ucdiv, K_ENTRIES_PER_PARK, K_OFFSET_SIZE, K_RVALUES, K_STUB_MINUS_BITS};
+use crate::encoding::{get_c_table, square_to_line_point128};
+use crate::entry_sizes::EntrySizes;
+use crate::finite_state_entropy::compress::{compress_using_ctable, CTable};
+use crate::plots::compression::{
+    create_compression_ctable_for_clevel, get_compression_info_for_k,
+    get_compression_info_for_level,
+};
+use crate::plots::plotting::bucket_sorter::BucketSorter;
+use crate::plots::plotting::manifest::{PlotManifest, PlotStage};
+use crate::plots::plotting::phase1::{new_sorter, pos_bits, table_entry_size};
+use crate::plots::plotting::{
+    entry_bytes, write_bits, EntryFileReader, EntryFileWriter, EntryPacker, EntryUnpacker,
+    PlotContext, PLOT_TABLES,
+};
+use dg_xch_core::plots::PlotTable;
+use log::info;
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs::{remove_file, File};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::time::Instant;
 
-pub async fn phase3() -> Result<Vec<()>, Error> {
-    todo!()
-}
\ No newline at end of file
+pub struct Phase3Results {
+    /// Begin pointers of tables 1-6 in the final file
+    pub table_begin_pointers: [u64; 10],
+    /// Offset in the final file where table 7 starts
+    pub end_offset: u64,
+    /// Positions into table 6 of every table 7 entry, in f7 order
+    pub p7_positions: PathBuf,
+    pub table_sizes: [u64; 8],
+}
+
+/// Compression. Converts the back pointers of every table into line points, sorts them and
+/// writes them into parks of the final file, starting at `start_offset`. The manifest is saved
+/// after every table, a resumed run expects `file` to be positioned at the saved offset.
+#[allow(clippy::too_many_lines)]
+#[allow(clippy::cast_possible_truncation)]
+pub async fn phase3(
+    ctx: &PlotContext,
+    manifest: &mut PlotManifest,
+    file: &mut BufWriter<File>,
+    start_offset: u64,
+) -> Result<Phase3Results, Error> {
+    let k = ctx.k;
+    let index_bits = pos_bits(k);
+    let lp_bits = EntrySizes::line_point_size_bits(u32::from(k));
+    let table_sizes = manifest.table_sizes;
+    let mut results = Phase3Results {
+        table_begin_pointers: [0u64; 10],
+        end_offset: start_offset,
+        p7_positions: PathBuf::new(),
+        table_sizes,
+    };
+    let mut offset = start_offset;
+    let (first_table, mut l_path) = match &manifest.stage {
+        PlotStage::Phase3 {
+            table,
+            table_begin_pointers,
+            left_values,
+            ..
+        } => {
+            info!("\tResuming after table {table}");
+            results.table_begin_pointers = *table_begin_pointers;
+            (table + 1, left_values.clone())
+        }
+        _ => (1, ctx.table_file(1).to_path_buf()),
+    };
+    let (mut l_size, mut l_bits) = if first_table == 1 {
+        (table_entry_size(k, 1), u32::from(k))
+    } else if ctx.compression_level > 0 && first_table == 2 {
+        (entry_bytes(lp_bits), lp_bits)
+    } else {
+        (entry_bytes(index_bits), index_bits)
+    };
+    // Compressed plots drop table 1 and store truncated x values in the lowest stored table
+    let x_shift = if ctx.compression_level > 0 {
+        u32::from(k) - get_compression_info_for_level(ctx.compression_level).entry_size_bits
+    } else {
+        0
+    };
+    for table_index in first_table..7 {
+        let dropped = ctx.compression_level > 0 && table_index == 1;
+        let table_timer = Instant::now();
+        info!("\tCompressing tables {table_index} and {}", table_index + 1);
+        let table = PLOT_TABLES[table_index as usize - 1];
+        let r_index = table_index + 1;
+        let r_size = table_entry_size(k, r_index);
+        let r_count = table_sizes[r_index as usize];
+        let prefix_bits = if r_index == 7 { u32::from(k) } else { 0 };
+
+        // Sort the right table on its back pointers
+        let pos_sorter = new_sorter(
+            ctx,
+            &format!("p3.t{r_index}.pos"),
+            entry_bytes(index_bits + K_OFFSET_SIZE + index_bits),
+        )
+        .await?;
+        let mut reader = EntryFileReader::open(ctx.table_file(r_index), r_size, r_count).await?;
+        let mut j = 0u64;
+        loop {
+            let chunk = reader.read_chunk().await?;
+            if chunk.is_empty() {
+                break;
+            }
+            let mut out = vec![0u8; chunk.len() / r_size * pos_sorter.entry_size()];
+            for (src, dst) in chunk
+                .chunks_exact(r_size)
+                .zip(out.chunks_exact_mut(pos_sorter.entry_size()))
+            {
+                let mut unpacker = EntryUnpacker::new(src);
+                unpacker.take(prefix_bits);
+                let pos = unpacker.take(index_bits);
+                let offset = unpacker.take(K_OFFSET_SIZE);
+                EntryPacker::new(dst)
+                    .put(pos, index_bits)
+                    .put(offset, K_OFFSET_SIZE)
+                    .put(u128::from(j), index_bits);
+                j += 1;
+            }
+            pos_sorter.insert(&out).await?;
+        }
+        pos_sorter.flush().await?;
+
+        // Walk the left values alongside the sorted back pointers to build the line points
+        let lp_sorter = new_sorter(
+            ctx,
+            &format!("p3.t{r_index}.lp"),
+            entry_bytes(lp_bits + index_bits),
+        )
+        .await?;
+        let mut l_reader =
+            EntryFileReader::open(&l_path, l_size, table_sizes[table_index as usize]).await?;
+        let mut window: VecDeque<u64> = VecDeque::new();
+        let mut window_start = 0u64;
+        let max_line_point = 1u128 << lp_bits;
+        for bucket in 0..pos_sorter.num_buckets() {
+            let data = pos_sorter.read_bucket(bucket).await?;
+            let mut out = vec![0u8; data.len() / pos_sorter.entry_size() * lp_sorter.entry_size()];
+            for (src, dst) in data
+                .chunks_exact(pos_sorter.entry_size())
+                .zip(out.chunks_exact_mut(lp_sorter.entry_size()))
+            {
+                let mut unpacker = EntryUnpacker::new(src);
+                let pos = unpacker.take_u64(index_bits);
+                let offset = unpacker.take_u64(K_OFFSET_SIZE);
+                let j = unpacker.take(index_bits);
+                while window_start < pos && !window.is_empty() {
+                    window.pop_front();
+                    window_start += 1;
+                }
+                while window_start + (window.len() as u64) <= pos + offset {
+                    let chunk = l_reader.read_chunk().await?;
+                    if chunk.is_empty() {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!(
+                                "Back pointer {} is past the end of table {table_index}",
+                                pos + offset
+                            ),
+                        ));
+                    }
+                    for entry in chunk.chunks_exact(l_size) {
+                        window.push_back(EntryUnpacker::new(entry).take_u64(l_bits));
+                    }
+                    while window_start < pos && !window.is_empty() {
+                        window.pop_front();
+                        window_start += 1;
+                    }
+                }
+                let mut left = window[(pos - window_start) as usize];
+                let mut right = window[(pos + offset - window_start) as usize];
+                if dropped {
+                    left >>= x_shift;
+                    right >>= x_shift;
+                }
+                let line_point = square_to_line_point128(left, right);
+                if line_point >= max_line_point {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Line point {line_point} does not fit in {lp_bits} bits"),
+                    ));
+                }
+                if dropped {
+                    EntryPacker::new(dst)
+                        .put(j, index_bits)
+                        .put(line_point, lp_bits);
+                } else {
+                    EntryPacker::new(dst)
+                        .put(line_point, lp_bits)
+                        .put(j, index_bits);
+                }
+            }
+            lp_sorter.insert(&out).await?;
+            ctx.report_progress(3, table_index, bucket, pos_sorter.num_buckets());
+        }
+        lp_sorter.flush().await?;
+        drop(l_reader);
+        let next_path = ctx.work_file(&format!("p3.l{r_index}"));
+
+        if dropped {
+            // Table 1 is not written, the x pairs of table 2 become the left values of table 3
+            let next_size = entry_bytes(lp_bits);
+            write_left_values(&lp_sorter, index_bits, lp_bits, &next_path).await?;
+            pos_sorter.cleanup().await?;
+            lp_sorter.cleanup().await?;
+            save_table(
+                ctx,
+                manifest,
+                file,
+                table_index,
+                offset,
+                &results,
+                &next_path,
+            )
+            .await?;
+            remove_file(&l_path).await?;
+            l_path = next_path;
+            l_size = next_size;
+            l_bits = lp_bits;
+            info!(
+                "\tDropped table {table_index} in {:.8} seconds",
+                table_timer.elapsed().as_secs_f64()
+            );
+            continue;
+        }
+
+        // Write the parks in line point order and record the new position of every entry
+        results.table_begin_pointers[table as usize] = offset;
+        if ctx.compression_level > 0 && table_index == 2 {
+            results.table_begin_pointers[PlotTable::Table1 as usize] = offset;
+        }
+        let layout = ParkLayout::new(k, table, ctx.compression_level)?;
+        let j_sorter = new_sorter(
+            ctx,
+            &format!("p3.t{r_index}.idx"),
+            entry_bytes(index_bits * 2),
+        )
+        .await?;
+        let mut park: Vec<u128> = Vec::with_capacity(K_ENTRIES_PER_PARK as usize);
+        let mut n = 0u64;
+        for bucket in 0..lp_sorter.num_buckets() {
+            let data = lp_sorter.read_bucket(bucket).await?;
+            let mut out = vec![0u8; data.len() / lp_sorter.entry_size() * j_sorter.entry_size()];
+            for (src, dst) in data
+                .chunks_exact(lp_sorter.entry_size())
+                .zip(out.chunks_exact_mut(j_sorter.entry_size()))
+            {
+                let mut unpacker = EntryUnpacker::new(src);
+                park.push(unpacker.take(lp_bits));
+                let j = unpacker.take(index_bits);
+                EntryPacker::new(dst)
+                    .put(j, index_bits)
+                    .put(u128::from(n), index_bits);
+                n += 1;
+                if park.len() == K_ENTRIES_PER_PARK as usize {
+                    let buf = write_park(k, &layout, &park)?;
+                    file.write_all(&buf).await?;
+                    offset += buf.len() as u64;
+                    park.clear();
+                }
+            }
+            j_sorter.insert(&out).await?;
+        }
+        if !park.is_empty() {
+            let buf = write_park(k, &layout, &park)?;
+            file.write_all(&buf).await?;
+            offset += buf.len() as u64;
+        }
+        j_sorter.flush().await?;
+
+        // The new positions, in the order of the right table, are the left values of the next step
+        let next_size = entry_bytes(index_bits);
+        write_left_values(&j_sorter, index_bits, index_bits, &next_path).await?;
+        pos_sorter.cleanup().await?;
+        lp_sorter.cleanup().await?;
+        j_sorter.cleanup().await?;
+        save_table(
+            ctx,
+            manifest,
+            file,
+            table_index,
+            offset,
+            &results,
+            &next_path,
+        )
+        .await?;
+        remove_file(&l_path).await?;
+        l_path = next_path;
+        l_size = next_size;
+        l_bits = index_bits;
+        info!(
+            "\tWrote {n} line points for table {table_index} in {:.8} seconds",
+            table_timer.elapsed().as_secs_f64()
+        );
+    }
+    results.end_offset = offset;
+    results.p7_positions = l_path;
+    Ok(results)
+}
+
+/// Saves the progress after `table_index` is written, then removes the right table which is
+/// only needed to redo this step. Table 7 is still needed by phase 4.
+async fn save_table(
+    ctx: &PlotContext,
+    manifest: &mut PlotManifest,
+    file: &mut BufWriter<File>,
+    table_index: u8,
+    offset: u64,
+    results: &Phase3Results,
+    left_values: &Path,
+) -> Result<(), Error> {
+    file.flush().await?;
+    file.get_ref().sync_data().await?;
+    manifest
+        .checkpoint(PlotStage::Phase3 {
+            table: table_index,
+            offset,
+            table_begin_pointers: results.table_begin_pointers,
+            left_values: left_values.to_path_buf(),
+        })
+        .await?;
+    if table_index + 1 != 7 {
+        remove_file(ctx.table_file(table_index + 1)).await?;
+    }
+    Ok(())
+}
+
+/// Writes the values of entries sorted by their index in the right table, skipping the index.
+pub async fn write_left_values(
+    sorter: &BucketSorter,
+    index_bits: u32,
+    value_bits: u32,
+    path: &Path,
+) -> Result<u64, Error> {
+    let value_size = entry_bytes(value_bits);
+    let mut writer = EntryFileWriter::create(path, value_size).await?;
+    for bucket in 0..sorter.num_buckets() {
+        let data = sorter.read_bucket(bucket).await?;
+        let mut out = vec![0u8; data.len() / sorter.entry_size() * value_size];
+        for (src, dst) in data
+            .chunks_exact(sorter.entry_size())
+            .zip(out.chunks_exact_mut(value_size))
+        {
+            let mut unpacker = EntryUnpacker::new(src);
+            unpacker.take(index_bits);
+            EntryPacker::new(dst).put(unpacker.take(value_bits), value_bits);
+        }
+        writer.write(&out).await?;
+    }
+    writer.finish().await
+}
+
+/// Sizes and encoding table of the parks of a line point table.
+pub struct ParkLayout {
+    pub park_size: usize,
+    pub stub_bits: u32,
+    pub stubs_size: usize,
+    pub max_deltas_size: usize,
+    pub c_table: Arc<CTable>,
+}
+impl ParkLayout {
+    /// Layout of `table`, the lowest stored table of a compressed plot uses the parameters of
+    /// the compression level for `k`.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn new(k: u8, table: PlotTable, compression_level: u8) -> Result<Self, Error> {
+        let lp_size = EntrySizes::line_point_size_bytes(u32::from(k)) as usize;
+        if compression_level > 0 && table == PlotTable::Table2 {
+            let info = get_compression_info_for_k(k, compression_level);
+            let stubs_size = ucdiv(info.stub_size_bits * (K_ENTRIES_PER_PARK - 1), 8) as usize;
+            let mut ct_size = 0;
+            Ok(Self {
+                park_size: info.table_park_size,
+                stub_bits: info.stub_size_bits,
+                stubs_size,
+                max_deltas_size: info.table_park_size - lp_size - stubs_size,
+                c_table: create_compression_ctable_for_clevel(compression_level, &mut ct_size)?,
+            })
+        } else {
+            let k = u32::from(k);
+            Ok(Self {
+                park_size: EntrySizes::calculate_park_size(table, k) as usize,
+                stub_bits: k - u32::from(K_STUB_MINUS_BITS),
+                stubs_size: EntrySizes::calculate_stubs_size(k) as usize,
+                max_deltas_size: EntrySizes::calculate_max_deltas_size(table) as usize,
+                c_table: get_c_table(K_RVALUES[table as usize])?,
+            })
+        }
+    }
+}
+
+/// Encodes a park of sorted line points, the first line point is stored as is followed by the
+/// stubs and the ANS encoded deltas of the following line points.
+#[allow(clippy::cast_possible_truncation)]
+pub fn write_park(k: u8, layout: &ParkLayout, line_points: &[u128]) -> Result<Vec<u8>, Error> {
+    let k = u32::from(k);
+    let lp_size = EntrySizes::line_point_size_bytes(k) as usize;
+    let stub_bits = layout.stub_bits;
+    if line_points.is_empty() || line_points.len() > K_ENTRIES_PER_PARK as usize {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid park entry count: {}", line_points.len()),
+        ));
+    }
+    let mut buf = vec![0u8; layout.park_size];
+    write_bits(
+        &mut buf,
+        0,
+        line_points[0],
+        EntrySizes::line_point_size_bits(k),
+    );
+    let mut deltas = Vec::with_capacity(line_points.len() - 1);
+    for (i, pair) in line_points.windows(2).enumerate() {
+        let big_delta = pair[1] - pair[0];
+        let stub = big_delta & ((1u128 << stub_bits) - 1);
+        let small_delta = big_delta >> stub_bits;
+        if small_delta >= 255 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Line point delta too large: {small_delta}"),
+            ));
+        }
+        write_bits(&mut buf[lp_size..], i * stub_bits as usize, stub, stub_bits);
+        deltas.push(small_delta as u8);
+    }
+    let deltas_start = lp_size + layout.stubs_size;
+    let max_deltas_size = layout.max_deltas_size;
+    let encoded = compress_using_ctable(&deltas, &layout.c_table).unwrap_or_default();
+    if !encoded.is_empty() && encoded.len() + 2 <= max_deltas_size {
+        buf[deltas_start..deltas_start + 2].copy_from_slice(&(encoded.len() as u16).to_le_bytes());
+        buf[deltas_start + 2..deltas_start + 2 + encoded.len()].copy_from_slice(&encoded);
+    } else if deltas.len() + 2 <= max_deltas_size {
+        // Store the deltas uncompressed
+        buf[deltas_start..deltas_start + 2]
+            .copy_from_slice(&(deltas.len() as u16 | 0x8000).to_le_bytes());
+        buf[deltas_start + 2..deltas_start + 2 + deltas.len()].copy_from_slice(&deltas);
+    } else {
+        return Err(Error::new(ErrorKind::InvalidData, "Overflowed park buffer"));
+    }
+    Ok(buf)
+}
+
+#[test]
+fn test_compressed_park_roundtrip() {
+    use crate::finite_state_entropy::decompress::decompress_using_dtable;
+    use crate::plots::compression::create_compression_dtable;
+    use crate::plots::plotting::read_bits;
+    use rand::{Rng, SeedableRng};
+    let k = 32;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+    for c_level in 1..=7 {
+        let layout = ParkLayout::new(k, PlotTable::Table2, c_level).unwrap();
+        let mut line_points = vec![rng.gen_range(0..1u128 << 40)];
+        for _ in 1..K_ENTRIES_PER_PARK {
+            let small: u128 = rng.gen_range(0..4);
+            let stub: u128 = rng.gen_range(0..1u128 << layout.stub_bits);
+            line_points.push(line_points.last().unwrap() + (small << layout.stub_bits) + stub);
+        }
+        let park = write_park(k, &layout, &line_points).unwrap();
+        assert_eq!(park.len(), layout.park_size);
+        let lp_size = EntrySizes::line_point_size_bytes(u32::from(k)) as usize;
+        assert_eq!(read_bits(&park, 0, u32::from(k) * 2), line_points[0]);
+        let stubs = &park[lp_size..lp_size + layout.stubs_size];
+        let deltas_start = lp_size + layout.stubs_size;
+        let size = u16::from_le_bytes([park[deltas_start], park[deltas_start + 1]]);
+        assert_eq!(size & 0x8000, 0);
+        let mut deltas = vec![0u8; K_ENTRIES_PER_PARK as usize - 1];
+        decompress_using_dtable(
+            &mut deltas,
+            K_ENTRIES_PER_PARK as usize - 1,
+            &park[deltas_start + 2..],
+            size as usize,
+            create_compression_dtable(c_level).unwrap(),
+        )
+        .unwrap();
+        let mut line_point = line_points[0];
+        for (i, delta) in deltas.iter().enumerate() {
+            let stub = read_bits(stubs, i * layout.stub_bits as usize, layout.stub_bits);
+            line_point += (u128::from(*delta) << layout.stub_bits) + stub;
+            assert_eq!(line_point, line_points[i + 1]);
+        }
+    }
+}
@@ -1,5 +1,178 @@
-use std::io::Error;
+use crate::constants::{K_C3R, K_CHECKPOINT1INTERVAL, K_CHECKPOINT2INTERVAL, K_ENTRIES_PER_PARK};
+use crate::encoding::ans_encode_deltas;
+use crate::entry_sizes::EntrySizes;
+use crate::plots::plotting::phase1::{pos_bits, table_entry_size};
+use crate::plots::plotting::phase3::Phase3Results;
+use crate::plots::plotting::{
+    entry_bytes, write_bits, EntryFileReader, EntryUnpacker, PlotContext,
+};
+use dg_xch_core::plots::PlotTable;
+use log::info;
+use std::io::{Error, ErrorKind};
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::time::Instant;
 
-pub async fn phase4() -> Result<Vec<()>, Error> {
-    todo!()
-}
\ No newline at end of file
+/// Writes table 7 and the checkpoint tables. Table 7 is stored as parks of the positions of its
+/// entries in table 6, C1 holds every 10000th f7, C2 every 10000th C1 entry and the C3 parks hold
+/// the deltas between the f7 values of each C1 interval. Returns the begin pointers of every
+/// table along with the end of the plot. The table 7 and position files are left for the caller
+/// to remove, so the phase can be redone if the plot is interrupted before it is finalized.
+#[allow(clippy::cast_possible_truncation)]
+pub async fn phase4(
+    ctx: &PlotContext,
+    phase3: &Phase3Results,
+    file: &mut BufWriter<File>,
+) -> Result<([u64; 10], u64), Error> {
+    let k = ctx.k;
+    let k_bits = u32::from(k);
+    let p7_bits = pos_bits(k);
+    let f7_size = entry_bytes(k_bits);
+    let t7_size = table_entry_size(k, 7);
+    let mut pointers = phase3.table_begin_pointers;
+    let mut num_entries = phase3.table_sizes[7];
+    // A final C3 park holding a single delta can not be ANS encoded, the reader skips missing
+    // parks so the largest f7 is dropped instead
+    if num_entries % u64::from(K_CHECKPOINT1INTERVAL) == 2 {
+        num_entries -= 1;
+    }
+    if num_entries == 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "Table 7 is empty"));
+    }
+    let mut offset = phase3.end_offset;
+    let timer = Instant::now();
+
+    // P7 parks and C1 entries
+    pointers[PlotTable::Table7 as usize] = offset;
+    let park_size = EntrySizes::calculate_park7_size(k_bits) as usize;
+    let mut f7_reader = EntryFileReader::open(ctx.table_file(7), t7_size, num_entries).await?;
+    let mut p7_reader =
+        EntryFileReader::open(&phase3.p7_positions, entry_bytes(p7_bits), num_entries).await?;
+    let mut c1_entries = vec![];
+    let mut park = vec![0u8; park_size];
+    let mut park_count = 0usize;
+    let mut index = 0u64;
+    loop {
+        let f7_chunk = f7_reader.read_chunk().await?;
+        let p7_chunk = p7_reader.read_chunk().await?;
+        if f7_chunk.is_empty() {
+            break;
+        }
+        for (f7_entry, p7_entry) in f7_chunk
+            .chunks_exact(t7_size)
+            .zip(p7_chunk.chunks_exact(entry_bytes(p7_bits)))
+        {
+            if index.is_multiple_of(u64::from(K_CHECKPOINT1INTERVAL)) {
+                c1_entries.push(EntryUnpacker::new(f7_entry).take(k_bits));
+            }
+            let position = EntryUnpacker::new(p7_entry).take(p7_bits);
+            write_bits(&mut park, park_count * p7_bits as usize, position, p7_bits);
+            park_count += 1;
+            if park_count == K_ENTRIES_PER_PARK as usize {
+                file.write_all(&park).await?;
+                offset += park_size as u64;
+                park.fill(0);
+                park_count = 0;
+            }
+            index += 1;
+        }
+    }
+    if park_count > 0 {
+        file.write_all(&park).await?;
+        offset += park_size as u64;
+    }
+    drop(p7_reader);
+    ctx.report_progress(4, 7, 0, 2);
+    info!(
+        "\tWrote table 7 in {:.8} seconds",
+        timer.elapsed().as_secs_f64()
+    );
+
+    // C1 and C2, each followed by an empty entry to mark the end of the table
+    let mut entry = vec![0u8; f7_size];
+    pointers[PlotTable::C1 as usize] = offset;
+    for c1 in &c1_entries {
+        entry.fill(0);
+        write_bits(&mut entry, 0, *c1, k_bits);
+        file.write_all(&entry).await?;
+    }
+    entry.fill(0);
+    file.write_all(&entry).await?;
+    offset += (c1_entries.len() as u64 + 1) * f7_size as u64;
+    pointers[PlotTable::C2 as usize] = offset;
+    let mut c2_count = 0u64;
+    for c2 in c1_entries.iter().step_by(K_CHECKPOINT2INTERVAL as usize) {
+        entry.fill(0);
+        write_bits(&mut entry, 0, *c2, k_bits);
+        file.write_all(&entry).await?;
+        c2_count += 1;
+    }
+    entry.fill(0);
+    file.write_all(&entry).await?;
+    offset += (c2_count + 1) * f7_size as u64;
+
+    // C3 parks
+    pointers[PlotTable::C3 as usize] = offset;
+    let c3_size = EntrySizes::calculate_c3size(k_bits) as usize;
+    let mut f7_reader = EntryFileReader::open(ctx.table_file(7), t7_size, num_entries).await?;
+    let mut deltas = Vec::with_capacity(K_CHECKPOINT1INTERVAL as usize);
+    let mut previous = 0u128;
+    let mut index = 0u64;
+    loop {
+        let chunk = f7_reader.read_chunk().await?;
+        if chunk.is_empty() {
+            break;
+        }
+        for f7_entry in chunk.chunks_exact(t7_size) {
+            let f7 = EntryUnpacker::new(f7_entry).take(k_bits);
+            if index.is_multiple_of(u64::from(K_CHECKPOINT1INTERVAL)) {
+                if !deltas.is_empty() {
+                    offset += write_c3_park(file, &deltas, c3_size).await?;
+                    deltas.clear();
+                }
+            } else {
+                let delta = f7 - previous;
+                if delta >= 0xff {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("f7 delta too large for C3 park: {delta}"),
+                    ));
+                }
+                deltas.push(delta as u8);
+            }
+            previous = f7;
+            index += 1;
+        }
+    }
+    if !deltas.is_empty() {
+        offset += write_c3_park(file, &deltas, c3_size).await?;
+    }
+    drop(f7_reader);
+    ctx.report_progress(4, 7, 1, 2);
+    info!(
+        "\tWrote {} checkpoints in {:.8} seconds",
+        c1_entries.len(),
+        timer.elapsed().as_secs_f64()
+    );
+    Ok((pointers, offset))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+async fn write_c3_park(
+    file: &mut BufWriter<File>,
+    deltas: &[u8],
+    c3_size: usize,
+) -> Result<u64, Error> {
+    let encoded = ans_encode_deltas(deltas, K_C3R)?;
+    if encoded.is_empty() || encoded.len() + 2 > c3_size {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Failed to encode C3 park of {} deltas", deltas.len()),
+        ));
+    }
+    let mut park = vec![0u8; c3_size];
+    park[0..2].copy_from_slice(&(encoded.len() as u16).to_be_bytes());
+    park[2..2 + encoded.len()].copy_from_slice(&encoded);
+    file.write_all(&park).await?;
+    Ok(c3_size as u64)
+}
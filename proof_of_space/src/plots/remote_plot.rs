@@ -0,0 +1,575 @@
+use crate::plots::plot_reader::parse_plot_header;
+use async_trait::async_trait;
+use dg_xch_core::plots::{PlotFile, PlotHeader, PlotRangeReader};
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use reqwest::header::{HeaderValue, CONTENT_RANGE, RANGE};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind, SeekFrom};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use tokio::sync::Mutex;
+
+type FetchResult = Result<Arc<Vec<u8>>, (ErrorKind, String)>;
+type Fetch = Shared<BoxFuture<'static, FetchResult>>;
+type StreamRead = BoxFuture<'static, Result<Vec<u8>, Error>>;
+
+#[derive(Debug, Clone)]
+pub struct RemotePlotConfig {
+    /// Plots are fetched and cached in blocks of this size
+    pub block_size: u64,
+    /// Blocks kept in the park cache of each plot, 0 disables the cache
+    pub cache_blocks: usize,
+    /// Sent with every request, for example an Authorization header
+    pub headers: Vec<(String, String)>,
+    pub timeout: Duration,
+}
+impl Default for RemotePlotConfig {
+    fn default() -> Self {
+        Self {
+            block_size: 32 * 1024,
+            cache_blocks: 128,
+            headers: vec![],
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RemotePlotStats {
+    /// HTTP requests sent, including the one made when opening the plot
+    pub requests: u64,
+    pub bytes_fetched: u64,
+    /// Blocks served from the park cache
+    pub cache_hits: u64,
+    /// Blocks that were already being fetched for another read
+    pub coalesced: u64,
+}
+
+#[derive(Default)]
+struct BlockCache {
+    blocks: HashMap<u64, (Arc<Vec<u8>>, u64)>,
+    in_flight: HashMap<u64, (u64, Fetch)>,
+    tick: u64,
+}
+impl BlockCache {
+    fn get(&mut self, block: u64) -> Option<Arc<Vec<u8>>> {
+        self.tick += 1;
+        let (data, last_used) = self.blocks.get_mut(&block)?;
+        *last_used = self.tick;
+        Some(data.clone())
+    }
+
+    fn insert(&mut self, block: u64, data: Arc<Vec<u8>>, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        while self.blocks.len() >= capacity {
+            let oldest = self
+                .blocks
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(block, _)| *block);
+            match oldest {
+                Some(oldest) => self.blocks.remove(&oldest),
+                None => break,
+            };
+        }
+        self.tick += 1;
+        self.blocks.insert(block, (data, self.tick));
+    }
+}
+
+enum BlockRead {
+    Cached(Arc<Vec<u8>>),
+    Fetching { first_block: u64, fetch: Fetch },
+}
+
+struct RangeSource {
+    client: Client,
+    url: String,
+    size: u64,
+    config: RemotePlotConfig,
+    cache: parking_lot::Mutex<BlockCache>,
+    requests: AtomicU64,
+    bytes_fetched: AtomicU64,
+    cache_hits: AtomicU64,
+    coalesced: AtomicU64,
+}
+impl RangeSource {
+    async fn fetch(&self, start: u64, end: u64) -> Result<Vec<u8>, Error> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let response = range_request(&self.client, &self.url, &self.config, start, end)
+            .send()
+            .await
+            .map_err(Error::other)?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(Error::other(format!(
+                "Range request for {start}-{end} of {self} failed: {}",
+                response.status()
+            )));
+        }
+        let data = response.bytes().await.map_err(Error::other)?;
+        self.bytes_fetched
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        if data.len() as u64 != end - start {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!(
+                    "Expected {} bytes at {start} of {self}, received {}",
+                    end - start,
+                    data.len()
+                ),
+            ));
+        }
+        Ok(data.to_vec())
+    }
+
+    /// Fetches blocks `first_block..=last_block` with a single request. The result is shared
+    /// with every read waiting on one of the blocks and added to the cache once it arrives.
+    fn start_fetch(self: &Arc<Self>, first_block: u64, last_block: u64) -> Fetch {
+        let source = self.clone();
+        async move {
+            let block_size = source.config.block_size;
+            let start = first_block * block_size;
+            let end = ((last_block + 1) * block_size).min(source.size);
+            let result = source.fetch(start, end).await;
+            let mut cache = source.cache.lock();
+            for block in first_block..=last_block {
+                cache.in_flight.remove(&block);
+            }
+            match result {
+                Ok(data) => {
+                    for (block, chunk) in (first_block..).zip(data.chunks(block_size as usize)) {
+                        cache.insert(block, Arc::new(chunk.to_vec()), source.config.cache_blocks);
+                    }
+                    Ok(Arc::new(data))
+                }
+                Err(e) => Err((e.kind(), e.to_string())),
+            }
+        }
+        .boxed()
+        .shared()
+    }
+
+    async fn read_exact_at(self: &Arc<Self>, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .filter(|end| *end <= self.size)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!(
+                        "Read of {} bytes at {offset} is past the end of plot {self}",
+                        buf.len()
+                    ),
+                )
+            })?;
+        let block_size = self.config.block_size;
+        let first = offset / block_size;
+        let last = (end - 1) / block_size;
+        let reads = {
+            let mut cache = self.cache.lock();
+            let mut reads = Vec::with_capacity((last - first + 1) as usize);
+            for block in first..=last {
+                if let Some(data) = cache.get(block) {
+                    self.cache_hits.fetch_add(1, Ordering::Relaxed);
+                    reads.push(Some(BlockRead::Cached(data)));
+                } else if let Some((first_block, fetch)) = cache.in_flight.get(&block) {
+                    self.coalesced.fetch_add(1, Ordering::Relaxed);
+                    reads.push(Some(BlockRead::Fetching {
+                        first_block: *first_block,
+                        fetch: fetch.clone(),
+                    }));
+                } else {
+                    reads.push(None);
+                }
+            }
+            // Neighbouring blocks that are missing are fetched together
+            let mut index = 0;
+            while index < reads.len() {
+                if reads[index].is_some() {
+                    index += 1;
+                    continue;
+                }
+                let run_start = index;
+                while index < reads.len() && reads[index].is_none() {
+                    index += 1;
+                }
+                let first_block = first + run_start as u64;
+                let fetch = self.start_fetch(first_block, first + index as u64 - 1);
+                for (block, read) in (first_block..).zip(&mut reads[run_start..index]) {
+                    cache.in_flight.insert(block, (first_block, fetch.clone()));
+                    *read = Some(BlockRead::Fetching {
+                        first_block,
+                        fetch: fetch.clone(),
+                    });
+                }
+            }
+            reads
+        };
+        for (block, read) in (first..=last).zip(reads) {
+            let (data, block_offset) = match read {
+                Some(BlockRead::Cached(data)) => (data, 0),
+                Some(BlockRead::Fetching { first_block, fetch }) => (
+                    fetch.await.map_err(|(kind, e)| Error::new(kind, e))?,
+                    (block - first_block) * block_size,
+                ),
+                None => unreachable!("Every missing block is fetched"),
+            };
+            let block_start = block * block_size;
+            let from = offset.max(block_start);
+            let to = end.min(block_start + block_size);
+            buf[(from - offset) as usize..(to - offset) as usize].copy_from_slice(
+                &data[(block_offset + from - block_start) as usize
+                    ..(block_offset + to - block_start) as usize],
+            );
+        }
+        Ok(())
+    }
+}
+impl Display for RangeSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Presigned URLs carry credentials in the query string
+        f.write_str(self.url.split('?').next().unwrap_or_default())
+    }
+}
+
+fn range_request(
+    client: &Client,
+    url: &str,
+    config: &RemotePlotConfig,
+    start: u64,
+    end: u64,
+) -> RequestBuilder {
+    let mut request = client.get(url);
+    for (name, value) in &config.headers {
+        request = request.header(name, value);
+    }
+    request.header(RANGE, format!("bytes={start}-{}", end - 1))
+}
+
+fn content_range_total(value: Option<&HeaderValue>) -> Option<u64> {
+    value?.to_str().ok()?.rsplit_once('/')?.1.parse().ok()
+}
+
+/// `AsyncRead` and `AsyncSeek` over a remote plot, returned by `PlotFile::file`.
+pub struct RemotePlotStream {
+    source: Arc<RangeSource>,
+    position: u64,
+    // Behind a mutex so the stream is Sync, it is only ever accessed through `get_mut`
+    read: parking_lot::Mutex<Option<StreamRead>>,
+}
+impl AsyncRead for RemotePlotStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        let pending = this.read.get_mut();
+        if pending.is_none() {
+            let len = (buf.remaining() as u64).min(this.source.size.saturating_sub(this.position));
+            if len == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            let source = this.source.clone();
+            let position = this.position;
+            *pending = Some(
+                async move {
+                    let mut data = vec![0; len as usize];
+                    source.read_exact_at(position, &mut data).await?;
+                    Ok(data)
+                }
+                .boxed(),
+            );
+        }
+        let Some(read) = pending.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+        let result = ready!(read.poll_unpin(cx));
+        *pending = None;
+        let data = result?;
+        let len = data.len().min(buf.remaining());
+        buf.put_slice(&data[..len]);
+        this.position += len as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+impl AsyncSeek for RemotePlotStream {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        let position = match position {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(offset) => self.source.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "Invalid seek to a negative or overflowing position",
+            )
+        })?;
+        *self.read.get_mut() = None;
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Poll::Ready(Ok(self.position))
+    }
+}
+
+/// A plot read with HTTP range requests, for plots on network or object storage. S3 compatible
+/// stores can be used through presigned or public object URLs.
+///
+/// Reads are made in blocks of `RemotePlotConfig::block_size`. Missing neighbouring blocks are
+/// fetched with one request, concurrent reads of a block share the request and recently used
+/// blocks are kept in a small cache, as a lookup reads the same C1, C3, P7 and line point parks.
+pub struct RemotePlot {
+    source: Arc<RangeSource>,
+    file: Arc<Mutex<RemotePlotStream>>,
+    header: PlotHeader,
+}
+impl RemotePlot {
+    pub async fn open(url: &str, config: RemotePlotConfig) -> Result<Self, Error> {
+        if config.block_size == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Remote plot block size must be above 0",
+            ));
+        }
+        let client = Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .map_err(Error::other)?;
+        let response = range_request(&client, url, &config, 0, config.block_size)
+            .send()
+            .await
+            .map_err(Error::other)?;
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT => {}
+            StatusCode::OK => {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    format!("Server for remote plot {url} does not support range requests"),
+                ));
+            }
+            status => {
+                return Err(Error::other(format!(
+                    "Failed to open remote plot {url}: {status}"
+                )));
+            }
+        }
+        let size = content_range_total(response.headers().get(CONTENT_RANGE)).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Response for remote plot {url} has no Content-Range length"),
+            )
+        })?;
+        let first_block = response.bytes().await.map_err(Error::other)?.to_vec();
+        if first_block.len() as u64 != config.block_size.min(size) {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("Short read opening remote plot {url}"),
+            ));
+        }
+        let header = parse_plot_header(&first_block)?;
+        let bytes_fetched = first_block.len() as u64;
+        let mut cache = BlockCache::default();
+        cache.insert(0, Arc::new(first_block), config.cache_blocks);
+        let source = Arc::new(RangeSource {
+            client,
+            url: url.to_string(),
+            size,
+            config,
+            cache: parking_lot::Mutex::new(cache),
+            requests: AtomicU64::new(1),
+            bytes_fetched: AtomicU64::new(bytes_fetched),
+            cache_hits: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+        });
+        Ok(Self {
+            file: Arc::new(Mutex::new(RemotePlotStream {
+                source: source.clone(),
+                position: 0,
+                read: parking_lot::Mutex::new(None),
+            })),
+            source,
+            header,
+        })
+    }
+
+    #[must_use]
+    pub fn stats(&self) -> RemotePlotStats {
+        RemotePlotStats {
+            requests: self.source.requests.load(Ordering::Relaxed),
+            bytes_fetched: self.source.bytes_fetched.load(Ordering::Relaxed),
+            cache_hits: self.source.cache_hits.load(Ordering::Relaxed),
+            coalesced: self.source.coalesced.load(Ordering::Relaxed),
+        }
+    }
+}
+impl Display for RemotePlot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.source.fmt(f)
+    }
+}
+#[async_trait]
+impl PlotRangeReader for RemotePlot {
+    async fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        self.source.read_exact_at(offset, buf).await
+    }
+}
+impl<'a> PlotFile<'a, RemotePlotStream> for RemotePlot {
+    fn header(&'a self) -> &'a PlotHeader {
+        &self.header
+    }
+
+    fn plot_size(&'a self) -> &'a u64 {
+        &self.source.size
+    }
+
+    fn load_p7_park(&'a self, _index: u64) -> Result<u128, Error> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "Loading P7 Parks is Not Supported, use a PlotReader",
+        ))
+    }
+
+    fn file(&'a self) -> Arc<Mutex<RemotePlotStream>> {
+        self.file.clone()
+    }
+
+    fn range_reader(&'a self) -> Option<&'a dyn PlotRangeReader> {
+        Some(self)
+    }
+}
+
+#[tokio::test]
+async fn test_remote_plot() {
+    use crate::plots::disk_plot::DiskPlot;
+    use crate::plots::plot_reader::PlotReader;
+    use dg_xch_core::blockchain::sized_bytes::Bytes32;
+    use dg_xch_core::consensus::constants::SIMULATOR;
+    use dg_xch_core::traits::SizedBytes;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    let k = 18;
+    let dir = std::env::temp_dir().join(format!("dg_pos_test_remote_plot_{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let plot = DiskPlot::create(
+        &dir,
+        &dir,
+        &dir,
+        k,
+        0,
+        &[3u8; 128],
+        Bytes32::new([9u8; 32]),
+        &SIMULATOR,
+    )
+    .await
+    .unwrap();
+    let data = Arc::new(tokio::fs::read(plot.filename.as_ref()).await.unwrap());
+    let local = PlotReader::new(plot, None, None).await.unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let served = data.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let data = served.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                let mut range: Option<(usize, usize)> = None;
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if let Some(bytes) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        let (start, end) = bytes.split_once('-').unwrap();
+                        range = Some((start.parse().unwrap(), end.parse().unwrap()));
+                    } else if line.is_empty() {
+                        let (start, end) = range.take().unwrap();
+                        let end = end.min(data.len() - 1);
+                        let head = format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\
+                            Content-Range: bytes {start}-{end}/{}\r\n\r\n",
+                            end + 1 - start,
+                            data.len()
+                        );
+                        writer.write_all(head.as_bytes()).await.unwrap();
+                        writer.write_all(&data[start..=end]).await.unwrap();
+                    }
+                }
+            });
+        }
+    });
+    let remote = RemotePlot::open(
+        &format!("http://{address}/plot?token=secret"),
+        RemotePlotConfig {
+            cache_blocks: 1024,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(remote.to_string(), format!("http://{address}/plot"));
+    assert_eq!(*remote.plot_size(), data.len() as u64);
+    assert_eq!(remote.plot_id(), local.plot_id());
+    assert_eq!(
+        remote.load_p7_park(0).unwrap_err().kind(),
+        ErrorKind::Unsupported
+    );
+    let remote = Arc::new(PlotReader::new(remote, None, None).await.unwrap());
+    let f7s = local
+        .read_c3park(local.get_c3_park_count() / 2)
+        .await
+        .unwrap();
+    let lookups: Vec<_> = f7s
+        .iter()
+        .step_by(251)
+        .map(|f7| {
+            let mut challenge = [0x3cu8; 32];
+            challenge[0..8].copy_from_slice(&(f7 << (64 - k)).to_be_bytes());
+            let remote = remote.clone();
+            let lookup = tokio::spawn(async move {
+                remote
+                    .fetch_qualities_for_challenge(&challenge)
+                    .await
+                    .unwrap()
+            });
+            (challenge, lookup)
+        })
+        .collect();
+    let mut challenges = vec![];
+    for (challenge, lookup) in lookups {
+        assert_eq!(
+            lookup.await.unwrap(),
+            local
+                .fetch_qualities_for_challenge(&challenge)
+                .await
+                .unwrap()
+        );
+        challenges.push(challenge);
+    }
+    let stats = remote.plot_file().stats();
+    assert!(stats.coalesced > 0);
+    // A repeated lookup only touches cached parks
+    remote
+        .fetch_qualities_for_challenge(&challenges[challenges.len() - 1])
+        .await
+        .unwrap();
+    assert_eq!(remote.plot_file().stats().requests, stats.requests);
+    let file = remote.plot_file().file();
+    let mut stream = file.lock().await;
+    let mut streamed = vec![0; 100_000];
+    stream.seek(SeekFrom::Start(12_345)).await.unwrap();
+    stream.read_exact(&mut streamed).await.unwrap();
+    assert_eq!(streamed, data[12_345..112_345]);
+    drop(stream);
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
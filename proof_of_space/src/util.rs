@@ -34,13 +34,13 @@ pub fn extract_num<T: AsRef<[u8]>>(
 // (regardless of 'num_bits'). In practice it can be ensured by allocating
 // extra 7 bytes to all memory buffers passed to this function.
 pub fn slice_u64from_bytes<T: AsRef<[u8]>>(bytes: T, start_bit: u32, num_bits: u32) -> u64 {
-    let mut bytes = bytes.as_ref().to_vec();
+    let mut bytes = bytes.as_ref();
     let mut start_bit = start_bit;
     if start_bit + num_bits > 64 {
-        bytes.push((start_bit / 8) as u8);
+        bytes = &bytes[(start_bit / 8) as usize..];
         start_bit %= 8;
     }
-    let mut tmp = bytes_to_u64(&bytes);
+    let mut tmp = bytes_to_u64(bytes);
     tmp <<= start_bit;
     tmp >>= 64 - num_bits;
     tmp
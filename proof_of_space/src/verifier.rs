@@ -1,8 +1,12 @@
-use crate::constants::{K_CHECKPOINT1INTERVAL, K_ENTRIES_PER_PARK, K_EXTRA_BITS};
+use crate::constants::{
+    K_CHECKPOINT1INTERVAL, K_ENTRIES_PER_PARK, K_EXTRA_BITS, K_MAX_PLOT_SIZE, K_MIN_PLOT_SIZE,
+};
+use crate::f_calc::F1Calculator;
 use crate::plots::decompressor::DecompressorPool;
 use crate::plots::disk_plot::DiskPlot;
-use crate::plots::fx_generator::{forward_prop_f1_to_f7, get_proof_f1_and_meta};
+use crate::plots::fx_generator::{forward_prop_f1_to_f7, fx_gen, fx_match, get_proof_f1_and_meta};
 use crate::plots::plot_reader::PlotReader;
+use crate::plots::plotting::PLOT_TABLES;
 use crate::plots::PROOF_X_COUNT;
 use crate::utils::bit_reader::BitReader;
 use dg_xch_core::blockchain::sized_bytes::Bytes32;
@@ -11,9 +15,13 @@ use dg_xch_core::traits::SizedBytes;
 use dg_xch_core::utils::hash_256;
 use futures_util::future::join_all;
 use log::{debug, error, info, warn};
+use rayon::prelude::*;
+use rayon::ThreadPool;
 use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use std::io::{Error, ErrorKind};
-use std::mem::size_of;
+use std::mem::{size_of, swap};
 use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
@@ -249,6 +257,164 @@ pub fn validate_proof(
     }
 }
 
+/// A proof checked by `verify_proofs`, the fields of a `ProofOfSpace` the proof depends on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofCheck {
+    pub plot_id: Bytes32,
+    pub k: u8,
+    pub proof: Vec<u8>,
+    pub challenge: Bytes32,
+}
+
+/// Why a proof failed to verify.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofFailure {
+    /// Proofs can only be checked for k between `K_MIN_PLOT_SIZE` and `K_MAX_PLOT_SIZE`
+    InvalidK(u8),
+    /// The proof does not hold 64 x values of k bits
+    InvalidLength {
+        expected: usize,
+        actual: usize,
+    },
+    /// Two x values of the proof do not match in `table`
+    NoMatch {
+        table: u8,
+    },
+    /// The proof is valid but its f7 does not match the challenge
+    WrongChallenge {
+        f7: u64,
+    },
+    Error(String),
+}
+impl Display for ProofFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProofFailure::InvalidK(k) => write!(f, "Invalid plot size k{k}"),
+            ProofFailure::InvalidLength { expected, actual } => {
+                write!(f, "Invalid proof length {actual}, expected {expected}")
+            }
+            ProofFailure::NoMatch { table } => {
+                write!(f, "Proof values do not match in table {table}")
+            }
+            ProofFailure::WrongChallenge { f7 } => {
+                write!(f, "Proof f7 {f7} does not match the challenge")
+            }
+            ProofFailure::Error(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Checks a batch of proofs on the rayon pool, returning the quality string of every proof or the
+/// reason it failed, in the order of `proofs`. Proofs from the same plot share one F1 key
+/// schedule and every worker reuses its buffers across the proofs it checks.
+#[must_use]
+pub fn verify_proofs(proofs: &[ProofCheck]) -> Vec<Result<Bytes32, ProofFailure>> {
+    let mut f1_calculators: HashMap<(u8, Bytes32), F1Calculator> = HashMap::new();
+    for proof in proofs {
+        if is_valid_proof_k(proof.k) {
+            f1_calculators
+                .entry((proof.k, proof.plot_id))
+                .or_insert_with(|| F1Calculator::new(proof.k, &proof.plot_id.bytes()));
+        }
+    }
+    proofs
+        .par_iter()
+        .map_init(
+            || (vec![0u64; PROOF_X_COUNT], Vec::with_capacity(PROOF_X_COUNT)),
+            |(fx, meta), proof| match f1_calculators.get(&(proof.k, proof.plot_id)) {
+                Some(f1) => check_proof(f1, proof, fx, meta),
+                None => Err(ProofFailure::InvalidK(proof.k)),
+            },
+        )
+        .collect()
+}
+
+/// Same as `verify_proofs`, running on `pool` instead of the global rayon pool.
+#[must_use]
+pub fn verify_proofs_in(
+    pool: &ThreadPool,
+    proofs: &[ProofCheck],
+) -> Vec<Result<Bytes32, ProofFailure>> {
+    pool.install(|| verify_proofs(proofs))
+}
+
+/// Checks a single proof, returning its quality string or the reason it failed.
+pub fn verify_proof(proof: &ProofCheck) -> Result<Bytes32, ProofFailure> {
+    if !is_valid_proof_k(proof.k) {
+        return Err(ProofFailure::InvalidK(proof.k));
+    }
+    let f1 = F1Calculator::new(proof.k, &proof.plot_id.bytes());
+    let mut fx = vec![0u64; PROOF_X_COUNT];
+    let mut meta = Vec::with_capacity(PROOF_X_COUNT);
+    check_proof(&f1, proof, &mut fx, &mut meta)
+}
+
+fn is_valid_proof_k(k: u8) -> bool {
+    (K_MIN_PLOT_SIZE..=K_MAX_PLOT_SIZE).contains(&u32::from(k))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn check_proof(
+    f1: &F1Calculator,
+    proof: &ProofCheck,
+    fx: &mut [u64],
+    meta: &mut Vec<BitReader>,
+) -> Result<Bytes32, ProofFailure> {
+    let k = proof.k;
+    let expected = k as usize * PROOF_X_COUNT / 8;
+    if proof.proof.len() != expected {
+        return Err(ProofFailure::InvalidLength {
+            expected,
+            actual: proof.proof.len(),
+        });
+    }
+    meta.clear();
+    for (x, y) in uncompress_proof(&proof.proof, k as usize)
+        .into_iter()
+        .zip(fx.iter_mut())
+    {
+        f1.calculate_buckets(x, 1, std::slice::from_mut(y));
+        meta.push(BitReader::new(x, k as usize));
+    }
+    let mut count = PROOF_X_COUNT;
+    for table in &PLOT_TABLES[1..] {
+        for dst in 0..count / 2 {
+            let (mut l, mut r) = (dst * 2, dst * 2 + 1);
+            if fx[l] > fx[r] {
+                swap(&mut l, &mut r);
+            }
+            if !fx_match(&fx[l], &fx[r]) {
+                return Err(ProofFailure::NoMatch {
+                    table: *table as u8 + 1,
+                });
+            }
+            let mut out_meta = BitReader::default();
+            fx_gen(
+                *table,
+                u32::from(k),
+                fx[l],
+                &meta[l],
+                &meta[r],
+                &mut fx[dst],
+                &mut out_meta,
+            )
+            .map_err(|e| ProofFailure::Error(e.to_string()))?;
+            meta[dst] = out_meta;
+        }
+        count /= 2;
+    }
+    let f7 = fx[0] >> K_EXTRA_BITS;
+    let challenge = proof.challenge.bytes();
+    let challenge_f7 =
+        u64::from_be_bytes(challenge[..8].try_into().unwrap_or_default()) >> (64 - u32::from(k));
+    if f7 != challenge_f7 {
+        return Err(ProofFailure::WrongChallenge { f7 });
+    }
+    let quality_index = u16::from(challenge[31] & 0x1f) << 1;
+    get_quality_string(k, &proof.proof, quality_index, &challenge)
+        .map_err(|e| ProofFailure::Error(e.to_string()))
+}
+
 pub fn get_f7_from_proof(
     k: u32,
     plot_id: &[u8; 32],
@@ -432,3 +598,87 @@ pub fn proof_to_bytes(src: &[u64]) -> Vec<u8> {
         .collect::<Vec<[u8; size_of::<u64>()]>>()
         .concat()
 }
+
+#[test]
+fn test_verify_proofs_failures() {
+    let plot_id = Bytes32::new([1u8; 32]);
+    let challenge = Bytes32::new([2u8; 32]);
+    let proofs = vec![
+        ProofCheck {
+            plot_id,
+            k: 17,
+            proof: vec![0u8; 17 * 8],
+            challenge,
+        },
+        ProofCheck {
+            plot_id,
+            k: 18,
+            proof: vec![0u8; 18 * 8 - 1],
+            challenge,
+        },
+        // Equal x values are never in adjacent groups
+        ProofCheck {
+            plot_id,
+            k: 18,
+            proof: vec![0u8; 18 * 8],
+            challenge,
+        },
+    ];
+    assert_eq!(
+        verify_proofs(&proofs),
+        vec![
+            Err(ProofFailure::InvalidK(17)),
+            Err(ProofFailure::InvalidLength {
+                expected: 144,
+                actual: 143
+            }),
+            Err(ProofFailure::NoMatch { table: 2 }),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_verify_plot_proofs() {
+    use dg_xch_core::consensus::constants::SIMULATOR;
+    let k = 18;
+    let dir =
+        std::env::temp_dir().join(format!("dg_pos_test_verify_proofs_{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let plot_id = Bytes32::new([5u8; 32]);
+    let plot = DiskPlot::create(&dir, &dir, &dir, k, 0, &[3u8; 128], plot_id, &SIMULATOR)
+        .await
+        .unwrap();
+    let reader = PlotReader::new(plot, None, None).await.unwrap();
+    let mut checks = vec![];
+    let mut expected = vec![];
+    for f7 in reader.read_c3park(0).await.unwrap().iter().step_by(997) {
+        let mut challenge = [0xa5u8; 32];
+        challenge[0..8].copy_from_slice(&(f7 << (64 - k)).to_be_bytes());
+        for (index, quality) in reader
+            .fetch_qualities_for_challenge(&challenge)
+            .await
+            .unwrap()
+        {
+            let proof = reader.fetch_ordered_proof(index).await.unwrap();
+            checks.push(ProofCheck {
+                plot_id,
+                k,
+                proof: proof_to_bytes(&proof),
+                challenge: Bytes32::new(challenge),
+            });
+            expected.push(Ok(quality));
+        }
+    }
+    assert!(!checks.is_empty());
+    let mut wrong_challenge = checks[0].clone();
+    wrong_challenge.challenge = Bytes32::new([0xffu8; 32]);
+    checks.push(wrong_challenge);
+    let results = verify_proofs(&checks);
+    assert!(matches!(
+        results.last(),
+        Some(Err(ProofFailure::WrongChallenge { .. }))
+    ));
+    assert_eq!(results[..expected.len()], expected);
+    drop(reader);
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
@@ -0,0 +1,140 @@
+use crate::verify_proof_of_space;
+use dg_xch_core::blockchain::proof_of_space::ProofOfSpace;
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::blockchain::weight_proof::WeightProof;
+use dg_xch_core::consensus::constants::ConsensusConstants;
+use dg_xch_core::consensus::pot_iterations::{
+    calculate_iterations_quality, calculate_sp_interval_iters, calculate_sp_iters,
+};
+use dg_xch_core::consensus::weight_proof::{
+    map_sub_epoch_summaries, recent_chain_difficulty_and_ssi, segment_block_challenge,
+    sub_epoch_difficulty_and_ssi, WeightProofVerifier,
+};
+use dg_xch_core::traits::SizedBytes;
+use dg_xch_core::utils::hash_256;
+use std::io::{Error, ErrorKind};
+
+/// Verifies the proofs of space of the challenge blocks in the segments and of every block of
+/// the recent chain, and that they qualify at the difficulty of their sub epoch. The signage
+/// point VDFs of the recent chain are checked to end at the iterations of their signage point,
+/// the VDF proofs themselves are not verified.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PosWeightProofVerifier;
+impl WeightProofVerifier for PosWeightProofVerifier {
+    fn verify(
+        &self,
+        constants: &ConsensusConstants,
+        weight_proof: &WeightProof,
+    ) -> Result<(), Error> {
+        let (summaries, _) = map_sub_epoch_summaries(constants, &weight_proof.sub_epochs)?;
+        let mut prev_sub_epoch = None;
+        for segment in &weight_proof.sub_epoch_segments {
+            let n = segment.sub_epoch_n;
+            let first_in_sub_epoch = prev_sub_epoch != Some(n);
+            prev_sub_epoch = Some(n);
+            let (difficulty, sub_slot_iters) =
+                sub_epoch_difficulty_and_ssi(constants, n as usize, &summaries);
+            for (idx, slot) in segment.sub_slots.iter().enumerate() {
+                let Some(pos) = &slot.proof_of_space else {
+                    continue;
+                };
+                let (challenge, cc_sp_hash) = segment_block_challenge(
+                    constants,
+                    segment,
+                    idx,
+                    &summaries,
+                    first_in_sub_epoch,
+                )?;
+                // Segments do not keep block heights, the plot filter is the one at the start of
+                // the sub epoch
+                required_iters(
+                    constants,
+                    pos,
+                    challenge,
+                    cc_sp_hash,
+                    n * constants.sub_epoch_blocks,
+                    difficulty,
+                    sub_slot_iters,
+                )
+                .map_err(|e| {
+                    Error::new(
+                        e.kind(),
+                        format!("Challenge block {idx} of sub epoch {n}: {e}"),
+                    )
+                })?;
+            }
+        }
+        let recent_chain = &weight_proof.recent_chain_data;
+        let difficulties = recent_chain_difficulty_and_ssi(constants, recent_chain, &summaries)?;
+        for (block, (difficulty, sub_slot_iters)) in recent_chain.iter().zip(difficulties) {
+            let rcb = &block.reward_chain_block;
+            let cc_sp_hash = if rcb.signage_point_index == 0 {
+                rcb.pos_ss_cc_challenge_hash
+            } else {
+                let sp_vdf = rcb.challenge_chain_sp_vdf.as_ref().ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Block at height {} has no signage point VDF", rcb.height),
+                    )
+                })?;
+                let sp_iters =
+                    calculate_sp_iters(constants, sub_slot_iters, rcb.signage_point_index)?;
+                if sp_vdf.number_of_iterations != sp_iters {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "Signage point VDF of the block at height {} has {} iterations \
+                            instead of {sp_iters}",
+                            rcb.height, sp_vdf.number_of_iterations
+                        ),
+                    ));
+                }
+                // A class group element serializes to its data bytes
+                hash_256(sp_vdf.output.data.bytes()).into()
+            };
+            required_iters(
+                constants,
+                &rcb.proof_of_space,
+                rcb.pos_ss_cc_challenge_hash,
+                cc_sp_hash,
+                rcb.height,
+                difficulty,
+                sub_slot_iters,
+            )
+            .map_err(|e| Error::new(e.kind(), format!("Block at height {}: {e}", rcb.height)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterations the proof of space needs at `difficulty`, which have to be below the signage point
+/// interval for the block to be valid.
+fn required_iters(
+    constants: &ConsensusConstants,
+    pos: &ProofOfSpace,
+    challenge: Bytes32,
+    cc_sp_hash: Bytes32,
+    height: u32,
+    difficulty: u64,
+    sub_slot_iters: u64,
+) -> Result<u64, Error> {
+    let quality = verify_proof_of_space(pos, constants, challenge, cc_sp_hash, height)?;
+    let required_iters = calculate_iterations_quality(
+        constants.difficulty_constant_factor,
+        quality,
+        pos.size,
+        difficulty,
+        cc_sp_hash,
+    );
+    let sp_interval_iters = calculate_sp_interval_iters(constants, sub_slot_iters)?;
+    if required_iters >= sp_interval_iters {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Proof of space needs {required_iters} iterations, not below the signage point \
+                interval of {sp_interval_iters}"
+            ),
+        ));
+    }
+    Ok(required_iters)
+}
@@ -0,0 +1,60 @@
+use crate::websocket::decompressor::{check_job, respond, run_job};
+use async_trait::async_trait;
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::protocols::decompressor::RequestDecompressProof;
+use dg_xch_core::protocols::{ChiaMessage, MessageHandler, PeerMap};
+use dg_xch_pos::plots::decompressor::DecompressorPool;
+use dg_xch_pos::plots::PROOF_X_COUNT;
+use dg_xch_serialize::ChiaSerialize;
+use std::io::{Cursor, Error, ErrorKind};
+use std::sync::Arc;
+
+pub struct DecompressProofHandle {
+    pub pool: Arc<DecompressorPool>,
+}
+#[async_trait]
+impl MessageHandler for DecompressProofHandle {
+    async fn handle(
+        &self,
+        msg: Arc<ChiaMessage>,
+        peer_id: Arc<Bytes32>,
+        peers: PeerMap,
+    ) -> Result<(), Error> {
+        let protocol_version = match peers.read().await.get(&peer_id) {
+            Some(peer) => *peer.protocol_version.read().await,
+            None => return Err(Error::new(ErrorKind::NotFound, "Failed to find peer")),
+        };
+        let mut cursor = Cursor::new(&msg.data);
+        let request = RequestDecompressProof::from_bytes(&mut cursor, protocol_version)?;
+        let result = decompress_proof(&self.pool, &request).await;
+        respond(&msg, &peer_id, &peers, result).await
+    }
+}
+
+async fn decompress_proof(
+    pool: &DecompressorPool,
+    request: &RequestDecompressProof,
+) -> Result<Vec<u64>, Error> {
+    check_job(request.k, request.compression_level)?;
+    let needed = if request.compression_level < 9 {
+        PROOF_X_COUNT / 2
+    } else {
+        PROOF_X_COUNT / 4
+    };
+    if request.compressed_proof.len() < needed {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Compression level {} needs {needed} compressed proof values, found {}",
+                request.compression_level,
+                request.compressed_proof.len()
+            ),
+        ));
+    }
+    let (k, compression_level) = (request.k, request.compression_level);
+    let (plot_id, compressed_proof) = (request.plot_id, request.compressed_proof.clone());
+    run_job(pool, k, compression_level, move |decompressor| {
+        decompressor.decompress_proof(&plot_id, k, compression_level, &compressed_proof, None)
+    })
+    .await
+}
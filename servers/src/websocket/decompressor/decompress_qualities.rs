@@ -0,0 +1,76 @@
+use crate::websocket::decompressor::{check_job, respond, run_job};
+use async_trait::async_trait;
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::protocols::decompressor::RequestDecompressQualities;
+use dg_xch_core::protocols::{ChiaMessage, MessageHandler, PeerMap};
+use dg_xch_pos::plots::decompressor::{CompressedQualitiesRequest, DecompressorPool, LinePoint};
+use dg_xch_serialize::ChiaSerialize;
+use std::io::{Cursor, Error, ErrorKind};
+use std::sync::Arc;
+
+pub struct DecompressQualitiesHandle {
+    pub pool: Arc<DecompressorPool>,
+}
+#[async_trait]
+impl MessageHandler for DecompressQualitiesHandle {
+    async fn handle(
+        &self,
+        msg: Arc<ChiaMessage>,
+        peer_id: Arc<Bytes32>,
+        peers: PeerMap,
+    ) -> Result<(), Error> {
+        let protocol_version = match peers.read().await.get(&peer_id) {
+            Some(peer) => *peer.protocol_version.read().await,
+            None => return Err(Error::new(ErrorKind::NotFound, "Failed to find peer")),
+        };
+        let mut cursor = Cursor::new(&msg.data);
+        let request = RequestDecompressQualities::from_bytes(&mut cursor, protocol_version)?;
+        let result = decompress_qualities(&self.pool, &request).await;
+        respond(&msg, &peer_id, &peers, result.map(|(x1, x2)| vec![x1, x2])).await
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+async fn decompress_qualities(
+    pool: &DecompressorPool,
+    request: &RequestDecompressQualities,
+) -> Result<(u64, u64), Error> {
+    check_job(request.k, request.compression_level)?;
+    let needed = if (6..9).contains(&request.compression_level) {
+        2
+    } else {
+        1
+    };
+    if request.line_points.len() < needed {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Compression level {} needs {needed} line points, found {}",
+                request.compression_level,
+                request.line_points.len()
+            ),
+        ));
+    }
+    let mut line_points = [LinePoint { hi: 0, lo: 0 }; 2];
+    for (dst, lp) in line_points.iter_mut().zip(&request.line_points) {
+        *dst = LinePoint {
+            hi: (lp >> 64) as u64,
+            lo: *lp as u64,
+        };
+    }
+    let (k, compression_level) = (request.k, request.compression_level);
+    let (plot_id, challenge) = (request.plot_id, request.challenge);
+    run_job(pool, k, compression_level, move |decompressor| {
+        decompressor.get_fetch_qualties_x_pair(
+            k,
+            CompressedQualitiesRequest {
+                plot_id,
+                compression_level,
+                challenge: challenge.as_ref(),
+                line_points,
+                f1_generator: None,
+            },
+        )
+    })
+    .await
+}
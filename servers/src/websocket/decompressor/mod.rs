@@ -0,0 +1,186 @@
+use crate::websocket::decompressor::decompress_proof::DecompressProofHandle;
+use crate::websocket::decompressor::decompress_qualities::DecompressQualitiesHandle;
+use crate::websocket::decompressor::handshake::HandshakeHandle;
+#[cfg(feature = "metrics")]
+use crate::websocket::WebSocketMetrics;
+use crate::websocket::{WebsocketServer, WebsocketServerConfig};
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::protocols::decompressor::RespondDecompression;
+use dg_xch_core::protocols::{
+    ChiaMessage, ChiaMessageFilter, ChiaMessageHandler, PeerMap, ProtocolMessageTypes,
+};
+use dg_xch_pos::plots::compression::compression_level_fits_k;
+use dg_xch_pos::plots::decompressor::{Decompressor, DecompressorPool};
+use dg_xch_serialize::ChiaSerialize;
+use hyper_tungstenite::tungstenite::Message;
+use log::warn;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+pub mod decompress_proof;
+pub mod decompress_qualities;
+pub mod handshake;
+
+/// Milliseconds a job waits for a free decompressor before it fails
+pub const DECOMPRESSOR_WAIT_MS: usize = 10000;
+
+pub struct DecompressorServerConfig {
+    pub network: String,
+    pub websocket: WebsocketServerConfig,
+}
+
+/// Runs decompression jobs sent by harvesters, so low power harvesters can farm compressed plots
+/// with the CPU of another machine.
+pub struct DecompressorServer {
+    pub server: WebsocketServer,
+    pub config: Arc<DecompressorServerConfig>,
+    pub pool: Arc<DecompressorPool>,
+}
+impl DecompressorServer {
+    pub fn new(
+        config: DecompressorServerConfig,
+        pool: Arc<DecompressorPool>,
+        #[cfg(feature = "metrics")] metrics: Arc<Option<WebSocketMetrics>>,
+    ) -> Result<Self, Error> {
+        let config = Arc::new(config);
+        let handles = Arc::new(RwLock::new(Self::handles(config.clone(), pool.clone())));
+        Ok(Self {
+            server: WebsocketServer::new(
+                &config.websocket,
+                Arc::default(),
+                handles,
+                #[cfg(feature = "metrics")]
+                metrics,
+            )?,
+            config,
+            pool,
+        })
+    }
+
+    fn handles(
+        config: Arc<DecompressorServerConfig>,
+        pool: Arc<DecompressorPool>,
+    ) -> HashMap<Uuid, Arc<ChiaMessageHandler>> {
+        HashMap::from([
+            (
+                Uuid::new_v4(),
+                Arc::new(ChiaMessageHandler::new(
+                    Arc::new(ChiaMessageFilter {
+                        msg_type: Some(ProtocolMessageTypes::Handshake),
+                        id: None,
+                        custom_fn: None,
+                    }),
+                    Arc::new(HandshakeHandle { config }),
+                )),
+            ),
+            (
+                Uuid::new_v4(),
+                Arc::new(ChiaMessageHandler::new(
+                    Arc::new(ChiaMessageFilter {
+                        msg_type: Some(ProtocolMessageTypes::RequestDecompressQualities),
+                        id: None,
+                        custom_fn: None,
+                    }),
+                    Arc::new(DecompressQualitiesHandle { pool: pool.clone() }),
+                )),
+            ),
+            (
+                Uuid::new_v4(),
+                Arc::new(ChiaMessageHandler::new(
+                    Arc::new(ChiaMessageFilter {
+                        msg_type: Some(ProtocolMessageTypes::RequestDecompressProof),
+                        id: None,
+                        custom_fn: None,
+                    }),
+                    Arc::new(DecompressProofHandle { pool }),
+                )),
+            ),
+        ])
+    }
+
+    pub async fn run(&self, run: Arc<AtomicBool>) -> Result<(), Error> {
+        self.server.run(run).await
+    }
+}
+
+/// Rejects jobs the decompressor can not run before a decompressor is taken from the pool.
+pub fn check_job(k: u8, compression_level: u8) -> Result<(), Error> {
+    if !(1..=9).contains(&compression_level) {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("Invalid compression level {compression_level}"),
+        ))
+    } else if !compression_level_fits_k(k, compression_level) {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("Compression level {compression_level} does not fit k{k} plots"),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Runs `job` on a decompressor from `pool` off the async runtime, returning the decompressor
+/// to the pool afterwards.
+pub async fn run_job<T: Send + 'static>(
+    pool: &DecompressorPool,
+    k: u8,
+    compression_level: u8,
+    job: impl FnOnce(&mut Decompressor) -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    let mut decompressor = pool.pull_wait(DECOMPRESSOR_WAIT_MS).await?;
+    let (decompressor, result) = tokio::task::spawn_blocking(move || {
+        decompressor.prealloc_for_clevel(k, compression_level);
+        let result = job(&mut decompressor);
+        (decompressor, result)
+    })
+    .await
+    .map_err(|e| Error::other(format!("Decompression job failed: {e:?}")))?;
+    pool.push(decompressor).await;
+    result
+}
+
+/// Sends the result of a job back to the peer that requested it, using the id of the request.
+pub async fn respond(
+    msg: &ChiaMessage,
+    peer_id: &Bytes32,
+    peers: &PeerMap,
+    result: Result<Vec<u64>, Error>,
+) -> Result<(), Error> {
+    let response = match result {
+        Ok(x_values) => RespondDecompression {
+            x_values,
+            error: None,
+        },
+        Err(e) => {
+            warn!("Failed decompression job from {peer_id}: {e}");
+            RespondDecompression {
+                x_values: vec![],
+                error: Some(e.to_string()),
+            }
+        }
+    };
+    if let Some(peer) = peers.read().await.get(peer_id).cloned() {
+        let protocol_version = *peer.protocol_version.read().await;
+        peer.websocket
+            .write()
+            .await
+            .send(Message::Binary(
+                ChiaMessage::new(
+                    ProtocolMessageTypes::RespondDecompression,
+                    protocol_version,
+                    &response,
+                    msg.id,
+                )?
+                .to_bytes(protocol_version)?
+                .into(),
+            ))
+            .await
+    } else {
+        Err(Error::new(ErrorKind::NotFound, "Failed to find peer"))
+    }
+}
@@ -1,4 +1,5 @@
 use crate::websocket::farmer::new_proof_or_space::NewProofOfSpaceHandle;
+use crate::websocket::farmer::plot_sync::PlotSyncHandle;
 use crate::websocket::farmer::respond_signatures::RespondSignaturesHandle;
 #[cfg(feature = "metrics")]
 use crate::websocket::WebSocketMetrics;
@@ -6,30 +7,36 @@ use crate::websocket::{WebsocketServer, WebsocketServerConfig};
 use blst::min_pk::SecretKey;
 use dg_xch_clients::api::pool::PoolClient;
 use dg_xch_clients::websocket::farmer::FarmerClient;
+use dg_xch_clients::websocket::{oneshot, WsClientConfig};
 use dg_xch_core::blockchain::sized_bytes::{Bytes32, Bytes48};
 use dg_xch_core::clvm::bls_bindings::{sign, verify_signature};
 use dg_xch_core::config::PoolWalletConfig;
 use dg_xch_core::protocols::farmer::{FarmerPoolState, FarmerSharedState};
+use dg_xch_core::protocols::harvester::{RequestPlots, RespondPlots};
+use dg_xch_core::protocols::plot_sync::PLOT_SYNC_ORDER;
 use dg_xch_core::protocols::pool::{
     get_current_authentication_token, AuthenticationPayload, GetFarmerRequest, GetFarmerResponse,
     PoolError, PoolErrorCode, PostFarmerPayload, PostFarmerRequest, PostFarmerResponse,
     PutFarmerPayload, PutFarmerRequest, PutFarmerResponse,
 };
-use dg_xch_core::protocols::{ChiaMessageFilter, ChiaMessageHandler, ProtocolMessageTypes};
+use dg_xch_core::protocols::{
+    ChiaMessage, ChiaMessageFilter, ChiaMessageHandler, ProtocolMessageTypes,
+};
 use dg_xch_core::traits::SizedBytes;
 use dg_xch_core::utils::hash_256;
 use dg_xch_keys::parse_payout_address;
 use dg_xch_serialize::{ChiaProtocolVersion, ChiaSerialize};
-use log::{error, info};
+use log::{error, info, warn};
 use std::collections::HashMap;
-use std::io::Error;
-use std::sync::atomic::AtomicBool;
+use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
 mod handshake;
 mod new_proof_or_space;
+pub mod plot_sync;
 mod respond_signatures;
 use handshake::HandshakeHandle;
 
@@ -45,6 +52,8 @@ pub struct FarmerServer<T, S> {
     pub shared_state: Arc<FarmerSharedState<S>>,
     pub pool_client: Arc<T>,
     pub config: Arc<FarmerServerConfig>,
+    pub full_node_client: Arc<RwLock<Option<FarmerClient<S>>>>,
+    next_request_id: AtomicU16,
 }
 impl<T: PoolClient + Sized + Sync + Send + 'static, S: Sync + Send + 'static> FarmerServer<T, S> {
     pub fn new(
@@ -60,7 +69,7 @@ impl<T: PoolClient + Sized + Sync + Send + 'static, S: Sync + Send + 'static> Fa
             config.clone(),
             pool_client.clone(),
             shared_state.as_ref(),
-            full_node_client,
+            full_node_client.clone(),
             additional_headers,
         )));
         Ok(Self {
@@ -74,6 +83,8 @@ impl<T: PoolClient + Sized + Sync + Send + 'static, S: Sync + Send + 'static> Fa
             shared_state,
             pool_client,
             config,
+            full_node_client,
+            next_request_id: AtomicU16::new(0),
         })
     }
 
@@ -146,12 +157,76 @@ impl<T: PoolClient + Sized + Sync + Send + 'static, S: Sync + Send + 'static> Fa
                     }),
                 )),
             ),
+            (
+                Uuid::new_v4(),
+                Arc::new(ChiaMessageHandler::new(
+                    Arc::new(ChiaMessageFilter {
+                        msg_type: None,
+                        id: None,
+                        custom_fn: Some(Box::new(|msg| PLOT_SYNC_ORDER.contains(&msg.msg_type))),
+                    }),
+                    Arc::new(PlotSyncHandle {
+                        plot_syncs: shared_state.harvester_plot_syncs.clone(),
+                        plot_counts: shared_state.plot_counts.clone(),
+                    }),
+                )),
+            ),
         ])
     }
 
     pub async fn run(&self, run: Arc<AtomicBool>) -> Result<(), Error> {
         self.server.run(run).await
     }
+
+    /// Connects to the full node that proofs are declared to. Signage points and signed value
+    /// requests from the full node are answered with the state of this server, an earlier full
+    /// node connection is closed.
+    pub async fn connect_full_node(
+        &self,
+        client_config: Arc<WsClientConfig>,
+        run: Arc<AtomicBool>,
+        timeout: u64,
+    ) -> Result<(), Error> {
+        let client =
+            FarmerClient::new(client_config, self.shared_state.clone(), run, timeout).await?;
+        let previous = self.full_node_client.write().await.replace(client);
+        if let Some(previous) = previous {
+            if let Err(e) = previous.join().await {
+                warn!("Error closing previous full node connection: {e}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Asks the harvester connected as `peer_id` for every plot it is farming.
+    pub async fn request_plots(&self, peer_id: &Bytes32) -> Result<RespondPlots, Error> {
+        let Some(peer) = self
+            .shared_state
+            .harvester_peers
+            .read()
+            .await
+            .get(peer_id)
+            .cloned()
+        else {
+            return Err(Error::new(ErrorKind::NotFound, "Failed to find peer"));
+        };
+        let protocol_version = *peer.protocol_version.read().await;
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        oneshot::<RespondPlots>(
+            peer.websocket.clone(),
+            ChiaMessage::new(
+                ProtocolMessageTypes::RequestPlots,
+                protocol_version,
+                &RequestPlots {},
+                Some(id),
+            )?,
+            Some(ProtocolMessageTypes::RespondPlots),
+            protocol_version,
+            Some(id),
+            None,
+        )
+        .await
+    }
 }
 
 pub async fn get_farmer<
@@ -27,7 +27,7 @@ use dg_xch_core::protocols::pool::{
 use dg_xch_core::protocols::{ChiaMessage, MessageHandler, PeerMap, ProtocolMessageTypes};
 use dg_xch_core::traits::SizedBytes;
 use dg_xch_core::utils::hash_256;
-use dg_xch_pos::verify_and_get_quality_string;
+use dg_xch_pos::verify_proof_of_space;
 use dg_xch_serialize::ChiaProtocolVersion;
 use dg_xch_serialize::ChiaSerialize;
 use hyper_tungstenite::tungstenite::Message;
@@ -74,48 +74,51 @@ impl<T: PoolClient + Sized + Sync + Send + 'static> MessageHandler for NewProofO
                     .cloned()
                     .unwrap_or_default();
                 for sp in sps {
-                    if let Some(qs) = verify_and_get_quality_string(
+                    match verify_proof_of_space(
                         &new_pos.proof,
                         &constants,
                         new_pos.challenge_hash,
                         new_pos.sp_hash,
                         sp.peak_height,
                     ) {
-                        let required_iters = calculate_iterations_quality(
-                            constants.difficulty_constant_factor,
-                            qs,
-                            new_pos.proof.size,
-                            sp.difficulty,
-                            new_pos.sp_hash,
-                        );
-                        if required_iters
-                            < calculate_sp_interval_iters(&constants, sp.sub_slot_iters)?
-                        {
-                            self._handle_proof(
-                                sp,
-                                &qs,
-                                &new_pos,
-                                protocol_version,
-                                peer_id.clone(),
-                                peers.clone(),
-                            )
-                            .await?;
-                        }
-                        if new_pos.proof.pool_contract_puzzle_hash.is_some() {
-                            self.handle_partial(
+                        Ok(qs) => {
+                            let required_iters = calculate_iterations_quality(
+                                constants.difficulty_constant_factor,
                                 qs,
-                                &new_pos,
-                                &constants,
-                                protocol_version,
-                                peer_id.clone(),
-                                peers.clone(),
-                            )
-                            .await?;
-                        } else {
-                            debug!("Not a pooling proof of space");
+                                new_pos.proof.size,
+                                sp.difficulty,
+                                new_pos.sp_hash,
+                            );
+                            if required_iters
+                                < calculate_sp_interval_iters(&constants, sp.sub_slot_iters)?
+                            {
+                                self._handle_proof(
+                                    sp,
+                                    &qs,
+                                    &new_pos,
+                                    protocol_version,
+                                    peer_id.clone(),
+                                    peers.clone(),
+                                )
+                                .await?;
+                            }
+                            if new_pos.proof.pool_contract_puzzle_hash.is_some() {
+                                self.handle_partial(
+                                    qs,
+                                    &new_pos,
+                                    &constants,
+                                    protocol_version,
+                                    peer_id.clone(),
+                                    peers.clone(),
+                                )
+                                .await?;
+                            } else {
+                                debug!("Not a pooling proof of space");
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Invalid proof of space {new_pos:?}: {e}");
                         }
-                    } else {
-                        warn!("Invalid proof of space {new_pos:?}");
                     }
                 }
             } else {
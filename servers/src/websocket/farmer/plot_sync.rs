@@ -0,0 +1,129 @@
+use async_trait::async_trait;
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::protocols::farmer::PlotCounts;
+use dg_xch_core::protocols::harvester::{
+    PlotSyncDone, PlotSyncError, PlotSyncIdentifier, PlotSyncPathList, PlotSyncPlotList,
+    PlotSyncResponse, PlotSyncStart,
+};
+use dg_xch_core::protocols::plot_sync::{HarvesterPlotSync, PlotSyncErrorCode};
+use dg_xch_core::protocols::{
+    ChiaMessage, MessageHandler, NodeType, PeerMap, ProtocolMessageTypes,
+};
+use dg_xch_serialize::{ChiaProtocolVersion, ChiaSerialize};
+use hyper_tungstenite::tungstenite::Message;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::io::{Cursor, Error, ErrorKind};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Receives the plot sync messages of every harvester and keeps their plot inventories.
+pub struct PlotSyncHandle {
+    pub plot_syncs: Arc<RwLock<HashMap<Bytes32, HarvesterPlotSync>>>,
+    pub plot_counts: Arc<PlotCounts>,
+}
+#[async_trait]
+impl MessageHandler for PlotSyncHandle {
+    async fn handle(
+        &self,
+        msg: Arc<ChiaMessage>,
+        peer_id: Arc<Bytes32>,
+        peers: PeerMap,
+    ) -> Result<(), Error> {
+        let Some(peer) = peers.read().await.get(&peer_id).cloned() else {
+            return Err(Error::new(ErrorKind::NotFound, "Failed to find peer"));
+        };
+        let protocol_version = *peer.protocol_version.read().await;
+        let mut cursor = Cursor::new(&msg.data);
+        let (identifier, result) = if *peer.node_type.read().await == NodeType::Harvester {
+            self.process(
+                msg.msg_type,
+                &mut cursor,
+                &peer_id,
+                &peers,
+                protocol_version,
+            )
+            .await?
+        } else {
+            // Every plot sync message starts with its identifier
+            let identifier = PlotSyncIdentifier::from_bytes(&mut cursor, protocol_version)?;
+            let result = Err(PlotSyncError::new(
+                PlotSyncErrorCode::InvalidConnectionType,
+                format!("Sync {} was not sent by a harvester", identifier.sync_id),
+                None,
+            ));
+            (identifier, result)
+        };
+        if let Err(e) = &result {
+            warn!("Plot sync from {peer_id} failed: {}", e.message);
+        }
+        let response = PlotSyncResponse::new(identifier, msg.msg_type, result);
+        peer.websocket
+            .write()
+            .await
+            .send(Message::Binary(
+                ChiaMessage::new(
+                    ProtocolMessageTypes::PlotSyncResponse,
+                    protocol_version,
+                    &response,
+                    msg.id,
+                )?
+                .to_bytes(protocol_version)?
+                .into(),
+            ))
+            .await?;
+        Ok(())
+    }
+}
+impl PlotSyncHandle {
+    async fn process(
+        &self,
+        msg_type: ProtocolMessageTypes,
+        cursor: &mut Cursor<&Vec<u8>>,
+        peer_id: &Bytes32,
+        peers: &PeerMap,
+        protocol_version: ChiaProtocolVersion,
+    ) -> Result<(PlotSyncIdentifier, Result<(), PlotSyncError>), Error> {
+        let mut plot_syncs = self.plot_syncs.write().await;
+        Ok(match msg_type {
+            ProtocolMessageTypes::PlotSyncStart => {
+                // Inventories of harvesters that disconnected are dropped with the next sync
+                let connected = peers.read().await;
+                plot_syncs.retain(|id, _| connected.contains_key(id));
+                drop(connected);
+                let start = PlotSyncStart::from_bytes(cursor, protocol_version)?;
+                let sync = plot_syncs.entry(*peer_id).or_default();
+                let result = sync.process_start(&start);
+                (start.identifier, result)
+            }
+            ProtocolMessageTypes::PlotSyncLoaded => {
+                let plots = PlotSyncPlotList::from_bytes(cursor, protocol_version)?;
+                let sync = plot_syncs.entry(*peer_id).or_default();
+                let result = sync.process_loaded(&plots);
+                (plots.identifier, result)
+            }
+            ProtocolMessageTypes::PlotSyncDone => {
+                let done = PlotSyncDone::from_bytes(cursor, protocol_version)?;
+                let sync = plot_syncs.entry(*peer_id).or_default();
+                let result = sync.process_done(&done);
+                if result.is_ok() {
+                    info!(
+                        "Harvester {peer_id} synced {} plots, {} invalid, {} missing keys, {} duplicates",
+                        sync.plots.len(),
+                        sync.invalid.len(),
+                        sync.keys_missing.len(),
+                        sync.duplicates.len()
+                    );
+                    self.plot_counts.update_from_syncs(&plot_syncs);
+                }
+                (done.identifier, result)
+            }
+            msg_type => {
+                let paths = PlotSyncPathList::from_bytes(cursor, protocol_version)?;
+                let sync = plot_syncs.entry(*peer_id).or_default();
+                let result = sync.process_path_list(msg_type, &paths);
+                (paths.identifier, result)
+            }
+        })
+    }
+}
@@ -17,7 +17,7 @@ use dg_xch_core::protocols::farmer::{
 use dg_xch_core::protocols::harvester::RespondSignatures;
 use dg_xch_core::protocols::{ChiaMessage, MessageHandler, PeerMap, ProtocolMessageTypes};
 use dg_xch_core::traits::SizedBytes;
-use dg_xch_pos::verify_and_get_quality_string;
+use dg_xch_pos::verify_proof_of_space;
 use dg_xch_serialize::{ChiaProtocolVersion, ChiaSerialize};
 use hyper_tungstenite::tungstenite::Message;
 use log::{debug, error, info, warn};
@@ -92,13 +92,15 @@ impl<T: Sync + Send + 'static> MessageHandler for RespondSignaturesHandle<T> {
                     let constants = CONSENSUS_CONSTANTS_MAP
                         .get(&self.config.network)
                         .unwrap_or(&MAINNET);
-                    if let Some(computed_quality_string) = verify_and_get_quality_string(
+                    if let Ok(computed_quality_string) = verify_proof_of_space(
                         &pospace,
                         constants,
                         response.challenge_hash,
                         response.sp_hash,
                         peak_height,
-                    ) {
+                    )
+                    .inspect_err(|e| warn!("Have invalid PoSpace {pospace:?}: {e}"))
+                    {
                         if is_sp_signatures {
                             let (challenge_chain_sp, challenge_chain_sp_harv_sig) =
                                 &response.message_signatures[0];
@@ -460,7 +462,6 @@ impl<T: Sync + Send + 'static> MessageHandler for RespondSignaturesHandle<T> {
                             return Ok(());
                         }
                     } else {
-                        warn!("Have invalid PoSpace {pospace:?}");
                         return Ok(());
                     }
                 } else {
@@ -2,9 +2,17 @@ use crate::websocket::harvester::handshake::HandshakeHandle;
 #[cfg(feature = "metrics")]
 use crate::websocket::WebSocketMetrics;
 use crate::websocket::{WebsocketServer, WebsocketServerConfig};
-use dg_xch_core::protocols::{ChiaMessageFilter, ChiaMessageHandler, ProtocolMessageTypes};
-use std::collections::HashMap;
-use std::io::Error;
+use dg_xch_clients::websocket::harvester::harvester_handles;
+use dg_xch_clients::websocket::harvester::plot_sync::{PlotSyncInventory, PlotSyncSenders};
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::consensus::constants::{CONSENSUS_CONSTANTS_MAP, MAINNET};
+use dg_xch_core::protocols::harvester::HarvesterState;
+use dg_xch_core::protocols::{
+    ChiaMessageFilter, ChiaMessageHandler, NodeType, PeerMap, ProtocolMessageTypes,
+};
+use dg_xch_pos::plots::harvest_scheduler::HarvestScheduler;
+use dg_xch_pos::PlotManagerAsync;
+use std::io::{Error, ErrorKind};
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -17,31 +25,39 @@ pub struct HarvesterServerConfig {
     pub websocket: WebsocketServerConfig,
 }
 
+/// Harvester that waits for farmers to connect instead of dialing out, answering them the same
+/// way `HarvesterClient` does. Plots are synced with every connected farmer, each with a sync of
+/// its own.
 pub struct HarvesterServer {
     pub server: WebsocketServer,
     pub config: Arc<HarvesterServerConfig>,
+    pub peers: PeerMap,
+    pub plot_syncs: PlotSyncSenders,
 }
 impl HarvesterServer {
-    pub fn new(
+    pub fn new<T: PlotManagerAsync + Send + Sync + 'static>(
         config: HarvesterServerConfig,
+        plot_manager: Arc<RwLock<T>>,
+        plots_ready: Arc<AtomicBool>,
+        harvester_state: Arc<RwLock<HarvesterState>>,
+        scheduler: Arc<HarvestScheduler>,
         #[cfg(feature = "metrics")] metrics: Arc<Option<WebSocketMetrics>>,
     ) -> Result<Self, Error> {
         let config = Arc::new(config);
-        let handles = Arc::new(RwLock::new(Self::handles(config.clone())));
-        Ok(Self {
-            server: WebsocketServer::new(
-                &config.websocket,
-                Arc::default(),
-                handles,
-                #[cfg(feature = "metrics")]
-                metrics,
-            )?,
-            config,
-        })
-    }
-
-    fn handles(config: Arc<HarvesterServerConfig>) -> HashMap<Uuid, Arc<ChiaMessageHandler>> {
-        HashMap::from([(
+        let constants = CONSENSUS_CONSTANTS_MAP
+            .get(&config.network)
+            .unwrap_or(&MAINNET);
+        let peers = PeerMap::default();
+        let plot_syncs = PlotSyncSenders::default();
+        let mut handles = harvester_handles(
+            constants,
+            plot_manager,
+            plots_ready,
+            harvester_state,
+            scheduler,
+            plot_syncs.clone(),
+        );
+        handles.insert(
             Uuid::new_v4(),
             Arc::new(ChiaMessageHandler::new(
                 Arc::new(ChiaMessageFilter {
@@ -49,12 +65,70 @@ impl HarvesterServer {
                     id: None,
                     custom_fn: None,
                 }),
-                Arc::new(HandshakeHandle { config }),
+                Arc::new(HandshakeHandle {
+                    config: config.clone(),
+                }),
             )),
-        )])
+        );
+        Ok(Self {
+            server: WebsocketServer::new(
+                &config.websocket,
+                peers.clone(),
+                Arc::new(RwLock::new(handles)),
+                #[cfg(feature = "metrics")]
+                metrics,
+            )?,
+            config,
+            peers,
+            plot_syncs,
+        })
     }
 
     pub async fn run(&self, run: Arc<AtomicBool>) -> Result<(), Error> {
         self.server.run(run).await
     }
+
+    /// Sends the plots loaded or removed since the last sync to every connected farmer, call
+    /// after the plots of `plot_manager` were reloaded. Every farmer is synced even when the
+    /// sync with another one fails, the last error is returned.
+    pub async fn sync_plots<T: PlotManagerAsync>(
+        &self,
+        plot_manager: &RwLock<T>,
+    ) -> Result<(), Error> {
+        let mut farmers = vec![];
+        for (peer_id, peer) in self.peers.read().await.iter() {
+            if *peer.node_type.read().await == NodeType::Farmer {
+                farmers.push((*peer_id, peer.clone()));
+            }
+        }
+        let peer_ids: Vec<Bytes32> = farmers.iter().map(|(peer_id, _)| *peer_id).collect();
+        self.plot_syncs.retain(&peer_ids).await;
+        if farmers.is_empty() {
+            return Err(Error::new(
+                ErrorKind::NotConnected,
+                "No farmer is connected",
+            ));
+        }
+        let inventory = PlotSyncInventory::new(&*plot_manager.read().await);
+        let mut result = Ok(());
+        for (peer_id, farmer) in farmers {
+            let protocol_version = *farmer.protocol_version.read().await;
+            if let Err(e) = self
+                .plot_syncs
+                .sender(&peer_id)
+                .await
+                .lock()
+                .await
+                .sync(
+                    farmer.websocket.clone(),
+                    protocol_version,
+                    inventory.clone(),
+                )
+                .await
+            {
+                result = Err(e);
+            }
+        }
+        result
+    }
 }
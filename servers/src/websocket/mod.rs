@@ -1,3 +1,4 @@
+pub mod decompressor;
 pub mod farmer;
 pub mod harvester;
 
@@ -132,6 +133,11 @@ impl WebsocketServer {
 
     pub async fn run(&self, run: Arc<AtomicBool>) -> Result<(), Error> {
         let listener = TcpListener::bind(self.socket_address).await?;
+        self.serve(listener, run).await
+    }
+
+    /// Accepts connections on an already bound `listener` until `run` is cleared.
+    pub async fn serve(&self, listener: TcpListener, run: Arc<AtomicBool>) -> Result<(), Error> {
         let acceptor = TlsAcceptor::from(self.server_config.clone());
         let mut http = Builder::new();
         http.keep_alive(true);
@@ -333,18 +339,28 @@ async fn handle_connection(
         peer_id.clone(),
         peers.clone(),
     );
-    let removed = peers.write().await.insert(
-        *peer_id,
-        Arc::new(SocketPeer {
-            node_type: Arc::new(RwLock::new(NodeType::Unknown)),
-            protocol_version: Arc::new(RwLock::new(ChiaProtocolVersion::default())),
-            websocket: Arc::new(RwLock::new(websocket)),
-        }),
-    );
+    let peer = Arc::new(SocketPeer {
+        node_type: Arc::new(RwLock::new(NodeType::Unknown)),
+        protocol_version: Arc::new(RwLock::new(ChiaProtocolVersion::default())),
+        websocket: Arc::new(RwLock::new(websocket)),
+    });
+    let removed = peers.write().await.insert(*peer_id, peer.clone());
     if let Some(removed) = removed {
         debug!("Sending Close to Peer");
         let _ = removed.websocket.write().await.close(None).await;
     }
     stream.run(run).await;
+    {
+        // After a reconnect with the same id the entry belongs to the new connection
+        let mut peers = peers.write().await;
+        if peers
+            .get(&*peer_id)
+            .is_some_and(|current| Arc::ptr_eq(current, &peer))
+        {
+            peers.remove(&*peer_id);
+        }
+    }
+    debug!("Closing connection to Peer");
+    let _ = peer.websocket.write().await.close(None).await;
     Ok(())
 }
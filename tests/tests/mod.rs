@@ -11,6 +11,7 @@ pub mod mempool_item;
 pub mod pot_iterations;
 pub mod program;
 pub mod proof_of_space;
+pub mod servers;
 pub mod simulator;
 pub mod sized_bytes;
 pub mod spend;
@@ -19,3 +20,5 @@ pub mod ssl_tests;
 pub mod tx_status;
 pub mod utils;
 pub mod wallet_type;
+pub mod websocket;
+pub mod weight_proof;
@@ -0,0 +1,1007 @@
+use async_trait::async_trait;
+use blst::min_pk::{PublicKey, SecretKey, Signature};
+use dg_xch_clients::api::pool::DefaultPoolClient;
+use dg_xch_clients::websocket::decompressor::DecompressorClient;
+use dg_xch_clients::websocket::harvester::plot_sync::{PlotSyncInventory, PlotSyncSender};
+use dg_xch_clients::websocket::harvester::HarvesterClient;
+use dg_xch_clients::websocket::{oneshot, WsClient, WsClientConfig};
+use dg_xch_core::blockchain::pool_target::PoolTarget;
+use dg_xch_core::blockchain::proof_of_space::{
+    calculate_pos_challenge, calculate_prefix_bits, passes_plot_filter,
+};
+use dg_xch_core::blockchain::sized_bytes::{Bytes32, Bytes48, Bytes96};
+use dg_xch_core::clvm::bls_bindings::verify_signature;
+use dg_xch_core::config::HarvesterConfig;
+use dg_xch_core::consensus::constants::SIMULATOR;
+use dg_xch_core::constants::{CHIA_CA_CRT, CHIA_CA_KEY};
+use dg_xch_core::plots::{PlotHeader, PlotHeaderV1, PlotMemo};
+use dg_xch_core::protocols::decompressor::{RequestDecompressProof, RequestDecompressQualities};
+use dg_xch_core::protocols::farmer::{
+    DeclareProofOfSpace, FarmerSharedState, NewSignagePoint, RequestSignedValues, SignedValues,
+};
+use dg_xch_core::protocols::harvester::{
+    HarvesterHandshake, HarvesterState, Plot, RequestPlots, RespondPlots,
+};
+use dg_xch_core::protocols::plot_sync::{HarvesterPlotSync, PLOT_SYNC_ORDER};
+use dg_xch_core::protocols::shared::{Handshake, CAPABILITIES};
+use dg_xch_core::protocols::{
+    ChiaMessage, ChiaMessageFilter, ChiaMessageHandler, MessageHandler, NodeType, PeerMap,
+    ProtocolMessageTypes,
+};
+use dg_xch_core::traits::SizedBytes;
+use dg_xch_keys::master_sk_to_farmer_sk;
+use dg_xch_keys::plot_memo::PlotMemoBuilder;
+use dg_xch_pos::constants::HEADER_MAGIC;
+use dg_xch_pos::plots::decompressor::DecompressorPool;
+use dg_xch_pos::plots::disk_plot::{write_plot_header, DiskPlot};
+use dg_xch_pos::plots::harvest_scheduler::{HarvestScheduler, HarvestSchedulerConfig};
+use dg_xch_pos::plots::plot_manager::DiskPlotManager;
+use dg_xch_pos::plots::plot_reader::PlotReader;
+use dg_xch_pos::plots::plotting::COMPRESSED_PLOT_K;
+use dg_xch_pos::verifier::{proof_to_bytes, validate_proof};
+use dg_xch_pos::{verify_proof_of_space, PlotManagerAsync};
+use dg_xch_serialize::{ChiaProtocolVersion, ChiaSerialize};
+use dg_xch_servers::websocket::decompressor::{DecompressorServer, DecompressorServerConfig};
+use dg_xch_servers::websocket::farmer::plot_sync::PlotSyncHandle;
+use dg_xch_servers::websocket::farmer::{FarmerServer, FarmerServerConfig};
+use dg_xch_servers::websocket::harvester::{HarvesterServer, HarvesterServerConfig};
+use dg_xch_servers::websocket::{WebsocketServer, WebsocketServerConfig};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Installs the default TLS provider and binds a free local port for a test server. The
+/// listener stays bound until it is passed to `WebsocketServer::serve`, so clients can connect
+/// without retrying and no other process can take the port in between.
+async fn bind_free_port() -> (TcpListener, u16) {
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    (listener, port)
+}
+
+fn client_config(port: u16, network_id: &str) -> Arc<WsClientConfig> {
+    Arc::new(WsClientConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        network_id: network_id.to_string(),
+        ssl_info: None,
+        software_version: None,
+        protocol_version: ChiaProtocolVersion::default(),
+        additional_headers: None,
+    })
+}
+
+/// A farmer server serving on a free local port until `run` is cleared
+struct FarmerFixture {
+    server: Arc<FarmerServer<DefaultPoolClient, ()>>,
+    port: u16,
+    handle: JoinHandle<Result<(), Error>>,
+}
+impl FarmerFixture {
+    async fn start(
+        network: &str,
+        shared_state: FarmerSharedState<()>,
+        farmer_reward_payout_address: Bytes32,
+        pool_rewards_payout_address: Bytes32,
+        run: Arc<AtomicBool>,
+    ) -> Self {
+        let (listener, port) = bind_free_port().await;
+        let server = Arc::new(
+            FarmerServer::new(
+                FarmerServerConfig {
+                    network: network.to_string(),
+                    websocket: WebsocketServerConfig {
+                        host: "127.0.0.1".to_string(),
+                        port,
+                        ssl_info: None,
+                    },
+                    farmer_reward_payout_address,
+                    pool_rewards_payout_address,
+                },
+                Arc::new(DefaultPoolClient::new()),
+                Arc::new(shared_state),
+                Arc::new(RwLock::new(None)),
+                Arc::default(),
+            )
+            .unwrap(),
+        );
+        let serving = server.clone();
+        let handle = tokio::spawn(async move { serving.server.serve(listener, run).await });
+        Self {
+            server,
+            port,
+            handle,
+        }
+    }
+}
+
+/// A decompressor server with `pool` serving on a free local port until `run` is cleared
+struct DecompressorFixture {
+    client: DecompressorClient,
+    handle: JoinHandle<Result<(), Error>>,
+}
+impl DecompressorFixture {
+    async fn start(pool: DecompressorPool, run: Arc<AtomicBool>) -> Self {
+        let (listener, port) = bind_free_port().await;
+        let server = DecompressorServer::new(
+            DecompressorServerConfig {
+                network: "testnet".to_string(),
+                websocket: WebsocketServerConfig {
+                    host: "127.0.0.1".to_string(),
+                    port,
+                    ssl_info: None,
+                },
+            },
+            Arc::new(pool),
+        )
+        .unwrap();
+        let server_run = run.clone();
+        let handle = tokio::spawn(async move { server.server.serve(listener, server_run).await });
+        let client = DecompressorClient::new(client_config(port, "testnet"), run, 5)
+            .await
+            .unwrap();
+        Self { client, handle }
+    }
+
+    async fn join(self) {
+        self.client.join().await.unwrap();
+        self.handle.await.unwrap().unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_plot_sync() {
+    let run = Arc::new(AtomicBool::new(true));
+    let farmer = FarmerFixture::start(
+        "testnet",
+        FarmerSharedState::default(),
+        Bytes32::default(),
+        Bytes32::default(),
+        run.clone(),
+    )
+    .await;
+    let client = WsClient::with_ca(
+        client_config(farmer.port, "testnet"),
+        NodeType::Harvester,
+        Arc::default(),
+        run.clone(),
+        CHIA_CA_CRT.as_bytes(),
+        CHIA_CA_KEY.as_bytes(),
+        5,
+    )
+    .await
+    .unwrap();
+    let plot = |filename: &str, pool_public_key: Option<Bytes48>| Plot {
+        filename: filename.to_string(),
+        size: 32,
+        plot_id: Bytes32::default(),
+        pool_public_key,
+        pool_contract_puzzle_hash: None,
+        plot_public_key: Bytes48::default(),
+        file_size: 100,
+        time_modified: 0,
+        compression_level: Some(0),
+    };
+    let mut inventory = PlotSyncInventory {
+        plots: vec![
+            plot("a.plot", Some(Bytes48::default())),
+            plot("b.plot", None),
+            plot("c.plot", None),
+        ],
+        invalid: vec!["d.plot".to_string()],
+        keys_missing: vec![],
+        duplicates: vec![],
+    };
+    // A batch size of 2 splits the loaded plots over two messages
+    let mut sender = PlotSyncSender::new(2);
+    let protocol_version = ChiaProtocolVersion::default();
+    sender
+        .sync(
+            client.connection.clone(),
+            protocol_version,
+            inventory.clone(),
+        )
+        .await
+        .unwrap();
+    let synced = |state: &FarmerSharedState<()>| {
+        let syncs = state.harvester_plot_syncs.clone();
+        async move {
+            let syncs = syncs.read().await;
+            assert_eq!(syncs.len(), 1);
+            let sync = syncs.values().next().unwrap();
+            let mut plots: Vec<String> = sync.plots.keys().cloned().collect();
+            plots.sort();
+            (plots, sync.invalid.clone(), sync.last_sync_id)
+        }
+    };
+    let (plots, invalid, last_sync_id) = synced(&farmer.server.shared_state).await;
+    assert_eq!(plots, vec!["a.plot", "b.plot", "c.plot"]);
+    assert_eq!(invalid, vec!["d.plot".to_string()]);
+    assert_eq!(last_sync_id, sender.last_sync_id());
+    assert_eq!(
+        farmer
+            .server
+            .shared_state
+            .plot_counts
+            .og_plot_count
+            .load(Ordering::Relaxed),
+        1
+    );
+    assert_eq!(
+        farmer
+            .server
+            .shared_state
+            .plot_counts
+            .nft_plot_count
+            .load(Ordering::Relaxed),
+        2
+    );
+    // The next sync only sends the changes
+    inventory.plots.remove(0);
+    inventory.plots.push(plot("e.plot", None));
+    inventory.invalid.clear();
+    sender
+        .sync(client.connection.clone(), protocol_version, inventory)
+        .await
+        .unwrap();
+    let (plots, invalid, last_sync_id) = synced(&farmer.server.shared_state).await;
+    assert_eq!(plots, vec!["b.plot", "c.plot", "e.plot"]);
+    assert!(invalid.is_empty());
+    assert_eq!(last_sync_id, sender.last_sync_id());
+    assert_eq!(
+        farmer
+            .server
+            .shared_state
+            .plot_counts
+            .og_plot_count
+            .load(Ordering::Relaxed),
+        0
+    );
+    assert_eq!(
+        farmer
+            .server
+            .shared_state
+            .plot_counts
+            .total_plot_space
+            .load(Ordering::Relaxed),
+        300
+    );
+    run.store(false, Ordering::Relaxed);
+    client.connection.write().await.shutdown().await.unwrap();
+    client.join().await.unwrap();
+    farmer.handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_request_plots() {
+    let dir = std::env::temp_dir().join(format!(
+        "dg_farmer_test_request_plots_{}",
+        std::process::id()
+    ));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let key = |seed: u8| SecretKey::key_gen(&[seed; 32], &[]).unwrap();
+    let farmer_sk = key(1);
+    let farmer_pk = farmer_sk.sk_to_pk().to_bytes();
+    let write_plot = |path: PathBuf, memo: Vec<u8>| async move {
+        let mut header = PlotHeaderV1 {
+            magic: HEADER_MAGIC,
+            id: [memo[60]; 32].into(),
+            k: 18,
+            format_desc_len: 4,
+            format_desc: b"v1.0".to_vec(),
+            memo_len: u16::try_from(memo.len()).unwrap(),
+            memo: PlotMemo::try_from(memo.as_slice()).unwrap(),
+            table_begin_pointers: [0u64; 10],
+        };
+        let header_size = write_plot_header(&PlotHeader::V1(header.clone()), &memo)
+            .unwrap()
+            .len() as u64;
+        // Empty tables followed by an empty C2 table
+        header.table_begin_pointers = [header_size; 10];
+        header.table_begin_pointers[9] = header_size + 64;
+        let mut bytes = write_plot_header(&PlotHeader::V1(header), &memo).unwrap();
+        bytes.resize(bytes.len() + 128, 0);
+        tokio::fs::write(path, bytes).await.unwrap();
+    };
+    let nft_memo = [&[7u8; 32][..], &farmer_pk[..], &key(2).to_bytes()[..]].concat();
+    let plot_id = Bytes32::from([nft_memo[60]; 32]);
+    let unknown_pk = key(3).sk_to_pk().to_bytes();
+    let unknown_memo = [&[7u8; 32][..], &unknown_pk[..], &key(4).to_bytes()[..]].concat();
+    write_plot(dir.join("plot-nft.plot"), nft_memo).await;
+    write_plot(dir.join("plot-unknown.plot"), unknown_memo).await;
+    tokio::fs::write(dir.join("plot-broken.plot"), [1u8; 64])
+        .await
+        .unwrap();
+    let run = Arc::new(AtomicBool::new(true));
+    let farmer = FarmerFixture::start(
+        "testnet",
+        FarmerSharedState {
+            farmer_private_keys: Arc::new(HashMap::from([(farmer_pk.into(), farmer_sk)])),
+            ..Default::default()
+        },
+        Bytes32::default(),
+        Bytes32::default(),
+        run.clone(),
+    )
+    .await;
+    let shared_state = farmer.server.shared_state.clone();
+    let plot_manager = DiskPlotManager::new(&HarvesterConfig {
+        plot_directories: vec![dir.to_string_lossy().to_string()],
+        ..Default::default()
+    });
+    let plots_ready = plot_manager.plots_ready();
+    let plot_manager = Arc::new(RwLock::new(plot_manager));
+    let client = HarvesterClient::new(
+        client_config(farmer.port, "testnet"),
+        plot_manager.clone(),
+        plots_ready.clone(),
+        Arc::new(RwLock::new(HarvesterState::default())),
+        run.clone(),
+        5,
+    )
+    .await
+    .unwrap();
+    // The harvester loads its plots once the farmer sent its keys, then syncs them
+    let mut harvester_id = None;
+    for _ in 0..100 {
+        harvester_id = shared_state
+            .harvester_plot_syncs
+            .read()
+            .await
+            .iter()
+            .find(|(_, sync)| sync.initial_sync_done())
+            .map(|(id, _)| *id);
+        if harvester_id.is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    let harvester_id = harvester_id.expect("Harvester never synced its plots");
+    let plots = farmer.server.request_plots(&harvester_id).await.unwrap();
+    assert_eq!(plots.plots.len(), 1);
+    let plot = &plots.plots[0];
+    assert!(plot.filename.ends_with("plot-nft.plot"));
+    assert_eq!(plot.size, 18);
+    assert_eq!(plot.plot_id, plot_id);
+    assert_eq!(plot.pool_public_key, None);
+    assert_eq!(
+        plot.pool_contract_puzzle_hash,
+        Some(Bytes32::from([7u8; 32]))
+    );
+    assert_eq!(plot.compression_level, Some(0));
+    assert!(plot.file_size > 0);
+    assert_eq!(plots.failed_to_open_filenames.len(), 1);
+    assert!(plots.failed_to_open_filenames[0].ends_with("plot-broken.plot"));
+    assert_eq!(plots.no_key_filenames.len(), 1);
+    assert!(plots.no_key_filenames[0].ends_with("plot-unknown.plot"));
+    assert!(farmer
+        .server
+        .request_plots(&Bytes32::default())
+        .await
+        .is_err());
+    run.store(false, Ordering::Relaxed);
+    client
+        .client
+        .connection
+        .write()
+        .await
+        .shutdown()
+        .await
+        .unwrap();
+    client.join().await.unwrap();
+    farmer.handle.await.unwrap().unwrap();
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
+
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_harvester_server() {
+    let dir = std::env::temp_dir().join(format!("dg_harvester_server_test_{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    tokio::fs::write(dir.join("plot-broken.plot"), [1u8; 64])
+        .await
+        .unwrap();
+    let (listener, port) = bind_free_port().await;
+    let plot_manager = DiskPlotManager::new(&HarvesterConfig {
+        plot_directories: vec![dir.to_string_lossy().to_string()],
+        ..Default::default()
+    });
+    let plots_ready = plot_manager.plots_ready();
+    let plot_manager = Arc::new(RwLock::new(plot_manager));
+    let server = HarvesterServer::new(
+        HarvesterServerConfig {
+            network: "testnet".to_string(),
+            websocket: WebsocketServerConfig {
+                host: "127.0.0.1".to_string(),
+                port,
+                ssl_info: None,
+            },
+        },
+        plot_manager.clone(),
+        plots_ready.clone(),
+        Arc::new(RwLock::new(HarvesterState::default())),
+        Arc::new(HarvestScheduler::new(HarvestSchedulerConfig::default())),
+    )
+    .unwrap();
+    let server = Arc::new(server);
+    let run = Arc::new(AtomicBool::new(true));
+    let server_run = run.clone();
+    let serving = server.clone();
+    let server_handle =
+        tokio::spawn(async move { serving.server.serve(listener, server_run).await });
+    let client_config = client_config(port, "testnet");
+    let protocol_version = client_config.protocol_version;
+    // Farmers dial out to the harvester and receive its plot syncs
+    let mut farmers = vec![];
+    for _ in 0..2 {
+        let plot_syncs: Arc<RwLock<HashMap<_, HarvesterPlotSync>>> = Arc::default();
+        let farmer_handles = HashMap::from([(
+            Uuid::new_v4(),
+            Arc::new(ChiaMessageHandler::new(
+                Arc::new(ChiaMessageFilter {
+                    msg_type: None,
+                    id: None,
+                    custom_fn: Some(Box::new(|msg| PLOT_SYNC_ORDER.contains(&msg.msg_type))),
+                }),
+                Arc::new(PlotSyncHandle {
+                    plot_syncs: plot_syncs.clone(),
+                    plot_counts: Arc::default(),
+                }),
+            )),
+        )]);
+        let client = WsClient::with_ca(
+            client_config.clone(),
+            NodeType::Farmer,
+            Arc::new(RwLock::new(farmer_handles)),
+            run.clone(),
+            CHIA_CA_CRT.as_bytes(),
+            CHIA_CA_KEY.as_bytes(),
+            5,
+        )
+        .await
+        .unwrap();
+        let handshake = client.handshake.as_ref().unwrap();
+        assert_eq!(handshake.node_type, NodeType::Harvester as u8);
+        client
+            .connection
+            .write()
+            .await
+            .send(
+                ChiaMessage::new(
+                    ProtocolMessageTypes::HarvesterHandshake,
+                    protocol_version,
+                    &HarvesterHandshake {
+                        farmer_public_keys: vec![],
+                        pool_public_keys: vec![],
+                    },
+                    None,
+                )
+                .unwrap()
+                .into(),
+            )
+            .await
+            .unwrap();
+        farmers.push((client, plot_syncs));
+    }
+    let last_sync_ids = |plot_syncs: Arc<RwLock<HashMap<_, HarvesterPlotSync>>>| async move {
+        plot_syncs
+            .read()
+            .await
+            .values()
+            .filter(|s| !s.sync_in_progress())
+            .map(|s| s.last_sync_id)
+            .collect::<Vec<_>>()
+    };
+    let mut initial_sync_ids = vec![];
+    for (_, plot_syncs) in &farmers {
+        let mut synced = vec![];
+        for _ in 0..100 {
+            synced = last_sync_ids(plot_syncs.clone()).await;
+            if synced.iter().any(|id| *id != 0) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        assert_eq!(synced.len(), 1, "Harvester never synced its plots");
+        assert_ne!(synced[0], 0, "Harvester never synced its plots");
+        initial_sync_ids.push(synced[0]);
+    }
+    assert!(plots_ready.load(Ordering::Relaxed));
+    // Each farmer continues its own sync from the last one it received
+    server.sync_plots(&*plot_manager).await.unwrap();
+    for ((_, plot_syncs), initial) in farmers.iter().zip(initial_sync_ids) {
+        let synced = last_sync_ids(plot_syncs.clone()).await;
+        assert_eq!(synced.len(), 1);
+        assert!(synced[0] > initial, "Farmer missed the delta sync");
+    }
+    let (client, plot_syncs) = &farmers[0];
+    let sync_invalid = plot_syncs
+        .read()
+        .await
+        .values()
+        .flat_map(|s| s.invalid.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(sync_invalid.len(), 1);
+    let plots = oneshot::<RespondPlots>(
+        client.connection.clone(),
+        ChiaMessage::new(
+            ProtocolMessageTypes::RequestPlots,
+            protocol_version,
+            &RequestPlots {},
+            Some(1),
+        )
+        .unwrap(),
+        Some(ProtocolMessageTypes::RespondPlots),
+        protocol_version,
+        Some(1),
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(plots.plots.is_empty());
+    assert_eq!(plots.failed_to_open_filenames, sync_invalid);
+    run.store(false, Ordering::Relaxed);
+    for (client, _) in farmers {
+        client.connection.write().await.shutdown().await.unwrap();
+        client.join().await.unwrap();
+    }
+    server_handle.await.unwrap().unwrap();
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
+
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_full_node_round_trip() {
+    /// Stand-in for a full node, answers the handshake and passes every other message on.
+    struct FullNodeHandle {
+        messages: UnboundedSender<Arc<ChiaMessage>>,
+    }
+    #[async_trait]
+    impl MessageHandler for FullNodeHandle {
+        async fn handle(
+            &self,
+            msg: Arc<ChiaMessage>,
+            peer_id: Arc<Bytes32>,
+            peers: PeerMap,
+        ) -> Result<(), Error> {
+            if msg.msg_type != ProtocolMessageTypes::Handshake {
+                let _ = self.messages.send(msg);
+                return Ok(());
+            }
+            let Some(peer) = peers.read().await.get(&peer_id).cloned() else {
+                return Err(Error::new(ErrorKind::NotFound, "Failed to find peer"));
+            };
+            let protocol_version = *peer.protocol_version.read().await;
+            *peer.node_type.write().await = NodeType::Farmer;
+            peer.websocket
+                .write()
+                .await
+                .send(
+                    ChiaMessage::new(
+                        ProtocolMessageTypes::Handshake,
+                        protocol_version,
+                        &Handshake {
+                            network_id: "simulator".to_string(),
+                            protocol_version: protocol_version.to_string(),
+                            software_version: dg_xch_servers::version(),
+                            server_port: 0,
+                            node_type: NodeType::FullNode as u8,
+                            capabilities: CAPABILITIES
+                                .iter()
+                                .map(|e| (e.0, e.1.to_string()))
+                                .collect(),
+                        },
+                        msg.id,
+                    )?
+                    .into(),
+                )
+                .await?;
+            Ok(())
+        }
+    }
+    async fn receive<R: ChiaSerialize>(
+        messages: &mut UnboundedReceiver<Arc<ChiaMessage>>,
+        msg_type: ProtocolMessageTypes,
+    ) -> R {
+        let msg = tokio::time::timeout(Duration::from_secs(60), messages.recv())
+            .await
+            .expect("Full node did not receive a message")
+            .unwrap();
+        assert_eq!(msg.msg_type, msg_type);
+        R::from_bytes(
+            &mut std::io::Cursor::new(&msg.data),
+            ChiaProtocolVersion::default(),
+        )
+        .unwrap()
+    }
+    let dir = std::env::temp_dir().join(format!("dg_farmer_test_full_node_{}", std::process::id()));
+    let (tmp_dir, plot_dir) = (dir.join("tmp"), dir.join("plots"));
+    tokio::fs::create_dir_all(&tmp_dir).await.unwrap();
+    tokio::fs::create_dir_all(&plot_dir).await.unwrap();
+    // An OG plot, its pool rewards go to the payout address signed for with the pool key
+    let master_sk = SecretKey::key_gen(&[1u8; 32], &[]).unwrap();
+    let farmer_sk = master_sk_to_farmer_sk(&master_sk).unwrap();
+    let farmer_pk: Bytes48 = farmer_sk.sk_to_pk().to_bytes().into();
+    let pool_sk = SecretKey::key_gen(&[2u8; 32], &[]).unwrap();
+    let pool_pk: Bytes48 = pool_sk.sk_to_pk().to_bytes().into();
+    let keys = PlotMemoBuilder::new()
+        .master_secret_key(&master_sk)
+        .unwrap()
+        .pool_public_key(pool_pk)
+        .local_master_secret_key(SecretKey::key_gen(&[3u8; 32], &[]).unwrap())
+        .build()
+        .unwrap();
+    let plot = DiskPlot::create(
+        &tmp_dir,
+        &tmp_dir,
+        &plot_dir,
+        18,
+        0,
+        &keys.memo_bytes,
+        keys.plot_id,
+        &SIMULATOR,
+    )
+    .await
+    .unwrap();
+    let reader = PlotReader::new(plot, None, None).await.unwrap();
+    // A signage point this plot has a single proof for, so exactly one proof is declared
+    let challenge_hash = Bytes32::new([4u8; 32]);
+    let prefix_bits = calculate_prefix_bits(&SIMULATOR, 0);
+    let mut sp_hash = None;
+    for i in 0u32.. {
+        let mut candidate = [0u8; 32];
+        candidate[..4].copy_from_slice(&i.to_be_bytes());
+        let candidate = Bytes32::new(candidate);
+        if passes_plot_filter(prefix_bits, keys.plot_id, challenge_hash, candidate)
+            && reader
+                .fetch_qualities_for_challenge(
+                    calculate_pos_challenge(keys.plot_id, challenge_hash, candidate).as_ref(),
+                )
+                .await
+                .is_ok_and(|qualities| qualities.len() == 1)
+        {
+            sp_hash = Some(candidate);
+            break;
+        }
+    }
+    let sp_hash = sp_hash.unwrap();
+    drop(reader);
+    let farmer_reward_payout_address = Bytes32::new([5u8; 32]);
+    let pool_rewards_payout_address = Bytes32::new([6u8; 32]);
+    let run = Arc::new(AtomicBool::new(true));
+    // The full node the farmer declares its proofs to
+    let (messages_tx, mut messages) = unbounded_channel();
+    let (full_node_listener, full_node_port) = bind_free_port().await;
+    let full_node_peers = PeerMap::default();
+    let full_node = WebsocketServer::new(
+        &WebsocketServerConfig {
+            host: "127.0.0.1".to_string(),
+            port: full_node_port,
+            ssl_info: None,
+        },
+        full_node_peers.clone(),
+        Arc::new(RwLock::new(HashMap::from([(
+            Uuid::new_v4(),
+            Arc::new(ChiaMessageHandler::new(
+                Arc::new(ChiaMessageFilter {
+                    msg_type: None,
+                    id: None,
+                    custom_fn: None,
+                }),
+                Arc::new(FullNodeHandle {
+                    messages: messages_tx,
+                }),
+            )),
+        )]))),
+    )
+    .unwrap();
+    let full_node_run = run.clone();
+    let full_node_handle =
+        tokio::spawn(async move { full_node.serve(full_node_listener, full_node_run).await });
+    let farmer = FarmerFixture::start(
+        "simulator",
+        FarmerSharedState {
+            farmer_private_keys: Arc::new(HashMap::from([(farmer_pk, farmer_sk)])),
+            pool_public_keys: Arc::new(HashMap::from([(pool_pk, pool_sk)])),
+            ..Default::default()
+        },
+        farmer_reward_payout_address,
+        pool_rewards_payout_address,
+        run.clone(),
+    )
+    .await;
+    let shared_state = farmer.server.shared_state.clone();
+    let plot_manager = DiskPlotManager::new(&HarvesterConfig {
+        plot_directories: vec![plot_dir.to_string_lossy().to_string()],
+        ..Default::default()
+    });
+    let plots_ready = plot_manager.plots_ready();
+    let plot_manager = Arc::new(RwLock::new(plot_manager));
+    let harvester = HarvesterClient::new(
+        client_config(farmer.port, "simulator"),
+        plot_manager.clone(),
+        plots_ready.clone(),
+        Arc::new(RwLock::new(HarvesterState::default())),
+        run.clone(),
+        5,
+    )
+    .await
+    .unwrap();
+    let mut synced = false;
+    for _ in 0..100 {
+        synced = shared_state
+            .harvester_plot_syncs
+            .read()
+            .await
+            .values()
+            .any(|sync| sync.initial_sync_done() && sync.plots.len() == 1);
+        if synced {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(synced, "Harvester never synced its plot");
+    farmer
+        .server
+        .connect_full_node(client_config(full_node_port, "simulator"), run.clone(), 5)
+        .await
+        .unwrap();
+    assert_eq!(
+        shared_state
+            .upstream_handshake
+            .read()
+            .await
+            .as_ref()
+            .map(|h| h.node_type),
+        Some(NodeType::FullNode as u8)
+    );
+    let farmer_peer = full_node_peers
+        .read()
+        .await
+        .values()
+        .next()
+        .cloned()
+        .unwrap();
+    let send = |msg: ChiaMessage| {
+        let farmer_peer = farmer_peer.clone();
+        async move {
+            farmer_peer
+                .websocket
+                .write()
+                .await
+                .send(msg.into())
+                .await
+                .unwrap();
+        }
+    };
+    // Signage point -> proof from the harvester -> declared proof of space
+    let reward_chain_sp = Bytes32::new([7u8; 32]);
+    send(
+        ChiaMessage::new(
+            ProtocolMessageTypes::NewSignagePoint,
+            ChiaProtocolVersion::default(),
+            &NewSignagePoint {
+                challenge_hash,
+                challenge_chain_sp: sp_hash,
+                reward_chain_sp,
+                difficulty: 1,
+                sub_slot_iters: 1 << 60,
+                signage_point_index: 3,
+                peak_height: 0,
+                last_tx_height: 0,
+                sp_source_data: None,
+            },
+            None,
+        )
+        .unwrap(),
+    )
+    .await;
+    let declare: DeclareProofOfSpace =
+        receive(&mut messages, ProtocolMessageTypes::DeclareProofOfSpace).await;
+    assert_eq!(declare.challenge_hash, challenge_hash);
+    assert_eq!(declare.challenge_chain_sp, sp_hash);
+    assert_eq!(declare.reward_chain_sp, reward_chain_sp);
+    assert_eq!(declare.signage_point_index, 3);
+    assert_eq!(declare.farmer_puzzle_hash, farmer_reward_payout_address);
+    assert_eq!(declare.proof_of_space.pool_public_key, Some(pool_pk));
+    assert_eq!(declare.proof_of_space.plot_public_key, keys.plot_public_key);
+    let pool_target = PoolTarget {
+        max_height: 0,
+        puzzle_hash: pool_rewards_payout_address,
+    };
+    assert_eq!(declare.pool_target, Some(pool_target));
+    let signature = |sig: &Bytes96| Signature::from_bytes(sig.as_ref()).unwrap();
+    assert!(verify_signature(
+        &PublicKey::from(pool_pk),
+        &pool_target
+            .to_bytes(ChiaProtocolVersion::default())
+            .unwrap(),
+        &signature(&declare.pool_signature.unwrap()),
+    ));
+    let plot_pk: PublicKey = keys.plot_public_key.into();
+    assert!(verify_signature(
+        &plot_pk,
+        sp_hash.as_ref(),
+        &signature(&declare.challenge_chain_sp_signature),
+    ));
+    assert!(verify_signature(
+        &plot_pk,
+        reward_chain_sp.as_ref(),
+        &signature(&declare.reward_chain_sp_signature),
+    ));
+    // The full node asks the farmer to sign the foliage of the unfinished block
+    let quality_string = verify_proof_of_space(
+        &declare.proof_of_space,
+        &SIMULATOR,
+        challenge_hash,
+        sp_hash,
+        0,
+    )
+    .unwrap();
+    let foliage_block_data_hash = Bytes32::new([8u8; 32]);
+    let foliage_transaction_block_hash = Bytes32::new([9u8; 32]);
+    send(
+        ChiaMessage::new(
+            ProtocolMessageTypes::RequestSignedValues,
+            ChiaProtocolVersion::default(),
+            &RequestSignedValues {
+                quality_string,
+                foliage_block_data_hash,
+                foliage_transaction_block_hash,
+                foliage_block_data: None,
+                foliage_transaction_block_data: None,
+                rc_block_unfinished: None,
+            },
+            None,
+        )
+        .unwrap(),
+    )
+    .await;
+    let signed: SignedValues = receive(&mut messages, ProtocolMessageTypes::SignedValues).await;
+    assert_eq!(signed.quality_string, quality_string);
+    assert!(verify_signature(
+        &plot_pk,
+        foliage_block_data_hash.as_ref(),
+        &signature(&signed.foliage_block_data_signature),
+    ));
+    assert!(verify_signature(
+        &plot_pk,
+        foliage_transaction_block_hash.as_ref(),
+        &signature(&signed.foliage_transaction_block_signature),
+    ));
+    run.store(false, Ordering::Relaxed);
+    let full_node_client = farmer.server.full_node_client.write().await.take().unwrap();
+    full_node_client.join().await.unwrap();
+    harvester
+        .client
+        .connection
+        .write()
+        .await
+        .shutdown()
+        .await
+        .unwrap();
+    harvester.join().await.unwrap();
+    farmer.handle.await.unwrap().unwrap();
+    full_node_handle.await.unwrap().unwrap();
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_decompressor_server() {
+    let run = Arc::new(AtomicBool::new(true));
+    let decompressor = DecompressorFixture::start(DecompressorPool::new(0, 1), run.clone()).await;
+    let qualities = RequestDecompressQualities {
+        plot_id: Bytes32::default(),
+        k: 18,
+        compression_level: 1,
+        challenge: Bytes32::default(),
+        line_points: vec![1],
+    };
+    let proof = RequestDecompressProof {
+        plot_id: Bytes32::default(),
+        k: COMPRESSED_PLOT_K,
+        compression_level: 3,
+        compressed_proof: vec![0; 4],
+    };
+    // Both jobs are in flight at once, each answer has to reach the request with its id
+    let (qualities, proof) = tokio::join!(
+        decompressor.client.decompress_qualities(&qualities),
+        decompressor.client.decompress_proof(&proof)
+    );
+    let qualities = qualities.unwrap_err().to_string();
+    assert!(qualities.contains("does not fit k18"), "{qualities}");
+    let proof = proof.unwrap_err().to_string();
+    assert!(
+        proof.contains("needs 32 compressed proof values"),
+        "{proof}"
+    );
+    run.store(false, Ordering::Relaxed);
+    decompressor.join().await;
+}
+
+/// Decompresses qualities and proofs of a C7 k20 plot through the server, they have to match
+/// what the plot reader decompresses locally.
+#[tokio::test]
+#[allow(clippy::cast_possible_truncation)]
+async fn test_decompressor_server_jobs() {
+    let k = 20;
+    let compression_level = 7;
+    let dir = std::env::temp_dir().join(format!("dg_decompressor_test_{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let plot_id = Bytes32::new([4u8; 32]);
+    let plot = DiskPlot::create(
+        &dir,
+        &dir,
+        &dir,
+        k,
+        compression_level,
+        &[5u8; 128],
+        plot_id,
+        &SIMULATOR,
+    )
+    .await
+    .unwrap();
+    let reader = PlotReader::new(plot, None, None).await.unwrap();
+    let run = Arc::new(AtomicBool::new(true));
+    let decompressor = DecompressorFixture::start(DecompressorPool::new(1, 1), run.clone()).await;
+    let (mut proofs, mut qualities) = (0, 0);
+    for f7 in reader.read_c3park(0).await.unwrap().iter().step_by(97) {
+        let mut challenge = [0xa5u8; 32];
+        challenge[0..8].copy_from_slice(&(f7 << (64 - k)).to_be_bytes());
+        let (count, base) = reader.get_p7indices_for_f7(*f7).await.unwrap();
+        for index in base as u64..(base + count) as u64 {
+            let line_points = reader
+                .fetch_quality_line_points(index, &challenge)
+                .await
+                .unwrap();
+            let xs = decompressor
+                .client
+                .decompress_qualities(&RequestDecompressQualities {
+                    plot_id,
+                    k,
+                    compression_level,
+                    challenge: Bytes32::new(challenge),
+                    line_points: line_points.to_vec(),
+                })
+                .await;
+            let local_xs = reader.fetch_quality_xs_for_p7entry(index, &challenge).await;
+            assert_eq!(xs.ok(), local_xs.ok());
+            qualities += 1;
+            let compressed_proof = reader.fetch_compressed_proof(index).await.unwrap();
+            // A compressed plot drops some proofs, the server has to fail the same ones
+            match decompressor
+                .client
+                .decompress_proof(&RequestDecompressProof {
+                    plot_id,
+                    k,
+                    compression_level,
+                    compressed_proof,
+                })
+                .await
+            {
+                Ok(proof) => {
+                    let proof = reader.reorder_proof(&proof).await.unwrap();
+                    validate_proof(&plot_id.bytes(), k, &proof_to_bytes(&proof), &challenge)
+                        .unwrap();
+                    proofs += 1;
+                }
+                Err(_) => assert!(reader.fetch_proof(index).await.is_err()),
+            }
+        }
+    }
+    assert!(qualities > 0);
+    assert!(proofs > 0);
+    run.store(false, Ordering::Relaxed);
+    decompressor.join().await;
+    drop(reader);
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
@@ -0,0 +1,1329 @@
+use async_trait::async_trait;
+use dg_xch_clients::websocket::{WsClient, WsClientConfig};
+use dg_xch_core::blockchain::sized_bytes::Bytes32;
+use dg_xch_core::constants::{CHIA_CA_CRT, CHIA_CA_KEY};
+use dg_xch_core::protocols::full_node::{
+    RejectBlock, RequestBlock, RequestBlocks, RespondBlock, RespondBlocks,
+};
+use dg_xch_core::protocols::harvester::{RequestPlots, RespondPlots};
+use dg_xch_core::protocols::shared::{Handshake, CAPABILITIES};
+use dg_xch_core::protocols::{
+    ChiaMessage, ChiaMessageFilter, ChiaMessageHandler, MessageHandler, NodeType, PeerMap,
+    ProtocolMessageTypes, SocketPeer,
+};
+use dg_xch_core::traits::SizedBytes;
+use dg_xch_serialize::{ChiaProtocolVersion, ChiaSerialize};
+use dg_xch_servers::websocket::harvester::handshake::HandshakeHandle;
+use dg_xch_servers::websocket::harvester::HarvesterServerConfig;
+use dg_xch_servers::websocket::{WebsocketServer, WebsocketServerConfig};
+use std::collections::HashMap;
+use std::io::{Cursor, Error, ErrorKind};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Installs the default TLS provider and binds a free port on `host` for a test server. The
+/// listener stays bound until it is passed to `WebsocketServer::serve`, so clients can connect
+/// without retrying and no other process can take the port in between.
+async fn bind_free_port(host: &str) -> (TcpListener, u16) {
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+    let listener = TcpListener::bind((host, 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    (listener, port)
+}
+
+/// Starts a websocket server with `handles` on a free port of `host`
+async fn serve(
+    host: &str,
+    handles: HashMap<Uuid, Arc<ChiaMessageHandler>>,
+    run: Arc<AtomicBool>,
+) -> (u16, JoinHandle<Result<(), Error>>) {
+    let (listener, port) = bind_free_port(host).await;
+    let server = WebsocketServer::new(
+        &WebsocketServerConfig {
+            host: host.to_string(),
+            port,
+            ssl_info: None,
+        },
+        PeerMap::default(),
+        Arc::new(RwLock::new(handles)),
+    )
+    .unwrap();
+    (
+        port,
+        tokio::spawn(async move { server.serve(listener, run).await }),
+    )
+}
+
+/// Handler map passing every message to `handler`
+fn handle_all(
+    handler: Arc<dyn MessageHandler + Send + Sync>,
+) -> HashMap<Uuid, Arc<ChiaMessageHandler>> {
+    HashMap::from([(
+        Uuid::new_v4(),
+        Arc::new(ChiaMessageHandler::new(
+            Arc::new(ChiaMessageFilter {
+                msg_type: None,
+                id: None,
+                custom_fn: None,
+            }),
+            handler,
+        )),
+    )])
+}
+
+fn client_config(host: &str, port: u16, network_id: &str) -> Arc<WsClientConfig> {
+    Arc::new(WsClientConfig {
+        host: host.to_string(),
+        port,
+        network_id: network_id.to_string(),
+        ssl_info: None,
+        software_version: None,
+        protocol_version: ChiaProtocolVersion::default(),
+        additional_headers: None,
+    })
+}
+
+async fn find_peer(peers: &PeerMap, peer_id: &Bytes32) -> Result<Arc<SocketPeer>, Error> {
+    peers
+        .read()
+        .await
+        .get(peer_id)
+        .cloned()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "Failed to find peer"))
+}
+
+/// Handshake a mock peer answers `msg` with
+fn handshake(
+    msg: &ChiaMessage,
+    network_id: &str,
+    node_type: NodeType,
+    protocol_version: ChiaProtocolVersion,
+) -> Result<ChiaMessage, Error> {
+    ChiaMessage::new(
+        ProtocolMessageTypes::Handshake,
+        protocol_version,
+        &Handshake {
+            network_id: network_id.to_string(),
+            protocol_version: protocol_version.to_string(),
+            software_version: dg_xch_servers::version(),
+            server_port: 0,
+            node_type: node_type as u8,
+            capabilities: CAPABILITIES
+                .iter()
+                .map(|e| (e.0, e.1.to_string()))
+                .collect(),
+        },
+        msg.id,
+    )
+}
+
+async fn send_all(peer: &SocketPeer, responses: Vec<ChiaMessage>) -> Result<(), Error> {
+    let mut websocket = peer.websocket.write().await;
+    for response in responses {
+        websocket.send(response.into()).await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_request() {
+    /// Answers `RequestPlots`, after an unrelated message reusing its id, and rejects every
+    /// `RequestBlock`, other requests are ignored.
+    struct Responder;
+    #[async_trait]
+    impl MessageHandler for Responder {
+        async fn handle(
+            &self,
+            msg: Arc<ChiaMessage>,
+            peer_id: Arc<Bytes32>,
+            peers: PeerMap,
+        ) -> Result<(), Error> {
+            let peer = find_peer(&peers, &peer_id).await?;
+            let protocol_version = *peer.protocol_version.read().await;
+            let responses = match msg.msg_type {
+                ProtocolMessageTypes::RequestPlots => vec![
+                    ChiaMessage::new(
+                        ProtocolMessageTypes::RejectBlock,
+                        protocol_version,
+                        &RejectBlock { height: 0 },
+                        msg.id,
+                    )?,
+                    ChiaMessage::new(
+                        ProtocolMessageTypes::RespondPlots,
+                        protocol_version,
+                        &RespondPlots {
+                            plots: vec![],
+                            failed_to_open_filenames: vec![format!("{:?}.plot", msg.id)],
+                            no_key_filenames: vec![],
+                        },
+                        msg.id,
+                    )?,
+                ],
+                ProtocolMessageTypes::RequestBlock => {
+                    let request =
+                        RequestBlock::from_bytes(&mut Cursor::new(&msg.data), protocol_version)?;
+                    vec![ChiaMessage::new(
+                        ProtocolMessageTypes::RejectBlock,
+                        protocol_version,
+                        &RejectBlock {
+                            height: request.height,
+                        },
+                        msg.id,
+                    )?]
+                }
+                _ => return Ok(()),
+            };
+            send_all(&peer, responses).await
+        }
+    }
+    let run = Arc::new(AtomicBool::new(true));
+    let mut handles = handle_all(Arc::new(Responder));
+    handles.insert(
+        Uuid::new_v4(),
+        Arc::new(ChiaMessageHandler::new(
+            Arc::new(ChiaMessageFilter {
+                msg_type: Some(ProtocolMessageTypes::Handshake),
+                id: None,
+                custom_fn: None,
+            }),
+            Arc::new(HandshakeHandle {
+                config: Arc::new(HarvesterServerConfig {
+                    network: "testnet".to_string(),
+                    websocket: WebsocketServerConfig {
+                        host: "127.0.0.1".to_string(),
+                        port: 0,
+                        ssl_info: None,
+                    },
+                }),
+            }),
+        )),
+    );
+    let (port, server_handle) = serve("127.0.0.1", handles, run.clone()).await;
+    let handlers = Arc::new(RwLock::new(HashMap::new()));
+    let client = WsClient::with_ca(
+        client_config("127.0.0.1", port, "testnet"),
+        NodeType::Farmer,
+        handlers.clone(),
+        run.clone(),
+        CHIA_CA_CRT.as_bytes(),
+        CHIA_CA_KEY.as_bytes(),
+        5,
+    )
+    .await
+    .unwrap();
+    // Concurrent requests each receive the response carrying their own message id
+    let (first, second) = tokio::join!(
+        client.request::<RequestPlots, RespondPlots>(
+            ProtocolMessageTypes::RequestPlots,
+            &RequestPlots {},
+            None,
+        ),
+        client.request::<RequestPlots, RespondPlots>(
+            ProtocolMessageTypes::RequestPlots,
+            &RequestPlots {},
+            None,
+        )
+    );
+    let mut answered = vec![
+        first.unwrap().failed_to_open_filenames,
+        second.unwrap().failed_to_open_filenames,
+    ];
+    answered.sort();
+    assert_eq!(
+        answered,
+        vec![
+            vec!["Some(0).plot".to_string()],
+            vec!["Some(1).plot".to_string()]
+        ]
+    );
+    let rejected = client
+        .request::<RequestBlock, RespondBlock>(
+            ProtocolMessageTypes::RequestBlock,
+            &RequestBlock {
+                height: 5,
+                include_transaction_block: false,
+            },
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(rejected.kind(), ErrorKind::NotFound);
+    let not_a_request = client
+        .request::<RespondPlots, RespondPlots>(
+            ProtocolMessageTypes::RespondPlots,
+            &RespondPlots {
+                plots: vec![],
+                failed_to_open_filenames: vec![],
+                no_key_filenames: vec![],
+            },
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(not_a_request.kind(), ErrorKind::InvalidInput);
+    let request_blocks = RequestBlocks {
+        start_height: 0,
+        end_height: 1,
+        include_transaction_block: false,
+    };
+    let timed_out = client
+        .request::<RequestBlocks, RespondBlocks>(
+            ProtocolMessageTypes::RequestBlocks,
+            &request_blocks,
+            Some(200),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(timed_out.kind(), ErrorKind::TimedOut);
+    // Dropping a pending request removes its response handler
+    assert!(tokio::time::timeout(
+        Duration::from_millis(200),
+        client.request::<RequestBlocks, RespondBlocks>(
+            ProtocolMessageTypes::RequestBlocks,
+            &request_blocks,
+            None,
+        ),
+    )
+    .await
+    .is_err());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(handlers.read().await.is_empty());
+    run.store(false, Ordering::Relaxed);
+    client.connection.write().await.shutdown().await.unwrap();
+    client.join().await.unwrap();
+    server_handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_fullnode_client() {
+    use dg_xch_clients::websocket::full_node::FullnodeClient;
+    use dg_xch_core::blockchain::class_group_element::ClassgroupElement;
+    use dg_xch_core::blockchain::peer_info::TimestampedPeerInfo;
+    use dg_xch_core::blockchain::spend_bundle::SpendBundle;
+    use dg_xch_core::blockchain::unsized_bytes::UnsizedBytes;
+    use dg_xch_core::blockchain::vdf_info::VdfInfo;
+    use dg_xch_core::blockchain::vdf_proof::VdfProof;
+    use dg_xch_core::protocols::full_node::{
+        NewPeak, NewTransaction, RequestCompactVDF, RequestTransaction, RespondCompactVDF,
+        RespondPeers, RespondTransaction,
+    };
+    use tokio::sync::Mutex;
+
+    /// Full node without a chain, it keeps pushed transactions in its mempool and announces
+    /// them with a new peak.
+    #[derive(Default)]
+    struct MockFullNode {
+        block_requests: Mutex<Vec<(u32, u32)>>,
+        mempool: Mutex<HashMap<Bytes32, SpendBundle>>,
+    }
+    #[async_trait]
+    impl MessageHandler for MockFullNode {
+        async fn handle(
+            &self,
+            msg: Arc<ChiaMessage>,
+            peer_id: Arc<Bytes32>,
+            peers: PeerMap,
+        ) -> Result<(), Error> {
+            let peer = find_peer(&peers, &peer_id).await?;
+            let protocol_version = *peer.protocol_version.read().await;
+            let mut cursor = Cursor::new(&msg.data);
+            let mut responses = vec![];
+            match msg.msg_type {
+                ProtocolMessageTypes::Handshake => responses.push(handshake(
+                    &msg,
+                    "simulator",
+                    NodeType::FullNode,
+                    protocol_version,
+                )?),
+                ProtocolMessageTypes::RequestBlock => {
+                    let request = RequestBlock::from_bytes(&mut cursor, protocol_version)?;
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::RejectBlock,
+                        protocol_version,
+                        &RejectBlock {
+                            height: request.height,
+                        },
+                        msg.id,
+                    )?);
+                }
+                ProtocolMessageTypes::RequestBlocks => {
+                    let request = RequestBlocks::from_bytes(&mut cursor, protocol_version)?;
+                    self.block_requests
+                        .lock()
+                        .await
+                        .push((request.start_height, request.end_height));
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::RespondBlocks,
+                        protocol_version,
+                        &RespondBlocks {
+                            start_height: request.start_height,
+                            end_height: request.end_height,
+                            blocks: vec![],
+                        },
+                        msg.id,
+                    )?);
+                }
+                ProtocolMessageTypes::RespondTransaction => {
+                    let transaction =
+                        RespondTransaction::from_bytes(&mut cursor, protocol_version)?.transaction;
+                    let transaction_id = transaction.name()?;
+                    self.mempool
+                        .lock()
+                        .await
+                        .insert(transaction_id, transaction);
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::NewTransaction,
+                        protocol_version,
+                        &NewTransaction {
+                            transaction_id,
+                            cost: 0,
+                            fees: 0,
+                        },
+                        None,
+                    )?);
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::NewPeak,
+                        protocol_version,
+                        &NewPeak {
+                            header_hash: transaction_id,
+                            height: 1,
+                            weight: 2,
+                            fork_point_with_previous_peak: 0,
+                            unfinished_reward_block_hash: Bytes32::default(),
+                        },
+                        None,
+                    )?);
+                }
+                ProtocolMessageTypes::RequestTransaction => {
+                    let request = RequestTransaction::from_bytes(&mut cursor, protocol_version)?;
+                    if let Some(transaction) =
+                        self.mempool.lock().await.get(&request.transaction_id)
+                    {
+                        responses.push(ChiaMessage::new(
+                            ProtocolMessageTypes::RespondTransaction,
+                            protocol_version,
+                            &RespondTransaction {
+                                transaction: transaction.clone(),
+                            },
+                            msg.id,
+                        )?);
+                    }
+                }
+                ProtocolMessageTypes::RequestPeers => responses.push(ChiaMessage::new(
+                    ProtocolMessageTypes::RespondPeers,
+                    protocol_version,
+                    &RespondPeers {
+                        peer_list: vec![TimestampedPeerInfo {
+                            host: "127.0.0.1".to_string(),
+                            port: 8444,
+                            timestamp: 1,
+                        }],
+                    },
+                    msg.id,
+                )?),
+                ProtocolMessageTypes::RequestCompactVdf => {
+                    let request = RequestCompactVDF::from_bytes(&mut cursor, protocol_version)?;
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::RespondCompactVdf,
+                        protocol_version,
+                        &RespondCompactVDF {
+                            height: request.height,
+                            header_hash: request.header_hash,
+                            field_vdf: request.field_vdf,
+                            vdf_info: request.vdf_info,
+                            vdf_proof: VdfProof {
+                                witness_type: 0,
+                                witness: UnsizedBytes::new(&[1, 2, 3]),
+                                normalized_to_identity: true,
+                            },
+                        },
+                        msg.id,
+                    )?);
+                }
+                _ => {}
+            }
+            send_all(&peer, responses).await
+        }
+    }
+    let full_node = Arc::new(MockFullNode::default());
+    let run = Arc::new(AtomicBool::new(true));
+    let (port, server_handle) =
+        serve("127.0.0.1", handle_all(full_node.clone()), run.clone()).await;
+    let client = FullnodeClient::new(
+        client_config("127.0.0.1", port, "simulator"),
+        run.clone(),
+        None,
+        5,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        client.client.handshake.as_ref().map(|h| h.node_type),
+        Some(NodeType::FullNode as u8)
+    );
+    // 32 blocks per request on the simulator
+    assert!(client
+        .request_blocks(10, 80, false)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        *full_node.block_requests.lock().await,
+        vec![(10, 41), (42, 73), (74, 80)]
+    );
+    assert_eq!(
+        client.request_blocks(5, 4, false).await.unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        client.request_block(3, true).await.unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+    let mut peaks = client.subscribe_new_peaks().await;
+    let mut transactions = client.subscribe_new_transactions().await;
+    let transaction = SpendBundle::default();
+    let transaction_id = transaction.name().unwrap();
+    client.push_transaction(transaction.clone()).await.unwrap();
+    let timeout = Duration::from_secs(5);
+    let announced = tokio::time::timeout(timeout, transactions.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(announced.transaction_id, transaction_id);
+    let peak = tokio::time::timeout(timeout, peaks.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(peak.height, 1);
+    assert_eq!(
+        client.request_transaction(transaction_id).await.unwrap(),
+        transaction
+    );
+    assert_eq!(
+        client.request_peers().await.unwrap(),
+        vec![TimestampedPeerInfo {
+            host: "127.0.0.1".to_string(),
+            port: 8444,
+            timestamp: 1,
+        }]
+    );
+    let vdf_info = VdfInfo {
+        challenge: Bytes32::new([1u8; 32]),
+        number_of_iterations: 100,
+        output: ClassgroupElement {
+            data: Default::default(),
+        },
+    };
+    let compact = client
+        .request_compact_vdf(&RequestCompactVDF {
+            height: 7,
+            header_hash: Bytes32::new([2u8; 32]),
+            field_vdf: 1,
+            vdf_info,
+        })
+        .await
+        .unwrap();
+    assert_eq!(compact.height, 7);
+    assert_eq!(compact.vdf_info, vdf_info);
+    assert!(compact.vdf_proof.normalized_to_identity);
+    drop(peaks);
+    drop(transactions);
+    run.store(false, Ordering::Relaxed);
+    client.join().await.unwrap();
+    server_handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_wallet_client() {
+    use dg_xch_clients::websocket::wallet::{apply_coin_state_update, WalletClient};
+    use dg_xch_core::blockchain::coin::Coin;
+    use dg_xch_core::blockchain::spend_bundle::SpendBundle;
+    use dg_xch_core::blockchain::tx_status::TXStatus;
+    use dg_xch_core::clvm::program::SerializedProgram;
+    use dg_xch_core::protocols::wallet::{
+        CoinState, CoinStateUpdate, NewPeakWallet, PuzzleSolutionResponse, RegisterForPhUpdates,
+        RejectPuzzleSolution, RequestChildren, RequestPuzzleSolution,
+        RequestRemovePuzzleSubscriptions, RespondChildren, RespondPuzzleSolution,
+        RespondRemovePuzzleSubscriptions, RespondToPhUpdates, SendTransaction, TransactionAck,
+    };
+    use tokio::sync::Mutex;
+
+    fn coin(puzzle_hash: Bytes32) -> Coin {
+        Coin {
+            parent_coin_info: Bytes32::default(),
+            puzzle_hash,
+            amount: 1000,
+        }
+    }
+    fn child(parent: &Coin) -> Coin {
+        Coin {
+            parent_coin_info: parent.name(),
+            puzzle_hash: parent.puzzle_hash,
+            amount: 900,
+        }
+    }
+
+    /// Full node serving light wallets, every registered puzzle hash has one coin created at
+    /// height 5. A sent transaction spends those coins at height 11 and is then reorged out.
+    #[derive(Default)]
+    struct MockFullNode {
+        puzzle_hashes: Mutex<Vec<Bytes32>>,
+    }
+    #[async_trait]
+    impl MessageHandler for MockFullNode {
+        async fn handle(
+            &self,
+            msg: Arc<ChiaMessage>,
+            peer_id: Arc<Bytes32>,
+            peers: PeerMap,
+        ) -> Result<(), Error> {
+            let peer = find_peer(&peers, &peer_id).await?;
+            let protocol_version = *peer.protocol_version.read().await;
+            let mut cursor = Cursor::new(&msg.data);
+            let mut responses = vec![];
+            match msg.msg_type {
+                ProtocolMessageTypes::Handshake => responses.push(handshake(
+                    &msg,
+                    "simulator",
+                    NodeType::FullNode,
+                    protocol_version,
+                )?),
+                ProtocolMessageTypes::RegisterInterestInPuzzleHash => {
+                    let request = RegisterForPhUpdates::from_bytes(&mut cursor, protocol_version)?;
+                    self.puzzle_hashes
+                        .lock()
+                        .await
+                        .extend(request.puzzle_hashes.iter().copied());
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::RespondToPhUpdate,
+                        protocol_version,
+                        &RespondToPhUpdates {
+                            coin_states: request
+                                .puzzle_hashes
+                                .iter()
+                                .map(|ph| CoinState {
+                                    coin: coin(*ph),
+                                    spent_height: None,
+                                    created_height: Some(5),
+                                })
+                                .collect(),
+                            puzzle_hashes: request.puzzle_hashes,
+                            min_height: request.min_height,
+                        },
+                        msg.id,
+                    )?);
+                }
+                ProtocolMessageTypes::RequestRemovePuzzleSubscriptions => {
+                    let request = RequestRemovePuzzleSubscriptions::from_bytes(
+                        &mut cursor,
+                        protocol_version,
+                    )?;
+                    let mut puzzle_hashes = self.puzzle_hashes.lock().await;
+                    let removed = match request.puzzle_hashes {
+                        Some(removed) => {
+                            puzzle_hashes.retain(|ph| !removed.contains(ph));
+                            removed
+                        }
+                        None => std::mem::take(&mut *puzzle_hashes),
+                    };
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::RespondRemovePuzzleSubscriptions,
+                        protocol_version,
+                        &RespondRemovePuzzleSubscriptions {
+                            puzzle_hashes: removed,
+                        },
+                        msg.id,
+                    )?);
+                }
+                ProtocolMessageTypes::RequestChildren => {
+                    let request = RequestChildren::from_bytes(&mut cursor, protocol_version)?;
+                    let coin_states = self
+                        .puzzle_hashes
+                        .lock()
+                        .await
+                        .iter()
+                        .map(|ph| coin(*ph))
+                        .filter(|c| c.name() == request.coin_name)
+                        .map(|c| CoinState {
+                            coin: child(&c),
+                            spent_height: None,
+                            created_height: Some(11),
+                        })
+                        .collect();
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::RespondChildren,
+                        protocol_version,
+                        &RespondChildren { coin_states },
+                        msg.id,
+                    )?);
+                }
+                ProtocolMessageTypes::RequestPuzzleSolution => {
+                    let request = RequestPuzzleSolution::from_bytes(&mut cursor, protocol_version)?;
+                    if request.height == 11 {
+                        responses.push(ChiaMessage::new(
+                            ProtocolMessageTypes::RespondPuzzleSolution,
+                            protocol_version,
+                            &RespondPuzzleSolution {
+                                response: PuzzleSolutionResponse {
+                                    coin_name: request.coin_name,
+                                    height: request.height,
+                                    puzzle: SerializedProgram::from_bytes(&[0x01]),
+                                    solution: SerializedProgram::from_bytes(&[0x80]),
+                                },
+                            },
+                            msg.id,
+                        )?);
+                    } else {
+                        responses.push(ChiaMessage::new(
+                            ProtocolMessageTypes::RejectPuzzleSolution,
+                            protocol_version,
+                            &RejectPuzzleSolution {
+                                coin_name: request.coin_name,
+                                height: request.height,
+                            },
+                            msg.id,
+                        )?);
+                    }
+                }
+                ProtocolMessageTypes::SendTransaction => {
+                    let transaction =
+                        SendTransaction::from_bytes(&mut cursor, protocol_version)?.transaction;
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::TransactionAck,
+                        protocol_version,
+                        &TransactionAck {
+                            txid: transaction.name()?,
+                            status: TXStatus::SUCCESS,
+                            error: None,
+                        },
+                        msg.id,
+                    )?);
+                    let items = self
+                        .puzzle_hashes
+                        .lock()
+                        .await
+                        .iter()
+                        .map(|ph| CoinState {
+                            coin: coin(*ph),
+                            spent_height: Some(11),
+                            created_height: Some(5),
+                        })
+                        .collect();
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::CoinStateUpdate,
+                        protocol_version,
+                        &CoinStateUpdate {
+                            height: 11,
+                            fork_height: 10,
+                            peak_hash: Bytes32::new([11u8; 32]),
+                            items,
+                        },
+                        None,
+                    )?);
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::NewPeakWallet,
+                        protocol_version,
+                        &NewPeakWallet {
+                            header_hash: Bytes32::new([11u8; 32]),
+                            height: 11,
+                            weight: 11,
+                            fork_point_with_previous_peak: 10,
+                        },
+                        None,
+                    )?);
+                    // A heavier chain forking at height 9 replaces block 11
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::CoinStateUpdate,
+                        protocol_version,
+                        &CoinStateUpdate {
+                            height: 12,
+                            fork_height: 9,
+                            peak_hash: Bytes32::new([12u8; 32]),
+                            items: vec![],
+                        },
+                        None,
+                    )?);
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::NewPeakWallet,
+                        protocol_version,
+                        &NewPeakWallet {
+                            header_hash: Bytes32::new([12u8; 32]),
+                            height: 12,
+                            weight: 13,
+                            fork_point_with_previous_peak: 9,
+                        },
+                        None,
+                    )?);
+                }
+                _ => {}
+            }
+            send_all(&peer, responses).await
+        }
+    }
+    let run = Arc::new(AtomicBool::new(true));
+    let (port, server_handle) = serve(
+        "127.0.0.1",
+        handle_all(Arc::new(MockFullNode::default())),
+        run.clone(),
+    )
+    .await;
+    let client = WalletClient::new(
+        client_config("127.0.0.1", port, "simulator"),
+        run.clone(),
+        5,
+    )
+    .await
+    .unwrap();
+    let puzzle_hashes = vec![Bytes32::new([1u8; 32]), Bytes32::new([2u8; 32])];
+    let mut coin_states: HashMap<Bytes32, CoinState> = client
+        .register_puzzle_hashes(puzzle_hashes.clone(), 0)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|state| (state.coin.name(), state))
+        .collect();
+    assert_eq!(coin_states.len(), 2);
+    let first = coin(puzzle_hashes[0]);
+    assert_eq!(
+        client.request_children(first.name()).await.unwrap(),
+        vec![CoinState {
+            coin: child(&first),
+            spent_height: None,
+            created_height: Some(11),
+        }]
+    );
+    let solution = client
+        .request_puzzle_solution(first.name(), 11)
+        .await
+        .unwrap();
+    assert_eq!(solution.coin_name, first.name());
+    assert_eq!(
+        client
+            .request_puzzle_solution(first.name(), 4)
+            .await
+            .unwrap_err()
+            .kind(),
+        ErrorKind::NotFound
+    );
+    let mut updates = client.subscribe_coin_states().await;
+    let mut peaks = client.subscribe_peaks().await;
+    let ack = client
+        .send_transaction(SpendBundle::default())
+        .await
+        .unwrap();
+    assert_eq!(ack.txid, SpendBundle::default().name().unwrap());
+    assert_eq!(ack.status, TXStatus::SUCCESS);
+    let timeout = Duration::from_secs(5);
+    let update = tokio::time::timeout(timeout, updates.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    apply_coin_state_update(&mut coin_states, &update);
+    assert!(coin_states.values().all(|s| s.spent_height == Some(11)));
+    let peak = tokio::time::timeout(timeout, peaks.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(peak.peak.height, 11);
+    assert!(!peak.reorg);
+    let update = tokio::time::timeout(timeout, updates.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    apply_coin_state_update(&mut coin_states, &update);
+    assert_eq!(coin_states.len(), 2);
+    assert!(coin_states.values().all(|s| s.spent_height.is_none()));
+    let peak = tokio::time::timeout(timeout, peaks.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(peak.peak.height, 12);
+    assert!(peak.reorg);
+    let mut tracked = None;
+    for _ in 0..50 {
+        tracked = client.peak.read().await.clone();
+        if tracked.as_ref().is_some_and(|p| p.height == 12) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(tracked, Some(peak.peak));
+    assert_eq!(
+        client
+            .remove_puzzle_subscriptions(Some(vec![puzzle_hashes[0]]))
+            .await
+            .unwrap(),
+        vec![puzzle_hashes[0]]
+    );
+    assert_eq!(
+        client.remove_puzzle_subscriptions(None).await.unwrap(),
+        vec![puzzle_hashes[1]]
+    );
+    drop(updates);
+    drop(peaks);
+    run.store(false, Ordering::Relaxed);
+    client.join().await.unwrap();
+    server_handle.await.unwrap().unwrap();
+}
+
+// Several workers, so handlers run in parallel tasks could overtake each other
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_wallet_subscription_order() {
+    use dg_xch_clients::websocket::wallet::WalletClient;
+    use dg_xch_core::protocols::wallet::{
+        CoinStateUpdate, NewPeakWallet, RegisterForPhUpdates, RespondToPhUpdates,
+    };
+    const UPDATES: u32 = 200;
+
+    /// Answers a puzzle hash registration with `UPDATES` back to back coin state updates and
+    /// peaks, each building on the one before.
+    struct BurstNode;
+    #[async_trait]
+    impl MessageHandler for BurstNode {
+        async fn handle(
+            &self,
+            msg: Arc<ChiaMessage>,
+            peer_id: Arc<Bytes32>,
+            peers: PeerMap,
+        ) -> Result<(), Error> {
+            let peer = find_peer(&peers, &peer_id).await?;
+            let protocol_version = *peer.protocol_version.read().await;
+            let mut responses = vec![];
+            match msg.msg_type {
+                ProtocolMessageTypes::Handshake => responses.push(handshake(
+                    &msg,
+                    "simulator",
+                    NodeType::FullNode,
+                    protocol_version,
+                )?),
+                ProtocolMessageTypes::RegisterInterestInPuzzleHash => {
+                    let request = RegisterForPhUpdates::from_bytes(
+                        &mut Cursor::new(&msg.data),
+                        protocol_version,
+                    )?;
+                    responses.push(ChiaMessage::new(
+                        ProtocolMessageTypes::RespondToPhUpdate,
+                        protocol_version,
+                        &RespondToPhUpdates {
+                            puzzle_hashes: request.puzzle_hashes,
+                            min_height: request.min_height,
+                            coin_states: vec![],
+                        },
+                        msg.id,
+                    )?);
+                    for height in 1..=UPDATES {
+                        responses.push(ChiaMessage::new(
+                            ProtocolMessageTypes::CoinStateUpdate,
+                            protocol_version,
+                            &CoinStateUpdate {
+                                height,
+                                fork_height: height - 1,
+                                peak_hash: Bytes32::default(),
+                                items: vec![],
+                            },
+                            None,
+                        )?);
+                        responses.push(ChiaMessage::new(
+                            ProtocolMessageTypes::NewPeakWallet,
+                            protocol_version,
+                            &NewPeakWallet {
+                                header_hash: Bytes32::default(),
+                                height,
+                                weight: u128::from(height),
+                                fork_point_with_previous_peak: height - 1,
+                            },
+                            None,
+                        )?);
+                    }
+                }
+                _ => {}
+            }
+            send_all(&peer, responses).await
+        }
+    }
+    let run = Arc::new(AtomicBool::new(true));
+    let (port, server_handle) =
+        serve("127.0.0.1", handle_all(Arc::new(BurstNode)), run.clone()).await;
+    let client = WalletClient::new(
+        client_config("127.0.0.1", port, "simulator"),
+        run.clone(),
+        5,
+    )
+    .await
+    .unwrap();
+    let mut updates = client.subscribe_coin_states().await;
+    let mut peaks = client.subscribe_peaks().await;
+    client
+        .register_puzzle_hashes(vec![Bytes32::new([1u8; 32])], 0)
+        .await
+        .unwrap();
+    let timeout = Duration::from_secs(5);
+    for height in 1..=UPDATES {
+        let update = tokio::time::timeout(timeout, updates.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(update.height, height);
+        let peak = tokio::time::timeout(timeout, peaks.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(peak.peak.height, height);
+        assert!(!peak.reorg);
+    }
+    drop(updates);
+    drop(peaks);
+    run.store(false, Ordering::Relaxed);
+    client.join().await.unwrap();
+    server_handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_peer_manager() {
+    use dg_xch_clients::websocket::peer_manager::address_book::{AddressBook, PeerAddress};
+    use dg_xch_clients::websocket::peer_manager::{PeerEvent, PeerManager, PeerManagerConfig};
+    use dg_xch_core::blockchain::peer_info::TimestampedPeerInfo;
+    use dg_xch_core::protocols::full_node::{RequestPeers, RespondPeers};
+    use dg_xch_core::protocols::introducer::RespondPeersIntroducer;
+
+    /// Peer that only handshakes and answers peer requests
+    struct MockPeer {
+        network_id: String,
+        node_type: NodeType,
+        peer_list: Vec<TimestampedPeerInfo>,
+        /// Answers `RequestPeers` with a body that does not parse
+        misbehave: bool,
+    }
+    #[async_trait]
+    impl MessageHandler for MockPeer {
+        async fn handle(
+            &self,
+            msg: Arc<ChiaMessage>,
+            peer_id: Arc<Bytes32>,
+            peers: PeerMap,
+        ) -> Result<(), Error> {
+            let peer = find_peer(&peers, &peer_id).await?;
+            let protocol_version = *peer.protocol_version.read().await;
+            let response = match msg.msg_type {
+                ProtocolMessageTypes::Handshake => {
+                    handshake(&msg, &self.network_id, self.node_type, protocol_version)?
+                }
+                ProtocolMessageTypes::RequestPeersIntroducer => ChiaMessage::new(
+                    ProtocolMessageTypes::RespondPeersIntroducer,
+                    protocol_version,
+                    &RespondPeersIntroducer {
+                        peer_list: self.peer_list.clone(),
+                    },
+                    msg.id,
+                )?,
+                ProtocolMessageTypes::RequestPeers if self.misbehave => ChiaMessage::new(
+                    ProtocolMessageTypes::RespondPeers,
+                    protocol_version,
+                    &RequestPeers {},
+                    msg.id,
+                )?,
+                ProtocolMessageTypes::RequestPeers => ChiaMessage::new(
+                    ProtocolMessageTypes::RespondPeers,
+                    protocol_version,
+                    &RespondPeers {
+                        peer_list: self.peer_list.clone(),
+                    },
+                    msg.id,
+                )?,
+                _ => return Ok(()),
+            };
+            send_all(&peer, vec![response]).await
+        }
+    }
+    let server_run = Arc::new(AtomicBool::new(true));
+    let mock = |network_id: &str, node_type: NodeType, misbehave: bool| {
+        handle_all(Arc::new(MockPeer {
+            network_id: network_id.to_string(),
+            node_type,
+            peer_list: vec![],
+            misbehave,
+        }))
+    };
+    let full_node = |network_id: &str| mock(network_id, NodeType::FullNode, false);
+    // Bans are per host, every peer gets its own loopback address
+    let (port, full_node_a_handle) =
+        serve("127.0.0.1", full_node("simulator"), server_run.clone()).await;
+    let full_node_a = PeerAddress::new("127.0.0.1", port);
+    let (port, full_node_b_handle) =
+        serve("127.0.0.2", full_node("simulator"), server_run.clone()).await;
+    let full_node_b = PeerAddress::new("127.0.0.2", port);
+    let (port, wrong_network_handle) =
+        serve("127.0.0.3", full_node("mainnet"), server_run.clone()).await;
+    let wrong_network = PeerAddress::new("127.0.0.3", port);
+    let (port, wallet_handle) = serve(
+        "127.0.0.5",
+        mock("simulator", NodeType::Wallet, false),
+        server_run.clone(),
+    )
+    .await;
+    let wallet = PeerAddress::new("127.0.0.5", port);
+    let (port, misbehaving_handle) = serve(
+        "127.0.0.6",
+        mock("simulator", NodeType::FullNode, true),
+        server_run.clone(),
+    )
+    .await;
+    let misbehaving = PeerAddress::new("127.0.0.6", port);
+    let (port, introducer_handle) = serve(
+        "127.0.0.4",
+        handle_all(Arc::new(MockPeer {
+            network_id: "simulator".to_string(),
+            node_type: NodeType::Introducer,
+            peer_list: [&full_node_b, &wrong_network, &wallet, &misbehaving]
+                .iter()
+                .map(|p| TimestampedPeerInfo {
+                    host: p.host.clone(),
+                    port: p.port,
+                    timestamp: 1,
+                })
+                .collect(),
+            misbehave: false,
+        })),
+        server_run.clone(),
+    )
+    .await;
+    let introducer = PeerAddress::new("127.0.0.4", port);
+    let address_book_path =
+        std::env::temp_dir().join(format!("peer_manager_{}.json", Uuid::new_v4()));
+    let manager = Arc::new(
+        PeerManager::new(
+            PeerManagerConfig {
+                network_id: "simulator".to_string(),
+                node_type: NodeType::Wallet,
+                // More than there are peers, every address gets tried
+                outbound_targets: HashMap::from([(NodeType::FullNode, 4)]),
+                introducer: Some(introducer.clone()),
+                dns_seeders: vec!["localhost".to_string()],
+                default_port: full_node_a.port,
+                address_book_path: Some(address_book_path.clone()),
+                connect_timeout: 5,
+                min_backoff: Duration::from_secs(60),
+                maintain_interval: Duration::from_millis(100),
+                ..Default::default()
+            },
+            HashMap::new(),
+        )
+        .await
+        .unwrap(),
+    );
+    let mut events = manager.subscribe();
+    let run = Arc::new(AtomicBool::new(true));
+    let manager_handle = {
+        let manager = manager.clone();
+        let run = run.clone();
+        tokio::spawn(async move { manager.run(run).await })
+    };
+    let expected = [
+        PeerEvent::Connected {
+            peer: full_node_a.clone(),
+            node_type: NodeType::FullNode,
+        },
+        PeerEvent::Connected {
+            peer: full_node_b.clone(),
+            node_type: NodeType::FullNode,
+        },
+        PeerEvent::Banned {
+            peer: wrong_network.clone(),
+            reason: "Peer is on network mainnet".to_string(),
+        },
+    ];
+    let misbehaving_banned = |events: &[PeerEvent]| {
+        events
+            .iter()
+            .any(|e| matches!(e, PeerEvent::Banned { peer, .. } if *peer == misbehaving))
+    };
+    let mut received = vec![];
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while !expected.iter().all(|e| received.contains(e)) || !misbehaving_banned(&received) {
+            received.push(events.recv().await.unwrap());
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("Missing peer events, received {received:?}"));
+    assert_eq!(manager.connected(NodeType::FullNode).await.len(), 2);
+    assert!(manager.is_banned(&wrong_network.host).await);
+    assert!(manager.is_banned(&misbehaving.host).await);
+    // Not needed, so closed again and backing off instead of being dialed every round
+    assert!(!received
+        .iter()
+        .any(|e| matches!(e, PeerEvent::Connected { peer, .. } if *peer == wallet)));
+    let wallet_attempts = || async {
+        manager
+            .address_book
+            .read()
+            .await
+            .get(&wallet)
+            .map(|i| i.attempts)
+    };
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while wallet_attempts().await != Some(1) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("Surplus peer was never dialed");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    {
+        let book = manager.address_book.read().await;
+        assert!(book.get(&full_node_a).is_some_and(|i| i.tried));
+        assert!(book.get(&full_node_b).is_some_and(|i| i.tried));
+        assert!(book.get(&wrong_network).is_none());
+        assert!(book.get(&misbehaving).is_none());
+        assert!(book
+            .get(&wallet)
+            .is_some_and(|i| !i.tried && i.attempts == 1));
+    }
+    manager.ban(&full_node_a, "misbehaving").await;
+    assert_eq!(
+        events.recv().await.unwrap(),
+        PeerEvent::Disconnected {
+            peer: full_node_a.clone(),
+            node_type: NodeType::FullNode,
+        }
+    );
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        PeerEvent::Banned { peer, .. } if peer == full_node_a
+    ));
+    // Banned peers are not connected again
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let connected = manager.connected(NodeType::FullNode).await;
+    assert_eq!(connected.len(), 1);
+    assert_eq!(connected[0].peer, full_node_b);
+    run.store(false, Ordering::Relaxed);
+    manager_handle.await.unwrap().unwrap();
+    assert!(manager.connected(NodeType::FullNode).await.is_empty());
+    let book = AddressBook::load(&address_book_path).await.unwrap();
+    assert!(book.get(&full_node_b).is_some_and(|i| i.tried));
+    assert!(book.get(&full_node_a).is_none());
+    let _ = std::fs::remove_file(&address_book_path);
+    server_run.store(false, Ordering::Relaxed);
+    for server in [
+        full_node_a_handle,
+        full_node_b_handle,
+        wrong_network_handle,
+        wallet_handle,
+        misbehaving_handle,
+        introducer_handle,
+    ] {
+        server.await.unwrap().unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_server_reconnect() {
+    use dg_xch_core::ssl::generate_ca_signed_cert_data;
+
+    /// Handshakes and answers `RequestPlots`
+    struct Harvester;
+    #[async_trait]
+    impl MessageHandler for Harvester {
+        async fn handle(
+            &self,
+            msg: Arc<ChiaMessage>,
+            peer_id: Arc<Bytes32>,
+            peers: PeerMap,
+        ) -> Result<(), Error> {
+            let peer = find_peer(&peers, &peer_id).await?;
+            let protocol_version = *peer.protocol_version.read().await;
+            let response = match msg.msg_type {
+                ProtocolMessageTypes::Handshake => {
+                    handshake(&msg, "testnet", NodeType::Harvester, protocol_version)?
+                }
+                ProtocolMessageTypes::RequestPlots => ChiaMessage::new(
+                    ProtocolMessageTypes::RespondPlots,
+                    protocol_version,
+                    &RespondPlots {
+                        plots: vec![],
+                        failed_to_open_filenames: vec![],
+                        no_key_filenames: vec![],
+                    },
+                    msg.id,
+                )?,
+                _ => return Ok(()),
+            };
+            send_all(&peer, vec![response]).await
+        }
+    }
+    let (listener, port) = bind_free_port("127.0.0.1").await;
+    let peers = PeerMap::default();
+    let server = WebsocketServer::new(
+        &WebsocketServerConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            ssl_info: None,
+        },
+        peers.clone(),
+        Arc::new(RwLock::new(handle_all(Arc::new(Harvester)))),
+    )
+    .unwrap();
+    let run = Arc::new(AtomicBool::new(true));
+    let server_run = run.clone();
+    let server_handle = tokio::spawn(async move { server.serve(listener, server_run).await });
+    // Both connections use the same certificate and so the same peer id
+    let (cert, key) =
+        generate_ca_signed_cert_data(CHIA_CA_CRT.as_bytes(), CHIA_CA_KEY.as_bytes()).unwrap();
+    let connect = || {
+        WsClient::with_cert(
+            client_config("127.0.0.1", port, "testnet"),
+            NodeType::Farmer,
+            Arc::default(),
+            run.clone(),
+            &cert,
+            &key,
+            5,
+        )
+    };
+    let first = connect().await.unwrap();
+    let first_peer = peers.read().await.values().next().cloned().unwrap();
+    let second = connect().await.unwrap();
+    // The server closes the replaced connection, its cleanup must leave the new entry alone
+    for _ in 0..50 {
+        if first.is_closed() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(first.is_closed());
+    let _ = first.connection.write().await.shutdown().await;
+    first.join().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    {
+        let peers = peers.read().await;
+        assert_eq!(peers.len(), 1);
+        assert!(!Arc::ptr_eq(peers.values().next().unwrap(), &first_peer));
+    }
+    second
+        .request::<RequestPlots, RespondPlots>(
+            ProtocolMessageTypes::RequestPlots,
+            &RequestPlots {},
+            None,
+        )
+        .await
+        .unwrap();
+    // Closing the current connection removes its entry
+    second.connection.write().await.shutdown().await.unwrap();
+    second.join().await.unwrap();
+    for _ in 0..50 {
+        if peers.read().await.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(peers.read().await.is_empty());
+    run.store(false, Ordering::Relaxed);
+    server_handle.await.unwrap().unwrap();
+}
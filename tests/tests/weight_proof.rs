@@ -0,0 +1,528 @@
+use dg_xch_core::blockchain::challenge_chain_subslot::ChallengeChainSubSlot;
+use dg_xch_core::blockchain::class_group_element::ClassgroupElement;
+use dg_xch_core::blockchain::end_of_subslot_bundle::EndOfSubSlotBundle;
+use dg_xch_core::blockchain::header_block::HeaderBlock;
+use dg_xch_core::blockchain::infused_challenge_chain_subslot::InfusedChallengeChainSubSlot;
+use dg_xch_core::blockchain::proof_of_space::{
+    calculate_plot_id_puzzle_hash, calculate_pos_challenge, ProofOfSpace,
+};
+use dg_xch_core::blockchain::reward_chain_subslot::RewardChainSubSlot;
+use dg_xch_core::blockchain::sized_bytes::{Bytes100, Bytes32, Bytes48};
+use dg_xch_core::blockchain::vdf_info::VdfInfo;
+use dg_xch_core::blockchain::vdf_proof::VdfProof;
+use dg_xch_core::blockchain::weight_proof::{
+    SubEpochChallengeSegment, SubEpochData, SubSlotData, WeightProof,
+};
+use dg_xch_core::consensus::constants::ConsensusConstants;
+use dg_xch_core::consensus::weight_proof::{
+    fork_point, map_sub_epoch_summaries, recent_chain_difficulty_and_ssi, segment_block_challenge,
+    segment_rc_sub_slot, validate_weight_proof, WeightProofTracker, WeightProofVerifier,
+};
+use dg_xch_core::traits::SizedBytes;
+use dg_xch_core::utils::hash_256;
+use dg_xch_pos::plots::disk_plot::DiskPlot;
+use dg_xch_pos::plots::plot_reader::PlotReader;
+use dg_xch_pos::verifier::proof_to_bytes;
+use dg_xch_pos::weight_proof::PosWeightProofVerifier;
+use dg_xch_serialize::{ChiaProtocolVersion, ChiaSerialize};
+use lazy_static::lazy_static;
+use std::io::{Cursor, Error, ErrorKind};
+use std::sync::Arc;
+use tokio::fs::File;
+
+lazy_static! {
+    static ref TEST_CONSTANTS: ConsensusConstants = ConsensusConstants {
+        sub_epoch_blocks: 4,
+        difficulty_starting: 3,
+        // Lets a k18 plot win every challenge it has a proof for
+        number_zero_bits_plot_filter: 0,
+        min_plot_size: 18,
+        difficulty_constant_factor: 1 << 20,
+        ..Default::default()
+    };
+}
+
+fn std_hash<T: ChiaSerialize>(value: &T) -> Bytes32 {
+    hash_256(value.to_bytes(ChiaProtocolVersion::default()).unwrap()).into()
+}
+
+/// Value with every field zero, empty or `None`, the validation only looks at a few fields
+fn zeroed<T: ChiaSerialize>() -> T {
+    T::from_bytes(
+        &mut Cursor::new(vec![0u8; 4096]),
+        ChiaProtocolVersion::default(),
+    )
+    .unwrap()
+}
+
+fn vdf_info(challenge: u8, number_of_iterations: u64) -> VdfInfo {
+    VdfInfo {
+        challenge: Bytes32::new([challenge; 32]),
+        number_of_iterations,
+        output: zeroed(),
+    }
+}
+
+fn sub_slot(cc_slot_end: bool, challenge: bool) -> SubSlotData {
+    let mut slot: SubSlotData = zeroed();
+    if cc_slot_end {
+        slot.cc_slot_end = Some(zeroed::<VdfProof>());
+        slot.cc_slot_end_info = Some(vdf_info(1, TEST_CONSTANTS.sub_slot_iters_starting));
+        slot.icc_slot_end_info = Some(vdf_info(2, 1000));
+    }
+    if challenge {
+        slot.proof_of_space = Some(zeroed::<ProofOfSpace>());
+        slot.signage_point_index = Some(0);
+    }
+    slot
+}
+
+/// Weight proof of a chain with three sub epochs, the recent chain holds heights 6 to 12 and
+/// the last summary is included at height 12.
+fn weight_proof() -> WeightProof {
+    let constants = &*TEST_CONSTANTS;
+    let mut sub_epochs = vec![SubEpochData {
+        reward_chain_hash: constants.genesis_challenge,
+        num_blocks_overflow: 0,
+        new_sub_slot_iters: None,
+        new_difficulty: None,
+    }];
+    let sub_epoch_segments = vec![
+        SubEpochChallengeSegment {
+            sub_epoch_n: 0,
+            sub_slots: vec![sub_slot(false, true)],
+            rc_slot_end_info: None,
+        },
+        SubEpochChallengeSegment {
+            sub_epoch_n: 1,
+            sub_slots: vec![sub_slot(true, false), sub_slot(false, true)],
+            rc_slot_end_info: Some(vdf_info(3, 1000)),
+        },
+    ];
+    let (summaries, _) = map_sub_epoch_summaries(constants, &sub_epochs).unwrap();
+    let rc_sub_slot = segment_rc_sub_slot(
+        constants,
+        &sub_epoch_segments[1],
+        &summaries,
+        constants.sub_slot_iters_starting,
+    )
+    .unwrap();
+    sub_epochs.push(SubEpochData {
+        reward_chain_hash: std_hash(&rc_sub_slot),
+        num_blocks_overflow: 0,
+        new_sub_slot_iters: None,
+        new_difficulty: None,
+    });
+    sub_epochs.push(SubEpochData {
+        reward_chain_hash: Bytes32::new([4u8; 32]),
+        num_blocks_overflow: 1,
+        new_sub_slot_iters: None,
+        new_difficulty: None,
+    });
+    let (summaries, weights) = map_sub_epoch_summaries(constants, &sub_epochs).unwrap();
+    // The last sub epoch ends at height 8, its start weight counts the block after it
+    let end_weight = weights[weights.len() - 1] - u128::from(constants.difficulty_starting);
+    let mut recent_chain_data: Vec<HeaderBlock> = vec![];
+    for height in 6u32..=12 {
+        let mut block: HeaderBlock = zeroed();
+        block.reward_chain_block.height = height;
+        block.reward_chain_block.weight =
+            end_weight + u128::from(constants.difficulty_starting) * u128::from(height) - 24;
+        block.reward_chain_block.total_iters = u128::from(height) * 1000;
+        if let Some(prev) = recent_chain_data.last() {
+            block.foliage.prev_block_hash = prev.header_hash().unwrap();
+        }
+        if height == 12 {
+            let mut end_of_slot: EndOfSubSlotBundle = zeroed();
+            end_of_slot.challenge_chain.subepoch_summary_hash =
+                Some(std_hash(&summaries[summaries.len() - 1]));
+            block.finished_sub_slots.push(end_of_slot);
+        }
+        recent_chain_data.push(block);
+    }
+    WeightProof {
+        sub_epochs,
+        sub_epoch_segments,
+        recent_chain_data,
+    }
+}
+
+#[test]
+fn test_map_sub_epoch_summaries() {
+    let constants = &*TEST_CONSTANTS;
+    let wp = weight_proof();
+    let (summaries, weights) = map_sub_epoch_summaries(constants, &wp.sub_epochs).unwrap();
+    assert_eq!(summaries.len(), 3);
+    assert_eq!(
+        summaries[0].prev_subepoch_summary_hash,
+        constants.genesis_challenge
+    );
+    assert_eq!(
+        summaries[2].prev_subepoch_summary_hash,
+        std_hash(&summaries[1])
+    );
+    // Difficulty 3, 4 blocks per sub epoch and one overflow block into the last sub epoch
+    assert_eq!(weights, vec![3, 15, 30]);
+}
+
+#[test]
+fn test_segment_rc_sub_slot() {
+    let constants = &*TEST_CONSTANTS;
+    let wp = weight_proof();
+    let (summaries, _) = map_sub_epoch_summaries(constants, &wp.sub_epochs).unwrap();
+    let segment = &wp.sub_epoch_segments[1];
+    let slot_end = &segment.sub_slots[0];
+    let icc_hash = std_hash(&InfusedChallengeChainSubSlot {
+        infused_challenge_chain_end_of_slot_vdf: slot_end.icc_slot_end_info.unwrap(),
+    });
+    let cc_sub_slot = ChallengeChainSubSlot {
+        challenge_chain_end_of_slot_vdf: slot_end.cc_slot_end_info.unwrap(),
+        infused_challenge_chain_sub_slot_hash: Some(icc_hash),
+        subepoch_summary_hash: Some(std_hash(&summaries[0])),
+        new_sub_slot_iters: None,
+        new_difficulty: None,
+    };
+    assert_eq!(
+        segment_rc_sub_slot(
+            constants,
+            segment,
+            &summaries,
+            constants.sub_slot_iters_starting
+        )
+        .unwrap(),
+        RewardChainSubSlot {
+            end_of_slot_vdf: segment.rc_slot_end_info.unwrap(),
+            challenge_chain_sub_slot_hash: std_hash(&cc_sub_slot),
+            infused_challenge_chain_sub_slot_hash: Some(icc_hash),
+            deficit: constants.min_blocks_per_challenge_block,
+        }
+    );
+}
+
+#[test]
+fn test_validate_weight_proof() {
+    let constants = &*TEST_CONSTANTS;
+    let wp = weight_proof();
+    let validated = validate_weight_proof(constants, &wp).unwrap();
+    assert_eq!(validated.summaries.len(), 3);
+    assert_eq!(validated.peak_height, 12);
+    assert_eq!(
+        validated.peak_hash,
+        wp.recent_chain_data[6].header_hash().unwrap()
+    );
+    let mut bad_reward_chain = wp.clone();
+    bad_reward_chain.sub_epochs[1].reward_chain_hash = Bytes32::new([9u8; 32]);
+    // Changing a summary breaks the chain of summary hashes up to the recent chain
+    assert_eq!(
+        validate_weight_proof(constants, &bad_reward_chain)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+    let mut bad_weight = wp.clone();
+    bad_weight.recent_chain_data[2].reward_chain_block.weight += 1;
+    assert!(validate_weight_proof(constants, &bad_weight).is_err());
+    let mut heavy_block = wp.clone();
+    heavy_block.recent_chain_data[6].reward_chain_block.weight +=
+        u128::from(constants.difficulty_starting);
+    assert!(validate_weight_proof(constants, &heavy_block).is_err());
+    let mut bad_segment = wp.clone();
+    bad_segment.sub_epoch_segments[1].rc_slot_end_info = Some(vdf_info(5, 1000));
+    assert!(validate_weight_proof(constants, &bad_segment).is_err());
+    let mut out_of_order = wp.clone();
+    out_of_order.sub_epoch_segments.swap(0, 1);
+    assert!(validate_weight_proof(constants, &out_of_order).is_err());
+    let mut broken_chain = wp.clone();
+    broken_chain.recent_chain_data[4].foliage.prev_block_hash = Bytes32::default();
+    assert!(validate_weight_proof(constants, &broken_chain).is_err());
+    let mut missing_summary = wp;
+    missing_summary.recent_chain_data[6]
+        .finished_sub_slots
+        .clear();
+    assert!(validate_weight_proof(constants, &missing_summary).is_err());
+}
+
+#[test]
+fn test_recent_chain_difficulty() {
+    let constants = &*TEST_CONSTANTS;
+    let mut wp = weight_proof();
+    // The last summary raises the difficulty for the blocks after the sub slot including it
+    wp.sub_epochs[2].new_difficulty = Some(5);
+    wp.sub_epochs[2].new_sub_slot_iters = Some(constants.sub_slot_iters_starting * 2);
+    let (summaries, _) = map_sub_epoch_summaries(constants, &wp.sub_epochs).unwrap();
+    wp.recent_chain_data[6].finished_sub_slots[0]
+        .challenge_chain
+        .subepoch_summary_hash = Some(std_hash(&summaries[2]));
+    assert!(validate_weight_proof(constants, &wp).is_err());
+    wp.recent_chain_data[6].reward_chain_block.weight += 2;
+    let difficulties =
+        recent_chain_difficulty_and_ssi(constants, &wp.recent_chain_data, &summaries).unwrap();
+    assert_eq!(
+        difficulties[5],
+        (
+            constants.difficulty_starting,
+            constants.sub_slot_iters_starting
+        )
+    );
+    assert_eq!(difficulties[6], (5, constants.sub_slot_iters_starting * 2));
+    validate_weight_proof(constants, &wp).unwrap();
+}
+
+/// Proof of space from `reader` for `challenge` and `signage_point`, when the plot has one.
+async fn prove(
+    reader: &PlotReader<File, DiskPlot<File>>,
+    challenge: Bytes32,
+    signage_point: Bytes32,
+) -> Option<ProofOfSpace> {
+    let pool_contract_puzzle_hash = Bytes32::new([1u8; 32]);
+    let plot_public_key = Bytes48::new([2u8; 48]);
+    let plot_id = calculate_plot_id_puzzle_hash(pool_contract_puzzle_hash, plot_public_key);
+    let pos_challenge = calculate_pos_challenge(plot_id, challenge, signage_point);
+    let qualities = reader
+        .fetch_qualities_for_challenge(pos_challenge.as_ref())
+        .await
+        .ok()?;
+    let proof = reader
+        .fetch_ordered_proof(qualities.first()?.0)
+        .await
+        .ok()?;
+    Some(ProofOfSpace {
+        challenge: pos_challenge,
+        pool_public_key: None,
+        pool_contract_puzzle_hash: Some(pool_contract_puzzle_hash),
+        plot_public_key,
+        size: reader.header().k(),
+        proof: proof_to_bytes(&proof).into(),
+    })
+}
+
+/// Signage point VDF with an output derived from `seed`.
+fn sp_vdf_info(seed: u8) -> VdfInfo {
+    VdfInfo {
+        challenge: Bytes32::default(),
+        number_of_iterations: 0,
+        output: ClassgroupElement {
+            data: Bytes100::new([seed; 100]),
+        },
+    }
+}
+
+#[tokio::test]
+async fn test_pos_weight_proof_verifier() {
+    let constants = &*TEST_CONSTANTS;
+    let dir = std::env::temp_dir().join(format!(
+        "dg_xch_test_weight_proof_verifier_{}",
+        std::process::id()
+    ));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let plot_id = calculate_plot_id_puzzle_hash(Bytes32::new([1u8; 32]), Bytes48::new([2u8; 48]));
+    let plot = DiskPlot::create(&dir, &dir, &dir, 18, 0, &[5u8; 128], plot_id, constants)
+        .await
+        .unwrap();
+    let reader = PlotReader::new(plot, None, None).await.unwrap();
+    let mut wp = weight_proof();
+    let (summaries, _) = map_sub_epoch_summaries(constants, &wp.sub_epochs).unwrap();
+    // Picks signage points the plot has proofs for, the signage point VDFs of the segments are
+    // not part of the reward chain hashes of the summaries
+    for (segment_index, idx) in [(0, 0), (1, 1)] {
+        for seed in 0u8.. {
+            let segment = &mut wp.sub_epoch_segments[segment_index];
+            segment.sub_slots[idx].cc_sp_vdf_info = Some(sp_vdf_info(seed));
+            let (challenge, cc_sp_hash) =
+                segment_block_challenge(constants, segment, idx, &summaries, true).unwrap();
+            if let Some(pos) = prove(&reader, challenge, cc_sp_hash).await {
+                segment.sub_slots[idx].proof_of_space = Some(pos);
+                break;
+            }
+        }
+    }
+    for i in 0..wp.recent_chain_data.len() {
+        for seed in 0u8.. {
+            let challenge = Bytes32::new(hash_256([u8::try_from(i).unwrap(), seed]));
+            if let Some(pos) = prove(&reader, challenge, challenge).await {
+                let rcb = &mut wp.recent_chain_data[i].reward_chain_block;
+                rcb.pos_ss_cc_challenge_hash = challenge;
+                rcb.proof_of_space = pos;
+                break;
+            }
+        }
+        if i > 0 {
+            wp.recent_chain_data[i].foliage.prev_block_hash =
+                wp.recent_chain_data[i - 1].header_hash().unwrap();
+        }
+    }
+    validate_weight_proof(constants, &wp).unwrap();
+    PosWeightProofVerifier.verify(constants, &wp).unwrap();
+    let mut wrong_challenge = wp.clone();
+    wrong_challenge.recent_chain_data[3]
+        .reward_chain_block
+        .pos_ss_cc_challenge_hash = Bytes32::default();
+    assert_eq!(
+        PosWeightProofVerifier
+            .verify(constants, &wrong_challenge)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+    let mut wrong_signage_point = wp.clone();
+    let slot = &mut wrong_signage_point.sub_epoch_segments[1].sub_slots[1];
+    slot.cc_sp_vdf_info = slot.cc_sp_vdf_info.map(|info| VdfInfo {
+        output: ClassgroupElement {
+            data: Bytes100::new([0xff; 100]),
+        },
+        ..info
+    });
+    assert!(PosWeightProofVerifier
+        .verify(constants, &wrong_signage_point)
+        .is_err());
+    // A signage point after the start of the sub slot needs its VDF to end at the signage point
+    let mut missing_sp_vdf = wp;
+    missing_sp_vdf.recent_chain_data[6]
+        .reward_chain_block
+        .signage_point_index = 1;
+    assert!(PosWeightProofVerifier
+        .verify(constants, &missing_sp_vdf)
+        .is_err());
+    drop(reader);
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
+
+/// Weight proof with one more block on top of the recent chain
+fn extend(wp: &WeightProof) -> WeightProof {
+    let mut wp = wp.clone();
+    let prev = wp.recent_chain_data.last().unwrap();
+    let mut block: HeaderBlock = zeroed();
+    block.reward_chain_block.height = prev.reward_chain_block.height + 1;
+    block.reward_chain_block.weight =
+        prev.reward_chain_block.weight + u128::from(TEST_CONSTANTS.difficulty_starting);
+    block.reward_chain_block.total_iters = prev.reward_chain_block.total_iters + 1000;
+    block.foliage.prev_block_hash = prev.header_hash().unwrap();
+    wp.recent_chain_data.push(block);
+    wp
+}
+
+struct MockVerifier {
+    valid: bool,
+}
+impl WeightProofVerifier for MockVerifier {
+    fn verify(&self, _: &ConsensusConstants, _: &WeightProof) -> Result<(), Error> {
+        if self.valid {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::InvalidData, "Invalid VDF"))
+        }
+    }
+}
+
+#[test]
+fn test_weight_proof_tracker() {
+    use dg_xch_core::protocols::full_node::RespondProofOfWeight;
+    let wp = weight_proof();
+    let tip = wp.recent_chain_data[6].header_hash().unwrap();
+    let mut tracker = WeightProofTracker::new(Arc::new(TEST_CONSTANTS.clone()));
+    let request = tracker.request(tip, 12);
+    assert_eq!(request.total_number_of_blocks, 13);
+    let wrong_tip = RespondProofOfWeight {
+        wp: wp.clone(),
+        tip: Bytes32::default(),
+    };
+    assert!(tracker.receive(&request, &wrong_tip).is_err());
+    let response = RespondProofOfWeight { wp, tip };
+    assert_eq!(tracker.receive(&request, &response).unwrap(), 0);
+    assert_eq!(tracker.summaries.len(), 3);
+    assert_eq!(tracker.peak.as_ref().map(|p| p.peak_height), Some(12));
+    assert_eq!(tracker.peak.as_ref().map(|p| p.verified), Some(false));
+    // The same peak again is not heavier
+    assert!(tracker.receive(&request, &response).is_err());
+    // A heavier proof is not taken without verifying it
+    let heavier = extend(&response.wp);
+    let heavier_tip = heavier.recent_chain_data[7].header_hash().unwrap();
+    let heavier_request = tracker.request(heavier_tip, 13);
+    let heavier_response = RespondProofOfWeight {
+        wp: heavier,
+        tip: heavier_tip,
+    };
+    assert_eq!(
+        tracker
+            .receive(&heavier_request, &heavier_response)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::Unsupported
+    );
+    assert_eq!(tracker.peak.as_ref().map(|p| p.peak_height), Some(12));
+    tracker.verifier = Some(Arc::new(MockVerifier { valid: false }));
+    assert!(tracker
+        .receive(&heavier_request, &heavier_response)
+        .is_err());
+    assert_eq!(tracker.peak.as_ref().map(|p| p.peak_height), Some(12));
+    tracker.verifier = Some(Arc::new(MockVerifier { valid: true }));
+    tracker
+        .receive(&heavier_request, &heavier_response)
+        .unwrap();
+    assert_eq!(tracker.peak.as_ref().map(|p| p.peak_height), Some(13));
+    assert_eq!(tracker.peak.as_ref().map(|p| p.verified), Some(true));
+}
+
+/// Byte layout of the streamable `SubEpochData` and `SubEpochChallengeSegment` classes:
+/// optionals are a presence byte followed by the value, lists a big endian u32 length.
+#[test]
+fn test_weight_proof_wire_format() {
+    let mut bytes = vec![7u8; 32];
+    bytes.push(2);
+    bytes.push(0);
+    bytes.push(1);
+    bytes.extend_from_slice(&42u64.to_be_bytes());
+    let data =
+        SubEpochData::from_bytes(&mut Cursor::new(&bytes), ChiaProtocolVersion::default()).unwrap();
+    assert_eq!(
+        data,
+        SubEpochData {
+            reward_chain_hash: Bytes32::new([7u8; 32]),
+            num_blocks_overflow: 2,
+            new_sub_slot_iters: None,
+            new_difficulty: Some(42),
+        }
+    );
+    assert_eq!(
+        data.to_bytes(ChiaProtocolVersion::default()).unwrap(),
+        bytes
+    );
+    let mut bytes = 5u32.to_be_bytes().to_vec();
+    bytes.extend_from_slice(&0u32.to_be_bytes());
+    bytes.push(0);
+    let segment = SubEpochChallengeSegment::from_bytes(
+        &mut Cursor::new(&bytes),
+        ChiaProtocolVersion::default(),
+    )
+    .unwrap();
+    assert_eq!(segment.sub_epoch_n, 5);
+    assert!(segment.sub_slots.is_empty());
+    assert!(segment.rc_slot_end_info.is_none());
+    let wp = weight_proof();
+    let bytes = wp.to_bytes(ChiaProtocolVersion::default()).unwrap();
+    assert_eq!(
+        WeightProof::from_bytes(&mut Cursor::new(&bytes), ChiaProtocolVersion::default()).unwrap(),
+        wp
+    );
+}
+
+#[test]
+fn test_fork_point() {
+    let constants = &*TEST_CONSTANTS;
+    let sub_epochs: Vec<SubEpochData> = (0u8..6)
+        .map(|i| SubEpochData {
+            reward_chain_hash: Bytes32::new([i; 32]),
+            num_blocks_overflow: 0,
+            new_sub_slot_iters: None,
+            new_difficulty: None,
+        })
+        .collect();
+    let (local, _) = map_sub_epoch_summaries(constants, &sub_epochs).unwrap();
+    // Same chain, the last received summary is not compared and three more are not trusted
+    assert_eq!(fork_point(constants, &local, &local), 8);
+    let mut forked = sub_epochs.clone();
+    forked[2].reward_chain_hash = Bytes32::new([9u8; 32]);
+    let (received, _) = map_sub_epoch_summaries(constants, &forked).unwrap();
+    assert_eq!(fork_point(constants, &local, &received), 0);
+    assert_eq!(fork_point(constants, &[], &received), 0);
+}